@@ -28,9 +28,10 @@ use uuid::Uuid;
 use wavesyncdb::dioxus::{
     SyncHandle, use_network_status, use_synced_table, use_wavesync, use_wavesync_provider,
 };
-use wavesyncdb::{NatStatus, RelayStatus, WaveSyncDb, WaveSyncDbBuilder};
+use wavesyncdb::pairing::PairingInvite;
+use wavesyncdb::{NatStatus, RelayStatus, SyncConfig, WaveSyncDb, WaveSyncDbBuilder};
 
-use crate::pairing::{PairingParams, parse_pairing_url};
+use crate::pairing::PairingParams;
 // `QrScannerOverlay` only mounts on Android (the `render_scan_button`
 // helper is empty everywhere else, so `show_scanner` never flips to
 // true). The import + the `if show_scanner()` rsx line still need to
@@ -172,12 +173,7 @@ pub fn run() {
 /// what lets the UI flip from "pairing screen" to "task list" without
 /// restarting the app.
 async fn build_db(paired: PairingParams) -> Result<WaveSyncDb, String> {
-    let data_directory = dioxus_sdk_storage::data_directory().join("qr-pairing.db");
-    if let Some(parent) = data_directory.parent() {
-        if let Err(e) = std::fs::create_dir_all(parent) {
-            return Err(format!("create data dir: {e}"));
-        }
-    }
+    let database_url = database_url()?;
 
     // Relay is plumbing — not part of the pairing payload. Both web
     // and native dial the same logical relay; only the transport
@@ -190,11 +186,8 @@ async fn build_db(paired: PairingParams) -> Result<WaveSyncDb, String> {
         paired.peer_id
     );
 
-    let mut builder = WaveSyncDbBuilder::new(
-        &format!("sqlite:{}?mode=rwc", data_directory.display()),
-        &paired.topic,
-    )
-    .with_relay_server(&relay);
+    let mut builder =
+        WaveSyncDbBuilder::new(&database_url, &paired.topic).with_relay_server(&relay);
     if let Some(pass) = paired.pass.as_deref() {
         builder = builder.with_passphrase(pass);
     }
@@ -210,6 +203,30 @@ async fn build_db(paired: PairingParams) -> Result<WaveSyncDb, String> {
     Ok(db)
 }
 
+/// URL of the SQLite database in `data_directory()`, creating the
+/// directory if needed.
+fn database_url() -> Result<String, String> {
+    let path = dioxus_sdk_storage::data_directory().join("qr-pairing.db");
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("create data dir: {e}"))?;
+    }
+    Ok(format!("sqlite:{}?mode=rwc", path.display()))
+}
+
+/// Run the pairing exchange with the browser that showed `invite`. The
+/// database's own identity is what pairs, so the browser sees the
+/// peer-id this device syncs as.
+async fn pair_with(invite: PairingInvite) -> Result<PairingParams, String> {
+    let config = SyncConfig::pair(&database_url()?, &invite)
+        .await
+        .map_err(|e| format!("pairing: {e}"))?;
+    Ok(PairingParams {
+        peer_id: invite.peer_id,
+        topic: config.topic,
+        pass: config.passphrase,
+    })
+}
+
 /// Init the right log backend per target. On Android, route to
 /// `adb logcat` via `android_logger`; everywhere else, use the
 /// standard `env_logger` (writes to stdout). Both honor `RUST_LOG`,
@@ -418,6 +435,7 @@ fn PairingScreen(
     let mut pass = use_signal(|| DEFAULT_PASS.to_string());
     let mut show_scanner = use_signal(|| false);
     let mut local_err = use_signal(|| Option::<String>::None);
+    let mut pairing = use_signal(|| false);
 
     let mut submit_pairing = move |p: PairingParams| {
         // Persist for next launch even though we don't strictly need
@@ -449,21 +467,31 @@ fn PairingScreen(
 
     let mut on_scan_detect = move |raw: String| {
         show_scanner.set(false);
-        match parse_pairing_url(&raw) {
-            Some(p) => submit_pairing(p),
-            None => local_err.set(Some(format!(
-                "Scanned QR isn't a wavesync:// pairing URL: {raw}"
-            ))),
-        }
+        let invite = match PairingInvite::parse(&raw) {
+            Ok(invite) => invite,
+            Err(e) => {
+                local_err.set(Some(format!("Scanned QR isn't a pairing invite: {e}")));
+                return;
+            }
+        };
+        pairing.set(true);
+        local_err.set(None);
+        spawn(async move {
+            match pair_with(invite).await {
+                Ok(p) => submit_pairing(p),
+                Err(e) => local_err.set(Some(e)),
+            }
+            pairing.set(false);
+        });
     };
 
     rsx! {
         div { class: "panel",
             h2 { "Pair this device" }
             p { class: "blurb",
-                "Scan the QR shown by the web build, or paste the web peer-id and "
-                "topic by hand. The relay is hardcoded into this app — see "
-                "src/native_app.rs RELAY_HOST."
+                "Scan the QR shown by the web build to pair with its one-time code, "
+                "or type the web peer-id, topic and passphrase by hand. The relay is "
+                "hardcoded into this app — see src/native_app.rs RELAY_HOST."
             }
             p { class: "kv", strong { "Relay " } code { "{relay_multiaddr()}" } }
             // Real-device hint. `#[cfg]` inside rsx isn't legal, so
@@ -505,6 +533,9 @@ fn PairingScreen(
                 if building { "Connecting…" } else { "Connect" }
             }
 
+            if pairing() {
+                div { class: "notice", "Pairing with the web peer…" }
+            }
             if let Some(err) = local_err() {
                 div { class: "error", "{err}" }
             }
@@ -97,7 +97,7 @@ struct ScannerMsg {
 
 /// Full-screen QR scanner overlay. On detect, calls `on_detect(raw)`
 /// where `raw` is the QR's text payload (the caller passes that to
-/// `parse_pairing_url`). On user cancel or fatal error, calls
+/// `PairingInvite::parse`). On user cancel or fatal error, calls
 /// `on_close()` and the parent unmounts the overlay.
 #[component]
 pub fn QrScannerOverlay(on_close: EventHandler<()>, on_detect: EventHandler<String>) -> Element {
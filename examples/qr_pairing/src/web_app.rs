@@ -3,20 +3,20 @@
 //! Renders a small pairing form (topic + passphrase — no relay; the
 //! relay multiaddr is hardcoded per platform, same way an app bakes
 //! in a STUN server). Connects the browser via
-//! `WebSyncClient::connect_via_relay`, then once it knows its own
-//! peer-id (read from the engine's status watch channel) generates a
-//! `wavesync://?peer=…&topic=…&pass=…` QR. The phone scans it, joins
-//! the same topic via *its* hardcoded relay, and the two peers
-//! discover each other through the relay's topic mesh.
+//! `WebSyncClient::connect_via_relay`, then once the relay is online
+//! opens a pairing invitation (`WebSyncClient::start_pairing`) and
+//! renders it as a `wavesync://pair?…` QR. The QR carries this tab's
+//! peer-id, its circuit address and a one-time code — never the
+//! passphrase. The phone scans it, runs the pairing exchange through
+//! the relay to receive the topic + passphrase encrypted, and joins.
 
 use dioxus::prelude::*;
 use wavesyncdb::{
     SyncEntity, WebSyncClient, WebSyncStatus,
     dioxus::{SyncHandle, use_synced_table},
+    pairing::{DEFAULT_PAIRING_TTL, PairingInvite},
 };
 
-use crate::pairing::{PairingParams, build_pairing_url};
-
 const STORE_NAME: &str = "qr-pairing-web";
 
 /// PeerId of the default relay (the one the README's
@@ -25,8 +25,8 @@ const STORE_NAME: &str = "qr-pairing-web";
 const DEFAULT_RELAY_PEER_ID: &str = "12D3KooWP6oyorVZmZvTRPHdxsEyE2V8mR1dTiAZJYoNsMNX19KW";
 
 /// Build the relay multiaddr the *browser* dials. The phone uses its
-/// own hardcoded TCP/4001 multiaddr and never sees this one — the QR
-/// only carries peer-id + topic + pass. `window.location.hostname`
+/// own hardcoded QUIC multiaddr to the same relay; the QR's circuit
+/// address names this one. `window.location.hostname`
 /// is the dev machine's address as the browser sees it
 /// (`localhost` when serving locally; LAN IP when a phone loads the
 /// page over wifi to inspect the QR).
@@ -96,10 +96,10 @@ pub fn App() -> Element {
             h1 { "WaveSyncDB · QR pairing" }
             p { class: "blurb",
                 "This browser is now a peer. Once it's connected to the relay, the "
-                "QR below carries this tab's libp2p peer-id plus the topic + "
-                "passphrase. Scan it with the phone build of this example — the "
-                "phone joins the same topic via its own hardcoded relay and the "
-                "two sides discover each other through the relay's topic mesh."
+                "QR below carries this tab's libp2p peer-id and a one-time pairing "
+                "code. Scan it with the phone build of this example — the phone "
+                "proves it knows the code, receives the topic + passphrase "
+                "encrypted, and the two sides sync through the relay."
             }
 
             if let Some(err) = error_msg() {
@@ -109,7 +109,7 @@ pub fn App() -> Element {
             }
 
             if client().is_some() {
-                PairingPanel { client: client, topic: topic(), pass: pass() }
+                PairingPanel { client: client }
                 DebugPanel { client: client }
                 TaskList { handle: SyncHandle::new(client) }
             } else {
@@ -122,30 +122,40 @@ pub fn App() -> Element {
     }
 }
 
-/// Renders the QR once we know the local peer-id. Subscribes to the
-/// engine's status watch channel and rebuilds the QR if the peer-id
-/// changes (it shouldn't, post-IndexedDB-keypair, but if a future
-/// refactor invalidates that assumption the QR stays correct).
+/// Opens a pairing invitation once the relay is online and renders it
+/// as a QR. The code works once and expires, so "New code" opens a
+/// fresh invitation for the next phone.
 #[component]
-fn PairingPanel(client: Signal<Option<WebSyncClient>>, topic: String, pass: String) -> Element {
+fn PairingPanel(client: Signal<Option<WebSyncClient>>) -> Element {
     let status = use_sync_status(client);
     let s = status();
+    let mut invite = use_signal(|| None::<PairingInvite>);
+    let mut invite_error = use_signal(|| None::<String>);
 
-    let qr_svg = use_memo(move || {
-        let s = status();
-        if s.local_peer_id.is_empty() {
-            return None;
-        }
-        let pass_opt = if pass.is_empty() {
-            None
-        } else {
-            Some(pass.clone())
+    let mut open_invitation = move || {
+        let Some(c) = client.peek().clone() else {
+            return;
         };
-        let url = build_pairing_url(&PairingParams {
-            peer_id: s.local_peer_id.clone(),
-            topic: topic.clone(),
-            pass: pass_opt,
+        spawn(async move {
+            match c.start_pairing(DEFAULT_PAIRING_TTL).await {
+                Ok(i) => {
+                    invite_error.set(None);
+                    invite.set(Some(i));
+                }
+                Err(e) => invite_error.set(Some(format!("start_pairing failed: {e}"))),
+            }
         });
+    };
+
+    // The invite points at a circuit through the relay, so wait for it.
+    use_effect(move || {
+        if status().relay_connected && invite.peek().is_none() {
+            open_invitation();
+        }
+    });
+
+    let qr_svg = use_memo(move || {
+        let url = invite()?.to_url();
         match qrcode::QrCode::new(&url) {
             Ok(code) => Some(
                 code.render::<qrcode::render::svg::Color>()
@@ -165,16 +175,27 @@ fn PairingPanel(client: Signal<Option<WebSyncClient>>, topic: String, pass: Stri
             if let Some(svg) = qr_svg() {
                 div { class: "qr-wrap",
                     div { class: "qr", dangerous_inner_html: "{svg}" }
+                    if let Some(i) = invite() {
+                        p { class: "kv", strong { "Code " } code { "{i.code}" } }
+                    }
                     p { class: "hint",
                         if s.relay_connected {
-                            "Relay online — scan the QR with the phone app."
+                            "Relay online — scan the QR with the phone app. The code works once."
                         } else {
                             "Waiting for relay…"
                         }
                     }
+                    button {
+                        class: "secondary",
+                        onclick: move |_| open_invitation(),
+                        "New code"
+                    }
                 }
             } else {
-                p { class: "hint", "Generating QR…" }
+                p { class: "hint", "Waiting for the relay to open a pairing invitation…" }
+            }
+            if let Some(err) = invite_error() {
+                p { class: "error", "{err}" }
             }
         }
     }
@@ -377,6 +398,7 @@ body { background: #0a0d12; color: #e6ecf2; font-family: -apple-system, BlinkMac
 .panel input:focus { outline: none; border-color: #6ce0c9; }
 button.primary { margin-top: 18px; padding: 10px 18px; background: #6ce0c9; color: #06231f; border: none; border-radius: 6px; font-weight: 600; cursor: pointer; }
 button.primary:hover { background: #8ff0d8; }
+button.secondary { margin-top: 8px; padding: 8px 14px; background: transparent; color: #9aa6b6; border: 1px solid #303949; border-radius: 6px; cursor: pointer; font-size: 0.85rem; }
 .error { margin-top: 14px; padding: 10px; background: rgba(247, 200, 124, 0.08); border: 1px solid #f7c87c; color: #f7c87c; border-radius: 6px; font-size: 0.85rem; }
 .qr-wrap { margin-top: 18px; display: flex; flex-direction: column; align-items: center; gap: 10px; }
 .qr { width: 280px; height: 280px; background: white; padding: 12px; border-radius: 8px; }
@@ -0,0 +1,260 @@
+//! `wavesync` — inspect a WaveSyncDB database without opening it by hand.
+//!
+//! Reads go straight to the SQLite file, read-only, through the same
+//! `shadow` and `peer_tracker` queries the engine uses, so the app can stay
+//! open while it runs. `sync` is the exception: it starts the engine from
+//! the saved [`SyncConfig`](wavesyncdb::SyncConfig), exactly like a push
+//! wake-up (see [`wavesyncdb::background_sync`]), so the app must not be
+//! running against the same database.
+
+use std::path::Path;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use sea_orm::{Database, DatabaseConnection};
+use wavesyncdb::background_sync::{BackgroundSyncResult, background_sync_with_peers};
+use wavesyncdb::registry::TableRegistry;
+use wavesyncdb::{peer_addrs, peer_tracker, shadow};
+
+#[derive(Parser)]
+#[command(name = "wavesync", about = "Inspect and sync WaveSyncDB databases")]
+struct Cli {
+    /// The database: a path to the SQLite file, or a `sqlite:` URL.
+    #[arg(long, short, env = "WAVESYNC_DB")]
+    db: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the site id, peer id, db_version, synced tables and watermarks.
+    Status,
+    /// Dump the per-column clocks of one row.
+    Clocks {
+        /// The synced table.
+        table: String,
+        /// The row's primary key.
+        pk: String,
+    },
+    /// List the peers synced with and their cached addresses.
+    Peers,
+    /// Print the changes written here after a local db_version, one JSON
+    /// object per line, as a catch-up would send them.
+    Changes {
+        /// Local db_version to start after (0 for everything).
+        #[arg(long, default_value_t = 0)]
+        since: u64,
+        /// Only this table.
+        #[arg(long)]
+        table: Option<String>,
+    },
+    /// Run one sync with the saved config, then exit.
+    Sync {
+        /// Seconds to wait for peers to finish syncing.
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+        /// A peer address to dial directly (repeatable).
+        #[arg(long)]
+        peer: Vec<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Status => status(&open(&cli.db).await?).await,
+        Command::Clocks { table, pk } => clocks(&open(&cli.db).await?, &table, &pk).await,
+        Command::Peers => peers(&open(&cli.db).await?).await,
+        Command::Changes { since, table } => {
+            changes(&open(&cli.db).await?, since, table.as_deref()).await
+        }
+        Command::Sync { timeout, peer } => sync(&cli.db, timeout, &peer).await,
+    }
+}
+
+/// The `sqlite:` URL for `db`, opened read-only or read-write. A URL keeps
+/// its other parameters but always gets our `mode`, so a `mode=rwc` copied
+/// from the app can't make an inspection write or create the file.
+fn database_url(db: &str, read_only: bool) -> Result<String, String> {
+    let mode = if read_only { "ro" } else { "rw" };
+    if let Some(rest) = db.strip_prefix("sqlite:") {
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let mut params: Vec<&str> = query
+            .split('&')
+            .filter(|p| !p.is_empty() && !p.starts_with("mode="))
+            .collect();
+        let mode = format!("mode={mode}");
+        params.push(&mode);
+        return Ok(format!("sqlite:{path}?{}", params.join("&")));
+    }
+    // Never create a database by mistyping its path.
+    if !Path::new(db).is_file() {
+        return Err(format!("no database at {db}"));
+    }
+    Ok(format!("sqlite:{db}?mode={mode}"))
+}
+
+async fn open(db: &str) -> Result<DatabaseConnection, Box<dyn std::error::Error>> {
+    Ok(Database::connect(database_url(db, true)?).await?)
+}
+
+async fn status(db: &DatabaseConnection) -> Result<(), Box<dyn std::error::Error>> {
+    // The database is open read-only: report what isn't there yet rather
+    // than generating it.
+    match shadow::find_site_id(db).await? {
+        Some(site_id) => println!("site_id     {site_id}"),
+        None => println!("site_id     (none yet)"),
+    }
+    match shadow::find_libp2p_keypair(db).await? {
+        Some(keypair) => println!("peer_id     {}", keypair.public().to_peer_id()),
+        None => println!("peer_id     (none yet)"),
+    }
+    println!("db_version  {}", shadow::get_db_version(db).await?);
+
+    let registry = shadow::registry_from_schema(db).await?;
+    let mut tables: Vec<String> = registry
+        .all_tables()
+        .into_iter()
+        .map(|t| t.table_name)
+        .collect();
+    tables.sort();
+    println!("tables      {}", tables.join(", "));
+
+    println!("watermarks");
+    let watermarks = peer_tracker::get_origin_versions(db).await?;
+    if watermarks.is_empty() {
+        println!("  (none)");
+    }
+    for (site, version) in watermarks.iter() {
+        println!("  {site}  {version}");
+    }
+    Ok(())
+}
+
+async fn clocks(
+    db: &DatabaseConnection,
+    table: &str,
+    pk: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !shadow::shadow_table_exists(db, table).await? {
+        return Err(format!("{table} is not a synced table").into());
+    }
+    let mut entries = shadow::get_clock_entries_for_row(db, table, pk).await?;
+    if entries.is_empty() {
+        return Err(format!("no clocks for {table}/{pk}").into());
+    }
+    entries.sort_by(|a, b| a.cid.cmp(&b.cid));
+    println!(
+        "{:<20} {:>12} {:>10} {:>5} {:>10}  site_id",
+        "column", "col_version", "db_version", "seq", "origin"
+    );
+    for e in entries {
+        println!(
+            "{:<20} {:>12} {:>10} {:>5} {:>10}  {}",
+            e.cid, e.col_version, e.db_version, e.seq, e.origin_version, e.site_id
+        );
+    }
+    Ok(())
+}
+
+async fn peers(db: &DatabaseConnection) -> Result<(), Box<dyn std::error::Error>> {
+    let peers = peer_tracker::get_peers(db).await?;
+    println!("peers");
+    if peers.is_empty() {
+        println!("  (none)");
+    }
+    for p in peers {
+        let site = p.site_id.map_or_else(|| "-".to_string(), |s| s.to_string());
+        println!(
+            "  {}  site {site}  db_version {}  last seen {}",
+            p.peer_id, p.db_version, p.last_seen
+        );
+    }
+
+    // No age or failure cutoff: every cached address.
+    let addrs = peer_addrs::load_recent(db, u64::MAX, u32::MAX).await?;
+    println!("cached addresses");
+    if addrs.is_empty() {
+        println!("  (none)");
+    }
+    for a in addrs {
+        println!(
+            "  {}  {}  last ok {}  failures {}",
+            a.peer_id, a.multiaddr, a.last_ok_at, a.fail_count
+        );
+    }
+    Ok(())
+}
+
+async fn changes(
+    db: &DatabaseConnection,
+    since: u64,
+    table: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut registry = shadow::registry_from_schema(db).await?;
+    if let Some(table) = table {
+        let meta = registry
+            .get(table)
+            .ok_or_else(|| format!("{table} is not a synced table"))?;
+        registry = TableRegistry::new();
+        registry.register(meta);
+    }
+    for change in shadow::get_changes_since(db, &registry, since, None).await? {
+        println!("{}", serde_json::to_string(&change)?);
+    }
+    Ok(())
+}
+
+async fn sync(db: &str, timeout: u64, peers: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let url = database_url(db, false)?;
+    let result = background_sync_with_peers(&url, Duration::from_secs(timeout), peers).await?;
+    match result {
+        BackgroundSyncResult::Synced { peers_synced } => {
+            println!("synced with {peers_synced} peer(s)");
+        }
+        BackgroundSyncResult::TimedOut { peers_synced } => {
+            println!("timed out after syncing with {peers_synced} peer(s)");
+        }
+        BackgroundSyncResult::NoPeers => return Err("no peers found".into()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_database_url() {
+        assert_eq!(
+            database_url("sqlite:/data/app.db?mode=rwc", true).unwrap(),
+            "sqlite:/data/app.db?mode=ro"
+        );
+        assert_eq!(
+            database_url("sqlite:/data/app.db", true).unwrap(),
+            "sqlite:/data/app.db?mode=ro"
+        );
+        assert_eq!(
+            database_url("sqlite:/data/app.db?cache=shared&mode=rwc", false).unwrap(),
+            "sqlite:/data/app.db?cache=shared&mode=rw"
+        );
+        assert!(database_url("/no/such/app.db", true).is_err());
+
+        let path = std::env::temp_dir().join("wavesync_cli_url_test.db");
+        std::fs::write(&path, b"").unwrap();
+        let path = path.to_str().unwrap();
+        assert_eq!(
+            database_url(path, true).unwrap(),
+            format!("sqlite:{path}?mode=ro")
+        );
+        assert_eq!(
+            database_url(path, false).unwrap(),
+            format!("sqlite:{path}?mode=rw")
+        );
+    }
+}
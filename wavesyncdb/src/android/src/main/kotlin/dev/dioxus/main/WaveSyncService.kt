@@ -53,24 +53,27 @@ class WaveSyncService : FirebaseMessagingService() {
         }
 
         /**
-         * Initialize Firebase from credentials saved in .wavesync_config.json.
+         * Initialize Firebase from the credentials saved with the WaveSyncDB
+         * config. They're kept in its secret store rather than in
+         * .wavesync_config.json, so the native library reads them back.
          * Called on cold start when the Google Services plugin isn't available.
          */
         private fun ensureFirebaseFromConfig(context: Context): Boolean {
             if (com.google.firebase.FirebaseApp.getApps(context).isNotEmpty()) return true
 
-            // Find and parse .wavesync_config.json
+            // Find .wavesync_config.json for the database URL
             val config = findConfigFile(context) ?: return false
             try {
-                val json = config.readText()
-                val projectId = Regex(""""fcm_project_id"\s*:\s*"([^"]+)"""").find(json)?.groupValues?.get(1) ?: return false
-                val appId = Regex(""""fcm_app_id"\s*:\s*"([^"]+)"""").find(json)?.groupValues?.get(1) ?: return false
-                val apiKey = Regex(""""fcm_api_key"\s*:\s*"([^"]+)"""").find(json)?.groupValues?.get(1) ?: return false
+                val dbUrl = Regex(""""database_url"\s*:\s*"([^"]+)"""").find(config.readText())?.groupValues?.get(1) ?: return false
+
+                ensureNativeLoaded()
+                if (!nativeLoaded) return false
+                val json = org.json.JSONObject(firebaseOptions(dbUrl) ?: return false)
 
                 val options = com.google.firebase.FirebaseOptions.Builder()
-                    .setProjectId(projectId)
-                    .setApplicationId(appId)
-                    .setApiKey(apiKey)
+                    .setProjectId(json.getString("project_id"))
+                    .setApplicationId(json.getString("app_id"))
+                    .setApiKey(json.getString("api_key"))
                     .build()
                 com.google.firebase.FirebaseApp.initializeApp(context, options)
                 Log.i(TAG, "Firebase initialized from wavesync config")
@@ -116,6 +119,9 @@ class WaveSyncService : FirebaseMessagingService() {
             }
         }
 
+        @JvmStatic
+        private external fun firebaseOptions(databaseUrl: String): String?
+
         @JvmStatic
         private external fun backgroundSync(databaseUrl: String, timeoutSecs: Int, peerAddrsJson: String?): Int
     }
@@ -5,6 +5,77 @@
 //!    passphrases yield different topics and peers never see each other's messages.
 //! 2. **HMAC on all messages** — request-response messages carry a BLAKE3-keyed MAC.
 //!    Peers without the PSK cannot forge or inject valid messages.
+//! 3. **Session binding** — once two peers have exchanged nonces, their
+//!    messages are MACed with a [`GroupKey::session_key`] for that connection,
+//!    so captured messages can't be replayed elsewhere. Messages outside a
+//!    session are MACed with a [`GroupKey::receiver_key`], so they can't be
+//!    replayed to another member.
+//! 4. **Payload encryption** — the column changes inside a message are sealed
+//!    with a [`GroupKey::payload_key`] (see [`crate::seal`]).
+//!
+//! The passphrase can be changed without splitting the group by rotating
+//! it (see [`crate::rotation`]).
+//!
+//! ## Key derivation versions
+//!
+//! The derived topic is visible to anyone on the LAN or at the rendezvous
+//! server, and checking a guessed passphrase against it costs one hash. So:
+//!
+//! - **v1** — [`GroupKey::from_passphrase`], a single BLAKE3 derivation.
+//!   Kept so groups created with it keep working.
+//! - **v2** — [`GroupKey::from_passphrase_salted`], Argon2id (19 MiB, two
+//!   passes) over the passphrase and a random [`GroupSalt`] shared by the
+//!   group, so each guess costs real memory and time, and a precomputed
+//!   dictionary is worthless.
+//!
+//! The two give different keys and topics; a group is on one or the other.
+//! The salt isn't secret and travels with the passphrase — in the sync
+//! config and in pairing. A v1 group moves to v2 by rotating its key (see
+//! [`crate::rotation`]), which only goes to peers announcing
+//! [`FEATURE_ARGON2ID_KDF`](crate::protocol::FEATURE_ARGON2ID_KDF).
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::seal::{PayloadKey, from_hex, to_hex};
+
+/// Argon2id memory cost in KiB, pass count and lanes of the v2 derivation
+/// (OWASP's minimum recommendation).
+const ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+const ARGON2_PASSES: u32 = 2;
+const ARGON2_LANES: u32 = 1;
+
+/// The random salt a group's v2 key is derived with. Not secret: every
+/// device needs it, alongside the passphrase, to derive the key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupSalt(pub [u8; 16]);
+
+impl GroupSalt {
+    /// A fresh random salt, for a new group or a rotation to v2.
+    pub fn generate() -> Self {
+        Self(*uuid::Uuid::new_v4().as_bytes())
+    }
+
+    /// Hex form, for configs and QR payloads.
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.0)
+    }
+
+    /// Parse the form [`GroupSalt::to_hex`] produces.
+    pub fn from_hex(s: &str) -> Option<Self> {
+        from_hex(s)?.try_into().ok().map(Self)
+    }
+}
+
+/// v2 keys already derived in this process, keyed by a hash of passphrase
+/// and salt: the engine asks for the same few keys on every message, and
+/// each derivation costs tens of milliseconds on purpose.
+fn derived_keys() -> &'static Mutex<HashMap<[u8; 32], [u8; 32]>> {
+    static KEYS: OnceLock<Mutex<HashMap<[u8; 32], [u8; 32]>>> = OnceLock::new();
+    KEYS.get_or_init(Default::default)
+}
 
 /// A group authentication key derived from a user-supplied passphrase.
 ///
@@ -14,12 +85,72 @@
 pub struct GroupKey([u8; 32]);
 
 impl GroupKey {
-    /// Derive a group key from a passphrase using BLAKE3 key derivation.
+    /// Derive a group key from a passphrase using BLAKE3 key derivation
+    /// (v1). Fast to check guesses against; new groups should use
+    /// [`GroupKey::from_passphrase_salted`].
     pub fn from_passphrase(passphrase: &str) -> Self {
         let key = blake3::derive_key("wavesyncdb-group-key-v1", passphrase.as_bytes());
         Self(key)
     }
 
+    /// Derive a group key from a passphrase and the group's salt using
+    /// Argon2id (v2).
+    ///
+    /// This takes tens of milliseconds; async code should go through
+    /// [`GroupKey::derive_blocking`].
+    pub fn from_passphrase_salted(passphrase: &str, salt: &GroupSalt) -> Self {
+        let mut cache_key = blake3::Hasher::new_derive_key("wavesyncdb-group-key-cache-v2");
+        cache_key.update(&salt.0);
+        cache_key.update(passphrase.as_bytes());
+        let cache_key = *cache_key.finalize().as_bytes();
+        if let Some(key) = derived_keys()
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&cache_key)
+        {
+            return Self(*key);
+        }
+
+        // Not under the lock: other derivations, and cache hits, go ahead
+        // while this one hashes.
+        let params = argon2::Params::new(ARGON2_MEMORY_KIB, ARGON2_PASSES, ARGON2_LANES, Some(32))
+            .expect("constant Argon2 parameters are valid");
+        let mut stretched = [0u8; 32];
+        argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt.0, &mut stretched)
+            .expect("a 16-byte salt and 32-byte output are within Argon2's limits");
+        let key = blake3::derive_key("wavesyncdb-group-key-v2", &stretched);
+        let mut cache = derived_keys().lock().unwrap_or_else(|e| e.into_inner());
+        // Rotations only ever leave a handful of keys worth keeping.
+        if cache.len() >= 8 {
+            cache.clear();
+        }
+        cache.insert(cache_key, key);
+        Self(key)
+    }
+
+    /// Derive the key for `passphrase`: v2 when the group has a salt, v1
+    /// otherwise.
+    pub fn derive(passphrase: &str, salt: Option<&GroupSalt>) -> Self {
+        match salt {
+            Some(salt) => Self::from_passphrase_salted(passphrase, salt),
+            None => Self::from_passphrase(passphrase),
+        }
+    }
+
+    /// [`GroupKey::derive`] on the blocking thread pool, so an Argon2id
+    /// derivation doesn't stall the async task asking for it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn derive_blocking(passphrase: &str, salt: Option<&GroupSalt>) -> Self {
+        let Some(salt) = salt.copied() else {
+            return Self::from_passphrase(passphrase);
+        };
+        let passphrase = passphrase.to_string();
+        tokio::task::spawn_blocking(move || Self::from_passphrase_salted(&passphrase, &salt))
+            .await
+            .expect("Argon2id derivation doesn't panic")
+    }
+
     /// Derive a sync topic name from the user topic and this group key.
     ///
     /// Different passphrases produce different topic names, providing topic-level
@@ -41,6 +172,50 @@ impl GroupKey {
         self.derive_topic(user_topic)
     }
 
+    /// Derive the key that authenticates messages from `sender` to
+    /// `receiver` on one connection, identified by the session nonces both
+    /// sides announced in their hellos. A tag made with it is worthless on
+    /// any other connection, in either direction.
+    pub fn session_key(
+        &self,
+        sender: &[u8],
+        receiver: &[u8],
+        sender_nonce: &[u8; 16],
+        receiver_nonce: &[u8; 16],
+    ) -> Self {
+        let mut hasher = blake3::Hasher::new_derive_key("wavesyncdb-session-v1");
+        hasher.update(&self.0);
+        for id in [sender, receiver] {
+            hasher.update(&(id.len() as u32).to_le_bytes());
+            hasher.update(id);
+        }
+        hasher.update(sender_nonce);
+        hasher.update(receiver_nonce);
+        Self(*hasher.finalize().as_bytes())
+    }
+
+    /// Derive the key that authenticates messages to `receiver` outside a
+    /// session, e.g. from browser clients, which never set one up. A tag
+    /// made with it is worthless sent to any other peer.
+    pub fn receiver_key(&self, receiver: &[u8]) -> Self {
+        let mut hasher = blake3::Hasher::new_derive_key("wavesyncdb-receiver-v1");
+        hasher.update(&self.0);
+        hasher.update(receiver);
+        Self(*hasher.finalize().as_bytes())
+    }
+
+    /// Derive the key that encrypts change payloads. A separate KDF context
+    /// keeps it independent of the MAC key.
+    pub fn payload_key(&self) -> PayloadKey {
+        PayloadKey::from_bytes(blake3::derive_key("wavesyncdb-payload-v1", &self.0))
+    }
+
+    /// A public identifier for this key. Lets peers tell keys apart — e.g.
+    /// to settle two rotations of the same epoch — without revealing them.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        blake3::derive_key("wavesyncdb-key-fingerprint-v1", &self.0)
+    }
+
     /// Compute a BLAKE3 keyed MAC over the given data.
     pub fn mac(&self, data: &[u8]) -> [u8; 32] {
         *blake3::keyed_hash(&self.0, data).as_bytes()
@@ -48,9 +223,9 @@ impl GroupKey {
 
     /// Verify a BLAKE3 keyed MAC over the given data.
     pub fn verify(&self, data: &[u8], tag: &[u8; 32]) -> bool {
-        let expected = self.mac(data);
-        // Constant-time comparison
-        expected == *tag
+        // `blake3::Hash` compares in constant time; a plain array `==`
+        // stops at the first differing byte.
+        blake3::keyed_hash(&self.0, data) == blake3::Hash::from(*tag)
     }
 }
 
@@ -113,6 +288,62 @@ mod tests {
         assert!(!k.verify(b"tampered", &tag));
     }
 
+    #[test]
+    fn test_session_keys_are_bound_to_direction_and_nonces() {
+        let k = GroupKey::from_passphrase("secret");
+        let (a, b) = (b"peer-a".as_slice(), b"peer-b".as_slice());
+        let (na, nb) = ([1u8; 16], [2u8; 16]);
+        let a_to_b = k.session_key(a, b, &na, &nb);
+        assert_eq!(a_to_b.0, k.session_key(a, b, &na, &nb).0);
+        assert_ne!(a_to_b.0, k.session_key(b, a, &nb, &na).0);
+        assert_ne!(a_to_b.0, k.session_key(a, b, &[3u8; 16], &nb).0);
+        assert_ne!(a_to_b.0, k.0);
+
+        let tag = a_to_b.mac(b"push");
+        assert!(a_to_b.verify(b"push", &tag));
+        assert!(!k.verify(b"push", &tag));
+    }
+
+    #[test]
+    fn test_receiver_keys_are_bound_to_the_receiver() {
+        let k = GroupKey::from_passphrase("secret");
+        let to_a = k.receiver_key(b"peer-a");
+        assert_eq!(to_a.0, k.receiver_key(b"peer-a").0);
+        assert_ne!(to_a.0, k.receiver_key(b"peer-b").0);
+        assert_ne!(to_a.0, k.0);
+
+        let tag = to_a.mac(b"push");
+        assert!(!k.receiver_key(b"peer-b").verify(b"push", &tag));
+        assert!(!k.verify(b"push", &tag));
+    }
+
+    #[test]
+    fn test_salted_derivation() {
+        let salt = GroupSalt([7u8; 16]);
+        let k1 = GroupKey::from_passphrase_salted("secret", &salt);
+        let k2 = GroupKey::from_passphrase_salted("secret", &salt);
+        assert_eq!(k1.0, k2.0);
+        assert_ne!(k1.0, GroupKey::from_passphrase("secret").0);
+        assert_ne!(
+            k1.0,
+            GroupKey::from_passphrase_salted("secret", &GroupSalt([8u8; 16])).0
+        );
+        assert_ne!(k1.0, GroupKey::from_passphrase_salted("other", &salt).0);
+        assert_eq!(GroupKey::derive("secret", Some(&salt)).0, k1.0);
+        assert_eq!(
+            GroupKey::derive("secret", None).0,
+            GroupKey::from_passphrase("secret").0
+        );
+    }
+
+    #[test]
+    fn test_salt_hex_roundtrip() {
+        let salt = GroupSalt::generate();
+        assert_eq!(GroupSalt::from_hex(&salt.to_hex()), Some(salt));
+        assert_eq!(GroupSalt::from_hex("abcd"), None);
+        assert_ne!(GroupSalt::generate(), salt);
+    }
+
     #[test]
     fn test_mac_wrong_key() {
         let k1 = GroupKey::from_passphrase("key1");
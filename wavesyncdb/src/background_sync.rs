@@ -20,11 +20,13 @@
 //! ```
 
 use std::collections::HashSet;
+use std::sync::Arc;
 use std::time::Duration;
 
 use crate::WaveSyncDbBuilder;
 use crate::connection::SyncConfig;
 use crate::network_status::NetworkEvent;
+use crate::secret_store::SecretStore;
 
 /// Result of a background sync operation.
 #[derive(Debug)]
@@ -92,6 +94,21 @@ pub async fn background_sync_with_peers(
     database_url: &str,
     timeout: Duration,
     peer_addrs: &[String],
+) -> Result<BackgroundSyncResult, BackgroundSyncError> {
+    let secrets = SyncConfig::default_secret_store(database_url)
+        .map_err(BackgroundSyncError::ConfigNotFound)?;
+    background_sync_with_store(database_url, timeout, peer_addrs, Arc::new(secrets)).await
+}
+
+/// Performs a one-shot background sync reading the config's secrets from
+/// `secrets` — the store the app passed to
+/// [`WaveSyncDbBuilder::with_secret_store`]. The other entry points use the
+/// default [`FileSecretStore`](crate::secret_store::FileSecretStore).
+pub async fn background_sync_with_store(
+    database_url: &str,
+    timeout: Duration,
+    peer_addrs: &[String],
+    secrets: Arc<dyn SecretStore>,
 ) -> Result<BackgroundSyncResult, BackgroundSyncError> {
     // Per-stage timing. When a sync round is slow (sometimes hits the 25s
     // timeout while typical runs are 2–3s), the question is always "where
@@ -117,7 +134,7 @@ pub async fn background_sync_with_peers(
     };
 
     // 1. Load saved config
-    let config = SyncConfig::load(database_url).map_err(|e| {
+    let config = SyncConfig::load_with(database_url, secrets.as_ref()).map_err(|e| {
         if e.contains("Failed to read") {
             BackgroundSyncError::ConfigNotFound(e)
         } else {
@@ -155,10 +172,15 @@ pub async fn background_sync_with_peers(
             builder = builder.with_bootstrap_peer(peer);
         }
     }
+    if let Some(salt) = config.group_salt {
+        builder = builder.with_group_salt(salt);
+    }
     if config.ipv6 {
         builder = builder.with_ipv6(true);
     }
 
+    builder = builder.with_secret_store(secrets);
+
     // Add dynamic peer addresses from FCM payload (direct dial, skips discovery)
     for addr in peer_addrs {
         builder = builder.with_bootstrap_peer(addr);
@@ -173,14 +195,30 @@ pub async fn background_sync_with_peers(
     log_stage("engine_built");
 
     // 4. Initialize schema registry (tables already exist, but registry needs populating)
-    if let Some(ref crate_name) = config.crate_name {
-        db.get_schema_registry(crate_name)
+    let schema = config
+        .crate_name
+        .as_deref()
+        .map(|crate_name| db.get_schema_registry(crate_name))
+        .filter(|schema| !schema.is_empty());
+    match schema {
+        // Signals the engine once the entities are registered.
+        Some(schema) => schema
             .sync()
             .await
-            .map_err(|e| BackgroundSyncError::RegistryError(e.to_string()))?;
-    } else {
-        // No crate name saved — signal registry ready anyway so engine can proceed
-        db.registry_ready();
+            .map_err(|e| BackgroundSyncError::RegistryError(e.to_string()))?,
+        // A process that doesn't link the app's entities (the `wavesync`
+        // CLI) finds none of them: sync the tables the database already
+        // has. They have to be registered before the engine is told the
+        // registry is ready, or it drops changes for them as unregistered.
+        None => {
+            let tables = crate::shadow::registry_from_schema(db.inner())
+                .await
+                .map_err(|e| BackgroundSyncError::RegistryError(e.to_string()))?;
+            for meta in tables.all_tables() {
+                db.registry().register(meta);
+            }
+            db.registry_ready();
+        }
     }
     log_stage("registry_ready");
 
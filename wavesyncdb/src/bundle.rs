@@ -0,0 +1,352 @@
+//! Offline changeset bundles, for sites that never share a network.
+//!
+//! [`WaveSyncDb::export_bundle`](crate::WaveSyncDb::export_bundle) writes
+//! every change past a set of watermarks to a file, which is carried to
+//! another device (on a USB stick, say) and merged there with
+//! [`WaveSyncDb::import_bundle`](crate::WaveSyncDb::import_bundle). Import
+//! goes through the same checks, conflict resolution and watermark
+//! bookkeeping as a catch-up over the network, so devices that only ever
+//! exchange bundles converge exactly like ones that sync.
+//!
+//! ## Format
+//!
+//! A bundle is one JSON document:
+//!
+//! - `format` and `version` — always `"wavesync-bundle"` and
+//!   [`BUNDLE_VERSION`].
+//! - `header` — a [`BundleHeader`]: who exported it, for which topic, the
+//!   watermarks it starts from and the exporter's watermarks when it was
+//!   made.
+//! - `changes` — the [`ColumnChange`]s, as they travel in a catch-up,
+//!   signatures included; or `sealed`, the same list encrypted under the
+//!   group's payload key (see [`crate::seal`]).
+//! - `tag` — hex BLAKE3 over the document with `tag` unset and the changes
+//!   in the clear: keyed with the group key when a passphrase is set,
+//!   plain otherwise. The plain hash only catches damage in transit;
+//!   anyone can recompute it.
+//!
+//! As with sync messages, the tag is computed before sealing and checked
+//! after opening.
+//!
+//! ## Watermarks
+//!
+//! A bundle holds every change past `since`. An importer that already held
+//! everything up to `since` for a site holds everything up to the
+//! exporter's watermark for it afterwards, and adopts that watermark; for
+//! other sites it only merges the changes. To export just what the other
+//! side is missing, pass the watermarks its last bundle reported
+//! ([`BundleImport::watermarks`]).
+
+use serde::{Deserialize, Serialize};
+
+use crate::auth::GroupKey;
+use crate::messages::{ColumnChange, NodeId};
+use crate::protocol::OriginVersions;
+use crate::seal::{SealError, SealedChanges, from_hex, to_hex};
+
+/// Value of a bundle's `format` field.
+pub const BUNDLE_FORMAT: &str = "wavesync-bundle";
+
+/// Version of the bundle format this build writes and reads.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Domain separator prefixed to the bytes a bundle's tag covers, so a
+/// bundle's tag can't pass for a sync message's MAC.
+const BUNDLE_DOMAIN: &[u8] = b"wavesyncdb-bundle-v1";
+
+/// Errors from reading or writing a bundle.
+#[derive(Debug, thiserror::Error)]
+pub enum BundleError {
+    #[error("not a wavesync bundle")]
+    NotABundle,
+    #[error("bundle format version {0} is not supported (this build reads {BUNDLE_VERSION})")]
+    UnsupportedVersion(u32),
+    #[error("bundle is malformed: {0}")]
+    Malformed(String),
+    #[error("bundle is for topic {0:?}")]
+    WrongTopic(String),
+    #[error("bundle was made with a passphrase, and none is configured")]
+    NoKey,
+    #[error("bundle was made without a passphrase, and one is configured")]
+    Unauthenticated,
+    #[error("bundle was made with a different passphrase")]
+    WrongKey,
+    #[error("encrypting a bundle needs a passphrase")]
+    CannotEncrypt,
+    #[error("bundle failed its integrity check")]
+    Tampered,
+    #[error("bundle could not be decrypted: {0}")]
+    Sealed(SealError),
+}
+
+/// Where a bundle comes from and what it covers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleHeader {
+    /// Site that exported it.
+    pub site_id: NodeId,
+    /// libp2p peer id of the exporting device.
+    pub peer_id: String,
+    /// The exporter's `db_version` at export.
+    pub db_version: u64,
+    /// The topic the exporter's app configured.
+    pub topic: String,
+    /// Unix seconds at export.
+    pub created_at: u64,
+    /// The watermarks it starts from: it holds every change past them.
+    pub since: OriginVersions,
+    /// The exporter's watermarks at export, its own site included.
+    pub watermarks: OriginVersions,
+    /// Hex fingerprint of the group key it was made with (see
+    /// [`GroupKey::fingerprint`]); `None` without a passphrase.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_fingerprint: Option<String>,
+}
+
+/// What [`WaveSyncDb::import_bundle`](crate::WaveSyncDb::import_bundle)
+/// did with a bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleImport {
+    /// The bundle's header.
+    pub header: BundleHeader,
+    /// Changes merged, winning or not.
+    pub merged: usize,
+    /// Changes refused by the same checks as changes from peers — forged,
+    /// unauthorized, with inflated clocks or mistyped values.
+    pub refused: usize,
+    /// The exporter's watermarks: pass them to
+    /// [`WaveSyncDb::export_bundle`](crate::WaveSyncDb::export_bundle) to
+    /// send it back only what it is missing.
+    pub watermarks: OriginVersions,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleFile {
+    format: String,
+    version: u32,
+    header: BundleHeader,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    changes: Vec<ColumnChange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sealed: Option<SealedChanges>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+}
+
+impl BundleFile {
+    /// The bytes the tag covers. `tag` must be unset and the changes in
+    /// the clear.
+    fn tagged_bytes(&self) -> Vec<u8> {
+        let mut bytes = BUNDLE_DOMAIN.to_vec();
+        bytes.extend(serde_json::to_vec(self).expect("bundle serializes to JSON"));
+        bytes
+    }
+
+    fn compute_tag(&self, key: Option<&GroupKey>) -> [u8; 32] {
+        let bytes = self.tagged_bytes();
+        match key {
+            Some(key) => key.mac(&bytes),
+            None => *blake3::hash(&bytes).as_bytes(),
+        }
+    }
+}
+
+/// Write `changes` as a bundle under `header`, tagged with `key` and, with
+/// `encrypt`, sealed under its payload key.
+pub fn encode(
+    mut header: BundleHeader,
+    changes: Vec<ColumnChange>,
+    key: Option<&GroupKey>,
+    encrypt: bool,
+) -> Result<Vec<u8>, BundleError> {
+    if encrypt && key.is_none() {
+        return Err(BundleError::CannotEncrypt);
+    }
+    header.key_fingerprint = key.map(|k| to_hex(&k.fingerprint()));
+    let mut file = BundleFile {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        header,
+        changes,
+        sealed: None,
+        tag: None,
+    };
+    let tag = file.compute_tag(key);
+    file.tag = Some(to_hex(&tag));
+    if let Some(key) = key.filter(|_| encrypt) {
+        crate::seal::seal_into(&key.payload_key(), &mut file.changes, &mut file.sealed);
+    }
+    serde_json::to_vec(&file).map_err(|e| BundleError::Malformed(e.to_string()))
+}
+
+/// Read a bundle for `topic`, checking its tag with `key`. Returns its
+/// header and changes.
+pub fn decode(
+    bytes: &[u8],
+    topic: &str,
+    key: Option<&GroupKey>,
+) -> Result<(BundleHeader, Vec<ColumnChange>), BundleError> {
+    let value: serde_json::Value =
+        serde_json::from_slice(bytes).map_err(|_| BundleError::NotABundle)?;
+    if value.get("format").and_then(|f| f.as_str()) != Some(BUNDLE_FORMAT) {
+        return Err(BundleError::NotABundle);
+    }
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version != BUNDLE_VERSION as u64 {
+        return Err(BundleError::UnsupportedVersion(version as u32));
+    }
+    let mut file: BundleFile =
+        serde_json::from_value(value).map_err(|e| BundleError::Malformed(e.to_string()))?;
+
+    if file.header.topic != topic {
+        return Err(BundleError::WrongTopic(file.header.topic));
+    }
+    match (key, file.header.key_fingerprint.as_deref()) {
+        (None, Some(_)) => return Err(BundleError::NoKey),
+        (Some(_), None) => return Err(BundleError::Unauthenticated),
+        (Some(k), Some(fingerprint)) if fingerprint != to_hex(&k.fingerprint()) => {
+            return Err(BundleError::WrongKey);
+        }
+        _ => {}
+    }
+
+    let payload_key = key.map(GroupKey::payload_key);
+    crate::seal::open_into(payload_key.as_ref(), &mut file.changes, &mut file.sealed)
+        .map_err(BundleError::Sealed)?;
+
+    let tag = file
+        .tag
+        .take()
+        .and_then(|t| from_hex(&t))
+        .and_then(|t| <[u8; 32]>::try_from(t).ok())
+        .ok_or(BundleError::Tampered)?;
+    let valid = match key {
+        Some(k) => k.verify(&file.tagged_bytes(), &tag),
+        None => file.compute_tag(None) == tag,
+    };
+    if !valid {
+        return Err(BundleError::Tampered);
+    }
+    Ok((file.header, file.changes))
+}
+
+/// The watermarks an importer holding `ours` may adopt from a bundle made
+/// under `header`: the exporter's, for each site whose changes up to
+/// `since` the importer already held. The other sites' changes still
+/// merge, but leave gaps the watermark must not paper over.
+pub fn adoptable_watermarks(header: &BundleHeader, ours: &OriginVersions) -> OriginVersions {
+    let mut adoptable = OriginVersions::default();
+    for (site, version) in header.watermarks.iter() {
+        if ours.get(site) >= header.since.get(site) {
+            adoptable.advance(*site, *version);
+        }
+    }
+    adoptable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> BundleHeader {
+        BundleHeader {
+            site_id: NodeId([1u8; 16]),
+            peer_id: "12D3KooWexporter".to_string(),
+            db_version: 7,
+            topic: "field-notes".to_string(),
+            created_at: 1_700_000_000,
+            since: OriginVersions::default(),
+            watermarks: OriginVersions::default().with(NodeId([1u8; 16]), 7),
+            key_fingerprint: None,
+        }
+    }
+
+    fn change(val: &str) -> ColumnChange {
+        ColumnChange {
+            table: "notes".into(),
+            pk: "pk-1".into(),
+            cid: "body".into(),
+            val: Some(serde_json::json!(val)),
+            site_id: NodeId([1u8; 16]),
+            col_version: 1,
+            cl: 1,
+            seq: 0,
+            db_version: 7,
+            sig: None,
+        }
+    }
+
+    #[test]
+    fn test_bundle_roundtrip_with_and_without_key() {
+        let bytes = encode(header(), vec![change("a")], None, false).unwrap();
+        let (h, changes) = decode(&bytes, "field-notes", None).unwrap();
+        assert_eq!(h, header());
+        assert_eq!(changes, vec![change("a")]);
+
+        let key = GroupKey::from_passphrase("secret");
+        for encrypt in [false, true] {
+            let bytes = encode(header(), vec![change("b")], Some(&key), encrypt).unwrap();
+            let plaintext = String::from_utf8_lossy(&bytes).contains("\"body\"");
+            assert_eq!(
+                plaintext, !encrypt,
+                "values are in the clear unless encrypted"
+            );
+            let (_, changes) = decode(&bytes, "field-notes", Some(&key)).unwrap();
+            assert_eq!(changes, vec![change("b")]);
+        }
+    }
+
+    #[test]
+    fn test_bundle_refuses_tampering_and_foreign_keys() {
+        let key = GroupKey::from_passphrase("secret");
+        let bytes = encode(header(), vec![change("a")], Some(&key), false).unwrap();
+        let tampered = String::from_utf8(bytes.clone())
+            .unwrap()
+            .replace("\"a\"", "\"z\"");
+        assert!(matches!(
+            decode(tampered.as_bytes(), "field-notes", Some(&key)),
+            Err(BundleError::Tampered)
+        ));
+
+        let other = GroupKey::from_passphrase("other");
+        assert!(matches!(
+            decode(&bytes, "field-notes", Some(&other)),
+            Err(BundleError::WrongKey)
+        ));
+        assert!(matches!(
+            decode(&bytes, "field-notes", None),
+            Err(BundleError::NoKey)
+        ));
+        assert!(matches!(
+            decode(&bytes, "other-topic", Some(&key)),
+            Err(BundleError::WrongTopic(_))
+        ));
+
+        let open = encode(header(), vec![change("a")], None, false).unwrap();
+        assert!(matches!(
+            decode(&open, "field-notes", Some(&key)),
+            Err(BundleError::Unauthenticated)
+        ));
+        assert!(matches!(
+            encode(header(), vec![], None, true),
+            Err(BundleError::CannotEncrypt)
+        ));
+        assert!(matches!(
+            decode(b"{\"hello\":1}", "field-notes", None),
+            Err(BundleError::NotABundle)
+        ));
+    }
+
+    #[test]
+    fn test_adoptable_watermarks_need_since_covered() {
+        let (a, b) = (NodeId([1u8; 16]), NodeId([2u8; 16]));
+        let mut h = header();
+        h.since = OriginVersions::default().with(a, 5).with(b, 5);
+        h.watermarks = OriginVersions::default().with(a, 9).with(b, 9);
+
+        // Held a up to 5 but b only up to 3: the bundle leaves b's 4..5 out.
+        let ours = OriginVersions::default().with(a, 5).with(b, 3);
+        let adoptable = adoptable_watermarks(&h, &ours);
+        assert_eq!(adoptable.get(&a), 9);
+        assert_eq!(adoptable.get(&b), 0);
+    }
+}
@@ -0,0 +1,463 @@
+//! Per-device capabilities: read-only members and per-table write rights.
+//!
+//! By default every member of a group may write to every synced table. A
+//! [`CapabilityGrant`] narrows that for one device: it names the device's
+//! identity key (the one behind its `PeerId` and its change signatures, see
+//! [`crate::signing`]) and the [`Capabilities`] it has — a role name and the
+//! tables and operations it may write. A device with no write rights at all
+//! is a read-only member. Grants are signed by the member that issued them,
+//! synced to the group and persisted in `_wavesync_capability_grants`.
+//!
+//! Members check each remote change against the grant of the device that
+//! wrote it before applying it, whoever relays it. Refused changes are
+//! recorded in `_wavesync_capability_denials`.
+//!
+//! Only the issuers the app configures may grant — the keys passed to
+//! [`WaveSyncDbBuilder::with_capability_issuer`](crate::WaveSyncDbBuilder::with_capability_issuer)
+//! and the group's administrators — and they are never restricted
+//! themselves. A newer grant for a device replaces an older one.
+//!
+//! Capabilities bind identities, not the passphrase: a device that holds
+//! the passphrase can still mint a new identity, which has no grant. What
+//! such a device may write is the table's default: everything in a group
+//! with no issuers, and nothing in one that has them, unless the app says
+//! otherwise with
+//! [`WaveSyncDbBuilder::with_default_capabilities`](crate::WaveSyncDbBuilder::with_default_capabilities).
+//! Removing a device takes [revocation](crate::revocation).
+//!
+//! Builds that enforce capabilities announce
+//! [`FEATURE_CAPABILITIES`](crate::protocol::FEATURE_CAPABILITIES).
+
+use std::collections::HashMap;
+
+use libp2p::identity::ed25519;
+use serde::{Deserialize, Serialize};
+
+use crate::messages::{ColumnChange, NodeId};
+use crate::revocation::key_for_peer;
+use crate::signing::{ChangeSignature, ChangeSigner, site_id_for_key};
+
+/// Domain separator prefixed to every signed grant.
+const GRANT_DOMAIN: &[u8] = b"wavesyncdb-capability-v1";
+
+/// Table name that stands for every table in a [`TablePermission`].
+pub const ALL_TABLES: &str = "*";
+
+/// Role of a device without a grant in a group that has issuers, unless
+/// the app sets another default.
+pub const UNGRANTED_ROLE: &str = "ungranted";
+
+/// A kind of write to a row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 3] = [Operation::Insert, Operation::Update, Operation::Delete];
+
+    /// What `change` does to its row, given whether the row exists here.
+    pub fn of(change: &ColumnChange, row_exists: bool) -> Self {
+        if change.cid.0 == "__deleted" {
+            Operation::Delete
+        } else if row_exists {
+            Operation::Update
+        } else {
+            Operation::Insert
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Insert => "insert",
+            Operation::Update => "update",
+            Operation::Delete => "delete",
+        }
+    }
+}
+
+/// Operations a device may perform on one table, or on every table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TablePermission {
+    /// Table name, or [`ALL_TABLES`].
+    pub table: String,
+    pub operations: Vec<Operation>,
+}
+
+/// What a device may write.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Application-defined role name (`"kiosk"`, `"guest"`, …).
+    pub role: String,
+    /// Write permissions; empty for a read-only member.
+    pub write: Vec<TablePermission>,
+}
+
+impl Capabilities {
+    /// No write rights at all.
+    pub fn read_only(role: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            write: Vec::new(),
+        }
+    }
+
+    /// Every operation on every table — the same as having no grant.
+    pub fn full(role: &str) -> Self {
+        Self::read_only(role).allow(ALL_TABLES, &Operation::ALL)
+    }
+
+    /// Also allow `operations` on `table`.
+    pub fn allow(mut self, table: &str, operations: &[Operation]) -> Self {
+        self.write.push(TablePermission {
+            table: table.to_string(),
+            operations: operations.to_vec(),
+        });
+        self
+    }
+
+    /// Whether `operation` on `table` is allowed.
+    pub fn allows(&self, table: &str, operation: Operation) -> bool {
+        self.write.iter().any(|p| {
+            (p.table == table || p.table == ALL_TABLES) && p.operations.contains(&operation)
+        })
+    }
+
+    /// Whether nothing is restricted.
+    pub fn is_full(&self) -> bool {
+        Operation::ALL.iter().all(|op| {
+            self.write
+                .iter()
+                .any(|p| p.table == ALL_TABLES && p.operations.contains(op))
+        })
+    }
+}
+
+/// Capabilities for one device, signed by the member that granted them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapabilityGrant {
+    /// The device's identity key.
+    pub subject: [u8; 32],
+    pub capabilities: Capabilities,
+    /// Unix seconds at which the grant was issued; the newest one counts.
+    pub issued_at: u64,
+    /// Signature over the above by the granting member's identity key.
+    pub sig: ChangeSignature,
+}
+
+impl CapabilityGrant {
+    /// Grant `capabilities` to the device with identity `subject`.
+    pub fn issue(
+        signer: &ChangeSigner,
+        subject: [u8; 32],
+        capabilities: Capabilities,
+        issued_at: u64,
+    ) -> Self {
+        let sig = signer.sign(&signing_bytes(&subject, &capabilities, issued_at));
+        Self {
+            subject,
+            capabilities,
+            issued_at,
+            sig,
+        }
+    }
+
+    /// Whether the signature verifies.
+    pub fn verify(&self) -> bool {
+        ed25519::PublicKey::try_from_bytes(&self.sig.key).is_ok_and(|by| {
+            by.verify(
+                &signing_bytes(&self.subject, &self.capabilities, self.issued_at),
+                &self.sig.sig,
+            )
+        })
+    }
+
+    /// Whether this grant replaces `other` for the same device: it is
+    /// newer, or as new with the higher signature.
+    pub fn supersedes(&self, other: &CapabilityGrant) -> bool {
+        (self.issued_at, self.sig.sig) > (other.issued_at, other.sig.sig)
+    }
+}
+
+fn signing_bytes(subject: &[u8; 32], capabilities: &Capabilities, issued_at: u64) -> Vec<u8> {
+    let caps = serde_json::to_vec(capabilities).expect("capabilities serialize to JSON");
+    let mut out = Vec::with_capacity(GRANT_DOMAIN.len() + 40 + caps.len());
+    out.extend_from_slice(GRANT_DOMAIN);
+    out.extend_from_slice(subject);
+    out.extend_from_slice(&issued_at.to_be_bytes());
+    out.extend_from_slice(&caps);
+    out
+}
+
+/// The grants a member holds, one per device, and who may issue them.
+#[derive(Debug, Clone, Default)]
+pub struct CapabilityTable {
+    grants: HashMap<[u8; 32], CapabilityGrant>,
+    /// Identity keys that may issue grants.
+    issuers: Vec<[u8; 32]>,
+    /// What a device without a grant may write; `None` for everything.
+    default: Option<Capabilities>,
+}
+
+impl CapabilityTable {
+    /// A table of grants loaded from storage, checked when they arrived.
+    pub fn from_entries(entries: impl IntoIterator<Item = CapabilityGrant>) -> Self {
+        Self {
+            grants: entries.into_iter().map(|g| (g.subject, g)).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Only take grants from `issuers`, and hold devices without one to
+    /// `default` — read-only if `None` and there are issuers. Grants held
+    /// from keys that are no longer issuers are dropped.
+    pub fn with_policy(mut self, issuers: Vec<[u8; 32]>, default: Option<Capabilities>) -> Self {
+        self.default = default
+            .or_else(|| (!issuers.is_empty()).then(|| Capabilities::read_only(UNGRANTED_ROLE)));
+        self.grants.retain(|_, g| issuers.contains(&g.sig.key));
+        self.issuers = issuers;
+        self
+    }
+
+    /// Whether no device is restricted.
+    pub fn is_empty(&self) -> bool {
+        self.grants.is_empty() && self.default.is_none()
+    }
+
+    /// Every grant held.
+    pub fn entries(&self) -> Vec<CapabilityGrant> {
+        self.grants.values().cloned().collect()
+    }
+
+    /// The capabilities of the device with identity `key`: its grant, or
+    /// the default. `None` means no restrictions, as for issuers.
+    pub fn get(&self, key: &[u8; 32]) -> Option<&Capabilities> {
+        if self.issuers.contains(key) {
+            return None;
+        }
+        self.grants
+            .get(key)
+            .map(|g| &g.capabilities)
+            .or(self.default.as_ref())
+    }
+
+    /// The capabilities of `peer`, if it has a grant.
+    pub fn for_peer(&self, peer: &libp2p::PeerId) -> Option<&Capabilities> {
+        if self.is_empty() {
+            return None;
+        }
+        self.get(&key_for_peer(peer)?)
+    }
+
+    /// The capabilities of the device that writes as `site`: the one `site`
+    /// is pinned to in `site_keys`, or whose key it is derived from. A site
+    /// that can't be tied to a key gets the default.
+    pub fn for_site(
+        &self,
+        site: &NodeId,
+        site_keys: &HashMap<NodeId, [u8; 32]>,
+    ) -> Option<&Capabilities> {
+        if self.is_empty() {
+            return None;
+        }
+        let key = site_keys.get(site).or_else(|| {
+            self.grants
+                .keys()
+                .chain(&self.issuers)
+                .find(|k| site_id_for_key(k) == *site)
+        });
+        match key {
+            Some(key) => self.get(key),
+            None => self.default.as_ref(),
+        }
+    }
+
+    /// Whether the device with identity `key` may issue grants.
+    pub fn can_grant(&self, key: &[u8; 32]) -> bool {
+        self.issuers.contains(key)
+    }
+
+    /// Add the grants in `incoming` that verify, were issued by an issuer
+    /// and replace what we hold. Returns the ones taken.
+    pub fn merge(
+        &mut self,
+        incoming: impl IntoIterator<Item = CapabilityGrant>,
+    ) -> Vec<CapabilityGrant> {
+        let mut added = Vec::new();
+        for g in incoming {
+            let newer = self
+                .grants
+                .get(&g.subject)
+                .is_none_or(|held| g.supersedes(held));
+            if !newer || !self.can_grant(&g.sig.key) || !g.verify() {
+                continue;
+            }
+            self.grants.insert(g.subject, g.clone());
+            added.push(g);
+        }
+        added
+    }
+
+    /// The grants held that `theirs` lacks or holds an older one of.
+    pub fn newer_than(&self, theirs: &[CapabilityGrant]) -> Vec<CapabilityGrant> {
+        self.grants
+            .values()
+            .filter(|g| {
+                !theirs
+                    .iter()
+                    .any(|t| t.subject == g.subject && !g.supersedes(t))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer() -> ChangeSigner {
+        let keypair = ed25519::Keypair::generate();
+        let site = site_id_for_key(&keypair.public().to_bytes());
+        ChangeSigner::new(keypair, site)
+    }
+
+    #[test]
+    fn test_capabilities_allow() {
+        let guest = Capabilities::read_only("guest").allow("comments", &[Operation::Insert]);
+        assert!(guest.allows("comments", Operation::Insert));
+        assert!(!guest.allows("comments", Operation::Delete));
+        assert!(!guest.allows("tasks", Operation::Insert));
+        assert!(!guest.is_full());
+
+        let kiosk = Capabilities::read_only("kiosk");
+        assert!(Operation::ALL.iter().all(|op| !kiosk.allows("tasks", *op)));
+
+        let admin = Capabilities::full("admin");
+        assert!(admin.is_full());
+        assert!(admin.allows("tasks", Operation::Delete));
+    }
+
+    #[test]
+    fn test_grant_verifies_and_roundtrips() {
+        let admin = signer();
+        let g = CapabilityGrant::issue(&admin, [4u8; 32], Capabilities::read_only("kiosk"), 10);
+        assert!(g.verify());
+        let json = serde_json::to_string(&g).unwrap();
+        assert!(json.contains("\"kiosk\""));
+        let back: CapabilityGrant = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, g);
+
+        let mut escalated = g.clone();
+        escalated.capabilities = Capabilities::full("kiosk");
+        assert!(!escalated.verify());
+    }
+
+    #[test]
+    fn test_merge_keeps_newest_and_refuses_other_issuers() {
+        let admin = signer();
+        let kiosk = signer();
+        let mut table = CapabilityTable::default().with_policy(vec![admin.public_key()], None);
+
+        let first = CapabilityGrant::issue(
+            &admin,
+            kiosk.public_key(),
+            Capabilities::read_only("kiosk"),
+            10,
+        );
+        assert_eq!(table.merge([first.clone()]), vec![first.clone()]);
+        assert!(!table.can_grant(&kiosk.public_key()));
+        let kiosk_site = site_id_for_key(&kiosk.public_key());
+        assert_eq!(
+            table.for_site(&kiosk_site, &HashMap::new()),
+            Some(&Capabilities::read_only("kiosk"))
+        );
+        assert!(
+            table
+                .for_site(&site_id_for_key(&admin.public_key()), &HashMap::new())
+                .is_none()
+        );
+
+        // The kiosk can't lift its own restrictions, nor can any other
+        // member that isn't an issuer.
+        let own =
+            CapabilityGrant::issue(&kiosk, kiosk.public_key(), Capabilities::full("kiosk"), 20);
+        assert!(table.merge([own]).is_empty());
+        let member = signer();
+        let from_member =
+            CapabilityGrant::issue(&member, kiosk.public_key(), Capabilities::full("kiosk"), 20);
+        assert!(table.merge([from_member]).is_empty());
+        assert!(!table.can_grant(&member.public_key()));
+
+        let older =
+            CapabilityGrant::issue(&admin, kiosk.public_key(), Capabilities::full("kiosk"), 5);
+        assert!(table.merge([older.clone()]).is_empty());
+        assert_eq!(table.newer_than(&[older]), vec![first.clone()]);
+        assert!(table.newer_than(&[first]).is_empty());
+
+        let lifted =
+            CapabilityGrant::issue(&admin, kiosk.public_key(), Capabilities::full("kiosk"), 30);
+        assert_eq!(table.merge([lifted]).len(), 1);
+        assert!(
+            table
+                .get(&kiosk.public_key())
+                .is_some_and(Capabilities::is_full)
+        );
+        assert!(!table.can_grant(&kiosk.public_key()));
+
+        // Grants from a key that is no longer an issuer are dropped.
+        let table = table.with_policy(vec![member.public_key()], None);
+        assert!(table.entries().is_empty());
+    }
+
+    #[test]
+    fn test_ungranted_devices_get_the_default() {
+        let admin = signer();
+        let stranger = site_id_for_key(&signer().public_key());
+        let legacy = NodeId([9u8; 16]);
+
+        // No issuers: nothing is restricted.
+        let open = CapabilityTable::default();
+        assert!(open.is_empty());
+        assert!(open.for_site(&stranger, &HashMap::new()).is_none());
+
+        // Issuers: devices without a grant are read-only, but not issuers.
+        let managed = CapabilityTable::default().with_policy(vec![admin.public_key()], None);
+        let caps = managed.for_site(&stranger, &HashMap::new()).unwrap();
+        assert_eq!(caps.role, UNGRANTED_ROLE);
+        assert!(!caps.allows("tasks", Operation::Insert));
+        assert!(managed.for_site(&legacy, &HashMap::new()).is_some());
+        let admin_site = site_id_for_key(&admin.public_key());
+        assert!(managed.for_site(&admin_site, &HashMap::new()).is_none());
+
+        // Unless the app sets another default.
+        let guest = Capabilities::read_only("guest").allow("comments", &[Operation::Insert]);
+        let lenient =
+            CapabilityTable::default().with_policy(vec![admin.public_key()], Some(guest.clone()));
+        assert_eq!(lenient.for_site(&stranger, &HashMap::new()), Some(&guest));
+    }
+
+    #[test]
+    fn test_operation_of_change() {
+        let mut change = ColumnChange {
+            table: "tasks".into(),
+            pk: "pk-1".into(),
+            cid: "title".into(),
+            val: Some(serde_json::json!("a")),
+            site_id: NodeId([1u8; 16]),
+            col_version: 1,
+            cl: 1,
+            seq: 0,
+            db_version: 0,
+            sig: None,
+        };
+        assert_eq!(Operation::of(&change, false), Operation::Insert);
+        assert_eq!(Operation::of(&change, true), Operation::Update);
+        change.cid = "__deleted".into();
+        change.val = None;
+        assert_eq!(Operation::of(&change, true), Operation::Delete);
+    }
+}
@@ -0,0 +1,163 @@
+//! Payload compression for the sync wire protocol.
+//!
+//! Catch-up `ChangesetResponse`s carry every change since the peer's last
+//! known `db_version` as serde_json, which is verbose: table names, column
+//! names and hex site ids repeat on every `ColumnChange`. On the lossy /
+//! high-latency netem profiles the transfer time of that JSON dominates
+//! convergence, and it compresses 5–10× with a fast zstd level.
+//!
+//! ## Framing
+//!
+//! Compression is only used on the `/wavesync/snapshot/3.1.0` protocol,
+//! which libp2p's multistream-select negotiates per stream. Peers that only
+//! speak `/wavesync/snapshot/3.0.0` (older native builds) fall back to it
+//! automatically and never see a compressed frame. On 3.1.0 every payload
+//! (the bytes inside the 4-byte length prefix) starts with one flag byte:
+//!
+//! * `0x00` — the rest is plain serde_json.
+//! * `0x01` — the rest is a single zstd frame wrapping the serde_json.
+//!
+//! Payloads at or below [`COMPRESSION_THRESHOLD`] are sent raw; the zstd
+//! frame header and the CPU cost aren't worth it for a `PushAck` or a
+//! single-column push.
+//!
+//! ## Why ruzstd
+//!
+//! The browser codec in `web_engine.rs` has to produce and read the same
+//! frames, and the C `zstd` crate does not build for
+//! `wasm32-unknown-unknown`. `ruzstd` is pure Rust, compiles on every
+//! target, and its `Fastest` level is wire-compatible with any zstd
+//! decoder, so a future switch to libzstd on native is a local change.
+
+use std::io::{self, Read};
+
+use ruzstd::encoding::CompressionLevel;
+
+use crate::diagnostics::Counters;
+
+/// Payloads larger than this many bytes are compressed before sending.
+pub(crate) const COMPRESSION_THRESHOLD: usize = 4 * 1024;
+
+/// Upper bound on a decompressed payload. Mirrors the 64 MiB cap the
+/// length-prefixed framing applies to the wire bytes, so a small
+/// compressed frame can't be used to allocate unbounded memory.
+pub(crate) const MAX_DECOMPRESSED_LEN: usize = 64 * 1024 * 1024;
+
+const FLAG_RAW: u8 = 0x00;
+const FLAG_ZSTD: u8 = 0x01;
+
+/// Result of [`encode`]: the framed payload plus whether compression was
+/// actually applied (callers feed this into the diagnostics counters).
+pub(crate) struct Encoded {
+    pub bytes: Vec<u8>,
+    pub compressed: bool,
+}
+
+/// Frame `payload` for the compressed protocol.
+///
+/// Compresses when the payload exceeds [`COMPRESSION_THRESHOLD`] and the
+/// zstd output is actually smaller — already-dense data (e.g. base64
+/// blobs) can grow slightly, in which case the raw bytes are sent.
+pub(crate) fn encode(payload: &[u8]) -> Encoded {
+    if payload.len() > COMPRESSION_THRESHOLD {
+        let compressed = ruzstd::encoding::compress_to_vec(payload, CompressionLevel::Fastest);
+        if compressed.len() < payload.len() {
+            let mut bytes = Vec::with_capacity(compressed.len() + 1);
+            bytes.push(FLAG_ZSTD);
+            bytes.extend_from_slice(&compressed);
+            return Encoded {
+                bytes,
+                compressed: true,
+            };
+        }
+    }
+    let mut bytes = Vec::with_capacity(payload.len() + 1);
+    bytes.push(FLAG_RAW);
+    bytes.extend_from_slice(payload);
+    Encoded {
+        bytes,
+        compressed: false,
+    }
+}
+
+/// [`encode`], counting the payload in `diagnostics` if it went out
+/// compressed. Both the native and the browser codec write through this,
+/// so compression ratios are reported the same way on every target.
+pub(crate) fn encode_recorded(payload: &[u8], diagnostics: Option<&Counters>) -> Vec<u8> {
+    let encoded = encode(payload);
+    if encoded.compressed
+        && let Some(diagnostics) = diagnostics
+    {
+        diagnostics.record_compression(payload.len(), encoded.bytes.len());
+    }
+    encoded.bytes
+}
+
+/// Undo [`encode`], returning the serde_json payload.
+pub(crate) fn decode(frame: &[u8]) -> io::Result<Vec<u8>> {
+    match frame.split_first() {
+        Some((&FLAG_RAW, rest)) => Ok(rest.to_vec()),
+        Some((&FLAG_ZSTD, rest)) => {
+            let decoder = ruzstd::decoding::StreamingDecoder::new(rest)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            let mut out = Vec::new();
+            decoder
+                .take(MAX_DECOMPRESSED_LEN as u64 + 1)
+                .read_to_end(&mut out)?;
+            if out.len() > MAX_DECOMPRESSED_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("decompressed payload exceeds {MAX_DECOMPRESSED_LEN} bytes"),
+                ));
+            }
+            Ok(out)
+        }
+        Some((flag, _)) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown compression flag: {flag:#04x}"),
+        )),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "empty compressed frame",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_payload_is_sent_raw() {
+        let payload = br#"{"PushAck":null}"#;
+        let enc = encode(payload);
+        assert!(!enc.compressed);
+        assert_eq!(enc.bytes[0], FLAG_RAW);
+        assert_eq!(decode(&enc.bytes).unwrap(), payload);
+    }
+
+    #[test]
+    fn large_repetitive_payload_is_compressed() {
+        let payload = r#"{"table":"tasks","cid":"title","val":"hello"},"#.repeat(500);
+        let enc = encode(payload.as_bytes());
+        assert!(enc.compressed);
+        assert_eq!(enc.bytes[0], FLAG_ZSTD);
+        assert!(enc.bytes.len() < payload.len() / 4);
+        assert_eq!(decode(&enc.bytes).unwrap(), payload.as_bytes());
+    }
+
+    #[test]
+    fn decode_rejects_unknown_flag_and_empty_frame() {
+        assert_eq!(
+            decode(&[0x7f, 1, 2, 3]).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(decode(&[]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn decode_rejects_corrupt_zstd_frame() {
+        let result = decode(&[FLAG_ZSTD, 0xde, 0xad, 0xbe, 0xef]);
+        assert!(result.is_err());
+    }
+}
@@ -7,9 +7,27 @@
 //!
 //! Delete operations use a `__deleted` sentinel column with a `causal_length`
 //! that must exceed the maximum `col_version` across all columns for the row.
+//!
+//! Since the highest clock always wins, a change stamped with a huge
+//! `col_version` — `u64::MAX`, say — would win every later write and block
+//! every later delete of its row. Remote changes are therefore bounded by
+//! [`exceeds_clock_bound`] before they are applied: a clock may run at most
+//! `max_clock_jump` past the highest one held locally for the row.
 
 use crate::messages::{DeletePolicy, NodeId};
 
+/// Default for how far a remote change's clock may run past the highest
+/// clock held locally for its row. A legitimate jump takes as many writes
+/// to one cell while out of touch.
+pub const DEFAULT_MAX_CLOCK_JUMP: u64 = 1_000_000;
+
+/// Whether a remote clock (`col_version`, or a delete's causal length)
+/// runs more than `max_jump` past `local_max`, the highest clock held
+/// locally for the row (0 for a row we don't have).
+pub fn exceeds_clock_bound(remote: u64, local_max: u64, max_jump: u64) -> bool {
+    remote > local_max.saturating_add(max_jump)
+}
+
 /// Determine whether a remote column change should be applied over local state.
 ///
 /// Returns `true` if:
@@ -145,6 +163,18 @@ mod tests {
         assert!(!should_apply_delete(5, 5, &DeletePolicy::AddWins));
     }
 
+    // ── exceeds_clock_bound ──
+
+    #[test]
+    fn test_clock_bound() {
+        assert!(!exceeds_clock_bound(1, 0, 10));
+        assert!(!exceeds_clock_bound(15, 5, 10));
+        assert!(exceeds_clock_bound(16, 5, 10));
+        assert!(exceeds_clock_bound(u64::MAX, 5, DEFAULT_MAX_CLOCK_JUMP));
+        // Whatever runs past an already-inflated clock is within bound.
+        assert!(!exceeds_clock_bound(u64::MAX, u64::MAX - 1, 10));
+    }
+
     #[test]
     fn test_delete_zero_cl_zero_local() {
         // Both at 0 — policy decides
@@ -2,8 +2,8 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 use sea_orm::{
-    ConnectOptions, ConnectionTrait, Database, DatabaseBackend, DatabaseConnection, DbErr,
-    EntityTrait, ExecResult, Iterable, PrimaryKeyToColumn, QueryResult, Schema, Statement,
+    ColumnTrait, ConnectOptions, ConnectionTrait, Database, DatabaseBackend, DatabaseConnection,
+    DbErr, EntityTrait, ExecResult, Iterable, PrimaryKeyToColumn, QueryResult, Schema, Statement,
     TransactionTrait, sea_query::SqliteQueryBuilder,
 };
 use serde::{Deserialize, Serialize};
@@ -12,7 +12,8 @@ use tokio::sync::{Mutex, Notify, broadcast, mpsc};
 use crate::messages::{
     ChangeNotification, ColumnChange, DeletePolicy, NodeId, SyncChangeset, WriteKind,
 };
-use crate::registry::{SyncEntityInfo, TableMeta, TableRegistry};
+use crate::registry::{ColumnType, SyncEntityInfo, TableMeta, TableRegistry};
+use crate::secret_store::{self, FileSecretStore, SecretStore};
 
 /// Try to classify a SQL statement as a write and extract relevant info.
 ///
@@ -274,6 +275,18 @@ fn extract_column_values_update(sql: &str) -> Vec<(String, serde_json::Value)> {
         .collect()
 }
 
+/// Declared types of `E`'s columns, for [`TableMeta::column_types`].
+fn entity_column_types<E: EntityTrait>() -> std::collections::HashMap<String, ColumnType> {
+    E::Column::iter()
+        .map(|c| {
+            (
+                sea_orm::IdenStatic::as_str(&c).to_string(),
+                ColumnType::from_column_def(&c.def()),
+            )
+        })
+        .collect()
+}
+
 /// Convert a SQL literal value to a JSON value.
 fn sql_value_to_json(val: &str) -> serde_json::Value {
     let val = val.trim();
@@ -505,6 +518,263 @@ impl WaveSyncDb {
             });
     }
 
+    /// Change the group passphrase without splitting the group.
+    ///
+    /// The new passphrase is announced to connected peers under the current
+    /// one; they switch over and pass it on, and devices that were offline
+    /// are brought over when they next reach a peer during the grace window
+    /// (see [`WaveSyncDbBuilder::with_key_rotation_grace`]). The new
+    /// passphrase is persisted with the database and in the saved sync
+    /// config, so it survives restarts even if the app keeps passing the old
+    /// one to [`WaveSyncDbBuilder::with_passphrase`].
+    ///
+    /// Returns the new key epoch. Fails if no passphrase is configured or
+    /// the engine isn't running.
+    pub async fn rotate_passphrase(&self, passphrase: &str) -> Result<u64, DbErr> {
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        self.inner
+            .cmd_tx
+            .send(crate::engine::EngineCommand::RotatePassphrase {
+                passphrase: passphrase.to_string(),
+                reply,
+            })
+            .await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?;
+        rx.await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?
+            .map_err(|e| DbErr::Custom(format!("Cannot rotate passphrase: {e}")))
+    }
+
+    /// Move a group whose key is derived with BLAKE3 (v1) to an Argon2id
+    /// key (v2), keeping the passphrase (see [`crate::auth`]).
+    ///
+    /// This is a key rotation to a fresh random [`GroupSalt`](crate::auth::GroupSalt):
+    /// it reaches peers the way [`Self::rotate_passphrase`] does, except
+    /// that peers on builds that can't derive v2 keys are left behind.
+    /// Upgrade once every device runs a build that can.
+    ///
+    /// Returns the new key epoch. Fails if no passphrase is configured, the
+    /// key is already v2, or the engine isn't running.
+    pub async fn upgrade_key_derivation(&self) -> Result<u64, DbErr> {
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        self.inner
+            .cmd_tx
+            .send(crate::engine::EngineCommand::UpgradeKeyDerivation { reply })
+            .await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?;
+        rx.await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?
+            .map_err(|e| DbErr::Custom(format!("Cannot upgrade key derivation: {e}")))
+    }
+
+    /// Revoke a lost or compromised device from the group.
+    ///
+    /// `peer_id` is the device's libp2p peer id, as reported in
+    /// [`NetworkStatus`](crate::network_status::NetworkStatus). The
+    /// revocation is signed with this device's identity key and synced to
+    /// every member, which from then on refuses the device's connections,
+    /// requests and changes. With a passphrase configured, the group key is
+    /// then rotated to a fresh random passphrase with no grace window, so
+    /// the device can't rejoin under a new identity either (see
+    /// [`Self::rotate_passphrase`]).
+    ///
+    /// Returns the new passphrase. Devices that were offline miss the
+    /// rotation and have to be given it by hand.
+    pub async fn revoke_device(&self, peer_id: &str) -> Result<Option<String>, DbErr> {
+        let peer = peer_id
+            .parse::<libp2p::PeerId>()
+            .map_err(|e| DbErr::Custom(format!("Invalid peer id {peer_id}: {e}")))?;
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        self.inner
+            .cmd_tx
+            .send(crate::engine::EngineCommand::RevokeDevice { peer, reply })
+            .await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?;
+        rx.await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?
+            .map_err(|e| DbErr::Custom(format!("Cannot revoke device: {e}")))
+    }
+
+    /// Restrict what a device may write.
+    ///
+    /// `peer_id` is the device's libp2p peer id, as reported in
+    /// [`NetworkStatus`](crate::network_status::NetworkStatus). The grant is
+    /// signed with this device's identity key and synced to every member,
+    /// which from then on refuses the device's changes that `capabilities`
+    /// don't allow (see [`crate::capability`]). A later grant replaces it;
+    /// [`Capabilities::full`](crate::capability::Capabilities::full) lifts
+    /// every restriction. Only a device configured as an issuer, with
+    /// [`WaveSyncDbBuilder::with_capability_issuer`] or
+    /// [`WaveSyncDbBuilder::with_admin_key`], may grant capabilities.
+    pub async fn grant_capabilities(
+        &self,
+        peer_id: &str,
+        capabilities: crate::capability::Capabilities,
+    ) -> Result<(), DbErr> {
+        let peer = peer_id
+            .parse::<libp2p::PeerId>()
+            .map_err(|e| DbErr::Custom(format!("Invalid peer id {peer_id}: {e}")))?;
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        self.inner
+            .cmd_tx
+            .send(crate::engine::EngineCommand::GrantCapabilities {
+                peer,
+                capabilities,
+                reply,
+            })
+            .await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?;
+        rx.await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?
+            .map_err(|e| DbErr::Custom(format!("Cannot grant capabilities: {e}")))
+    }
+
+    /// The most recent `limit` remote changes refused under a capability
+    /// grant, newest first.
+    pub async fn capability_denials(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<crate::peer_tracker::CapabilityDenial>, DbErr> {
+        crate::peer_tracker::get_capability_denials(self.inner(), limit).await
+    }
+
+    /// Invite a new device into the group.
+    ///
+    /// Returns an invite — this device's peer id and addresses plus a short
+    /// one-time code — to show the new device, e.g. as a QR code of
+    /// [`PairingInvite::to_url`](crate::pairing::PairingInvite::to_url).
+    /// The new device joins with [`SyncConfig::pair`]; the group credentials
+    /// then travel encrypted under a key only the holder of the code can
+    /// derive (see [`crate::pairing`]). The code works once and expires
+    /// after `ttl`; a new invite replaces an open one.
+    pub async fn start_pairing(
+        &self,
+        ttl: std::time::Duration,
+    ) -> Result<crate::pairing::PairingInvite, DbErr> {
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        self.inner
+            .cmd_tx
+            .send(crate::engine::EngineCommand::StartPairing { ttl, reply })
+            .await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?;
+        rx.await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?
+            .map_err(|e| DbErr::Custom(format!("Cannot start pairing: {e}")))
+    }
+
+    /// Sites whose changes are refused because one of them ran a clock too
+    /// far ahead (see [`crate::conflict`]), oldest first.
+    pub async fn quarantined_sites(&self) -> Result<Vec<crate::peer_tracker::Quarantine>, DbErr> {
+        crate::peer_tracker::get_quarantined_sites(self.inner()).await
+    }
+
+    /// Accept changes from a quarantined site again. Returns whether it
+    /// was quarantined. Repair the clocks it inflated first with
+    /// [`Self::repair_inflated_clocks`], or its next change will put it
+    /// straight back.
+    pub async fn release_quarantine(&self, site_id: &NodeId) -> Result<bool, DbErr> {
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        self.inner
+            .cmd_tx
+            .send(crate::engine::EngineCommand::ReleaseQuarantine {
+                site: *site_id,
+                reply,
+            })
+            .await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?;
+        rx.await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?
+            .map_err(|e| DbErr::Custom(format!("Cannot release quarantine: {e}")))
+    }
+
+    /// Rewrite every clock of the synced tables above `ceiling` to
+    /// `ceiling`, undoing clocks inflated before they were bounded or by a
+    /// site before it was quarantined. Row values are left alone. Returns
+    /// how many clocks were rewritten.
+    ///
+    /// Every device holding the inflated clocks has to run it with the
+    /// same `ceiling`: an unrepaired one keeps winning with them, and a
+    /// repaired one quarantines the site that wrote them when they come
+    /// back.
+    pub async fn repair_inflated_clocks(&self, ceiling: u64) -> Result<u64, DbErr> {
+        use sea_orm::TransactionTrait;
+
+        let txn = self.inner().begin().await?;
+        let mut rewritten = 0;
+        for meta in self.inner.registry.all_tables() {
+            rewritten += crate::shadow::clamp_clocks(&txn, &meta.table_name, ceiling).await?;
+        }
+        txn.commit().await?;
+        if rewritten > 0 {
+            log::warn!("Rewrote {rewritten} clocks above {ceiling}");
+        }
+        Ok(rewritten)
+    }
+
+    /// This device's watermarks: for each site, the newest of its changes
+    /// held here, our own site included. Another device exporting a
+    /// bundle from them sends only what this one is missing.
+    pub async fn origin_versions(&self) -> Result<crate::protocol::OriginVersions, DbErr> {
+        let mut versions = crate::peer_tracker::get_origin_versions(self.inner()).await?;
+        let local = crate::shadow::get_db_version(self.inner()).await?;
+        versions.advance(self.inner.site_id, local);
+        Ok(versions)
+    }
+
+    /// Write every change past `since` to a bundle at `path`, for carrying
+    /// to a device this one never shares a network with (see
+    /// [`crate::bundle`]). Pass [`OriginVersions::default`] to export
+    /// everything. With `encrypt`, values are sealed under the group key,
+    /// which needs a passphrase. Returns how many changes it holds.
+    ///
+    /// [`OriginVersions::default`]: crate::protocol::OriginVersions
+    pub async fn export_bundle(
+        &self,
+        since: &crate::protocol::OriginVersions,
+        path: impl AsRef<std::path::Path>,
+        encrypt: bool,
+    ) -> Result<usize, DbErr> {
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        self.inner
+            .cmd_tx
+            .send(crate::engine::EngineCommand::ExportBundle {
+                since: since.clone(),
+                encrypt,
+                reply,
+            })
+            .await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?;
+        let (bytes, count) = rx
+            .await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?
+            .map_err(|e| DbErr::Custom(format!("Cannot export bundle: {e}")))?;
+        tokio::fs::write(path.as_ref(), bytes)
+            .await
+            .map_err(|e| DbErr::Custom(format!("Cannot write bundle: {e}")))?;
+        Ok(count)
+    }
+
+    /// Merge the bundle at `path`, made by [`Self::export_bundle`] on
+    /// another device, as if it had arrived over the network: the same
+    /// checks, conflict resolution and change notifications.
+    pub async fn import_bundle(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<crate::bundle::BundleImport, DbErr> {
+        let bytes = tokio::fs::read(path.as_ref())
+            .await
+            .map_err(|e| DbErr::Custom(format!("Cannot read bundle: {e}")))?;
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        self.inner
+            .cmd_tx
+            .send(crate::engine::EngineCommand::ImportBundle { bytes, reply })
+            .await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?;
+        rx.await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?
+            .map_err(|e| DbErr::Custom(format!("Cannot import bundle: {e}")))
+    }
+
     /// Returns the parent directory of the database file.
     ///
     /// This is where push token files (`wavesync_apns_token`, `wavesync_fcm_token`)
@@ -519,7 +789,8 @@ impl WaveSyncDb {
     /// The identity is an opaque string — WaveSyncDB does not interpret it.
     /// It is announced to all currently verified peers and to any peer that
     /// becomes verified in the future. Identities are ephemeral (session-scoped)
-    /// and cleared on disconnect.
+    /// and cleared on disconnect. Peers see it signed by this device's key
+    /// but not vouched for; see [`Self::set_endorsed_peer_identity`].
     pub fn set_peer_identity(&self, app_id: &str) {
         let _ = self
             .inner
@@ -529,6 +800,27 @@ impl WaveSyncDb {
             )));
     }
 
+    /// Set the application-level identity for this peer to the one
+    /// `endorsement` vouches for, announcing the endorsement with it so
+    /// peers trusting its issuer see the identity as
+    /// [`IdentityVerification::Endorsed`](crate::identity::IdentityVerification::Endorsed)
+    /// (see [`crate::identity`]). Fails if the endorsement is for another
+    /// device, has expired, or doesn't verify.
+    pub async fn set_endorsed_peer_identity(
+        &self,
+        endorsement: crate::identity::IdentityEndorsement,
+    ) -> Result<(), DbErr> {
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        self.inner
+            .cmd_tx
+            .send(crate::engine::EngineCommand::SetEndorsedPeerIdentity { endorsement, reply })
+            .await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?;
+        rx.await
+            .map_err(|_| DbErr::Custom("sync engine is not running".to_string()))?
+            .map_err(|e| DbErr::Custom(format!("Cannot set peer identity: {e}")))
+    }
+
     /// Clear the application-level identity for this peer.
     pub fn clear_peer_identity(&self) {
         let _ = self
@@ -540,7 +832,9 @@ impl WaveSyncDb {
     /// Get all connected peers grouped by their application-level identity.
     ///
     /// Returns only peers that have announced an identity. Peers without
-    /// an identity are excluded.
+    /// an identity are excluded. Any group member can announce any identity;
+    /// check [`PeerInfo::identity_verification`](crate::PeerInfo::identity_verification)
+    /// before relying on one.
     pub fn peers_by_identity(
         &self,
     ) -> std::collections::HashMap<String, Vec<crate::network_status::PeerInfo>> {
@@ -657,6 +951,7 @@ impl WaveSyncDb {
             primary_key_column,
             columns,
             delete_policy: DeletePolicy::default(),
+            column_types: entity_column_types::<E>(),
         });
 
         Ok(())
@@ -796,7 +1091,7 @@ impl WaveSyncDb {
                     .unwrap_or_default();
 
                     let max_cv = entries.iter().map(|e| e.col_version).max().unwrap_or(0);
-                    let tombstone_cv = max_cv + 1;
+                    let tombstone_cv = max_cv.saturating_add(1);
 
                     if let Err(e) = crate::shadow::insert_tombstone(
                         &txn,
@@ -821,6 +1116,7 @@ impl WaveSyncDb {
                         cl: tombstone_cv,
                         seq: 0,
                         db_version: new_db_version,
+                        sig: None,
                     });
                 }
                 WriteKind::Insert | WriteKind::Update => {
@@ -872,6 +1168,7 @@ impl WaveSyncDb {
                             cl: new_cv,
                             seq: seq as u32,
                             db_version: new_db_version,
+                            sig: None,
                         });
                     }
                 }
@@ -1144,6 +1441,11 @@ impl<'a> SchemaBuilder<'a> {
         self
     }
 
+    /// Whether no entities have been registered.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
     /// Create all registered tables and register synced ones for P2P replication.
     pub async fn sync(self) -> Result<(), DbErr> {
         for entry in &self.entries {
@@ -1166,9 +1468,7 @@ impl<'a> SchemaBuilder<'a> {
             && let Ok(mut config) = serde_json::from_str::<SyncConfig>(&json)
         {
             config.crate_name = Some(crate_name.clone());
-            if let Ok(updated) = serde_json::to_string_pretty(&config) {
-                let _ = std::fs::write(&config_path, updated);
-            }
+            let _ = config.save_to(&config_path);
         }
         // Signal the engine that tables are registered and sync can begin
         self.db.inner.registry_ready.notify_one();
@@ -1205,6 +1505,7 @@ impl<'a> SchemaBuilder<'a> {
                 primary_key_column,
                 columns,
                 delete_policy: DeletePolicy::default(),
+                column_types: entity_column_types::<E>(),
             },
             synced,
         });
@@ -1309,27 +1610,39 @@ async fn lookup_first_addr(host: &str) -> std::io::Result<std::net::IpAddr> {
 /// Read by [`background_sync()`](crate::background_sync::background_sync) to reconstruct
 /// the builder without the app developer passing any configuration.
 ///
-/// **Security note**: The passphrase is stored in plaintext. On Android/iOS the app's
-/// data directory is sandboxed (same protection as the SQLite database itself).
+/// The passphrase, API key and Firebase credentials are never written to the
+/// file: they're kept in a [`SecretStore`](crate::secret_store::SecretStore)
+/// (see [`crate::secret_store`]) and filled back in by [`SyncConfig::load`].
 #[derive(Serialize, Deserialize)]
 pub struct SyncConfig {
     pub database_url: String,
     pub topic: String,
     pub relay_server: Option<String>,
+    /// Kept in the secret store. Still read from the file so plaintext
+    /// configs from older versions can be migrated.
+    #[serde(skip_serializing, default)]
     pub passphrase: Option<String>,
+    /// Salt of a v2 group key (see [`crate::auth`]). Not secret.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_salt: Option<crate::auth::GroupSalt>,
     pub rendezvous_server: Option<String>,
     pub bootstrap_peers: Vec<String>,
+    /// Kept in the secret store, like `passphrase`.
+    #[serde(skip_serializing, default)]
     pub api_key: Option<String>,
     pub ipv6: bool,
     pub crate_name: Option<String>,
-    /// Firebase project ID for background service cold-start init.
-    #[serde(skip_serializing_if = "Option::is_none", default)]
+    /// Firebase project ID for background service cold-start init. Kept
+    /// in the secret store, like `passphrase`.
+    #[serde(skip_serializing, default)]
     pub fcm_project_id: Option<String>,
     /// Firebase application ID for background service cold-start init.
-    #[serde(skip_serializing_if = "Option::is_none", default)]
+    /// Kept in the secret store.
+    #[serde(skip_serializing, default)]
     pub fcm_app_id: Option<String>,
-    /// Firebase API key for background service cold-start init.
-    #[serde(skip_serializing_if = "Option::is_none", default)]
+    /// Firebase API key for background service cold-start init. Kept in
+    /// the secret store.
+    #[serde(skip_serializing, default)]
     pub fcm_api_key: Option<String>,
 }
 
@@ -1350,25 +1663,154 @@ impl SyncConfig {
             .map(|dir| dir.join(".wavesync_config.json"))
     }
 
-    /// Read a previously saved config from the database directory.
+    /// Read a previously saved config from the database directory, with its
+    /// secrets from the default [`FileSecretStore`] next to it.
     pub fn load(database_url: &str) -> Result<Self, String> {
+        Self::load_with(database_url, &Self::default_secret_store(database_url)?)
+    }
+
+    /// Read a previously saved config, with its secrets from `secrets`.
+    ///
+    /// A config still carrying its secrets in plaintext has them moved into
+    /// `secrets` and is rewritten without them.
+    pub fn load_with(database_url: &str, secrets: &dyn SecretStore) -> Result<Self, String> {
         let path = Self::config_path(database_url)
             .ok_or_else(|| "Cannot derive config path from database URL".to_string())?;
         let json = std::fs::read_to_string(&path)
             .map_err(|e| format!("Failed to read config at {}: {e}", path.display()))?;
-        serde_json::from_str(&json)
-            .map_err(|e| format!("Invalid config JSON at {}: {e}", path.display()))
+        let mut config: Self = serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid config JSON at {}: {e}", path.display()))?;
+        // Configs from before the Firebase credentials moved carry those
+        // in plaintext but not the passphrase: whatever the file doesn't
+        // hold comes from the store.
+        let plaintext = config.secrets().any(|(_, value)| value.is_some());
+        for (name, value) in config.secrets_mut() {
+            if value.is_none() {
+                *value = secrets
+                    .get(name)
+                    .map_err(|e| format!("Secrets for {} unavailable: {e}", path.display()))?;
+            }
+        }
+        if plaintext {
+            config.store_secrets(secrets)?;
+            config.save_to(&path)?;
+            log::info!(
+                "Moved the plaintext secrets of {} into the secret store",
+                path.display()
+            );
+        }
+        Ok(config)
     }
 
-    /// Save this config to the database directory.
-    fn save(&self) -> Result<(), String> {
+    /// The [`FileSecretStore`] next to the database at `database_url`.
+    pub(crate) fn default_secret_store(database_url: &str) -> Result<FileSecretStore, String> {
+        FileSecretStore::for_database(database_url)
+            .ok_or_else(|| "Cannot derive config path from database URL".to_string())
+    }
+
+    /// Save this config to the database directory, its secrets to `secrets`.
+    fn save_with(&self, secrets: &dyn SecretStore) -> Result<(), String> {
         let path = Self::config_path(&self.database_url)
             .ok_or_else(|| "Cannot derive config path from database URL".to_string())?;
+        self.store_secrets(secrets)?;
+        self.save_to(&path)
+    }
+
+    /// The fields kept in the secret store, by name.
+    fn secrets(&self) -> impl Iterator<Item = (&'static str, &Option<String>)> {
+        [
+            (secret_store::PASSPHRASE, &self.passphrase),
+            (secret_store::API_KEY, &self.api_key),
+            (secret_store::FCM_PROJECT_ID, &self.fcm_project_id),
+            (secret_store::FCM_APP_ID, &self.fcm_app_id),
+            (secret_store::FCM_API_KEY, &self.fcm_api_key),
+        ]
+        .into_iter()
+    }
+
+    fn secrets_mut(&mut self) -> impl Iterator<Item = (&'static str, &mut Option<String>)> {
+        [
+            (secret_store::PASSPHRASE, &mut self.passphrase),
+            (secret_store::API_KEY, &mut self.api_key),
+            (secret_store::FCM_PROJECT_ID, &mut self.fcm_project_id),
+            (secret_store::FCM_APP_ID, &mut self.fcm_app_id),
+            (secret_store::FCM_API_KEY, &mut self.fcm_api_key),
+        ]
+        .into_iter()
+    }
+
+    /// Put the secrets into `secrets` — before the file loses them, so a
+    /// crash in between leaves them in plaintext rather than nowhere.
+    fn store_secrets(&self, secrets: &dyn SecretStore) -> Result<(), String> {
+        for (name, value) in self.secrets() {
+            match value {
+                Some(value) => secrets.set(name, value),
+                None => secrets.delete(name),
+            }
+            .map_err(|e| format!("Failed to store secret '{name}': {e}"))?;
+        }
+        Ok(())
+    }
+
+    /// Write this config to `path` atomically: into a temporary file next
+    /// to it, then renamed over it. A background service reading the config
+    /// — or a crash mid-write — never sees half of it.
+    fn save_to(&self, path: &std::path::Path) -> Result<(), String> {
         let json = serde_json::to_string_pretty(self)
             .map_err(|e| format!("Failed to serialize config: {e}"))?;
-        std::fs::write(&path, json)
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)
+            .and_then(|()| std::fs::rename(&tmp, path))
             .map_err(|e| format!("Failed to write config to {}: {e}", path.display()))
     }
+
+    /// Join a group by pairing with the device that showed `invite` (see
+    /// [`crate::pairing`]).
+    ///
+    /// Runs the exchange under this device's own identity, read from (or
+    /// created in) the database at `database_url`, then saves the received
+    /// credentials as the config next to it — the passphrase into the
+    /// default [`FileSecretStore`] — and returns them. Build the database
+    /// with [`WaveSyncDbBuilder::from_config`] to start syncing.
+    pub async fn pair(
+        database_url: &str,
+        invite: &crate::pairing::PairingInvite,
+    ) -> Result<Self, crate::pairing::PairingError> {
+        use crate::pairing::PairingError;
+
+        let db = sea_orm::Database::connect(database_url)
+            .await
+            .map_err(|e| PairingError::Config(format!("Failed to open the database: {e}")))?;
+        crate::shadow::create_meta_table(&db)
+            .await
+            .map_err(|e| PairingError::Config(e.to_string()))?;
+        let keypair = crate::shadow::get_or_create_libp2p_keypair(&db)
+            .await
+            .map_err(|e| PairingError::Config(e.to_string()))?;
+        drop(db);
+
+        let credentials =
+            crate::engine::pairing::join(keypair, invite, crate::pairing::JOIN_TIMEOUT).await?;
+        let config = Self {
+            database_url: database_url.to_string(),
+            topic: credentials.topic,
+            relay_server: credentials.relay_server,
+            passphrase: credentials.passphrase,
+            group_salt: credentials.group_salt,
+            rendezvous_server: credentials.rendezvous_server,
+            bootstrap_peers: credentials.bootstrap_peers,
+            api_key: None,
+            ipv6: false,
+            crate_name: None,
+            fcm_project_id: None,
+            fcm_app_id: None,
+            fcm_api_key: None,
+        };
+        Self::default_secret_store(database_url)
+            .and_then(|secrets| config.save_with(&secrets))
+            .map_err(PairingError::Config)?;
+        Ok(config)
+    }
 }
 
 /// Builder for `WaveSyncDb`.
@@ -1383,6 +1825,7 @@ pub struct WaveSyncDbBuilder {
     mdns_ttl: std::time::Duration,
     group_key: Option<crate::auth::GroupKey>,
     passphrase: Option<String>,
+    group_salt: Option<crate::auth::GroupSalt>,
     bootstrap_peers: Vec<String>,
     rendezvous_server: Option<String>,
     rendezvous_discover_interval: std::time::Duration,
@@ -1394,6 +1837,29 @@ pub struct WaveSyncDbBuilder {
     api_key: Option<String>,
     keep_alive_interval: std::time::Duration,
     circuit_max_duration: std::time::Duration,
+    app_version: Option<String>,
+    anti_entropy_interval: std::time::Duration,
+    gossip_max_hops: u8,
+    encrypt_payloads: bool,
+    require_sessions: bool,
+    key_rotation_grace: std::time::Duration,
+    secret_store: Option<Arc<dyn SecretStore>>,
+    identity_issuers: Vec<[u8; 32]>,
+    admin_keys: Vec<[u8; 32]>,
+    capability_issuers: Vec<[u8; 32]>,
+    default_capabilities: Option<crate::capability::Capabilities>,
+    max_clock_jump: u64,
+    max_changeset_changes: usize,
+    max_value_bytes: usize,
+    peer_request_rate: u32,
+    peer_request_burst: u32,
+    peer_ban_duration: std::time::Duration,
+    #[cfg(feature = "webrtc")]
+    webrtc: bool,
+    #[cfg(feature = "websocket")]
+    websocket_port: Option<u16>,
+    #[cfg(feature = "websocket")]
+    websocket_tls: Option<crate::engine::WebSocketTls>,
 }
 
 impl WaveSyncDbBuilder {
@@ -1410,6 +1876,7 @@ impl WaveSyncDbBuilder {
             mdns_ttl: defaults.mdns_ttl,
             group_key: None,
             passphrase: None,
+            group_salt: None,
             bootstrap_peers: Vec::new(),
             rendezvous_server: None,
             rendezvous_discover_interval: defaults.rendezvous_discover_interval,
@@ -1421,9 +1888,57 @@ impl WaveSyncDbBuilder {
             api_key: None,
             keep_alive_interval: defaults.keep_alive_interval,
             circuit_max_duration: defaults.circuit_max_duration,
+            app_version: None,
+            anti_entropy_interval: defaults.anti_entropy_interval,
+            gossip_max_hops: defaults.gossip_max_hops,
+            encrypt_payloads: defaults.encrypt_payloads,
+            require_sessions: defaults.require_sessions,
+            key_rotation_grace: defaults.key_rotation_grace,
+            secret_store: None,
+            identity_issuers: defaults.identity_issuers,
+            admin_keys: defaults.admin_keys,
+            capability_issuers: defaults.capability_issuers,
+            default_capabilities: defaults.default_capabilities,
+            max_clock_jump: defaults.max_clock_jump,
+            max_changeset_changes: defaults.max_changeset_changes,
+            max_value_bytes: defaults.max_value_bytes,
+            peer_request_rate: defaults.peer_request_rate,
+            peer_request_burst: defaults.peer_request_burst,
+            peer_ban_duration: defaults.peer_ban_duration,
+            #[cfg(feature = "webrtc")]
+            webrtc: defaults.webrtc,
+            #[cfg(feature = "websocket")]
+            websocket_port: defaults.websocket_port,
+            #[cfg(feature = "websocket")]
+            websocket_tls: defaults.websocket_tls,
         }
     }
 
+    /// Start a builder from a saved config — one [`SyncConfig::load`] read
+    /// back or [`SyncConfig::pair`] received.
+    pub fn from_config(config: &SyncConfig) -> Self {
+        let mut builder = Self::new(&config.database_url, &config.topic);
+        if let Some(ref relay) = config.relay_server {
+            builder = match config.api_key {
+                Some(ref api_key) => builder.managed_relay(relay, api_key),
+                None => builder.with_relay_server(relay),
+            };
+        }
+        if let Some(ref passphrase) = config.passphrase {
+            builder = builder.with_passphrase(passphrase);
+        }
+        if let Some(salt) = config.group_salt {
+            builder = builder.with_group_salt(salt);
+        }
+        if let Some(ref rendezvous) = config.rendezvous_server {
+            builder = builder.with_rendezvous_server(rendezvous);
+        }
+        for peer in &config.bootstrap_peers {
+            builder = builder.with_bootstrap_peer(peer);
+        }
+        builder.with_ipv6(config.ipv6)
+    }
+
     pub fn with_node_id(mut self, id: NodeId) -> Self {
         self.node_id = Some(id);
         self
@@ -1519,6 +2034,17 @@ impl WaveSyncDbBuilder {
         self
     }
 
+    /// Derive the group key from the passphrase with Argon2id under `salt`
+    /// (v2) rather than BLAKE3 (v1); see [`crate::auth`].
+    ///
+    /// Every device of the group needs the same salt. Create it once with
+    /// [`GroupSalt::generate`](crate::auth::GroupSalt::generate) and hand it
+    /// out with the passphrase — pairing does so on its own.
+    pub fn with_group_salt(mut self, salt: crate::auth::GroupSalt) -> Self {
+        self.group_salt = Some(salt);
+        self
+    }
+
     /// Register a push notification token for mobile wake-up via the relay server.
     ///
     /// When connected to a relay, the engine will send a `RegisterToken` request
@@ -1595,6 +2121,217 @@ impl WaveSyncDbBuilder {
         self
     }
 
+    /// Set the application version announced to peers during the
+    /// capability handshake. Purely informational — compatibility is
+    /// decided by protocol version and table schemas.
+    pub fn with_app_version(mut self, version: &str) -> Self {
+        self.app_version = Some(version.to_string());
+        self
+    }
+
+    /// Set how often to compare range hashes with each peer (default: 10 min).
+    ///
+    /// Anti-entropy catches divergence that version vector sync can't see,
+    /// e.g. after restoring a backup. Each round scans the shadow tables, so
+    /// keep this well above the sync interval on large databases.
+    pub fn with_anti_entropy_interval(mut self, interval: std::time::Duration) -> Self {
+        self.anti_entropy_interval = interval;
+        self
+    }
+
+    /// Set how many times a pushed change may be forwarded between peers
+    /// (default: 4).
+    ///
+    /// Peers relay changes they receive to their own peers, so devices that
+    /// aren't directly connected still see edits in real time. `0` turns
+    /// forwarding off; changes then reach indirect peers at the next sync
+    /// round.
+    pub fn with_gossip_max_hops(mut self, hops: u8) -> Self {
+        self.gossip_max_hops = hops;
+        self
+    }
+
+    /// Encrypt synced row data with the passphrase (default: `true`).
+    ///
+    /// With a passphrase set, the changes inside every sync message are
+    /// encrypted as well as authenticated, so relays and other
+    /// intermediaries never see row values. Peers running a build that
+    /// predates payload encryption are sent plaintext regardless. Turn this
+    /// off to keep a group unencrypted, e.g. while an external tool still
+    /// reads its traffic.
+    pub fn with_payload_encryption(mut self, enabled: bool) -> Self {
+        self.encrypt_payloads = enabled;
+        self
+    }
+
+    /// Only accept authenticated sync messages sent on a session with the
+    /// peer (default: off; no effect without a passphrase).
+    ///
+    /// Browser clients never set up sessions; they MAC their messages with
+    /// a key bound to the receiving device, which keeps a captured message
+    /// from being replayed to another member but not to the same one.
+    /// Builds that predate sessions use the plain group key, which can be
+    /// replayed to any member. Both are refused with this on, so turn it on
+    /// once every device in the group sets up sessions.
+    pub fn with_required_sessions(mut self, required: bool) -> Self {
+        self.require_sessions = required;
+        self
+    }
+
+    /// Set how long the previous passphrase is still accepted after the
+    /// group key is rotated (default: 7 days).
+    ///
+    /// Devices that were offline during a rotation are brought over when
+    /// they next reach a device that still accepts their key. After this
+    /// window they have to be given the new passphrase by hand. See
+    /// [`WaveSyncDb::rotate_passphrase`].
+    pub fn with_key_rotation_grace(mut self, grace: std::time::Duration) -> Self {
+        self.key_rotation_grace = grace;
+        self
+    }
+
+    /// Keep the passphrase and API key in `secrets` instead of the default
+    /// [`FileSecretStore`] next to the database — an OS keyring, for
+    /// instance (see [`crate::secret_store`]). Background sync has to be
+    /// given the same store via
+    /// [`background_sync_with_store`](crate::background_sync::background_sync_with_store).
+    pub fn with_secret_store(mut self, secrets: Arc<dyn SecretStore>) -> Self {
+        self.secret_store = Some(secrets);
+        self
+    }
+
+    /// How far a remote change's clock may run past the highest one held
+    /// for its row (default:
+    /// [`DEFAULT_MAX_CLOCK_JUMP`](crate::conflict::DEFAULT_MAX_CLOCK_JUMP)).
+    /// A site that sends a change past it is quarantined; see
+    /// [`WaveSyncDb::quarantined_sites`].
+    pub fn with_max_clock_jump(mut self, max_jump: u64) -> Self {
+        self.max_clock_jump = max_jump;
+        self
+    }
+
+    /// Whether to listen for browser peers over WebRTC (default: `false`;
+    /// needs the `webrtc` feature). Browsers that reach this device
+    /// through the relay learn its `webrtc-direct` address and connect to
+    /// it directly, which keeps their traffic off the relay when they can
+    /// reach it (on the same LAN, say). Native peers always connect over
+    /// QUIC, and browsers still reach each other through the relay.
+    #[cfg(feature = "webrtc")]
+    pub fn with_webrtc(mut self, enabled: bool) -> Self {
+        self.webrtc = enabled;
+        self
+    }
+
+    /// Listen for browser peers over WebSocket on TCP `port` (`0` picks a
+    /// free one; default: no listener; needs the `websocket` feature). A
+    /// browser can then dial this device without a relay — e.g. a web UI
+    /// syncing with a desktop app on the same machine, via
+    /// `WebSyncClient::connect_persistent` and
+    /// `/ip4/127.0.0.1/tcp/<port>/ws/p2p/<peer-id>`. The address is
+    /// advertised over identify and in pairing invitations.
+    ///
+    /// Pages served over HTTPS can't open plain `ws` connections; see
+    /// [`Self::with_websocket_tls`].
+    #[cfg(feature = "websocket")]
+    pub fn with_websocket_listener(mut self, port: u16) -> Self {
+        self.websocket_port = Some(port);
+        self
+    }
+
+    /// Serve the WebSocket listener over TLS (`wss`) with the given
+    /// DER-encoded certificate chain, leaf first, and private key
+    /// (PKCS#8 or PKCS#1). Needs [`Self::with_websocket_listener`]. The
+    /// certificate must be one the browser trusts for the name it dials.
+    /// [`Self::build`] fails if the certificate or key can't be parsed.
+    #[cfg(feature = "websocket")]
+    pub fn with_websocket_tls(
+        mut self,
+        certificate_chain: Vec<Vec<u8>>,
+        private_key: Vec<u8>,
+    ) -> Self {
+        self.websocket_tls = Some(crate::engine::WebSocketTls {
+            certificate_chain,
+            private_key,
+        });
+        self
+    }
+
+    /// Refuse pushed changesets of more than `max` changes (default:
+    /// 10,000). The changes of a refused push still arrive with the next
+    /// catch-up, which is paged.
+    pub fn with_max_changeset_changes(mut self, max: usize) -> Self {
+        self.max_changeset_changes = max;
+        self
+    }
+
+    /// Refuse remote changes whose value serializes to more than `max`
+    /// bytes (default: 1 MiB).
+    pub fn with_max_value_bytes(mut self, max: usize) -> Self {
+        self.max_value_bytes = max;
+        self
+    }
+
+    /// Let each peer send `rate` requests per second on average, and up to
+    /// `burst` at once (default: 50/s, burst 200). A `rate` of 0 disables
+    /// rate limiting.
+    pub fn with_peer_rate_limit(mut self, rate: u32, burst: u32) -> Self {
+        self.peer_request_rate = rate;
+        self.peer_request_burst = burst;
+        self
+    }
+
+    /// How long a peer that keeps breaking the rate and size limits is
+    /// banned (default: 10 min).
+    pub fn with_peer_ban_duration(mut self, duration: std::time::Duration) -> Self {
+        self.peer_ban_duration = duration;
+        self
+    }
+
+    /// Trust identity endorsements signed by the issuer key `key`, so
+    /// peers presenting one are reported as
+    /// [`IdentityVerification::Endorsed`](crate::identity::IdentityVerification::Endorsed)
+    /// (see [`crate::identity`]). May be called more than once.
+    pub fn with_identity_issuer(mut self, key: [u8; 32]) -> Self {
+        if !self.identity_issuers.contains(&key) {
+            self.identity_issuers.push(key);
+        }
+        self
+    }
+
+    /// Make the device with identity key `key` an administrator of the
+    /// group: its revocations are accepted even from a device this one has
+    /// never synced with (see [`crate::revocation`]), and it may grant
+    /// capabilities. May be called more than once.
+    pub fn with_admin_key(mut self, key: [u8; 32]) -> Self {
+        if !self.admin_keys.contains(&key) {
+            self.admin_keys.push(key);
+        }
+        self
+    }
+
+    /// Accept capability grants issued by the device with identity key
+    /// `key` (see [`crate::capability`]). Issuers are never restricted
+    /// themselves; once there are any, devices without a grant are
+    /// read-only unless [`Self::with_default_capabilities`] says
+    /// otherwise. May be called more than once.
+    pub fn with_capability_issuer(mut self, key: [u8; 32]) -> Self {
+        if !self.capability_issuers.contains(&key) {
+            self.capability_issuers.push(key);
+        }
+        self
+    }
+
+    /// What a device without a capability grant may write (default:
+    /// everything when no issuers or administrators are configured,
+    /// nothing when they are).
+    pub fn with_default_capabilities(
+        mut self,
+        capabilities: crate::capability::Capabilities,
+    ) -> Self {
+        self.default_capabilities = Some(capabilities);
+        self
+    }
+
     #[allow(unused_mut)]
     pub async fn build(mut self) -> Result<WaveSyncDb, DbErr> {
         // Auto-read FCM token from file written by WaveSyncInitProvider / WaveSyncService.
@@ -1665,6 +2402,35 @@ impl WaveSyncDbBuilder {
 
         let node_id = self.node_id.unwrap_or(site_id);
 
+        let secret_store = match self.secret_store.take() {
+            Some(secrets) => Some(secrets),
+            None => SyncConfig::default_secret_store(&self.database_url)
+                .ok()
+                .map(|secrets| Arc::new(secrets) as Arc<dyn SecretStore>),
+        };
+
+        // The passphrase may have been rotated since the app shipped with
+        // it: run with the rotated one, so a restart doesn't drop the device
+        // out of its group. Any other passphrase is a deliberate change and
+        // starts over.
+        let mut key_epoch = None;
+        if let Some(configured) = self.passphrase.take() {
+            let state = settle_key_epoch(
+                &inner,
+                secret_store.as_deref(),
+                &configured,
+                self.group_salt,
+            )
+            .await?;
+            if state.epoch > 0 {
+                log::info!("Using the group key of rotation epoch {}", state.epoch);
+            }
+            self.group_key = state.key();
+            self.group_salt = state.salt;
+            self.passphrase = Some(state.passphrase.clone());
+            key_epoch = Some(state);
+        }
+
         let db_version = crate::shadow::get_db_version(&inner).await?;
 
         let (sync_tx, sync_rx) = mpsc::channel::<SyncChangeset>(256);
@@ -1676,11 +2442,38 @@ impl WaveSyncDbBuilder {
         // Create peer versions table
         crate::peer_tracker::create_peer_versions_table(&inner).await?;
 
+        // Create the catch-up checkpoint table so an interrupted paginated
+        // sync resumes from its last applied page after a restart.
+        crate::peer_tracker::create_catchup_cursors_table(&inner).await?;
+
+        // Per-origin watermarks, so catch-up only asks peers for the writes
+        // of each site we don't already hold.
+        crate::peer_tracker::create_origin_versions_table(&inner).await?;
+
+        // Signing keys pinned for sites, and the signatures of changes we
+        // adopted from them, so forged and relayed changes can be told apart.
+        crate::peer_tracker::create_site_keys_table(&inner).await?;
+        crate::shadow::create_change_sigs_table(&inner).await?;
+
+        // Devices revoked from the group, enforced before anything they
+        // send is looked at.
+        crate::peer_tracker::create_revocations_table(&inner).await?;
+        crate::peer_tracker::create_capability_tables(&inner).await?;
+        crate::peer_tracker::create_quarantine_table(&inner).await?;
+
         // Create cached peer-addresses table (issue #29). Used by the
         // engine to pre-dial known good peers at startup before discovery
         // has had time to find them.
         crate::peer_addrs::create_peer_addrs_table(&inner).await?;
 
+        // Check the `wss` certificate here rather than fail the engine
+        // later.
+        #[cfg(feature = "websocket")]
+        if let Some(tls) = &self.websocket_tls {
+            crate::engine::websocket_listener::tls_config(tls)
+                .map_err(|e| DbErr::Custom(format!("Invalid WebSocket TLS certificate: {e}")))?;
+        }
+
         let (cmd_tx, cmd_rx) = mpsc::channel::<crate::engine::EngineCommand>(4);
 
         let network_status = Arc::new(std::sync::RwLock::new(
@@ -1744,6 +2537,7 @@ impl WaveSyncDbBuilder {
             topic: self.topic.clone(),
             relay_server: self.relay_server.clone(),
             passphrase: self.passphrase,
+            group_salt: self.group_salt,
             rendezvous_server: self.rendezvous_server.clone(),
             bootstrap_peers: self.bootstrap_peers.clone(),
             api_key: self.api_key.clone(),
@@ -1753,7 +2547,11 @@ impl WaveSyncDbBuilder {
             fcm_app_id,
             fcm_api_key,
         };
-        if let Err(e) = sync_config.save() {
+        if let Err(e) = secret_store
+            .as_deref()
+            .ok_or_else(|| "Cannot derive config path from database URL".to_string())
+            .and_then(|secrets| sync_config.save_with(secrets))
+        {
             log::warn!("Failed to save sync config for background services: {e}");
         }
 
@@ -1772,6 +2570,29 @@ impl WaveSyncDbBuilder {
             api_key: self.api_key,
             keep_alive_interval: self.keep_alive_interval,
             circuit_max_duration: self.circuit_max_duration,
+            app_version: self.app_version,
+            anti_entropy_interval: self.anti_entropy_interval,
+            gossip_max_hops: self.gossip_max_hops,
+            encrypt_payloads: self.encrypt_payloads,
+            require_sessions: self.require_sessions,
+            key_rotation_grace: self.key_rotation_grace,
+            secret_store,
+            identity_issuers: self.identity_issuers,
+            admin_keys: self.admin_keys,
+            capability_issuers: self.capability_issuers,
+            default_capabilities: self.default_capabilities,
+            max_clock_jump: self.max_clock_jump,
+            max_changeset_changes: self.max_changeset_changes,
+            max_value_bytes: self.max_value_bytes,
+            peer_request_rate: self.peer_request_rate,
+            peer_request_burst: self.peer_request_burst,
+            peer_ban_duration: self.peer_ban_duration,
+            #[cfg(feature = "webrtc")]
+            webrtc: self.webrtc,
+            #[cfg(feature = "websocket")]
+            websocket_port: self.websocket_port,
+            #[cfg(feature = "websocket")]
+            websocket_tls: self.websocket_tls,
         };
 
         // Diagnostics counters are owned jointly by the engine task (writer)
@@ -1792,6 +2613,7 @@ impl WaveSyncDbBuilder {
             registry_ready.clone(),
             cmd_rx,
             self.group_key,
+            key_epoch,
             network_status.clone(),
             network_event_tx.clone(),
             diagnostics.clone(),
@@ -1820,6 +2642,106 @@ impl WaveSyncDbBuilder {
     }
 }
 
+/// The rotation state to run with for `configured` (and `salt`): the
+/// stored one if it applies, with its passphrases from `secrets`, or else a
+/// fresh one at epoch 0. Keys are derived on the blocking pool.
+async fn settle_key_epoch(
+    db: &DatabaseConnection,
+    secrets: Option<&dyn SecretStore>,
+    configured: &str,
+    salt: Option<crate::auth::GroupSalt>,
+) -> Result<crate::rotation::KeyEpoch, DbErr> {
+    use crate::auth::GroupKey;
+
+    let get = |name| {
+        secrets.and_then(|secrets| {
+            secrets
+                .get(name)
+                .inspect_err(|e| log::warn!("Failed to read '{name}' from the secret store: {e}"))
+                .ok()
+                .flatten()
+        })
+    };
+    let mut stored = crate::shadow::get_key_epoch(db).await?;
+    // Older versions kept the passphrases in the row itself: move them
+    // into the store, then rewrite the row without them.
+    if let Some(legacy) = stored.take_if(|state| !state.passphrase.is_empty()) {
+        let key = GroupKey::derive_blocking(&legacy.passphrase, legacy.salt.as_ref()).await;
+        let previous_key = match legacy.previous {
+            Some(ref previous) => {
+                Some(GroupKey::derive_blocking(previous, legacy.previous_salt.as_ref()).await)
+            }
+            None => None,
+        };
+        let legacy = legacy.with_keys(key, previous_key);
+        let moved = secrets.map(|secrets| {
+            secrets
+                .set(secret_store::PASSPHRASE, &legacy.passphrase)
+                .and_then(|()| match legacy.previous {
+                    Some(ref previous) => secrets.set(secret_store::PREVIOUS_PASSPHRASE, previous),
+                    None => Ok(()),
+                })
+        });
+        match moved {
+            Some(Ok(())) => {
+                crate::shadow::set_key_epoch(db, &legacy).await?;
+                log::info!("Moved the rotated passphrases out of _wavesync_meta");
+            }
+            Some(Err(e)) => log::warn!("Failed to move the rotated passphrases: {e}"),
+            None => log::warn!("No secret store to move the rotated passphrases into"),
+        }
+        stored = Some(legacy);
+    }
+
+    let configured_key = GroupKey::derive_blocking(configured, salt.as_ref()).await;
+    if let Some(state) = stored {
+        // The configured passphrase may be the one in use under the salt
+        // the state moved to.
+        let configured_now = if state.salt == salt {
+            configured_key.clone()
+        } else {
+            GroupKey::derive_blocking(configured, state.salt.as_ref()).await
+        };
+        if state.applies_to(&configured_key) || state.is_key(&configured_now) {
+            let current = if state.is_key(&configured_now) {
+                Some((configured.to_string(), configured_now))
+            } else {
+                match get(secret_store::PASSPHRASE) {
+                    Some(passphrase) => {
+                        let key = GroupKey::derive_blocking(&passphrase, state.salt.as_ref()).await;
+                        state.is_key(&key).then_some((passphrase, key))
+                    }
+                    None => None,
+                }
+            };
+            match current {
+                Some((passphrase, key)) => {
+                    let previous = match get(secret_store::PREVIOUS_PASSPHRASE) {
+                        Some(previous) => {
+                            let key =
+                                GroupKey::derive_blocking(&previous, state.previous_salt.as_ref())
+                                    .await;
+                            state.is_previous_key(&key).then_some((previous, key))
+                        }
+                        None => None,
+                    };
+                    let (previous, previous_key) = previous.unzip();
+                    let mut state = state.with_keys(key, previous_key);
+                    state.passphrase = passphrase;
+                    state.previous = previous;
+                    return Ok(state);
+                }
+                None => log::warn!(
+                    "The secret store doesn't hold the passphrase of rotation epoch {}; starting over from the configured one",
+                    state.epoch
+                ),
+            }
+        }
+    }
+    let state = crate::rotation::KeyEpoch::new(configured, salt, configured_key);
+    crate::shadow::set_key_epoch(db, &state).await?;
+    Ok(state)
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2226,4 +3148,130 @@ mod tests {
         let val = sql_value_to_json("X'DEADBEEF'");
         assert!(val.is_string(), "Hex blob should fall back to string");
     }
+
+    /// A plaintext config from an older version has its secrets moved into
+    /// the secret store on first load, and keeps them from then on.
+    #[test]
+    fn test_load_migrates_plaintext_secrets() {
+        let dir = std::env::temp_dir().join(format!("wavesync_config_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let url = format!("sqlite://{}/app.db?mode=rwc", dir.display());
+        let config_path = SyncConfig::config_path(&url).unwrap();
+        std::fs::write(
+            &config_path,
+            r#"{"database_url":"x","topic":"t","relay_server":null,"passphrase":"correct horse",
+                "rendezvous_server":null,"bootstrap_peers":[],"api_key":"wsc_live_1",
+                "ipv6":false,"crate_name":null,"fcm_api_key":"AIza_1"}"#,
+        )
+        .unwrap();
+
+        let config = SyncConfig::load(&url).unwrap();
+        assert_eq!(config.passphrase.as_deref(), Some("correct horse"));
+        assert_eq!(config.api_key.as_deref(), Some("wsc_live_1"));
+        assert_eq!(config.fcm_api_key.as_deref(), Some("AIza_1"));
+        let on_disk = std::fs::read_to_string(&config_path).unwrap();
+        assert!(!on_disk.contains("correct horse"));
+        assert!(!on_disk.contains("wsc_live_1"));
+        assert!(!on_disk.contains("AIza_1"));
+
+        let config = SyncConfig::load(&url).unwrap();
+        assert_eq!(config.passphrase.as_deref(), Some("correct horse"));
+        assert_eq!(config.api_key.as_deref(), Some("wsc_live_1"));
+        assert_eq!(config.fcm_api_key.as_deref(), Some("AIza_1"));
+
+        // A config that only has the Firebase credentials in plaintext
+        // keeps the secrets already in the store.
+        std::fs::write(
+            &config_path,
+            on_disk.replacen('{', r#"{"fcm_app_id":"1:app","#, 1),
+        )
+        .unwrap();
+        let config = SyncConfig::load(&url).unwrap();
+        assert_eq!(config.fcm_app_id.as_deref(), Some("1:app"));
+        assert_eq!(config.passphrase.as_deref(), Some("correct horse"));
+        assert!(
+            !std::fs::read_to_string(&config_path)
+                .unwrap()
+                .contains("1:app")
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// A restart after a rotation runs with the rotated passphrase from the
+    /// secret store, not one from the database — where older versions put
+    /// it, and from where it is moved.
+    #[tokio::test]
+    async fn test_settle_key_epoch_reads_rotated_passphrase_from_store() {
+        let dir = std::env::temp_dir().join(format!("wavesync_settle_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let secrets = FileSecretStore::new(dir.join("secrets.json"), dir.join("secret.key"));
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        crate::shadow::create_meta_table(&db).await.unwrap();
+
+        let v1 = crate::auth::GroupKey::from_passphrase;
+        let keypair = libp2p::identity::ed25519::Keypair::generate();
+        let site = crate::signing::site_id_for_key(&keypair.public().to_bytes());
+        let signer = crate::signing::ChangeSigner::new(keypair, site);
+        let rotation = crate::rotation::SignedRotation::issue(&signer, 1, &v1("new"), None, None);
+        let rotated = crate::rotation::KeyEpoch::new("old", None, v1("old")).advance(
+            "new",
+            v1("new"),
+            rotation,
+            60,
+        );
+        crate::shadow::set_key_epoch(&db, &rotated).await.unwrap();
+        secrets.set(secret_store::PASSPHRASE, "new").unwrap();
+        secrets
+            .set(secret_store::PREVIOUS_PASSPHRASE, "old")
+            .unwrap();
+
+        let state = settle_key_epoch(&db, Some(&secrets), "old", None)
+            .await
+            .unwrap();
+        assert_eq!(state.epoch, 1);
+        assert_eq!(state.passphrase, "new");
+        assert_eq!(state.previous.as_deref(), Some("old"));
+        assert!(state.key().is_some_and(|key| rotated.is_key(&key)));
+        assert!(state.previous_key().is_some());
+
+        // Without the rotated passphrase there is nothing to run with.
+        secrets.delete(secret_store::PASSPHRASE).unwrap();
+        let state = settle_key_epoch(&db, Some(&secrets), "old", None)
+            .await
+            .unwrap();
+        assert_eq!(state.epoch, 0);
+        assert_eq!(state.passphrase, "old");
+
+        // A row from an older version gives its passphrases to the store.
+        secrets.delete(secret_store::PREVIOUS_PASSPHRASE).unwrap();
+        let legacy = format!(
+            r#"{{"epoch":1,"passphrase":"new","previous":"old","grace_until":99999999999,
+                "superseded":["{}"]}}"#,
+            crate::seal::to_hex(&v1("old").fingerprint())
+        );
+        db.execute_raw(sea_orm::Statement::from_sql_and_values(
+            sea_orm::DatabaseBackend::Sqlite,
+            "INSERT OR REPLACE INTO _wavesync_meta (key, value) VALUES ('key_epoch', $1)",
+            [legacy.into_bytes().into()],
+        ))
+        .await
+        .unwrap();
+        let state = settle_key_epoch(&db, Some(&secrets), "old", None)
+            .await
+            .unwrap();
+        assert_eq!(state.epoch, 1);
+        assert_eq!(state.passphrase, "new");
+        assert_eq!(
+            secrets
+                .get(secret_store::PREVIOUS_PASSPHRASE)
+                .unwrap()
+                .as_deref(),
+            Some("old")
+        );
+        let row = crate::shadow::get_key_epoch(&db).await.unwrap().unwrap();
+        assert!(row.passphrase.is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
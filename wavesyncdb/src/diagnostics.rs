@@ -105,6 +105,80 @@ pub(crate) struct Counters {
     /// is actually facing — typically ~70% on mixed home / office NATs,
     /// ~10–30% on cellular (carrier-grade NAT defeats hole punching).
     pub dcutr_upgrades_succeeded: AtomicU64,
+
+    /// Sync payloads (requests and responses on the snapshot protocol)
+    /// that went out zstd-compressed. Only counts payloads above
+    /// [`crate::compression::COMPRESSION_THRESHOLD`] sent to peers that
+    /// negotiated `/wavesync/snapshot/3.1.0`.
+    pub payloads_compressed: AtomicU64,
+    /// Serialized JSON size of the payloads counted in
+    /// [`Self::payloads_compressed`], before compression.
+    pub compression_input_bytes: AtomicU64,
+    /// Wire size of the payloads counted in [`Self::payloads_compressed`],
+    /// after compression. `compression_input_bytes / compression_output_bytes`
+    /// is the achieved ratio — see [`Snapshot::compression_ratio`].
+    pub compression_output_bytes: AtomicU64,
+
+    /// Anti-entropy rounds started (one per eligible peer per
+    /// `anti_entropy_interval`).
+    pub anti_entropy_rounds: AtomicU64,
+    /// Column changes received through anti-entropy — clocks version
+    /// vector sync had missed. Non-zero means `peer_db_versions`
+    /// bookkeeping drifted somewhere and anti-entropy repaired it.
+    pub anti_entropy_repairs: AtomicU64,
+
+    /// Remote changesets forwarded to other peers (multi-hop gossip). One
+    /// per changeset, regardless of how many peers it went to.
+    pub changesets_forwarded: AtomicU64,
+
+    /// Pushes that revealed an earlier push from the same peer never
+    /// arrived (or was dropped before applying), each triggering a
+    /// catch-up for the missing range.
+    pub push_gaps_detected: AtomicU64,
+
+    /// Authentic messages rejected because they were MACed for another
+    /// connection or reused a request counter — replays, or a peer that
+    /// lost track of its session.
+    pub replays_rejected: AtomicU64,
+
+    /// Messages dropped because their sealed changes wouldn't open — wrong
+    /// passphrase, tampering, or sealed changes while we have no passphrase.
+    pub sealed_payloads_rejected: AtomicU64,
+
+    /// Remote changes refused because they couldn't be attributed to the
+    /// site they claim — bad signature, wrong key, or unsigned for a site
+    /// that signs.
+    pub forged_changes_rejected: AtomicU64,
+
+    /// Group key rotations adopted — started here or announced by a peer.
+    pub key_rotations: AtomicU64,
+
+    /// Connections, requests and remote changes refused because they came
+    /// from a revoked device.
+    pub revoked_rejected: AtomicU64,
+
+    /// Remote changes refused because their author's capability grant
+    /// doesn't allow them.
+    pub unauthorized_changes_rejected: AtomicU64,
+
+    /// Remote changes refused because they ran a clock further ahead than
+    /// `max_clock_jump` allows, or came from a site quarantined for it.
+    pub inflated_clocks_rejected: AtomicU64,
+
+    /// Requests dropped because their peer was over its request rate or
+    /// had too many pushes queued.
+    pub requests_rate_limited: AtomicU64,
+
+    /// Pushes and responses refused for carrying too many changes or a
+    /// value over `max_value_bytes`.
+    pub oversized_messages_rejected: AtomicU64,
+
+    /// Peers banned for repeatedly breaking the inbound limits.
+    pub peers_banned: AtomicU64,
+
+    /// Remote changes refused because their value doesn't fit the declared
+    /// type of its column.
+    pub mistyped_values_rejected: AtomicU64,
 }
 
 impl Counters {
@@ -126,8 +200,38 @@ impl Counters {
             cached_addr_dials: self.cached_addr_dials.load(Ordering::Relaxed),
             dcutr_upgrades_attempted: self.dcutr_upgrades_attempted.load(Ordering::Relaxed),
             dcutr_upgrades_succeeded: self.dcutr_upgrades_succeeded.load(Ordering::Relaxed),
+            payloads_compressed: self.payloads_compressed.load(Ordering::Relaxed),
+            compression_input_bytes: self.compression_input_bytes.load(Ordering::Relaxed),
+            compression_output_bytes: self.compression_output_bytes.load(Ordering::Relaxed),
+            anti_entropy_rounds: self.anti_entropy_rounds.load(Ordering::Relaxed),
+            anti_entropy_repairs: self.anti_entropy_repairs.load(Ordering::Relaxed),
+            changesets_forwarded: self.changesets_forwarded.load(Ordering::Relaxed),
+            push_gaps_detected: self.push_gaps_detected.load(Ordering::Relaxed),
+            replays_rejected: self.replays_rejected.load(Ordering::Relaxed),
+            sealed_payloads_rejected: self.sealed_payloads_rejected.load(Ordering::Relaxed),
+            forged_changes_rejected: self.forged_changes_rejected.load(Ordering::Relaxed),
+            key_rotations: self.key_rotations.load(Ordering::Relaxed),
+            revoked_rejected: self.revoked_rejected.load(Ordering::Relaxed),
+            unauthorized_changes_rejected: self
+                .unauthorized_changes_rejected
+                .load(Ordering::Relaxed),
+            inflated_clocks_rejected: self.inflated_clocks_rejected.load(Ordering::Relaxed),
+            requests_rate_limited: self.requests_rate_limited.load(Ordering::Relaxed),
+            oversized_messages_rejected: self.oversized_messages_rejected.load(Ordering::Relaxed),
+            peers_banned: self.peers_banned.load(Ordering::Relaxed),
+            mistyped_values_rejected: self.mistyped_values_rejected.load(Ordering::Relaxed),
         }
     }
+
+    /// Record one compressed payload of `input` JSON bytes that went out
+    /// as `output` wire bytes.
+    pub(crate) fn record_compression(&self, input: usize, output: usize) {
+        self.payloads_compressed.fetch_add(1, Ordering::Relaxed);
+        self.compression_input_bytes
+            .fetch_add(input as u64, Ordering::Relaxed);
+        self.compression_output_bytes
+            .fetch_add(output as u64, Ordering::Relaxed);
+    }
 }
 
 /// Read-only snapshot of the engine's diagnostics counters.
@@ -148,6 +252,54 @@ pub struct Snapshot {
     pub cached_addr_dials: u64,
     pub dcutr_upgrades_attempted: u64,
     pub dcutr_upgrades_succeeded: u64,
+    #[serde(default)]
+    pub payloads_compressed: u64,
+    #[serde(default)]
+    pub compression_input_bytes: u64,
+    #[serde(default)]
+    pub compression_output_bytes: u64,
+    #[serde(default)]
+    pub anti_entropy_rounds: u64,
+    #[serde(default)]
+    pub anti_entropy_repairs: u64,
+    #[serde(default)]
+    pub changesets_forwarded: u64,
+    #[serde(default)]
+    pub push_gaps_detected: u64,
+    #[serde(default)]
+    pub replays_rejected: u64,
+    #[serde(default)]
+    pub sealed_payloads_rejected: u64,
+    #[serde(default)]
+    pub forged_changes_rejected: u64,
+    #[serde(default)]
+    pub key_rotations: u64,
+    #[serde(default)]
+    pub revoked_rejected: u64,
+    #[serde(default)]
+    pub unauthorized_changes_rejected: u64,
+    #[serde(default)]
+    pub inflated_clocks_rejected: u64,
+    #[serde(default)]
+    pub requests_rate_limited: u64,
+    #[serde(default)]
+    pub oversized_messages_rejected: u64,
+    #[serde(default)]
+    pub peers_banned: u64,
+    #[serde(default)]
+    pub mistyped_values_rejected: u64,
+}
+
+impl Snapshot {
+    /// Achieved compression ratio over every compressed sync payload
+    /// (uncompressed bytes / wire bytes, so `4.0` means 4× smaller).
+    /// `None` until the first payload has been compressed.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        if self.compression_output_bytes == 0 {
+            return None;
+        }
+        Some(self.compression_input_bytes as f64 / self.compression_output_bytes as f64)
+    }
 }
 
 #[cfg(test)]
@@ -183,4 +335,15 @@ mod tests {
         // Untouched counters stay zero.
         assert_eq!(snap.mdns_discoveries, 0);
     }
+
+    #[test]
+    fn compression_ratio_is_none_until_first_payload() {
+        let c = Counters::default();
+        assert_eq!(c.snapshot().compression_ratio(), None);
+        c.record_compression(8_000, 1_000);
+        c.record_compression(2_000, 1_000);
+        let snap = c.snapshot();
+        assert_eq!(snap.payloads_compressed, 2);
+        assert_eq!(snap.compression_ratio(), Some(5.0));
+    }
 }
@@ -236,7 +236,9 @@ pub fn use_network_status(db: WaveSyncDb) -> Signal<NetworkStatus> {
 /// Refreshes whenever a [`NetworkEvent::PeerIdentityReceived`](crate::NetworkEvent),
 /// [`NetworkEvent::PeerConnected`](crate::NetworkEvent), or
 /// [`NetworkEvent::PeerDisconnected`](crate::NetworkEvent) event is received.
-/// Only includes peers that have announced an identity.
+/// Only includes peers that have announced an identity; check each peer's
+/// [`identity_verification`](crate::PeerInfo::identity_verification) before
+/// trusting it.
 pub fn use_peer_identities(
     db: WaveSyncDb,
 ) -> Signal<std::collections::HashMap<String, Vec<crate::network_status::PeerInfo>>> {
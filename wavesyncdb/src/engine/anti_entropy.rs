@@ -0,0 +1,365 @@
+//! Periodic anti-entropy rounds over range hashes (see [`crate::merkle`]).
+//!
+//! A round is a walk down the range-hash tree driven entirely by the
+//! requester: each query carries the requester's hashes (or, at the leaves,
+//! its clocks), the responder compares them with its own and answers with
+//! what differs. Hashing touches the database, so both sides compute in
+//! spawned tasks; the requester's next query comes back to the main loop
+//! through `anti_entropy_tx` to be sent.
+//!
+//! Each round only pulls: the responder's clocks that the requester lacks.
+//! Divergence the other way is repaired by the peer's own round.
+
+use super::*;
+use crate::merkle;
+use crate::protocol::{AntiEntropyQuery, AntiEntropyReply, FEATURE_ANTI_ENTROPY, SyncResponse};
+use crate::signing::ChangeSigner;
+
+/// Differing ranges followed per reply. A badly diverged table is repaired
+/// over several rounds rather than with one burst of requests.
+const MAX_RANGES_PER_STEP: usize = 16;
+
+impl EngineRunner {
+    /// Start a round with every connected peer that announced anti-entropy.
+    pub(super) fn start_anti_entropy_rounds(&mut self) {
+        let peers: Vec<libp2p::PeerId> = self
+            .peer_handshakes
+            .iter()
+            .filter(|(peer, hs)| {
+                matches!(hs, handshake::Handshake::Compatible(hello) if hello.supports(FEATURE_ANTI_ENTROPY))
+                    && self.swarm.is_connected(peer)
+                    && !self.infrastructure_peers.contains(*peer)
+            })
+            .map(|(peer, _)| *peer)
+            .collect();
+
+        for peer in peers {
+            self.diagnostics
+                .anti_entropy_rounds
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let db = self.db.clone();
+            let registry = self.registry.clone();
+            let tx = self.anti_entropy_tx.clone();
+            tokio::spawn(async move {
+                let mut roots = std::collections::BTreeMap::new();
+                for meta in registry.all_tables() {
+                    match merkle::table_root(&db, &meta.table_name).await {
+                        Ok(root) => {
+                            roots.insert(meta.table_name, root);
+                        }
+                        Err(e) => {
+                            log::warn!("Anti-entropy: failed to hash {}: {e}", meta.table_name);
+                            return;
+                        }
+                    }
+                }
+                let _ = tx.send((peer, AntiEntropyQuery::Roots { roots })).await;
+            });
+        }
+    }
+
+    /// Sign and send one anti-entropy query.
+    pub(super) fn send_anti_entropy(&mut self, peer: libp2p::PeerId, query: AntiEntropyQuery) {
+        if !self.swarm.is_connected(&peer) {
+            return;
+        }
+        let auth = self.request_auth(&peer);
+        let mut req = SyncRequest::AntiEntropy {
+            query,
+            topic: self.topic_name.clone(),
+            seq: auth.as_ref().and_then(|(_, seq)| *seq),
+            hmac: None,
+        };
+
+        if let Some((ref key, _)) = auth
+            && let Ok(bytes) = serde_json::to_vec(&req)
+        {
+            let tag = key.mac(&bytes);
+            if let SyncRequest::AntiEntropy { ref mut hmac, .. } = req {
+                *hmac = Some(tag);
+            }
+        }
+
+        self.swarm.behaviour_mut().snapshot.send_request(&peer, req);
+    }
+
+    /// Verify HMAC + topic, then compare the requester's hashes or clocks
+    /// with ours in a spawned task and answer with what differs.
+    pub(super) fn handle_anti_entropy_request(
+        &mut self,
+        peer: libp2p::PeerId,
+        channel: request_response::ResponseChannel<SyncResponse>,
+        query: AntiEntropyQuery,
+        peer_topic: String,
+        seq: Option<u64>,
+        req_hmac: Option<[u8; 32]>,
+    ) {
+        if self.group_key.is_some() {
+            let tag = match req_hmac {
+                Some(t) => t,
+                None => {
+                    log::debug!("Rejecting unauthenticated anti-entropy request from peer {peer}");
+                    return;
+                }
+            };
+            let verify_req = SyncRequest::AntiEntropy {
+                query: query.clone(),
+                topic: peer_topic.clone(),
+                seq,
+                hmac: None,
+            };
+            if let Ok(bytes) = serde_json::to_vec(&verify_req)
+                && !self.verify_request(peer, &bytes, &tag, seq)
+            {
+                log::debug!("Rejecting anti-entropy request with invalid HMAC from peer {peer}");
+                return;
+            }
+        }
+
+        if !self.accepts_topic(&peer_topic) {
+            log::debug!(
+                "Ignoring anti-entropy request from peer {peer}: topic mismatch (theirs={peer_topic}, ours={})",
+                self.topic_name
+            );
+            self.reject_peer(peer);
+            return;
+        }
+
+        let db = self.db.clone();
+        let registry = self.registry.clone();
+        let resp_tx = self.snapshot_resp_tx.clone();
+        let group_key = self.response_key(&peer);
+        let sealing_key = self.sealing_key(&peer);
+        let signer = self.change_signer(&peer);
+
+        tokio::spawn(async move {
+            let mut reply = match answer_anti_entropy(&db, &registry, query).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    log::error!("Anti-entropy: failed to answer peer {peer}: {e}");
+                    return;
+                }
+            };
+            if let AntiEntropyReply::Clocks { ref mut changes } = reply {
+                ChangeSigner::sign_for(signer.as_ref(), changes);
+            }
+
+            let mut resp = SyncResponse::AntiEntropy {
+                reply,
+                sealed: None,
+                hmac: None,
+            };
+            if let Some(ref gk) = group_key
+                && let Ok(bytes) = serde_json::to_vec(&resp)
+            {
+                let tag = gk.mac(&bytes);
+                if let SyncResponse::AntiEntropy { ref mut hmac, .. } = resp {
+                    *hmac = Some(tag);
+                }
+            }
+
+            if let Some(ref key) = sealing_key {
+                resp.seal_changes(key);
+            }
+
+            if let Err(e) = resp_tx.send((channel, resp)).await {
+                log::error!("Failed to queue anti-entropy response: {e}");
+            }
+        });
+    }
+
+    /// Follow the responder's answer one level down the tree, or apply the
+    /// clocks it sent from the leaves.
+    pub(super) fn handle_anti_entropy_reply(
+        &mut self,
+        peer: libp2p::PeerId,
+        reply: AntiEntropyReply,
+        resp_hmac: Option<[u8; 32]>,
+    ) {
+        if self.group_key.is_some() {
+            let tag = match resp_hmac {
+                Some(t) => t,
+                None => {
+                    log::debug!("Rejecting unauthenticated anti-entropy reply from peer {peer}");
+                    return;
+                }
+            };
+            let verify_resp = SyncResponse::AntiEntropy {
+                reply: reply.clone(),
+                sealed: None,
+                hmac: None,
+            };
+            if let Ok(bytes) = serde_json::to_vec(&verify_resp)
+                && !self.verify_response(peer, &bytes, &tag)
+            {
+                log::debug!("Rejecting anti-entropy reply with invalid HMAC from peer {peer}");
+                return;
+            }
+        }
+
+        match reply {
+            AntiEntropyReply::Roots { differing } => {
+                if differing.is_empty() {
+                    log::debug!("Anti-entropy with peer {peer}: in agreement");
+                    return;
+                }
+                log::info!("Anti-entropy with peer {peer}: tables {differing:?} differ");
+                for table in differing {
+                    if self.registry.is_registered(&table) {
+                        self.spawn_anti_entropy_step(peer, table, Vec::new(), vec![None]);
+                    }
+                }
+            }
+            AntiEntropyReply::Children {
+                table,
+                prefix,
+                differing,
+            } => {
+                if prefix.len() >= merkle::MAX_DEPTH || !self.registry.is_registered(&table) {
+                    return;
+                }
+                let children = differing
+                    .into_iter()
+                    .take(MAX_RANGES_PER_STEP)
+                    .map(Some)
+                    .collect();
+                self.spawn_anti_entropy_step(peer, table, prefix, children);
+            }
+            AntiEntropyReply::Clocks { mut changes } => {
+                if changes.is_empty() {
+                    return;
+                }
+                log::info!(
+                    "Anti-entropy with peer {peer}: repairing {} missed changes",
+                    changes.len()
+                );
+                self.diagnostics
+                    .anti_entropy_repairs
+                    .fetch_add(changes.len() as u64, std::sync::atomic::Ordering::Relaxed);
+                if !self.peer_stamps_origin(&peer) {
+                    strip_origin_versions(&mut changes, None);
+                }
+                if let Err(e) = self
+                    .remote_changeset_tx
+                    .try_send(RemoteBatch::from(changes))
+                {
+                    log::warn!("Remote changeset queue full, dropping anti-entropy repair: {e}");
+                }
+            }
+        }
+    }
+
+    /// Compute our side of the next step under `prefix` — child hashes for
+    /// interior ranges, clocks for leaves — and queue the query.
+    /// `children` lists the ranges to descend into (`None` = `prefix`
+    /// itself, used for the table root).
+    fn spawn_anti_entropy_step(
+        &self,
+        peer: libp2p::PeerId,
+        table: String,
+        prefix: Vec<u8>,
+        children: Vec<Option<u8>>,
+    ) {
+        let db = self.db.clone();
+        let tx = self.anti_entropy_tx.clone();
+        tokio::spawn(async move {
+            let ranges: Vec<Vec<u8>> = children
+                .into_iter()
+                .map(|child| {
+                    let mut range = prefix.clone();
+                    range.extend(child);
+                    range
+                })
+                .collect();
+            let mut leaves = Vec::new();
+            for range in ranges {
+                if range.len() < merkle::MAX_DEPTH {
+                    let hashes = match merkle::child_hashes(&db, &table, &range).await {
+                        Ok(h) => h,
+                        Err(e) => {
+                            log::warn!("Anti-entropy: failed to hash {table}: {e}");
+                            return;
+                        }
+                    };
+                    let query = AntiEntropyQuery::Children {
+                        table: table.clone(),
+                        prefix: range,
+                        hashes,
+                    };
+                    if tx.send((peer, query)).await.is_err() {
+                        return;
+                    }
+                } else {
+                    match merkle::leaf_entries(&db, &table, &range).await {
+                        Ok(entries) => {
+                            leaves.push((range, entries.iter().map(merkle::digest).collect()))
+                        }
+                        Err(e) => {
+                            log::warn!("Anti-entropy: failed to read clocks of {table}: {e}");
+                            return;
+                        }
+                    }
+                }
+            }
+            if !leaves.is_empty() {
+                let _ = tx
+                    .send((peer, AntiEntropyQuery::Clocks { table, leaves }))
+                    .await;
+            }
+        });
+    }
+}
+
+/// Responder side of one anti-entropy step.
+async fn answer_anti_entropy(
+    db: &DatabaseConnection,
+    registry: &TableRegistry,
+    query: AntiEntropyQuery,
+) -> Result<AntiEntropyReply, sea_orm::DbErr> {
+    match query {
+        AntiEntropyQuery::Roots { roots } => {
+            let mut differing = Vec::new();
+            for (table, theirs) in roots {
+                if registry.is_registered(&table) && merkle::table_root(db, &table).await? != theirs
+                {
+                    differing.push(table);
+                }
+            }
+            Ok(AntiEntropyReply::Roots { differing })
+        }
+        AntiEntropyQuery::Children {
+            table,
+            prefix,
+            hashes,
+        } => {
+            let differing = if registry.is_registered(&table) && prefix.len() < merkle::MAX_DEPTH {
+                let ours = merkle::child_hashes(db, &table, &prefix).await?;
+                merkle::differing_children(&ours, &hashes)
+            } else {
+                Vec::new()
+            };
+            Ok(AntiEntropyReply::Children {
+                table,
+                prefix,
+                differing,
+            })
+        }
+        AntiEntropyQuery::Clocks { table, leaves } => {
+            let Some(meta) = registry.get(&table) else {
+                return Ok(AntiEntropyReply::Clocks {
+                    changes: Vec::new(),
+                });
+            };
+            let mut changes = Vec::new();
+            for (prefix, theirs) in leaves {
+                if prefix.len() != merkle::MAX_DEPTH {
+                    continue;
+                }
+                let ours = merkle::leaf_entries(db, &table, &prefix).await?;
+                let missing = merkle::entries_missing_from(ours, &theirs);
+                changes.extend(shadow::changes_for_entries(db, &meta, missing).await?);
+            }
+            Ok(AntiEntropyReply::Clocks { changes })
+        }
+    }
+}
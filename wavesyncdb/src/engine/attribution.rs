@@ -0,0 +1,97 @@
+//! Signing outgoing changes and checking that incoming ones come from the
+//! site they claim (see [`crate::signing`]).
+
+use super::*;
+
+use std::collections::HashSet;
+
+use crate::protocol::FEATURE_SIGNED_CHANGES;
+use crate::signing::{ChangeSigner, site_id_for_key, verify_change};
+
+impl EngineRunner {
+    /// Signer for changes sent to `peer`, if its hello says it verifies
+    /// signatures. Without one, signatures have to be stripped — a peer
+    /// that doesn't know the field would drop it and fail the message's MAC.
+    pub(super) fn change_signer(&self, peer: &libp2p::PeerId) -> Option<ChangeSigner> {
+        self.peer_supports(peer, FEATURE_SIGNED_CHANGES)
+            .then(|| self.signer.clone())
+    }
+
+    /// Pin the site id `peer`'s key derives to that key as soon as it
+    /// connects — Noise has already proven it holds the key. Until its first
+    /// signed change arrives the site would otherwise be unpinned, and an
+    /// unsigned change claiming it would pass as one from an older build.
+    pub(super) async fn pin_peer_site(&mut self, peer: &libp2p::PeerId) {
+        let Some(key) = crate::revocation::key_for_peer(peer) else {
+            return;
+        };
+        let site = site_id_for_key(&key);
+        if self.site_keys.get(&site) == Some(&key) {
+            return;
+        }
+        if let Err(e) = peer_tracker::pin_site_key(&self.db, &site, &key).await {
+            log::warn!("Failed to persist signing key for site {site:?}: {e}");
+        }
+        self.site_keys.insert(site, key);
+    }
+
+    /// Remove the changes that can't be attributed to the site they claim,
+    /// or that come from a revoked device, before they are applied, and pin
+    /// the keys of sites seen signing for the first time. Returns the sites
+    /// whose changes were refused.
+    pub(super) async fn drop_forged_changes(
+        &mut self,
+        changes: &mut Vec<ColumnChange>,
+    ) -> HashSet<NodeId> {
+        let mut forged = HashSet::new();
+        let mut refused = 0u64;
+        let mut revoked = 0u64;
+        let mut last_error = None;
+
+        let mut kept = Vec::with_capacity(changes.len());
+        for change in changes.drain(..) {
+            let site = change.site_id;
+            if self.is_revoked_site(&site) {
+                revoked += 1;
+                forged.insert(site);
+                continue;
+            }
+            match verify_change(&change, self.site_keys.get(&site)) {
+                Ok(Some(key)) => {
+                    // New sites get pinned; a pin that differs from a
+                    // verified key can only be one someone else got in
+                    // before the site's key-derived id was seen.
+                    if self.site_keys.get(&site) != Some(&key) {
+                        if let Err(e) = peer_tracker::pin_site_key(&self.db, &site, &key).await {
+                            log::warn!("Failed to persist signing key for site {site:?}: {e}");
+                        }
+                        self.site_keys.insert(site, key);
+                    }
+                    kept.push(change);
+                }
+                Ok(None) => kept.push(change),
+                Err(e) => {
+                    refused += 1;
+                    forged.insert(site);
+                    last_error = Some(e);
+                }
+            }
+        }
+        *changes = kept;
+
+        if revoked > 0 {
+            self.diagnostics
+                .revoked_rejected
+                .fetch_add(revoked, std::sync::atomic::Ordering::Relaxed);
+            log::warn!("Refusing {revoked} remote changes from revoked devices");
+        }
+
+        if let Some(e) = last_error {
+            self.diagnostics
+                .forged_changes_rejected
+                .fetch_add(refused, std::sync::atomic::Ordering::Relaxed);
+            log::warn!("Refusing {refused} remote changes from sites {forged:?}: {e}");
+        }
+        forged
+    }
+}
@@ -1,3 +1,4 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use libp2p::{
@@ -10,7 +11,9 @@ use super::auth_protocol::{
     AUTH_CHALLENGE_PROTOCOL, AUTH_RESULT_PROTOCOL, AuthChallengeCodec, AuthResultCodec,
 };
 use super::push_protocol::{PUSH_PROTOCOL, PushCodec};
-use super::snapshot_protocol::{SNAPSHOT_PROTOCOL, SnapshotCodec};
+use super::snapshot_protocol::{SNAPSHOT_PROTOCOL, SNAPSHOT_PROTOCOL_COMPRESSED, SnapshotCodec};
+use crate::diagnostics::Counters;
+use crate::pairing::{PAIRING_PROTOCOL, PairingCodec};
 
 #[derive(NetworkBehaviour)]
 pub struct WaveSyncBehaviour {
@@ -26,6 +29,7 @@ pub struct WaveSyncBehaviour {
     pub rendezvous: rendezvous::client::Behaviour,
     pub auth: request_response::Behaviour<AuthChallengeCodec>,
     pub auth_result: request_response::Behaviour<AuthResultCodec>,
+    pub pairing: request_response::Behaviour<PairingCodec>,
 }
 
 impl WaveSyncBehaviour {
@@ -34,6 +38,7 @@ impl WaveSyncBehaviour {
         relay_client: relay::client::Behaviour,
         mdns_config: Option<mdns::Config>,
         keep_alive_interval: Duration,
+        diagnostics: Arc<Counters>,
     ) -> Self {
         let identify_behaviour = identify::Behaviour::new(
             identify::Config::new("/wavesync/2.0.0".into(), key.public())
@@ -77,8 +82,19 @@ impl WaveSyncBehaviour {
 
         let rendezvous_behaviour = rendezvous::client::Behaviour::new(key.clone());
 
-        let snapshot_behaviour = request_response::Behaviour::new(
-            [(SNAPSHOT_PROTOCOL, request_response::ProtocolSupport::Full)],
+        // Compressed framing first: multistream-select takes the dialer's
+        // order, so two current peers settle on 3.1.0 while an older peer
+        // (or anything that only speaks 3.0.0) falls through to the legacy
+        // framing on the same connection.
+        let snapshot_behaviour = request_response::Behaviour::with_codec(
+            SnapshotCodec::with_diagnostics(diagnostics),
+            [
+                (
+                    SNAPSHOT_PROTOCOL_COMPRESSED,
+                    request_response::ProtocolSupport::Full,
+                ),
+                (SNAPSHOT_PROTOCOL, request_response::ProtocolSupport::Full),
+            ],
             request_response::Config::default().with_request_timeout(Duration::from_secs(30)),
         );
 
@@ -103,6 +119,11 @@ impl WaveSyncBehaviour {
             request_response::Config::default(),
         );
 
+        let pairing_behaviour = request_response::Behaviour::new(
+            [(PAIRING_PROTOCOL, request_response::ProtocolSupport::Full)],
+            request_response::Config::default(),
+        );
+
         // Up to 2 simultaneous connections per peer.
         //
         // Why 2: DCUtR upgrades by dialing a *direct* connection to the same
@@ -137,6 +158,7 @@ impl WaveSyncBehaviour {
             rendezvous: rendezvous_behaviour,
             auth: auth_behaviour,
             auth_result: auth_result_behaviour,
+            pairing: pairing_behaviour,
         }
     }
 }
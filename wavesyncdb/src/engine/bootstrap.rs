@@ -0,0 +1,357 @@
+//! Compacted state-snapshot bootstrap for peers we have never synced with.
+//!
+//! Version vector sync from `your_last_db_version = 0` walks the responder's
+//! whole clock history one column at a time. A first contact instead asks
+//! for a [`SyncRequest::StateSnapshot`]: the current rows and their winning
+//! clocks, paged by `(table, pk)`. The pages are buffered and installed in a
+//! single transaction once the last one arrives, after which the peer is
+//! treated as synced up to the first page's `db_version` and ordinary
+//! version vector sync picks up anything written during the transfer.
+//!
+//! Responders that predate snapshots can't decode the request and reset the
+//! stream; the requester then falls back to paginated version vector
+//! catch-up for that peer.
+
+use super::*;
+use crate::protocol::{OriginVersions, SnapshotCursor, SyncResponse};
+use crate::signing::ChangeSigner;
+
+/// Rows per snapshot page served by this engine.
+pub(crate) const SNAPSHOT_PAGE_ROWS: usize = 500;
+
+/// A snapshot bootstrap in progress with one peer.
+#[derive(Debug, Default)]
+pub(crate) struct SnapshotBootstrap {
+    /// Responder's `db_version` when the first page was read. Anything
+    /// written after that is fetched by version vector sync from here.
+    pub since_db_version: Option<u64>,
+    /// Pages received so far, installed together after the last one.
+    pub changes: Vec<ColumnChange>,
+    /// Where the next page starts.
+    pub next: Option<SnapshotCursor>,
+    /// Responder's watermarks when the first page was read, adopted once
+    /// the snapshot is installed. `None` if it doesn't stamp origins.
+    pub origin_versions: Option<OriginVersions>,
+    /// The in-flight page request, to tell its failure apart from that of
+    /// a push or identity announce sent to the same peer.
+    pub request_id: Option<request_response::OutboundRequestId>,
+}
+
+impl EngineRunner {
+    /// Ask `peer_id` for the next page of its state snapshot.
+    pub(super) fn request_state_snapshot(&mut self, peer_id: libp2p::PeerId) {
+        let after = self
+            .snapshot_bootstraps
+            .entry(peer_id)
+            .or_default()
+            .next
+            .clone();
+
+        log::info!(
+            "Requesting state snapshot from peer {peer_id} (continuing: {})",
+            after.is_some()
+        );
+
+        let auth = self.request_auth(&peer_id);
+        let mut req = SyncRequest::StateSnapshot {
+            site_id: self.site_id,
+            topic: self.topic_name.clone(),
+            after,
+            seq: auth.as_ref().and_then(|(_, seq)| *seq),
+            hmac: None,
+        };
+
+        if let Some((ref key, _)) = auth
+            && let Ok(bytes) = serde_json::to_vec(&req)
+        {
+            let tag = key.mac(&bytes);
+            if let SyncRequest::StateSnapshot { ref mut hmac, .. } = req {
+                *hmac = Some(tag);
+            }
+        }
+
+        let request_id = self
+            .swarm
+            .behaviour_mut()
+            .snapshot
+            .send_request(&peer_id, req);
+        if let Some(bootstrap) = self.snapshot_bootstraps.get_mut(&peer_id) {
+            bootstrap.request_id = Some(request_id);
+        }
+        self.pending_sync_peers.insert(peer_id);
+    }
+
+    /// Verify HMAC + topic, then spawn a task to read one page of current
+    /// state and send it back as a `StateSnapshot` response.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn handle_state_snapshot_request(
+        &mut self,
+        peer: libp2p::PeerId,
+        channel: request_response::ResponseChannel<SyncResponse>,
+        peer_site_id: NodeId,
+        peer_topic: String,
+        after: Option<SnapshotCursor>,
+        seq: Option<u64>,
+        req_hmac: Option<[u8; 32]>,
+    ) {
+        if self.group_key.is_some() {
+            let tag = match req_hmac {
+                Some(t) => t,
+                None => {
+                    log::debug!("Rejecting unauthenticated snapshot request from peer {peer}");
+                    return;
+                }
+            };
+            let verify_req = SyncRequest::StateSnapshot {
+                site_id: peer_site_id,
+                topic: peer_topic.clone(),
+                after: after.clone(),
+                seq,
+                hmac: None,
+            };
+            if let Ok(bytes) = serde_json::to_vec(&verify_req)
+                && !self.verify_request(peer, &bytes, &tag, seq)
+            {
+                log::debug!("Rejecting snapshot request with invalid HMAC from peer {peer}");
+                return;
+            }
+            if !self.verified_peers.contains(&peer) {
+                self.verified_peers.insert(peer);
+                self.emit_network_event(crate::network_status::NetworkEvent::PeerVerified(
+                    crate::network_status::PeerId(peer.to_string()),
+                ));
+                self.update_network_status();
+                if let Some(ref id) = self.local_app_id {
+                    let id = id.clone();
+                    self.send_identity_announce(peer, &id);
+                }
+            }
+        }
+
+        if !self.accepts_topic(&peer_topic) {
+            log::debug!(
+                "Ignoring snapshot request from peer {peer}: topic mismatch (theirs={peer_topic}, ours={})",
+                self.topic_name
+            );
+            self.reject_peer(peer);
+            return;
+        }
+
+        let db = self.db.clone();
+        let registry = self.registry.clone();
+        let resp_tx = self.snapshot_resp_tx.clone();
+        // Captured before the rows are read, so every write the page might
+        // miss has a higher db_version and reaches the peer incrementally.
+        let local_db_version = self.local_db_version;
+        let origin_versions = self
+            .peer_stamps_origin(&peer)
+            .then(|| self.local_origin_versions());
+        let local_site_id = self.site_id;
+        let topic_name = self.topic_name.clone();
+        let group_key = self.response_key(&peer);
+        let sealing_key = self.sealing_key(&peer);
+        let signer = self.change_signer(&peer);
+
+        tokio::spawn(async move {
+            let (mut changes, next) =
+                match shadow::get_state_page(&db, &registry, after.as_ref(), SNAPSHOT_PAGE_ROWS)
+                    .await
+                {
+                    Ok(page) => page,
+                    Err(e) => {
+                        // Dropping the channel fails the request; the peer
+                        // retries the bootstrap on its next sync round.
+                        log::error!("Failed to read state snapshot page: {e}");
+                        return;
+                    }
+                };
+
+            ChangeSigner::sign_for(signer.as_ref(), &mut changes);
+
+            let mut resp = SyncResponse::StateSnapshot {
+                changes,
+                my_db_version: local_db_version,
+                site_id: local_site_id,
+                topic: topic_name,
+                next,
+                origin_versions,
+                sealed: None,
+                hmac: None,
+            };
+
+            if let Some(ref gk) = group_key
+                && let Ok(bytes) = serde_json::to_vec(&resp)
+            {
+                let tag = gk.mac(&bytes);
+                if let SyncResponse::StateSnapshot { ref mut hmac, .. } = resp {
+                    *hmac = Some(tag);
+                }
+            }
+
+            if let Some(ref key) = sealing_key {
+                resp.seal_changes(key);
+            }
+
+            if let Err(e) = resp_tx.send((channel, resp)).await {
+                log::error!("Failed to queue snapshot response: {e}");
+            }
+        });
+    }
+
+    /// Buffer one snapshot page; after the last, install the whole snapshot
+    /// and switch the peer over to version vector sync.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn handle_state_snapshot_response(
+        &mut self,
+        peer: libp2p::PeerId,
+        mut changes: Vec<ColumnChange>,
+        my_db_version: u64,
+        peer_site_id: NodeId,
+        peer_topic: String,
+        next: Option<SnapshotCursor>,
+        origin_versions: Option<OriginVersions>,
+        resp_hmac: Option<[u8; 32]>,
+    ) {
+        if self.group_key.is_some() {
+            let tag = match resp_hmac {
+                Some(t) => t,
+                None => {
+                    log::debug!("Rejecting unauthenticated snapshot response from peer {peer}");
+                    return;
+                }
+            };
+            let verify_resp = SyncResponse::StateSnapshot {
+                changes: changes.clone(),
+                my_db_version,
+                site_id: peer_site_id,
+                topic: peer_topic.clone(),
+                next: next.clone(),
+                origin_versions: origin_versions.clone(),
+                sealed: None,
+                hmac: None,
+            };
+            if let Ok(bytes) = serde_json::to_vec(&verify_resp)
+                && !self.verify_response(peer, &bytes, &tag)
+            {
+                log::debug!("Rejecting snapshot response with invalid HMAC from peer {peer}");
+                return;
+            }
+        }
+
+        if !self.accepts_topic(&peer_topic) {
+            log::debug!(
+                "Ignoring snapshot response from peer {peer}: topic mismatch (theirs={peer_topic}, ours={})",
+                self.topic_name
+            );
+            self.reject_peer(peer);
+            return;
+        }
+
+        if !self.admit_changes(peer, &changes) {
+            self.snapshot_bootstraps.remove(&peer);
+            return;
+        }
+        let Some(bootstrap) = self.snapshot_bootstraps.get_mut(&peer) else {
+            log::debug!("Ignoring unsolicited snapshot response from peer {peer}");
+            return;
+        };
+        let first_page = bootstrap.since_db_version.is_none();
+        let since = *bootstrap.since_db_version.get_or_insert(my_db_version);
+        if first_page {
+            bootstrap.origin_versions = origin_versions;
+        }
+        if bootstrap.origin_versions.is_none() {
+            strip_origin_versions(&mut changes, Some(peer_site_id));
+        }
+        bootstrap.changes.extend(changes);
+        let reported = self.peer_reported_versions.entry(peer).or_insert(0);
+        *reported = (*reported).max(my_db_version);
+
+        if next.is_some() {
+            bootstrap.next = next;
+            if self.swarm.is_connected(&peer) {
+                self.request_state_snapshot(peer);
+            }
+            return;
+        }
+
+        let Some(bootstrap) = self.snapshot_bootstraps.remove(&peer) else {
+            return;
+        };
+        log::info!(
+            "Received state snapshot of {} changes from peer {peer} (as of db_version {since})",
+            bootstrap.changes.len(),
+        );
+
+        // Install before recording the peer as synced: if the queue is full
+        // the snapshot is dropped and the next round bootstraps again,
+        // rather than leaving us marked synced without the data.
+        let batch = RemoteBatch {
+            changes: bootstrap.changes,
+            catchup: None,
+            origin_versions: bootstrap.origin_versions,
+            push: None,
+        };
+        if let Err(e) = self.remote_changeset_tx.try_send(batch) {
+            log::warn!("Remote changeset queue full, dropping state snapshot: {e}");
+            return;
+        }
+
+        self.peer_db_versions.insert(peer, since);
+        self.emit_network_event(crate::network_status::NetworkEvent::PeerSynced {
+            peer_id: crate::network_status::PeerId(peer.to_string()),
+            db_version: since,
+        });
+        self.update_network_status();
+
+        let lamport_bump = if my_db_version > self.local_db_version {
+            self.local_db_version = my_db_version;
+            true
+        } else {
+            false
+        };
+        let db = self.db.clone();
+        let peer_str = peer.to_string();
+        tokio::spawn(async move {
+            if lamport_bump {
+                let _ = shadow::set_db_version(&db, my_db_version).await;
+            }
+            let _ = peer_tracker::upsert_peer_version(&db, &peer_str, &peer_site_id, since).await;
+        });
+
+        // Fetch whatever was written while the snapshot was in flight.
+        if since > 0 && self.swarm.is_connected(&peer) {
+            self.initiate_sync_for_peer(peer);
+        }
+    }
+
+    /// A snapshot request failed. An I/O failure before any page arrived is
+    /// what a responder that can't decode `StateSnapshot` produces, so fall
+    /// back to version vector catch-up with that peer; otherwise just drop
+    /// the partial snapshot and start over on the next round.
+    pub(super) fn handle_state_snapshot_failure(
+        &mut self,
+        peer: libp2p::PeerId,
+        request_id: request_response::OutboundRequestId,
+        error: &request_response::OutboundFailure,
+    ) {
+        if self
+            .snapshot_bootstraps
+            .get(&peer)
+            .is_none_or(|b| b.request_id != Some(request_id))
+        {
+            return;
+        }
+        let Some(bootstrap) = self.snapshot_bootstraps.remove(&peer) else {
+            return;
+        };
+        if bootstrap.since_db_version.is_none()
+            && matches!(error, request_response::OutboundFailure::Io(_))
+        {
+            log::info!(
+                "Peer {peer} does not serve state snapshots; falling back to version vector catch-up"
+            );
+            self.snapshot_unsupported.insert(peer);
+        }
+    }
+}
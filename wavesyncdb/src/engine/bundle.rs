@@ -0,0 +1,140 @@
+//! Exporting and importing offline bundles (see [`crate::bundle`]).
+//!
+//! An export reads changes the way a catch-up response does; an import is
+//! handled as a catch-up from the bundle's exporter — the same checks
+//! before applying, the same conflict resolution, and the exporter's
+//! watermarks adopted where the bundle leaves no gap.
+//!
+//! Without a passphrase a bundle's tag is a plain hash, so anyone can
+//! write its header. Its changes are still checked like any others, but
+//! the exporter's watermarks and version are only taken from bundles
+//! tagged with the group key.
+
+use super::*;
+
+use crate::bundle::{self as format, BundleHeader, BundleImport};
+use crate::protocol::OriginVersions;
+use crate::signing::ChangeSigner;
+
+impl EngineRunner {
+    /// Write every change past `since` as a bundle and reply with its
+    /// bytes and how many changes it holds.
+    pub(super) fn export_bundle(
+        &self,
+        since: OriginVersions,
+        encrypt: bool,
+        reply: oneshot::Sender<Result<(Vec<u8>, usize), String>>,
+    ) {
+        if !self.registry_is_ready {
+            let _ = reply.send(Err("the schema isn't registered yet".to_string()));
+            return;
+        }
+        let db = self.db.clone();
+        let registry = self.registry.clone();
+        let signer = self.signer.clone();
+        let key = self.group_key.clone();
+        // Captured before the changes are read, as for a catch-up response.
+        let header = BundleHeader {
+            site_id: self.site_id,
+            peer_id: self.local_peer_id.to_string(),
+            db_version: self.local_db_version,
+            topic: self.user_topic.clone(),
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            watermarks: self.local_origin_versions(),
+            since,
+            key_fingerprint: None,
+        };
+
+        tokio::spawn(async move {
+            let result = async {
+                let mut changes = shadow::get_changes_since(&db, &registry, 0, Some(&header.since))
+                    .await
+                    .map_err(|e| format!("failed to read changes: {e}"))?;
+                ChangeSigner::sign_for(Some(&signer), &mut changes);
+                let count = changes.len();
+                let bytes = format::encode(header, changes, key.as_ref(), encrypt)
+                    .map_err(|e| e.to_string())?;
+                log::info!("Exported a bundle of {count} change(s)");
+                Ok((bytes, count))
+            }
+            .await;
+            let _ = reply.send(result);
+        });
+    }
+
+    /// Merge a bundle, as a catch-up from its exporter.
+    pub(super) async fn import_bundle(&mut self, bytes: Vec<u8>) -> Result<BundleImport, String> {
+        if !self.registry_is_ready {
+            return Err("the schema isn't registered yet".to_string());
+        }
+        let (header, changes) = format::decode(&bytes, &self.user_topic, self.group_key.as_ref())
+            .map_err(|e| e.to_string())?;
+        if header.site_id == self.site_id {
+            return Err("the bundle was exported by this device".to_string());
+        }
+
+        let authenticated = self.group_key.is_some();
+
+        let ours = self.local_origin_versions();
+        let adoptable = format::adoptable_watermarks(&header, &ours);
+        // Without a gap before the bundle, we now hold everything the
+        // exporter did at export.
+        let complete = header.since.iter().all(|(site, v)| ours.get(site) >= *v);
+
+        let total = changes.len();
+        let batch = RemoteBatch {
+            origin_versions: authenticated.then_some(adoptable),
+            ..RemoteBatch::from(changes)
+        };
+        let merged = self
+            .handle_remote_batch(batch)
+            .await
+            .ok_or_else(|| "failed to apply the bundle's changes".to_string())?;
+
+        let refused = total - merged;
+        // Changes refused for now (a quarantine, a missing grant) would be
+        // skipped by every later catch-up from the exporter past this.
+        if authenticated && complete && refused == 0 {
+            self.record_exporter_version(&header).await;
+        }
+        log::info!(
+            "Imported a bundle from site {:?}: {merged} of {total} change(s) merged",
+            header.site_id
+        );
+        Ok(BundleImport {
+            watermarks: header.watermarks.clone(),
+            header,
+            merged,
+            refused,
+        })
+    }
+
+    /// Raise the exporter's peer version to its `db_version` at export,
+    /// as a completed catch-up from it would.
+    async fn record_exporter_version(&mut self, header: &BundleHeader) {
+        let known = peer_tracker::get_peer_version(&self.db, &header.peer_id)
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or(0);
+        if header.db_version > known
+            && let Err(e) = peer_tracker::upsert_peer_version(
+                &self.db,
+                &header.peer_id,
+                &header.site_id,
+                header.db_version,
+            )
+            .await
+        {
+            log::warn!("Failed to record the bundle exporter's version: {e}");
+        }
+        if let Ok(peer) = header.peer_id.parse::<libp2p::PeerId>()
+            && let Some(version) = self.peer_db_versions.get_mut(&peer)
+        {
+            *version = (*version).max(header.db_version);
+        }
+    }
+}
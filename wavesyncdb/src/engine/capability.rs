@@ -0,0 +1,258 @@
+//! Granting capabilities and enforcing them on remote changes (see
+//! [`crate::capability`]).
+//!
+//! Grants are exchanged after every handshake with a peer that announces
+//! them, and a newly learned one is passed on to every connected peer that
+//! does. Remote changes are checked against their author's grant before
+//! they are applied; the ones it doesn't allow are dropped and recorded.
+
+use super::*;
+
+use std::collections::HashSet;
+
+use crate::capability::{Capabilities, CapabilityGrant, Operation};
+use crate::protocol::{FEATURE_CAPABILITIES, SyncResponse};
+use crate::revocation::key_for_peer;
+
+impl EngineRunner {
+    /// Grant `capabilities` to `peer`, replacing any grant it had.
+    pub(super) async fn grant_capabilities(
+        &mut self,
+        peer: libp2p::PeerId,
+        capabilities: Capabilities,
+    ) -> Result<(), String> {
+        if peer == *self.swarm.local_peer_id() {
+            return Err("a device can't grant capabilities to itself".to_string());
+        }
+        if !self.capabilities.can_grant(&self.signer.public_key()) {
+            return Err("this device isn't a capability issuer".to_string());
+        }
+        let subject = key_for_peer(&peer)
+            .ok_or_else(|| format!("peer {peer} has no ed25519 identity key"))?;
+        if self.revocations.is_revoked(&subject) {
+            return Err(format!("peer {peer} is revoked"));
+        }
+
+        // The newest grant wins, so never issue one older than the last.
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let issued_at = self
+            .capabilities
+            .entries()
+            .iter()
+            .find(|g| g.subject == subject)
+            .map_or(now, |held| now.max(held.issued_at + 1));
+        log::info!("Granting role {:?} to device {peer}", capabilities.role);
+        let grant = CapabilityGrant::issue(&self.signer, subject, capabilities, issued_at);
+        self.learn_grants(vec![grant], None).await;
+        Ok(())
+    }
+
+    /// Add `incoming` to our grants, then persist and pass on the ones we
+    /// took — to every peer but `from`, which sent them. Grants issued by
+    /// revoked devices are ignored.
+    async fn learn_grants(&mut self, incoming: Vec<CapabilityGrant>, from: Option<libp2p::PeerId>) {
+        let incoming: Vec<CapabilityGrant> = incoming
+            .into_iter()
+            .filter(|g| !self.revocations.is_revoked(&g.sig.key))
+            .collect();
+        let added = self.capabilities.merge(incoming);
+        if added.is_empty() {
+            return;
+        }
+
+        for grant in &added {
+            if let Err(e) = peer_tracker::save_capability_grant(&self.db, grant).await {
+                log::warn!("Failed to persist a capability grant: {e}");
+            }
+        }
+        self.update_network_status();
+
+        let others: Vec<libp2p::PeerId> = self
+            .peers
+            .keys()
+            .copied()
+            .filter(|p| Some(*p) != from && self.peer_supports(p, FEATURE_CAPABILITIES))
+            .collect();
+        for peer in others {
+            self.send_capabilities(peer, added.clone());
+        }
+    }
+
+    /// Remove the remote changes their author's grant doesn't allow before
+    /// they are applied, and record each one. Returns the sites whose
+    /// changes were refused.
+    pub(super) async fn drop_unauthorized_changes(
+        &mut self,
+        changes: &mut Vec<ColumnChange>,
+    ) -> HashSet<NodeId> {
+        let mut refused_sites = HashSet::new();
+        if self.capabilities.is_empty() {
+            return refused_sites;
+        }
+
+        let mut refused = 0u64;
+        // Whether each row touched exists here, looked up once per batch.
+        let mut rows: HashMap<(String, String), bool> = HashMap::new();
+        let mut kept = Vec::with_capacity(changes.len());
+        for change in changes.drain(..) {
+            let Some(caps) = self
+                .capabilities
+                .for_site(&change.site_id, &self.site_keys)
+                .cloned()
+            else {
+                kept.push(change);
+                continue;
+            };
+
+            let row = (change.table.0.clone(), change.pk.0.clone());
+            let row_exists = match rows.get(&row) {
+                Some(exists) => *exists,
+                None => {
+                    let exists = shadow::get_clock_entries_for_row(&self.db, &row.0, &row.1)
+                        .await
+                        .is_ok_and(|entries| {
+                            !entries.is_empty() && entries.iter().all(|e| e.cid != "__deleted")
+                        });
+                    rows.insert(row, exists);
+                    exists
+                }
+            };
+
+            let operation = Operation::of(&change, row_exists);
+            if caps.allows(&change.table.0, operation) {
+                kept.push(change);
+                continue;
+            }
+            refused += 1;
+            refused_sites.insert(change.site_id);
+            if let Err(e) =
+                peer_tracker::record_capability_denial(&self.db, &change, &caps.role, operation)
+                    .await
+            {
+                log::warn!("Failed to record a capability denial: {e}");
+            }
+        }
+        *changes = kept;
+
+        if refused > 0 {
+            self.diagnostics
+                .unauthorized_changes_rejected
+                .fetch_add(refused, std::sync::atomic::Ordering::Relaxed);
+            log::warn!(
+                "Refusing {refused} remote changes not allowed by their grants, from sites {refused_sites:?}"
+            );
+        }
+        refused_sites
+    }
+
+    /// Offer our grants to `peer` once its handshake has settled. The list
+    /// goes even when empty: the reply carries the peer's.
+    pub(super) fn exchange_capabilities(&mut self, peer: libp2p::PeerId) {
+        if self.peer_supports(&peer, FEATURE_CAPABILITIES) {
+            self.send_capabilities(peer, self.capabilities.entries());
+        }
+    }
+
+    fn send_capabilities(&mut self, peer: libp2p::PeerId, grants: Vec<CapabilityGrant>) {
+        let auth = self.request_auth(&peer);
+        let mut req = SyncRequest::Capabilities {
+            grants,
+            seq: auth.as_ref().and_then(|(_, seq)| *seq),
+            hmac: None,
+        };
+
+        if let Some((ref key, _)) = auth
+            && let Ok(bytes) = serde_json::to_vec(&req)
+        {
+            let tag = key.mac(&bytes);
+            if let SyncRequest::Capabilities { ref mut hmac, .. } = req {
+                *hmac = Some(tag);
+            }
+        }
+
+        self.swarm.behaviour_mut().snapshot.send_request(&peer, req);
+    }
+
+    /// Verify HMAC, take the grants that are newer than ours and answer
+    /// with the ones that are newer than the peer's.
+    pub(super) async fn handle_capabilities_request(
+        &mut self,
+        peer: libp2p::PeerId,
+        channel: request_response::ResponseChannel<SyncResponse>,
+        grants: Vec<CapabilityGrant>,
+        seq: Option<u64>,
+        req_hmac: Option<[u8; 32]>,
+    ) {
+        if self.refuse_revoked(peer) {
+            return;
+        }
+        if self.group_key.is_some() {
+            let Some(tag) = req_hmac else {
+                log::debug!("Rejecting unauthenticated capabilities from peer {peer}");
+                return;
+            };
+            let verify_req = SyncRequest::Capabilities {
+                grants: grants.clone(),
+                seq,
+                hmac: None,
+            };
+            if let Ok(bytes) = serde_json::to_vec(&verify_req)
+                && !self.verify_request(peer, &bytes, &tag, seq)
+            {
+                log::debug!("Rejecting capabilities with invalid HMAC from peer {peer}");
+                return;
+            }
+        }
+
+        let mut resp = SyncResponse::Capabilities {
+            grants: self.capabilities.newer_than(&grants),
+            hmac: None,
+        };
+        if let Some(key) = self.response_key(&peer)
+            && let Ok(bytes) = serde_json::to_vec(&resp)
+        {
+            let tag = key.mac(&bytes);
+            if let SyncResponse::Capabilities { ref mut hmac, .. } = resp {
+                *hmac = Some(tag);
+            }
+        }
+        let resp_tx = self.snapshot_resp_tx.clone();
+        tokio::spawn(async move {
+            let _ = resp_tx.send((channel, resp)).await;
+        });
+
+        self.learn_grants(grants, Some(peer)).await;
+    }
+
+    /// Verify HMAC and take the grants that are newer than ours.
+    pub(super) async fn handle_capabilities_response(
+        &mut self,
+        peer: libp2p::PeerId,
+        grants: Vec<CapabilityGrant>,
+        resp_hmac: Option<[u8; 32]>,
+    ) {
+        if self.refuse_revoked(peer) {
+            return;
+        }
+        if self.group_key.is_some() {
+            let Some(tag) = resp_hmac else {
+                log::debug!("Rejecting unauthenticated capabilities response from peer {peer}");
+                return;
+            };
+            let verify_resp = SyncResponse::Capabilities {
+                grants: grants.clone(),
+                hmac: None,
+            };
+            if let Ok(bytes) = serde_json::to_vec(&verify_resp)
+                && !self.verify_response(peer, &bytes, &tag)
+            {
+                log::debug!("Rejecting capabilities response with invalid HMAC from peer {peer}");
+                return;
+            }
+        }
+        self.learn_grants(grants, Some(peer)).await;
+    }
+}
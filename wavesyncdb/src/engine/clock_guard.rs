@@ -0,0 +1,138 @@
+//! Bounding how far remote changes may advance our clocks (see
+//! [`crate::conflict::exceeds_clock_bound`]).
+//!
+//! A change whose clock runs more than `max_clock_jump` past the highest
+//! one we hold for its row is refused. If it is signed with the key pinned
+//! for its site (see [`crate::signing`]), its site is quarantined too:
+//! every later change written as that site is refused, until the
+//! quarantine is lifted. Quarantines are kept in `_wavesync_quarantine`.
+//!
+//! An unsigned change proves nothing about who wrote it — anyone in the
+//! group can write one under any site id — so it is only dropped: the site
+//! it names stays trusted, rather than being shut out by whoever forged it.
+
+use super::*;
+
+use std::collections::HashSet;
+
+use crate::conflict::exceeds_clock_bound;
+use crate::network_status::NetworkEvent;
+
+impl EngineRunner {
+    /// Remove the remote changes of quarantined sites, and those that run a
+    /// clock too far ahead, before they are applied. The sites of the
+    /// latter are quarantined when the change verified against their pinned
+    /// key. Returns the sites whose changes were refused.
+    pub(super) async fn drop_inflated_changes(
+        &mut self,
+        changes: &mut Vec<ColumnChange>,
+    ) -> HashSet<NodeId> {
+        let max_jump = self.config.max_clock_jump;
+        // The highest clock held for each row touched, looked up once per
+        // batch.
+        let mut rows: HashMap<(String, String), u64> = HashMap::new();
+        let mut inflated = vec![false; changes.len()];
+        for (i, change) in changes.iter().enumerate() {
+            if self.quarantined_sites.contains(&change.site_id)
+                || self.registry.get(&change.table.0).is_none()
+            {
+                continue;
+            }
+            let row = (change.table.0.clone(), change.pk.0.clone());
+            let local_max = match rows.get(&row) {
+                Some(max) => *max,
+                None => {
+                    let max = shadow::get_clock_entries_for_row(&self.db, &row.0, &row.1)
+                        .await
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|e| e.col_version)
+                        .max()
+                        .unwrap_or(0);
+                    rows.insert(row, max);
+                    max
+                }
+            };
+            let clock = change.col_version.max(change.cl);
+            if !exceeds_clock_bound(clock, local_max, max_jump) {
+                continue;
+            }
+            // Forged changes are gone by now, so a signature here is one
+            // that verified.
+            let attributed = change
+                .sig
+                .as_ref()
+                .is_some_and(|sig| self.site_keys.get(&change.site_id) == Some(&sig.key));
+            if attributed {
+                self.quarantine_site(change, clock, local_max).await;
+            } else {
+                log::warn!(
+                    "Dropping unsigned change with clock {clock} on {}/{}/{}: runs past our {local_max}",
+                    change.table.0,
+                    change.pk.0,
+                    change.cid.0
+                );
+                inflated[i] = true;
+            }
+        }
+
+        let mut refused_sites = HashSet::new();
+        let before = changes.len();
+        let mut inflated = inflated.into_iter();
+        changes.retain(|c| {
+            let refused =
+                inflated.next().unwrap_or(false) || self.quarantined_sites.contains(&c.site_id);
+            if refused {
+                refused_sites.insert(c.site_id);
+            }
+            !refused
+        });
+        let refused = (before - changes.len()) as u64;
+        if refused > 0 {
+            self.diagnostics
+                .inflated_clocks_rejected
+                .fetch_add(refused, std::sync::atomic::Ordering::Relaxed);
+            log::warn!(
+                "Refusing {refused} remote changes with inflated clocks or from quarantined sites {refused_sites:?}"
+            );
+        }
+        refused_sites
+    }
+
+    async fn quarantine_site(&mut self, change: &ColumnChange, clock: u64, local_max: u64) {
+        let site = change.site_id;
+        log::warn!(
+            "Quarantining site {site:?}: clock {clock} on {}/{}/{} runs past our {local_max}",
+            change.table.0,
+            change.pk.0,
+            change.cid.0
+        );
+        if let Err(e) = peer_tracker::quarantine_site(&self.db, change, clock, local_max).await {
+            log::warn!("Failed to persist the quarantine of site {site:?}: {e}");
+        }
+        self.quarantined_sites.insert(site);
+        let peer_id = self
+            .site_keys
+            .get(&site)
+            .and_then(crate::revocation::peer_for_key)
+            .map(|peer| crate::network_status::PeerId(peer.to_string()));
+        self.emit_network_event(NetworkEvent::SiteQuarantined {
+            site_id: site,
+            peer_id,
+            clock,
+            local_max,
+        });
+    }
+
+    /// Accept `site`'s changes again.
+    pub(super) async fn release_quarantine(&mut self, site: NodeId) -> Result<bool, String> {
+        let released = peer_tracker::release_quarantine(&self.db, &site)
+            .await
+            .map_err(|e| e.to_string())?;
+        let held = self.quarantined_sites.remove(&site);
+        if released || held {
+            log::info!("Released site {site:?} from quarantine");
+        }
+        Ok(released || held)
+    }
+}
@@ -24,6 +24,11 @@ impl EngineRunner {
                 // Reset peer versions to trigger full re-sync
                 self.peer_db_versions.clear();
                 self.peer_reported_versions.clear();
+                // A full re-sync starts every peer from 0, so half-finished
+                // catch-ups are moot.
+                for peer in std::mem::take(&mut self.catchup_cursors).into_keys() {
+                    let _ = peer_tracker::clear_catchup_cursor(&self.db, &peer.to_string()).await;
+                }
                 self.pending_sync_peers.clear();
                 self.dialing_peers.clear();
                 self.pending_rendezvous_dials.clear();
@@ -53,6 +58,7 @@ impl EngineRunner {
             }
             EngineCommand::SetPeerIdentity(app_id) => {
                 self.local_app_id = app_id.clone();
+                self.local_identity_endorsement = None;
                 if let Some(ref id) = app_id {
                     self.announce_identity_to_verified_peers(id);
                 }
@@ -62,6 +68,50 @@ impl EngineRunner {
                 self.set_mdns_enabled(enabled);
                 false
             }
+            EngineCommand::RotatePassphrase { passphrase, reply } => {
+                let _ = reply.send(self.rotate_passphrase(&passphrase, None).await);
+                false
+            }
+            EngineCommand::UpgradeKeyDerivation { reply } => {
+                let _ = reply.send(self.upgrade_key_derivation().await);
+                false
+            }
+            EngineCommand::RevokeDevice { peer, reply } => {
+                let _ = reply.send(self.revoke_device(peer).await);
+                false
+            }
+            EngineCommand::GrantCapabilities {
+                peer,
+                capabilities,
+                reply,
+            } => {
+                let _ = reply.send(self.grant_capabilities(peer, capabilities).await);
+                false
+            }
+            EngineCommand::SetEndorsedPeerIdentity { endorsement, reply } => {
+                let _ = reply.send(self.set_endorsed_identity(endorsement));
+                false
+            }
+            EngineCommand::ReleaseQuarantine { site, reply } => {
+                let _ = reply.send(self.release_quarantine(site).await);
+                false
+            }
+            EngineCommand::StartPairing { ttl, reply } => {
+                let _ = reply.send(self.start_pairing(ttl));
+                false
+            }
+            EngineCommand::ExportBundle {
+                since,
+                encrypt,
+                reply,
+            } => {
+                self.export_bundle(since, encrypt, reply);
+                false
+            }
+            EngineCommand::ImportBundle { bytes, reply } => {
+                let _ = reply.send(self.import_bundle(bytes).await);
+                false
+            }
             EngineCommand::Shutdown => {
                 log::info!("Engine shutdown requested");
                 true
@@ -0,0 +1,156 @@
+//! Multi-hop forwarding of pushed changesets.
+//!
+//! The origin of a write pushes it to its direct peers only. A peer that
+//! receives a push it hasn't seen before forwards it to its own peers, so in
+//! a chain `laptop ↔ phone ↔ tablet` the tablet gets the laptop's edit in
+//! real time instead of at the next version vector round.
+//!
+//! Changesets are identified by `(site_id, db_version)` of their origin, and
+//! each node remembers the ones it has recently applied or written so echoes
+//! die out. `Push::hops` bounds how far a changeset travels in meshes where
+//! the seen set alone would still let it loop (e.g. after eviction).
+//!
+//! Forwarded pushes carry `hops`, which older builds would drop when
+//! re-serializing for HMAC verification, so they only go to peers whose
+//! hello announced [`FEATURE_GOSSIP`].
+
+use super::*;
+use crate::protocol::{FEATURE_GOSSIP, FEATURE_SIGNED_CHANGES};
+use std::collections::HashSet;
+
+/// How many changeset ids to remember. At one changeset per write, this
+/// covers the last few minutes of even a busy group.
+const SEEN_CHANGESETS_CAPACITY: usize = 4096;
+
+/// Recently seen changesets, oldest evicted first.
+#[derive(Debug, Default)]
+pub(crate) struct SeenChangesets {
+    set: HashSet<(NodeId, u64)>,
+    order: VecDeque<(NodeId, u64)>,
+}
+
+impl SeenChangesets {
+    /// Whether the changeset `(site_id, db_version)` was seen.
+    pub fn contains(&self, site_id: NodeId, db_version: u64) -> bool {
+        self.set.contains(&(site_id, db_version))
+    }
+
+    /// Remember a changeset. Returns `false` if it was already known.
+    pub fn insert(&mut self, site_id: NodeId, db_version: u64) -> bool {
+        if !self.set.insert((site_id, db_version)) {
+            return false;
+        }
+        self.order.push_back((site_id, db_version));
+        if self.order.len() > SEEN_CHANGESETS_CAPACITY
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.set.remove(&oldest);
+        }
+        true
+    }
+}
+
+impl EngineRunner {
+    /// Forward a changeset received from `from` to every other connected
+    /// peer that takes gossip, unless it has travelled `gossip_max_hops`.
+    pub(super) fn forward_changeset(
+        &mut self,
+        from: libp2p::PeerId,
+        changeset: &SyncChangeset,
+        hops: Option<u8>,
+    ) {
+        let hops = hops.unwrap_or(0);
+        if hops >= self.config.gossip_max_hops {
+            return;
+        }
+
+        let peer_ids: Vec<libp2p::PeerId> = self
+            .peer_handshakes
+            .iter()
+            .filter(|(peer, hs)| {
+                matches!(hs, handshake::Handshake::Compatible(hello) if hello.supports(FEATURE_GOSSIP))
+                    && **peer != from
+                    && self.swarm.is_connected(peer)
+                    && !self.rejected_peers.contains(*peer)
+                    && !self.infrastructure_peers.contains(*peer)
+            })
+            .map(|(peer, _)| *peer)
+            .collect();
+        if peer_ids.is_empty() {
+            return;
+        }
+
+        for peer_id in &peer_ids {
+            let auth = self.request_auth(peer_id);
+            // Forwarded changes keep their authors' signatures for peers
+            // that check them.
+            let mut outgoing = changeset.clone();
+            if !self.peer_supports(peer_id, FEATURE_SIGNED_CHANGES) {
+                crate::signing::strip_signatures(&mut outgoing.changes);
+            }
+            let mut req = SyncRequest::Push {
+                changeset: outgoing,
+                topic: self.topic_name.clone(),
+                hops: Some(hops + 1),
+                prev_db_version: None,
+                seq: auth.as_ref().and_then(|(_, seq)| *seq),
+                sealed: None,
+                hmac: None,
+            };
+
+            if let Some((ref key, _)) = auth
+                && let Ok(bytes) = serde_json::to_vec(&req)
+            {
+                let tag = key.mac(&bytes);
+                if let SyncRequest::Push { ref mut hmac, .. } = req {
+                    *hmac = Some(tag);
+                }
+            }
+            self.seal_request(peer_id, &mut req);
+
+            self.swarm
+                .behaviour_mut()
+                .snapshot
+                .send_request(peer_id, req);
+        }
+
+        self.diagnostics
+            .changesets_forwarded
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        log::debug!(
+            "Forwarded changeset (site={:?}, db_version={}, hop {}) to {} peers",
+            changeset.site_id,
+            changeset.db_version,
+            hops + 1,
+            peer_ids.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seen_changesets_dedups() {
+        let mut seen = SeenChangesets::default();
+        let site = NodeId([1u8; 16]);
+        assert!(seen.insert(site, 1));
+        assert!(!seen.insert(site, 1));
+        assert!(seen.insert(site, 2));
+        assert!(seen.insert(NodeId([2u8; 16]), 1));
+        assert!(seen.contains(site, 2));
+    }
+
+    #[test]
+    fn test_seen_changesets_evicts_oldest() {
+        let mut seen = SeenChangesets::default();
+        let site = NodeId([1u8; 16]);
+        for v in 0..=SEEN_CHANGESETS_CAPACITY as u64 {
+            seen.insert(site, v);
+        }
+        assert!(!seen.contains(site, 0));
+        assert!(seen.contains(site, 1));
+        assert!(seen.contains(site, SEEN_CHANGESETS_CAPACITY as u64));
+    }
+}
@@ -0,0 +1,276 @@
+//! Capability handshake: exchange [`PeerHello`]s before syncing.
+//!
+//! Sync with a peer is gated on its hello. `initiate_sync_for_peer` sends
+//! ours on first contact and returns; the reply (or the peer's own hello
+//! request) settles the [`Handshake`] state, and a compatible outcome kicks
+//! off the sync that was held back.
+//!
+//! Builds that predate the handshake can't decode `Hello` and reset the
+//! stream. Such peers are synced with as before, minus the optional
+//! features they can't have.
+
+use super::*;
+use crate::protocol::{FEATURE_SEALED_PAYLOADS, FEATURE_STATE_SNAPSHOT, PeerHello, SyncResponse};
+
+/// Where the capability handshake with a connected peer stands.
+#[derive(Debug, Clone)]
+pub(crate) enum Handshake {
+    /// Our hello is in flight.
+    Pending(request_response::OutboundRequestId),
+    /// The peer predates the handshake.
+    Legacy,
+    /// The peer's hello checked out.
+    Compatible(PeerHello),
+    /// The peer's hello ruled out syncing; the reason was reported.
+    Incompatible(String),
+}
+
+impl EngineRunner {
+    /// Our hello, reflecting the tables registered right now.
+    fn local_hello(&self) -> PeerHello {
+        let mut hello = PeerHello::local(&self.registry, self.config.app_version.clone());
+        // Without encryption we still open sealed changes, but don't ask
+        // for them.
+        if !self.config.encrypt_payloads {
+            hello.features.retain(|f| f != FEATURE_SEALED_PAYLOADS);
+        }
+        hello
+    }
+
+    /// Our hello for `peer`, carrying our session nonce for it.
+    fn hello_for(&mut self, peer: libp2p::PeerId) -> PeerHello {
+        PeerHello {
+            session_nonce: self.session_nonce(peer),
+            ..self.local_hello()
+        }
+    }
+
+    /// Whether `peer_id`'s hello announced `feature`. Optional request
+    /// fields may only go to such peers: older builds drop them when
+    /// re-serializing a request to check its HMAC.
+    pub(super) fn peer_supports(&self, peer_id: &libp2p::PeerId, feature: &str) -> bool {
+        matches!(
+            self.peer_handshakes.get(peer_id),
+            Some(Handshake::Compatible(hello)) if hello.supports(feature)
+        )
+    }
+
+    /// Whether sync with `peer_id` may proceed. Sends our hello on first
+    /// contact; sync is retried once the handshake settles.
+    pub(super) fn handshake_ready(&mut self, peer_id: libp2p::PeerId) -> bool {
+        match self.peer_handshakes.get(&peer_id) {
+            Some(Handshake::Legacy | Handshake::Compatible(_)) => true,
+            Some(Handshake::Pending(_) | Handshake::Incompatible(_)) => false,
+            None => {
+                self.send_hello(peer_id);
+                false
+            }
+        }
+    }
+
+    fn send_hello(&mut self, peer_id: libp2p::PeerId) {
+        let mut req = SyncRequest::Hello {
+            hello: self.hello_for(peer_id),
+            topic: self.topic_name.clone(),
+            hmac: None,
+        };
+
+        if let Some(ref gk) = self.group_key
+            && let Ok(bytes) = serde_json::to_vec(&req)
+        {
+            let tag = gk.mac(&bytes);
+            if let SyncRequest::Hello { ref mut hmac, .. } = req {
+                *hmac = Some(tag);
+            }
+        }
+
+        let request_id = self
+            .swarm
+            .behaviour_mut()
+            .snapshot
+            .send_request(&peer_id, req);
+        self.peer_handshakes
+            .insert(peer_id, Handshake::Pending(request_id));
+    }
+
+    /// Record a peer's hello, reporting it once if we can't sync with it.
+    /// Returns whether the peer is compatible.
+    fn record_peer_hello(&mut self, peer: libp2p::PeerId, hello: PeerHello) -> bool {
+        match self.local_hello().check_compatible(&hello) {
+            Ok(()) => {
+                if !hello.supports(FEATURE_STATE_SNAPSHOT) {
+                    self.snapshot_unsupported.insert(peer);
+                }
+                self.peer_handshakes
+                    .insert(peer, Handshake::Compatible(hello));
+                true
+            }
+            Err(why) => {
+                let reason = why.to_string();
+                let already_reported = matches!(
+                    self.peer_handshakes.get(&peer),
+                    Some(Handshake::Incompatible(r)) if *r == reason
+                );
+                log::warn!("Not syncing with peer {peer}: {reason}");
+                self.peer_handshakes
+                    .insert(peer, Handshake::Incompatible(reason.clone()));
+                if !already_reported {
+                    self.emit_network_event(
+                        crate::network_status::NetworkEvent::PeerIncompatible {
+                            peer_id: crate::network_status::PeerId(peer.to_string()),
+                            reason,
+                        },
+                    );
+                }
+                false
+            }
+        }
+    }
+
+    /// Verify HMAC + topic, record the peer's hello, and answer with ours —
+    /// even when incompatible, so the peer can report why.
+    pub(super) fn handle_hello_request(
+        &mut self,
+        peer: libp2p::PeerId,
+        channel: request_response::ResponseChannel<SyncResponse>,
+        hello: PeerHello,
+        peer_topic: String,
+        req_hmac: Option<[u8; 32]>,
+    ) {
+        if self.refuse_revoked(peer) {
+            return;
+        }
+        if let Some(gk) = self.group_key.clone() {
+            let tag = match req_hmac {
+                Some(t) => t,
+                None => {
+                    log::debug!("Rejecting unauthenticated hello from peer {peer}");
+                    return;
+                }
+            };
+            let verify_req = SyncRequest::Hello {
+                hello: hello.clone(),
+                topic: peer_topic.clone(),
+                hmac: None,
+            };
+            if let Ok(bytes) = serde_json::to_vec(&verify_req)
+                && !gk.verify(&bytes, &tag)
+            {
+                // A peer that missed a rotation gets it instead of an
+                // answer, and says hello again under the new key.
+                if !self.bring_over_previous_epoch(peer, &hello, &bytes, &tag) {
+                    log::debug!("Rejecting hello with invalid HMAC from peer {peer}");
+                }
+                return;
+            }
+        }
+
+        if peer_topic != self.topic_name {
+            log::debug!(
+                "Ignoring hello from peer {peer}: topic mismatch (theirs={peer_topic}, ours={})",
+                self.topic_name
+            );
+            self.reject_peer(peer);
+            return;
+        }
+
+        // The session starts as soon as we have both nonces, even while
+        // our own hello is still in flight.
+        self.note_peer_nonce(peer, hello.session_nonce);
+
+        // An inbound hello settles the handshake just like a reply to ours,
+        // unless ours is still in flight — its reply will settle it then.
+        if !matches!(self.peer_handshakes.get(&peer), Some(Handshake::Pending(_))) {
+            self.record_peer_hello(peer, hello);
+        }
+
+        let mut resp = SyncResponse::Hello {
+            hello: self.hello_for(peer),
+            hmac: None,
+        };
+        if let Some(ref gk) = self.group_key
+            && let Ok(bytes) = serde_json::to_vec(&resp)
+        {
+            let tag = gk.mac(&bytes);
+            if let SyncResponse::Hello { ref mut hmac, .. } = resp {
+                *hmac = Some(tag);
+            }
+        }
+
+        let resp_tx = self.snapshot_resp_tx.clone();
+        tokio::spawn(async move {
+            let _ = resp_tx.send((channel, resp)).await;
+        });
+    }
+
+    /// Settle the handshake from the peer's reply and start the sync that
+    /// was waiting on it.
+    pub(super) fn handle_hello_response(
+        &mut self,
+        peer: libp2p::PeerId,
+        hello: PeerHello,
+        resp_hmac: Option<[u8; 32]>,
+    ) {
+        if let Some(ref gk) = self.group_key {
+            let tag = match resp_hmac {
+                Some(t) => t,
+                None => {
+                    log::debug!("Rejecting unauthenticated hello response from peer {peer}");
+                    return;
+                }
+            };
+            let verify_resp = SyncResponse::Hello {
+                hello: hello.clone(),
+                hmac: None,
+            };
+            if let Ok(bytes) = serde_json::to_vec(&verify_resp)
+                && !gk.verify(&bytes, &tag)
+            {
+                log::debug!("Rejecting hello response with invalid HMAC from peer {peer}");
+                return;
+            }
+        }
+
+        self.note_peer_nonce(peer, hello.session_nonce);
+        log::info!(
+            "Handshake with peer {peer}: protocol v{}, app version {}, features {:?}",
+            hello.protocol_version,
+            hello.app_version.as_deref().unwrap_or("unknown"),
+            hello.features,
+        );
+        if self.record_peer_hello(peer, hello) {
+            self.exchange_revocations(peer);
+            self.exchange_capabilities(peer);
+            if self.registry_is_ready {
+                self.initiate_sync_for_peer(peer);
+            }
+        }
+    }
+
+    /// Our hello failed. An I/O failure is what a peer that can't decode
+    /// `Hello` produces, so treat it as a legacy peer and sync anyway; other
+    /// failures (timeout, closed connection) retry on the next sync round.
+    pub(super) fn handle_hello_failure(
+        &mut self,
+        peer: libp2p::PeerId,
+        request_id: request_response::OutboundRequestId,
+        error: &request_response::OutboundFailure,
+    ) {
+        if !matches!(
+            self.peer_handshakes.get(&peer),
+            Some(Handshake::Pending(id)) if *id == request_id
+        ) {
+            return;
+        }
+        if matches!(error, request_response::OutboundFailure::Io(_)) {
+            log::info!("Peer {peer} predates the capability handshake; syncing without it");
+            self.peer_handshakes.insert(peer, Handshake::Legacy);
+            self.snapshot_unsupported.insert(peer);
+            if self.registry_is_ready && self.swarm.is_connected(&peer) {
+                self.initiate_sync_for_peer(peer);
+            }
+        } else {
+            self.peer_handshakes.remove(&peer);
+        }
+    }
+}
@@ -2,18 +2,34 @@
 
 use super::*;
 
+use crate::identity::{IdentityClaim, IdentityEndorsement};
+use crate::protocol::FEATURE_SIGNED_IDENTITIES;
+
 impl EngineRunner {
-    /// Send an `IdentityAnnounce` to a single verified peer.
+    /// Send an `IdentityAnnounce` to a single verified peer, signed when
+    /// the peer checks signed identities.
     pub(super) fn send_identity_announce(&mut self, peer_id: libp2p::PeerId, app_id: &str) {
+        let claim = self
+            .peer_supports(&peer_id, FEATURE_SIGNED_IDENTITIES)
+            .then(|| {
+                let endorsement = self
+                    .local_identity_endorsement
+                    .clone()
+                    .filter(|e| e.app_id == app_id);
+                IdentityClaim::issue(&self.signer, app_id, endorsement)
+            });
+        let auth = self.request_auth(&peer_id);
         let mut req = SyncRequest::IdentityAnnounce {
             app_id: app_id.to_string(),
+            claim,
+            seq: auth.as_ref().and_then(|(_, seq)| *seq),
             hmac: None,
         };
 
-        if let Some(ref gk) = self.group_key
+        if let Some((ref key, _)) = auth
             && let Ok(bytes) = serde_json::to_vec(&req)
         {
-            let tag = gk.mac(&bytes);
+            let tag = key.mac(&bytes);
             if let SyncRequest::IdentityAnnounce { ref mut hmac, .. } = req {
                 *hmac = Some(tag);
             }
@@ -32,4 +48,26 @@ impl EngineRunner {
             self.send_identity_announce(peer_id, app_id);
         }
     }
+
+    /// Take on the identity `endorsement` vouches for and announce it.
+    pub(super) fn set_endorsed_identity(
+        &mut self,
+        endorsement: IdentityEndorsement,
+    ) -> Result<(), String> {
+        if endorsement.device != self.signer.public_key() {
+            return Err("the endorsement is for another device's key".to_string());
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if !endorsement.verify(now) {
+            return Err("the endorsement's signature is invalid or it has expired".to_string());
+        }
+        let app_id = endorsement.app_id.clone();
+        self.local_app_id = Some(app_id.clone());
+        self.local_identity_endorsement = Some(endorsement);
+        self.announce_identity_to_verified_peers(&app_id);
+        Ok(())
+    }
 }
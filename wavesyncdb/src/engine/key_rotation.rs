@@ -0,0 +1,375 @@
+//! Rotating the group key (see [`crate::rotation`]).
+//!
+//! A rotation is announced to every connected peer that accepts one, under
+//! the key it replaces and with the new passphrase sealed to the peer's
+//! identity, and each peer that adopts it passes it on the same way. Adopting a key moves the engine to the key's topic and rendezvous
+//! namespace, moves the relay registrations made under the old topic, and
+//! redoes the handshake with connected peers under the new key.
+//!
+//! Until the grace window closes the previous key and topic are accepted
+//! too: a peer still on them is sent the rotation when its hello arrives,
+//! and its messages — pushes a relay held while it was offline, say — are
+//! still verified and applied.
+
+use super::*;
+
+use crate::auth::GroupSalt;
+use crate::protocol::{FEATURE_ARGON2ID_KDF, FEATURE_KEY_ROTATION, PeerHello, SyncResponse};
+use crate::revocation::key_for_peer;
+use crate::rotation::{KeyGrant, SignedRotation, supersedes};
+
+/// The fields of a [`SyncRequest::KeyRotation`].
+pub(super) struct ReceivedRotation {
+    pub(super) rotation: SignedRotation,
+    pub(super) grant: KeyGrant,
+    pub(super) hmac: Option<[u8; 32]>,
+}
+
+impl EngineRunner {
+    /// The key replaced by the last rotation, while its grace window lasts.
+    pub(super) fn previous_key(&self) -> Option<GroupKey> {
+        self.key_epoch.as_ref()?.previous_key()
+    }
+
+    /// Whether `peer_topic` is our topic, or the previous key's during the
+    /// grace window.
+    pub(super) fn accepts_topic(&self, peer_topic: &str) -> bool {
+        peer_topic == self.topic_name
+            || self
+                .previous_key()
+                .is_some_and(|gk| gk.derive_topic(&self.user_topic) == peer_topic)
+    }
+
+    /// Connected peers a rotation can go to: those that accept one — and
+    /// can derive its key, when it is `salted` — minus revoked devices and
+    /// `except`.
+    fn rotation_peers(&self, except: Option<libp2p::PeerId>, salted: bool) -> Vec<libp2p::PeerId> {
+        self.peers
+            .keys()
+            .copied()
+            .filter(|p| Some(*p) != except)
+            .filter(|p| self.peer_supports(p, FEATURE_KEY_ROTATION) && !self.is_revoked_peer(p))
+            .filter(|p| !salted || self.peer_supports(p, FEATURE_ARGON2ID_KDF))
+            .collect()
+    }
+
+    /// Rotate the group key to `passphrase`: announce it to connected peers
+    /// under the current key, then switch. `grace_secs` overrides how long
+    /// the group keeps accepting the current key. Returns the new epoch.
+    pub(super) async fn rotate_passphrase(
+        &mut self,
+        passphrase: &str,
+        grace_secs: Option<u64>,
+    ) -> Result<u64, String> {
+        let (Some(current), Some(state)) = (self.group_key.clone(), self.key_epoch.as_ref()) else {
+            return Err("no passphrase is configured to rotate from".to_string());
+        };
+        if passphrase.is_empty() {
+            return Err("the new passphrase is empty".to_string());
+        }
+        if passphrase == state.passphrase {
+            return Err("the new passphrase is the one in use".to_string());
+        }
+        let salt = state.salt;
+        Ok(self.rotate_key(current, passphrase, salt, grace_secs).await)
+    }
+
+    /// Move a v1 group to a v2 key: rotate to the same passphrase under a
+    /// fresh [`GroupSalt`]. Returns the new epoch.
+    pub(super) async fn upgrade_key_derivation(&mut self) -> Result<u64, String> {
+        let (Some(current), Some(state)) = (self.group_key.clone(), self.key_epoch.as_ref()) else {
+            return Err("no passphrase is configured to upgrade".to_string());
+        };
+        if state.salt.is_some() {
+            return Err("the group key is already derived with Argon2id".to_string());
+        }
+        let passphrase = state.passphrase.clone();
+        Ok(self
+            .rotate_key(current, &passphrase, Some(GroupSalt::generate()), None)
+            .await)
+    }
+
+    /// Announce the rotation from `current` to `passphrase` under `salt`
+    /// to connected peers, then switch. Returns the new epoch.
+    async fn rotate_key(
+        &mut self,
+        current: GroupKey,
+        passphrase: &str,
+        salt: Option<GroupSalt>,
+        grace_secs: Option<u64>,
+    ) -> u64 {
+        let epoch = self.key_epoch.as_ref().map_or(0, |state| state.epoch) + 1;
+        let key = GroupKey::derive_blocking(passphrase, salt.as_ref()).await;
+        let rotation = SignedRotation::issue(&self.signer, epoch, &key, salt, grace_secs);
+        let peers = self.rotation_peers(None, salt.is_some());
+        log::info!(
+            "Rotating the group key to epoch {epoch}; announcing to {} peers",
+            peers.len()
+        );
+        for peer in peers {
+            self.send_key_rotation(peer, &rotation, passphrase, &current);
+        }
+        // Peers re-handshake with us once they have adopted the key.
+        self.adopt_key(passphrase, key, rotation).await;
+        epoch
+    }
+
+    /// Send `rotation` to `peer` with `passphrase` sealed to its identity,
+    /// authenticated with `under` — the key being replaced.
+    fn send_key_rotation(
+        &mut self,
+        peer: libp2p::PeerId,
+        rotation: &SignedRotation,
+        passphrase: &str,
+        under: &GroupKey,
+    ) {
+        let Some(grant) =
+            key_for_peer(&peer).and_then(|key| KeyGrant::seal(&key, rotation.epoch, passphrase))
+        else {
+            log::warn!("Peer {peer} has no ed25519 identity key to seal the rotation to");
+            return;
+        };
+        let mut req = SyncRequest::KeyRotation {
+            rotation: rotation.clone(),
+            grant,
+            hmac: None,
+        };
+        if let Ok(bytes) = serde_json::to_vec(&req) {
+            let tag = under.mac(&bytes);
+            if let SyncRequest::KeyRotation { ref mut hmac, .. } = req {
+                *hmac = Some(tag);
+            }
+        }
+        self.swarm.behaviour_mut().snapshot.send_request(&peer, req);
+    }
+
+    /// A hello that failed our key. If it is from a peer still on the
+    /// previous key, send it the rotation it missed and return `true`.
+    pub(super) fn bring_over_previous_epoch(
+        &mut self,
+        peer: libp2p::PeerId,
+        hello: &PeerHello,
+        signed_bytes: &[u8],
+        tag: &[u8; 32],
+    ) -> bool {
+        let Some(previous) = self.previous_key() else {
+            return false;
+        };
+        if !previous.verify(signed_bytes, tag)
+            || !hello.supports(FEATURE_KEY_ROTATION)
+            || self.is_revoked_peer(&peer)
+        {
+            return false;
+        }
+        let Some(state) = self.key_epoch.as_ref() else {
+            return false;
+        };
+        if state.salt.is_some() && !hello.supports(FEATURE_ARGON2ID_KDF) {
+            log::warn!(
+                "Peer {peer} can't derive the group's Argon2id key; it can't be brought over"
+            );
+            return false;
+        }
+        let Some(rotation) = state.rotation.clone() else {
+            return false;
+        };
+        let passphrase = state.passphrase.clone();
+        log::info!(
+            "Peer {peer} is still on the previous group key; sending it epoch {}",
+            rotation.epoch
+        );
+        self.send_key_rotation(peer, &rotation, &passphrase, &previous);
+        true
+    }
+
+    /// Adopt a rotation announced by `peer` if it opens under one of our
+    /// keys, is signed by a device that isn't revoked and beats the
+    /// rotation we're on, and pass it on.
+    pub(super) async fn handle_key_rotation_request(
+        &mut self,
+        peer: libp2p::PeerId,
+        channel: request_response::ResponseChannel<SyncResponse>,
+        received: ReceivedRotation,
+    ) {
+        let ReceivedRotation {
+            rotation,
+            grant,
+            hmac: req_hmac,
+        } = received;
+        if self.refuse_revoked(peer) {
+            return;
+        }
+        let (Some(current), Some(state)) = (self.group_key.clone(), self.key_epoch.clone()) else {
+            log::debug!("Ignoring key rotation from peer {peer}: no passphrase to rotate from");
+            return;
+        };
+        let Some(tag) = req_hmac else {
+            log::debug!("Rejecting unauthenticated key rotation from peer {peer}");
+            return;
+        };
+        let verify_req = SyncRequest::KeyRotation {
+            rotation: rotation.clone(),
+            grant: grant.clone(),
+            hmac: None,
+        };
+        let Ok(bytes) = serde_json::to_vec(&verify_req) else {
+            return;
+        };
+        // Authenticated with our key, it moves the group on from our epoch.
+        // With the previous one, it can only be a rival to the rotation we
+        // took, made from the same key at the same time.
+        let (under, in_sequence) = if current.verify(&bytes, &tag) {
+            (current.clone(), rotation.epoch > state.epoch)
+        } else if let Some(previous) = self.previous_key()
+            && previous.verify(&bytes, &tag)
+        {
+            (previous, rotation.epoch == state.epoch)
+        } else {
+            log::debug!("Rejecting key rotation under none of our keys from peer {peer}");
+            return;
+        };
+        if !rotation.verify() {
+            log::debug!("Rejecting key rotation with an invalid signature from peer {peer}");
+            return;
+        }
+        // Whoever passes it on, a revoked device can't move the group.
+        if self.revocations.is_revoked(&rotation.issuer()) {
+            self.diagnostics
+                .revoked_rejected
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            log::warn!("Refusing key rotation issued by a revoked device, from peer {peer}");
+            return;
+        }
+        let Some(passphrase) = grant.open(&self.signer, rotation.epoch) else {
+            log::debug!("Rejecting key rotation not sealed to us from peer {peer}");
+            return;
+        };
+
+        let resp_tx = self.snapshot_resp_tx.clone();
+        tokio::spawn(async move {
+            let _ = resp_tx.send((channel, SyncResponse::KeyRotationAck)).await;
+        });
+
+        let epoch = rotation.epoch;
+        if !in_sequence || !supersedes(&rotation, state.epoch, state.rotation.as_ref()) {
+            log::debug!(
+                "Ignoring key rotation to epoch {epoch} from peer {peer}: already on epoch {}",
+                state.epoch
+            );
+            return;
+        }
+        let new_key = GroupKey::derive_blocking(&passphrase, rotation.salt.as_ref()).await;
+        if !rotation.is_for(&new_key) {
+            log::warn!(
+                "Rejecting key rotation from peer {peer}: the passphrase isn't the signed key's"
+            );
+            return;
+        }
+
+        log::info!("Peer {peer} rotated the group key to epoch {epoch}");
+        // Pass it on while the handshakes still say who can take it.
+        for other in self.rotation_peers(Some(peer), rotation.salt.is_some()) {
+            self.send_key_rotation(other, &rotation, &passphrase, &under);
+        }
+        self.adopt_key(&passphrase, new_key, rotation).await;
+        if self.registry_is_ready {
+            self.initiate_sync_for_peer(peer);
+        }
+    }
+
+    /// Switch to `key`, derived from `passphrase`, as `rotation` says,
+    /// keeping the current one acceptable for the rotation's grace window,
+    /// or else the configured one.
+    async fn adopt_key(&mut self, passphrase: &str, key: GroupKey, rotation: SignedRotation) {
+        let Some(state) = self.key_epoch.as_ref() else {
+            return;
+        };
+        let epoch = rotation.epoch;
+        let grace_secs = rotation
+            .grace_secs
+            .unwrap_or(self.config.key_rotation_grace.as_secs());
+        let state = state.advance(passphrase, key.clone(), rotation, grace_secs);
+        // The passphrases first: a restart that finds the new fingerprint
+        // in the database looks for them in the store.
+        if let Some(ref secrets) = self.config.secret_store {
+            let stored = secrets
+                .set(crate::secret_store::PASSPHRASE, passphrase)
+                .and_then(|()| match state.previous {
+                    Some(ref previous) => {
+                        secrets.set(crate::secret_store::PREVIOUS_PASSPHRASE, previous)
+                    }
+                    None => secrets.delete(crate::secret_store::PREVIOUS_PASSPHRASE),
+                });
+            if let Err(e) = stored {
+                log::warn!("Failed to update the passphrase in the secret store: {e}");
+            }
+        }
+        if let Err(e) = shadow::set_key_epoch(&self.db, &state).await {
+            log::warn!("Failed to persist the rotated group key: {e}");
+        }
+
+        let old_topic = std::mem::replace(&mut self.topic_name, key.derive_topic(&self.user_topic));
+        let old_namespace = std::mem::replace(
+            &mut self.rendezvous_namespace,
+            key.derive_namespace(&self.user_topic),
+        );
+        self.group_key = Some(key);
+        self.key_epoch = Some(state);
+
+        // Sessions and handshakes were authenticated with the old key, and a
+        // topic rejected under it says nothing about the new one.
+        self.sessions.clear();
+        self.peer_handshakes.clear();
+        self.rejected_peers.clear();
+        self.move_registrations(old_topic, old_namespace);
+
+        self.diagnostics
+            .key_rotations
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.emit_network_event(crate::network_status::NetworkEvent::GroupKeyRotated { epoch });
+        self.update_network_status();
+    }
+
+    /// Move the rendezvous registration and the relay's push token and
+    /// presence from the old topic to the current one.
+    fn move_registrations(&mut self, old_topic: String, old_namespace: String) {
+        if let Some(libp2p::multiaddr::Protocol::P2p(server)) = self
+            .config
+            .rendezvous_server
+            .as_ref()
+            .and_then(|addr| addr.iter().last())
+            && self.swarm.is_connected(&server)
+        {
+            if let Ok(namespace) = rendezvous::Namespace::new(old_namespace) {
+                self.swarm
+                    .behaviour_mut()
+                    .rendezvous
+                    .unregister(namespace, server);
+            }
+            self.rendezvous_cookie = None;
+            self.rendezvous_registered = false;
+            // Registers under the new namespace as well.
+            self.rendezvous_discover();
+        }
+
+        if let RelayState::Connected { relay_peer_id, .. }
+        | RelayState::Listening { relay_peer_id } = self.relay_state
+        {
+            if self.push_registered
+                && let Some((_, ref token)) = self.push_token
+            {
+                let req = push_protocol::PushRequest::UnregisterToken {
+                    topic: old_topic,
+                    token: token.clone(),
+                };
+                self.swarm
+                    .behaviour_mut()
+                    .push
+                    .send_request(&relay_peer_id, req);
+            }
+            self.push_registered = false;
+            self.maybe_register_push_token(relay_peer_id);
+            self.announce_presence_to_relay(relay_peer_id);
+        }
+    }
+}
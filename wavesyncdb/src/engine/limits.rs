@@ -0,0 +1,312 @@
+//! Inbound resource limits and per-peer rate limiting.
+//!
+//! Every request a peer sends takes a token from its bucket, which refills
+//! at `peer_request_rate` per second up to `peer_request_burst`. A push may
+//! carry at most `max_changeset_changes` changes, no change value (pushed
+//! or in a sync response) may serialize to more than `max_value_bytes`,
+//! and a peer may have at most [`MAX_QUEUED_PUSHES_PER_PEER`] pushes
+//! waiting to be applied, so one peer can't fill the queue the others
+//! share.
+//!
+//! Each refusal adds to the peer's score, which decays over time. A peer
+//! whose score reaches [`BAN_SCORE`] is banned for `peer_ban_duration`: its
+//! connections are dropped and refused until the ban runs out. Bans live in
+//! memory only and end with the engine.
+
+use super::*;
+
+use std::time::Instant;
+
+use crate::network_status::NetworkEvent;
+
+/// Score added for a request over the rate limit.
+const RATE_LIMITED_PENALTY: f64 = 5.0;
+
+/// Score added for a message over a size limit.
+const OVERSIZED_PENALTY: f64 = 20.0;
+
+/// Score at which a peer is banned.
+const BAN_SCORE: f64 = 100.0;
+
+/// Score forgiven per second of good behaviour.
+const SCORE_DECAY_PER_SEC: f64 = 1.0;
+
+/// How many of one peer's pushes may wait to be applied at once.
+pub(crate) const MAX_QUEUED_PUSHES_PER_PEER: usize = 8;
+
+/// Token bucket: `burst` tokens, refilled at `rate` per second.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    rate: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u32, burst: u32, now: Instant) -> Self {
+        Self {
+            capacity: burst.max(1) as f64,
+            tokens: burst.max(1) as f64,
+            rate: rate as f64,
+            refilled_at: now,
+        }
+    }
+
+    /// Take a token. Returns `false` if none is left.
+    pub fn take(&mut self, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled_at = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// What we hold against one peer.
+#[derive(Debug)]
+pub(crate) struct PeerStanding {
+    bucket: TokenBucket,
+    score: f64,
+    scored_at: Instant,
+    banned_until: Option<Instant>,
+    /// Pushes queued for application and not yet finished.
+    queued_pushes: usize,
+}
+
+impl PeerStanding {
+    pub fn new(config: &EngineConfig, now: Instant) -> Self {
+        Self {
+            bucket: TokenBucket::new(config.peer_request_rate, config.peer_request_burst, now),
+            score: 0.0,
+            scored_at: now,
+            banned_until: None,
+            queued_pushes: 0,
+        }
+    }
+
+    /// The score at `now`, after decay.
+    fn score(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.scored_at).as_secs_f64();
+        (self.score - elapsed * SCORE_DECAY_PER_SEC).max(0.0)
+    }
+
+    /// Add `points` to the score. Returns `true` if that reaches the ban
+    /// score; the score starts over from zero.
+    pub fn penalize(&mut self, points: f64, now: Instant) -> bool {
+        self.score = self.score(now) + points;
+        self.scored_at = now;
+        if self.score < BAN_SCORE {
+            return false;
+        }
+        self.score = 0.0;
+        true
+    }
+
+    pub fn is_banned(&self, now: Instant) -> bool {
+        self.banned_until.is_some_and(|until| now < until)
+    }
+
+    /// Nothing left worth keeping once the peer is gone.
+    fn is_clean(&self, now: Instant) -> bool {
+        !self.is_banned(now) && self.queued_pushes == 0 && self.score(now) == 0.0
+    }
+}
+
+/// Serialized size of a change value.
+fn value_bytes(val: &Option<serde_json::Value>) -> usize {
+    val.as_ref()
+        .and_then(|v| serde_json::to_vec(v).ok())
+        .map_or(0, |bytes| bytes.len())
+}
+
+impl EngineRunner {
+    fn standing(&mut self, peer: libp2p::PeerId) -> &mut PeerStanding {
+        let config = &self.config;
+        self.peer_standings
+            .entry(peer)
+            .or_insert_with(|| PeerStanding::new(config, Instant::now()))
+    }
+
+    /// If `peer` is banned, drop its connection and return `true`.
+    pub(super) fn refuse_banned(&mut self, peer: libp2p::PeerId) -> bool {
+        let banned = self
+            .peer_standings
+            .get(&peer)
+            .is_some_and(|s| s.is_banned(Instant::now()));
+        if banned {
+            log::debug!("Refusing banned peer {peer}");
+            let _ = self.swarm.disconnect_peer_id(peer);
+        }
+        banned
+    }
+
+    /// Take a request token for `peer`. Returns `false` if the request
+    /// must be dropped: the peer is banned or over its rate.
+    pub(super) fn admit_request(&mut self, peer: libp2p::PeerId) -> bool {
+        if self.refuse_banned(peer) {
+            return false;
+        }
+        if self.config.peer_request_rate == 0 {
+            return true;
+        }
+        if self.standing(peer).bucket.take(Instant::now()) {
+            return true;
+        }
+        self.diagnostics
+            .requests_rate_limited
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        log::debug!("Dropping request from peer {peer}: over its rate limit");
+        self.penalize(peer, RATE_LIMITED_PENALTY);
+        false
+    }
+
+    /// Whether every change value from `peer` fits `max_value_bytes`.
+    pub(super) fn admit_changes(&mut self, peer: libp2p::PeerId, changes: &[ColumnChange]) -> bool {
+        let max = self.config.max_value_bytes;
+        let Some(change) = changes.iter().find(|c| value_bytes(&c.val) > max) else {
+            return true;
+        };
+        log::warn!(
+            "Refusing changes from peer {peer}: value of {}/{}/{} is over {max} bytes",
+            change.table.0,
+            change.pk.0,
+            change.cid.0
+        );
+        self.refuse_oversized(peer);
+        false
+    }
+
+    /// Admit a push of `changes` from `peer` and reserve it a queue slot,
+    /// released by [`Self::release_push`]. Returns `false` if the push is
+    /// too large, the peer already has too many queued, or a value is too
+    /// large.
+    pub(super) fn admit_push(&mut self, peer: libp2p::PeerId, changes: &[ColumnChange]) -> bool {
+        let max = self.config.max_changeset_changes;
+        if changes.len() > max {
+            log::warn!(
+                "Refusing push of {} changes from peer {peer}: over {max}",
+                changes.len()
+            );
+            self.refuse_oversized(peer);
+            return false;
+        }
+        if !self.admit_changes(peer, changes) {
+            return false;
+        }
+        if self.standing(peer).queued_pushes >= MAX_QUEUED_PUSHES_PER_PEER {
+            self.diagnostics
+                .requests_rate_limited
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            log::debug!("Dropping push from peer {peer}: too many of its pushes are queued");
+            self.penalize(peer, RATE_LIMITED_PENALTY);
+            return false;
+        }
+        self.standing(peer).queued_pushes += 1;
+        true
+    }
+
+    /// Give back the queue slot of a push from `peer`.
+    pub(super) fn release_push(&mut self, peer: libp2p::PeerId) {
+        if let Some(standing) = self.peer_standings.get_mut(&peer) {
+            standing.queued_pushes = standing.queued_pushes.saturating_sub(1);
+        }
+    }
+
+    /// Forget `peer`'s standing once it has disconnected, unless it still
+    /// counts against the peer.
+    pub(super) fn forget_standing(&mut self, peer: &libp2p::PeerId) {
+        let now = Instant::now();
+        if self
+            .peer_standings
+            .get(peer)
+            .is_some_and(|s| s.is_clean(now))
+        {
+            self.peer_standings.remove(peer);
+        }
+    }
+
+    fn refuse_oversized(&mut self, peer: libp2p::PeerId) {
+        self.diagnostics
+            .oversized_messages_rejected
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.penalize(peer, OVERSIZED_PENALTY);
+    }
+
+    fn penalize(&mut self, peer: libp2p::PeerId, points: f64) {
+        let now = Instant::now();
+        if !self.standing(peer).penalize(points, now) {
+            return;
+        }
+        let duration = self.config.peer_ban_duration;
+        self.standing(peer).banned_until = Some(now + duration);
+        self.diagnostics
+            .peers_banned
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        log::warn!("Banning peer {peer} for {duration:?}");
+        let until = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+            + duration.as_secs();
+        self.emit_network_event(NetworkEvent::PeerBanned {
+            peer_id: crate::network_status::PeerId(peer.to_string()),
+            until,
+        });
+        let _ = self.swarm.disconnect_peer_id(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_token_bucket_refills_up_to_burst() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(10, 3, start);
+        assert!(bucket.take(start));
+        assert!(bucket.take(start));
+        assert!(bucket.take(start));
+        assert!(!bucket.take(start));
+
+        // A tenth of a second buys one request at 10/s.
+        let later = start + Duration::from_millis(100);
+        assert!(bucket.take(later));
+        assert!(!bucket.take(later));
+
+        // A long pause refills no more than the burst.
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(bucket.take(much_later));
+        }
+        assert!(!bucket.take(much_later));
+    }
+
+    #[test]
+    fn test_score_decays_and_bans_at_threshold() {
+        let start = Instant::now();
+        let mut standing = PeerStanding::new(&EngineConfig::default(), start);
+        for _ in 0..4 {
+            assert!(!standing.penalize(OVERSIZED_PENALTY, start));
+        }
+        // Forgiven over time, so the fifth strike no longer bans.
+        let later = start + Duration::from_secs(30);
+        assert!(!standing.penalize(OVERSIZED_PENALTY, later));
+        assert!(!standing.is_clean(later));
+
+        assert!(!standing.penalize(OVERSIZED_PENALTY, later));
+        assert!(standing.penalize(OVERSIZED_PENALTY, later));
+        assert!(standing.is_clean(later), "the score starts over");
+
+        standing.banned_until = Some(later + Duration::from_secs(10));
+        assert!(standing.is_banned(later));
+        assert!(!standing.is_banned(later + Duration::from_secs(10)));
+    }
+}
@@ -11,17 +11,34 @@
 //! Incoming remote changesets are applied column-by-column using per-column
 //! Lamport clocks for conflict resolution.
 
+pub(crate) mod anti_entropy;
+pub(crate) mod attribution;
 pub(crate) mod auth_protocol;
 pub(crate) mod behaviour;
+pub(crate) mod bootstrap;
+pub(crate) mod bundle;
+pub(crate) mod capability;
+pub(crate) mod clock_guard;
 pub(crate) mod command_handler;
+pub(crate) mod gossip;
+pub(crate) mod handshake;
 pub(crate) mod identity_handler;
+pub(crate) mod key_rotation;
+pub(crate) mod limits;
+pub(crate) mod pairing;
 pub(crate) mod peer_manager;
 pub(crate) mod push_protocol;
 pub(crate) mod relay_manager;
+pub(crate) mod replay;
+pub(crate) mod revocation;
+pub(crate) mod sealing;
 pub(crate) mod snapshot_protocol;
 pub(crate) mod sync_handler;
+pub(crate) mod type_guard;
+pub(crate) mod webrtc_listener;
+pub(crate) mod websocket_listener;
 
-use sync_handler::apply_remote_changeset;
+use sync_handler::{RemoteBatch, strip_origin_versions};
 
 use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
@@ -35,13 +52,13 @@ use libp2p::{
 };
 use sea_orm::{ConnectionTrait, DatabaseConnection};
 use std::panic::AssertUnwindSafe;
-use tokio::sync::{Notify, broadcast, mpsc};
+use tokio::sync::{Notify, broadcast, mpsc, oneshot};
 
 use crate::auth::GroupKey;
 use crate::conflict;
 use crate::messages::{ChangeNotification, ColumnChange, NodeId, SyncChangeset, WriteKind};
 use crate::peer_tracker;
-use crate::protocol::SyncRequest;
+use crate::protocol::{FEATURE_PUSH_SEQUENCE, FEATURE_SIGNED_CHANGES, SyncRequest};
 use crate::registry::TableRegistry;
 use crate::shadow;
 
@@ -64,6 +81,62 @@ pub enum EngineCommand {
     /// announcements and queries are silenced. When enabling, the mDNS
     /// behaviour is rebuilt and starts queries immediately.
     SetMdnsEnabled(bool),
+    /// Rotate the group key to a new passphrase and announce it to the
+    /// group (see [`crate::rotation`]). Replies with the new epoch.
+    RotatePassphrase {
+        passphrase: String,
+        reply: oneshot::Sender<Result<u64, String>>,
+    },
+    /// Move the group from a v1 to a v2 key (see [`crate::auth`]).
+    /// Replies with the new key epoch.
+    UpgradeKeyDerivation {
+        reply: oneshot::Sender<Result<u64, String>>,
+    },
+    /// Revoke a device from the group and rotate the group key away from
+    /// it (see [`crate::revocation`]). Replies with the new passphrase,
+    /// when a passphrase is configured.
+    RevokeDevice {
+        peer: libp2p::PeerId,
+        reply: oneshot::Sender<Result<Option<String>, String>>,
+    },
+    /// Grant capabilities to a device, replacing any it had (see
+    /// [`crate::capability`]).
+    GrantCapabilities {
+        peer: libp2p::PeerId,
+        capabilities: crate::capability::Capabilities,
+        reply: oneshot::Sender<Result<(), String>>,
+    },
+    /// Set the local application-level identity to the one `endorsement`
+    /// vouches for, announcing the endorsement with it (see
+    /// [`crate::identity`]).
+    SetEndorsedPeerIdentity {
+        endorsement: crate::identity::IdentityEndorsement,
+        reply: oneshot::Sender<Result<(), String>>,
+    },
+    /// Lift the quarantine of a site whose clocks ran too far ahead (see
+    /// [`crate::conflict`]). Replies with whether it was quarantined.
+    ReleaseQuarantine {
+        site: NodeId,
+        reply: oneshot::Sender<Result<bool, String>>,
+    },
+    /// Open a pairing invitation for a new device (see [`crate::pairing`]).
+    StartPairing {
+        ttl: std::time::Duration,
+        reply: oneshot::Sender<Result<crate::pairing::PairingInvite, String>>,
+    },
+    /// Write every change past `since` as a bundle (see
+    /// [`crate::bundle`]). Replies with its bytes and how many changes it
+    /// holds.
+    ExportBundle {
+        since: crate::protocol::OriginVersions,
+        encrypt: bool,
+        reply: oneshot::Sender<Result<(Vec<u8>, usize), String>>,
+    },
+    /// Merge a bundle as if it were a catch-up from its exporter.
+    ImportBundle {
+        bytes: Vec<u8>,
+        reply: oneshot::Sender<Result<crate::bundle::BundleImport, String>>,
+    },
     /// Graceful shutdown — stop the engine loop.
     Shutdown,
 }
@@ -104,8 +177,82 @@ pub struct EngineConfig {
     /// Maximum relay circuit duration the server allows (default: 3600s).
     /// The engine proactively renews at 80% of this duration.
     pub circuit_max_duration: Duration,
+    /// Application version announced to peers in the capability handshake.
+    pub app_version: Option<String>,
+    /// How often to run an anti-entropy round with each peer (default: 10 min).
+    pub anti_entropy_interval: Duration,
+    /// How many times a pushed changeset may be forwarded beyond the
+    /// origin's direct peers (default: 4; 0 disables forwarding).
+    pub gossip_max_hops: u8,
+    /// Whether changes are encrypted with the group key on links to peers
+    /// that can open them (default: `true`; no effect without a
+    /// passphrase). When off, the hello stops asking peers to encrypt.
+    pub encrypt_payloads: bool,
+    /// Whether authenticated requests and responses are only accepted on a
+    /// session keyed by both hellos' nonces (default: `false`; no effect
+    /// without a passphrase). Turn on once no browser clients or older
+    /// builds, which never set up sessions, need to sync with this device.
+    pub require_sessions: bool,
+    /// How long the previous passphrase is still accepted after a key
+    /// rotation (default: 7 days).
+    pub key_rotation_grace: Duration,
+    /// Where [`SyncConfig`](crate::connection::SyncConfig) keeps its
+    /// secrets, so a key rotation can update the passphrase background sync
+    /// starts with.
+    pub secret_store: Option<Arc<dyn crate::secret_store::SecretStore>>,
+    /// Issuer keys whose [`IdentityEndorsement`](crate::identity::IdentityEndorsement)s
+    /// are trusted (see [`crate::identity`]).
+    pub identity_issuers: Vec<[u8; 32]>,
+    /// Identity keys of the group's administrators: devices whose
+    /// revocations are accepted whether or not this device has met them
+    /// (see [`crate::revocation`]).
+    pub admin_keys: Vec<[u8; 32]>,
+    /// Identity keys that may issue capability grants, besides
+    /// `admin_keys` (see [`crate::capability`]).
+    pub capability_issuers: Vec<[u8; 32]>,
+    /// What a device without a grant may write. `None` leaves it
+    /// unrestricted when no issuers are configured, and read-only when
+    /// they are.
+    pub default_capabilities: Option<crate::capability::Capabilities>,
+    /// How far a remote change's clock may run past the highest one held
+    /// for its row before its site is quarantined (default:
+    /// [`DEFAULT_MAX_CLOCK_JUMP`](crate::conflict::DEFAULT_MAX_CLOCK_JUMP)).
+    pub max_clock_jump: u64,
+    /// Most changes a single pushed changeset may carry (default: 10,000).
+    /// Larger pushes are refused; the changes arrive with the next
+    /// catch-up instead.
+    pub max_changeset_changes: usize,
+    /// Largest serialized size, in bytes, of a remote change value
+    /// (default: 1 MiB).
+    pub max_value_bytes: usize,
+    /// Requests per second each peer may send on average (default: 50;
+    /// 0 disables rate limiting).
+    pub peer_request_rate: u32,
+    /// Requests a peer may send at once before its rate applies
+    /// (default: 200).
+    pub peer_request_burst: u32,
+    /// How long a peer that keeps breaking these limits is banned
+    /// (default: 10 min).
+    pub peer_ban_duration: Duration,
+    /// Whether to listen for browser peers over WebRTC (`webrtc-direct`),
+    /// so they can connect without going through the relay (default:
+    /// `false`).
+    #[cfg(feature = "webrtc")]
+    pub webrtc: bool,
+    /// TCP port of a WebSocket listener for browser peers, `0` for any;
+    /// `None` for no listener (default). Lets a browser sync without a
+    /// relay.
+    #[cfg(feature = "websocket")]
+    pub websocket_port: Option<u16>,
+    /// Certificate the WebSocket listener serves, making it `wss`
+    /// (default: `None`, plain `ws`).
+    #[cfg(feature = "websocket")]
+    pub websocket_tls: Option<WebSocketTls>,
 }
 
+#[cfg(feature = "websocket")]
+pub use websocket_listener::WebSocketTls;
+
 impl Default for EngineConfig {
     fn default() -> Self {
         Self {
@@ -123,6 +270,29 @@ impl Default for EngineConfig {
             api_key: None,
             keep_alive_interval: Duration::from_secs(90),
             circuit_max_duration: Duration::from_secs(3600),
+            app_version: None,
+            anti_entropy_interval: Duration::from_secs(600),
+            gossip_max_hops: 4,
+            encrypt_payloads: true,
+            require_sessions: false,
+            key_rotation_grace: Duration::from_secs(7 * 24 * 3600),
+            secret_store: None,
+            identity_issuers: Vec::new(),
+            admin_keys: Vec::new(),
+            capability_issuers: Vec::new(),
+            default_capabilities: None,
+            max_clock_jump: conflict::DEFAULT_MAX_CLOCK_JUMP,
+            max_changeset_changes: 10_000,
+            max_value_bytes: 1024 * 1024,
+            peer_request_rate: 50,
+            peer_request_burst: 200,
+            peer_ban_duration: Duration::from_secs(600),
+            #[cfg(feature = "webrtc")]
+            webrtc: false,
+            #[cfg(feature = "websocket")]
+            websocket_port: None,
+            #[cfg(feature = "websocket")]
+            websocket_tls: None,
         }
     }
 }
@@ -151,6 +321,7 @@ pub(crate) fn start_engine(
     registry_ready: Arc<Notify>,
     cmd_rx: mpsc::Receiver<EngineCommand>,
     group_key: Option<GroupKey>,
+    key_epoch: Option<crate::rotation::KeyEpoch>,
     network_status: Arc<std::sync::RwLock<crate::network_status::NetworkStatus>>,
     network_event_tx: broadcast::Sender<crate::network_status::NetworkEvent>,
     diagnostics: Arc<crate::diagnostics::Counters>,
@@ -168,6 +339,7 @@ pub(crate) fn start_engine(
             registry_ready,
             cmd_rx,
             group_key,
+            key_epoch,
             network_status,
             network_event_tx,
             diagnostics,
@@ -197,6 +369,47 @@ pub(crate) fn start_engine(
     })
 }
 
+/// What the transports browser peers dial, next to QUIC, are built from.
+struct BrowserTransports {
+    /// The `webrtc-direct` listener's certificate, when it's on.
+    #[cfg(feature = "webrtc")]
+    webrtc_certificate: Option<libp2p_webrtc::tokio::Certificate>,
+    /// The certificate the WebSocket listener serves as `wss`.
+    #[cfg(feature = "websocket")]
+    websocket_tls: Option<libp2p::websocket::tls::Config>,
+}
+
+impl BrowserTransports {
+    /// The browser transports built in, as one: none without the `webrtc`
+    /// and `websocket` features.
+    #[allow(unused_variables)] // `key`, with neither feature.
+    fn transport(
+        &self,
+        key: &identity::Keypair,
+    ) -> Result<
+        libp2p::core::transport::Boxed<(libp2p::PeerId, libp2p::core::muxing::StreamMuxerBox)>,
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
+        use libp2p::Transport;
+
+        let transport = libp2p::core::transport::dummy::DummyTransport::new().boxed();
+        #[cfg(feature = "websocket")]
+        let transport = websocket_listener::transport(key, self.websocket_tls.clone())?
+            .or_transport(transport)
+            .map(|either, _| either.into_inner())
+            .boxed();
+        #[cfg(feature = "webrtc")]
+        let transport = match &self.webrtc_certificate {
+            Some(certificate) => webrtc_listener::transport(key, certificate.clone())
+                .or_transport(transport)
+                .map(|either, _| either.into_inner())
+                .boxed(),
+            None => transport,
+        };
+        Ok(transport)
+    }
+}
+
 /// Build the libp2p swarm with DNS resolution.
 ///
 /// Tries system DNS first (`/etc/resolv.conf`). If that fails (e.g. on Android
@@ -206,6 +419,8 @@ fn build_swarm(
     keypair: identity::Keypair,
     mdns_config: Option<mdns::Config>,
     keep_alive_interval: Duration,
+    diagnostics: Arc<crate::diagnostics::Counters>,
+    browser: BrowserTransports,
 ) -> Result<libp2p::Swarm<WaveSyncBehaviour>, Box<dyn std::error::Error + Send + Sync>> {
     // QUIC-only (no TCP). Two reasons:
     //
@@ -227,9 +442,15 @@ fn build_swarm(
     // The cost: networks that block UDP entirely (corporate firewalls,
     // captive-portal Wi-Fi) can't sync. In practice this is rare for the
     // mobile-sync target audience.
+    //
+    // WebRTC and WebSocket sit next to QUIC for browsers only, which can't
+    // speak QUIC: native peers never dial those addresses (see
+    // `is_browser_listener`), so they still hold a single connection to
+    // each other. Each is only there with its feature built in.
     let system_result = SwarmBuilder::with_existing_identity(keypair.clone())
         .with_tokio()
         .with_quic()
+        .with_other_transport(|key| browser.transport(key))?
         .with_dns();
 
     match system_result {
@@ -239,7 +460,7 @@ fn build_swarm(
             Ok(builder
                 .with_relay_client(noise::Config::new, yamux::Config::default)?
                 .with_behaviour(move |key, relay_client| {
-                    WaveSyncBehaviour::new(key, relay_client, mdns_cfg, ping_interval)
+                    WaveSyncBehaviour::new(key, relay_client, mdns_cfg, ping_interval, diagnostics)
                 })?
                 .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(300)))
                 .build())
@@ -254,10 +475,11 @@ fn build_swarm(
             Ok(SwarmBuilder::with_existing_identity(keypair)
                 .with_tokio()
                 .with_quic()
+                .with_other_transport(|key| browser.transport(key))?
                 .with_dns_config(dns::ResolverConfig::google(), dns::ResolverOpts::default())
                 .with_relay_client(noise::Config::new, yamux::Config::default)?
                 .with_behaviour(move |key, relay_client| {
-                    WaveSyncBehaviour::new(key, relay_client, mdns_cfg, ping_interval)
+                    WaveSyncBehaviour::new(key, relay_client, mdns_cfg, ping_interval, diagnostics)
                 })?
                 .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(300)))
                 .build())
@@ -277,6 +499,7 @@ async fn run_engine(
     registry_ready: Arc<Notify>,
     cmd_rx: mpsc::Receiver<EngineCommand>,
     group_key: Option<GroupKey>,
+    key_epoch: Option<crate::rotation::KeyEpoch>,
     network_status: Arc<std::sync::RwLock<crate::network_status::NetworkStatus>>,
     network_event_tx: broadcast::Sender<crate::network_status::NetworkEvent>,
     diagnostics: Arc<crate::diagnostics::Counters>,
@@ -292,7 +515,29 @@ async fn run_engine(
         None
     };
 
-    let swarm = build_swarm(keypair.clone(), mdns_config, config.keep_alive_interval)?;
+    let browser = BrowserTransports {
+        // No certificate, no WebRTC transport.
+        #[cfg(feature = "webrtc")]
+        webrtc_certificate: if config.webrtc {
+            Some(shadow::get_or_create_webrtc_certificate(&db).await?)
+        } else {
+            None
+        },
+        #[cfg(feature = "websocket")]
+        websocket_tls: config
+            .websocket_tls
+            .as_ref()
+            .map(websocket_listener::tls_config)
+            .transpose()
+            .map_err(|e| format!("invalid WebSocket TLS certificate: {e}"))?,
+    };
+    let swarm = build_swarm(
+        keypair.clone(),
+        mdns_config,
+        config.keep_alive_interval,
+        Arc::clone(&diagnostics),
+        browser,
+    )?;
 
     let local_peer_id = keypair.public().to_peer_id();
     log::info!("Local libp2p PeerId (persistent): {local_peer_id}");
@@ -305,7 +550,86 @@ async fn run_engine(
         crate::protocol::SyncResponse,
     )>(8);
 
-    let (remote_changeset_tx, remote_changeset_rx) = mpsc::channel::<Vec<ColumnChange>>(32);
+    let (remote_changeset_tx, remote_changeset_rx) = mpsc::channel::<RemoteBatch>(32);
+
+    let (anti_entropy_tx, anti_entropy_rx) =
+        mpsc::channel::<(libp2p::PeerId, crate::protocol::AntiEntropyQuery)>(64);
+
+    // Checkpoints of paginated catch-ups that were interrupted by the last
+    // shutdown — the next sync with each of those peers resumes mid-history.
+    let catchup_cursors = match peer_tracker::get_all_catchup_cursors(&db).await {
+        Ok(map) => map
+            .into_iter()
+            .filter_map(|(peer, entry)| Some((peer.parse::<libp2p::PeerId>().ok()?, entry)))
+            .collect(),
+        Err(e) => {
+            log::warn!("Failed to load catch-up checkpoints: {e}");
+            HashMap::new()
+        }
+    };
+
+    let origin_versions = match peer_tracker::get_origin_versions(&db).await {
+        Ok(versions) => versions,
+        Err(e) => {
+            log::warn!("Failed to load origin watermarks: {e}");
+            crate::protocol::OriginVersions::default()
+        }
+    };
+
+    // Our changes are signed with the identity key, so it has to be ed25519
+    // (which is all `get_or_create_libp2p_keypair` ever generates).
+    let signer = crate::signing::ChangeSigner::new(keypair.clone().try_into_ed25519()?, site_id);
+    let mut site_keys = match peer_tracker::get_site_keys(&db).await {
+        Ok(keys) => keys,
+        Err(e) => {
+            log::warn!("Failed to load pinned site keys: {e}");
+            HashMap::new()
+        }
+    };
+    // Nobody else may speak for our own site.
+    site_keys.insert(site_id, signer.public_key());
+    let revocations = match peer_tracker::get_revocations(&db).await {
+        Ok(entries) => crate::revocation::RevocationList::from_entries(entries),
+        Err(e) => {
+            log::warn!("Failed to load revocations: {e}");
+            crate::revocation::RevocationList::default()
+        }
+    };
+    let capabilities = match peer_tracker::get_capability_grants(&db).await {
+        Ok(grants) => crate::capability::CapabilityTable::from_entries(grants),
+        Err(e) => {
+            log::warn!("Failed to load capability grants: {e}");
+            crate::capability::CapabilityTable::default()
+        }
+    };
+    let mut issuers = config.admin_keys.clone();
+    issuers.extend(
+        config
+            .capability_issuers
+            .iter()
+            .filter(|k| !config.admin_keys.contains(k)),
+    );
+    let capabilities = capabilities.with_policy(issuers, config.default_capabilities.clone());
+    let quarantined_sites = match peer_tracker::get_quarantined_sites(&db).await {
+        Ok(held) => held.into_iter().map(|q| q.site_id).collect(),
+        Err(e) => {
+            log::warn!("Failed to load quarantined sites: {e}");
+            std::collections::HashSet::new()
+        }
+    };
+
+    // The rotation state the builder settled on. It has to describe the
+    // key we were given, or a rotation from it would be sealed wrongly.
+    let key_epoch = match (&group_key, key_epoch) {
+        (Some(gk), Some(state)) if state.fingerprint == crate::seal::to_hex(&gk.fingerprint()) => {
+            Some(state)
+        }
+        (Some(_), _) => {
+            log::warn!("No rotation state for the configured passphrase; key rotation is off");
+            None
+        }
+        (None, _) => None,
+    };
 
     let effective_topic = match &group_key {
         Some(gk) => gk.derive_topic(&topic_name),
@@ -342,6 +666,7 @@ async fn run_engine(
         local_peer_id,
         site_id,
         topic_name: effective_topic,
+        user_topic: topic_name,
         config,
         mdns_enabled,
         local_db_version,
@@ -351,10 +676,13 @@ async fn run_engine(
         snapshot_resp_rx,
         remote_changeset_tx,
         remote_changeset_rx,
+        anti_entropy_tx,
+        anti_entropy_rx,
         registry_ready,
         registry_is_ready: false,
         cmd_rx,
         group_key,
+        key_epoch,
         relay_state: RelayState::Disabled,
         nat_status: NatStatus::Unknown,
         rendezvous_namespace,
@@ -364,9 +692,26 @@ async fn run_engine(
         rejected_peers: std::collections::HashSet::new(),
         verified_peers: std::collections::HashSet::new(),
         local_app_id: None,
+        local_identity_endorsement: None,
         peer_identities: HashMap::new(),
         infrastructure_peers,
         pending_sync_peers: std::collections::HashSet::new(),
+        pending_sync_since: HashMap::new(),
+        catchup_cursors,
+        origin_versions,
+        signer,
+        site_keys,
+        revocations,
+        capabilities,
+        quarantined_sites,
+        peer_standings: HashMap::new(),
+        pairing: None,
+        last_pushed_db_version: None,
+        peer_handshakes: HashMap::new(),
+        sessions: HashMap::new(),
+        snapshot_bootstraps: HashMap::new(),
+        snapshot_unsupported: std::collections::HashSet::new(),
+        seen_changesets: gossip::SeenChangesets::default(),
         dialing_peers: std::collections::HashSet::new(),
         pending_rendezvous_dials: VecDeque::new(),
         push_token,
@@ -443,12 +788,21 @@ struct EngineRunner {
         crate::protocol::SyncResponse,
     )>,
     /// Channel for queuing remote changesets to be applied sequentially.
-    pub(crate) remote_changeset_tx: mpsc::Sender<Vec<ColumnChange>>,
-    pub(crate) remote_changeset_rx: mpsc::Receiver<Vec<ColumnChange>>,
+    pub(crate) remote_changeset_tx: mpsc::Sender<RemoteBatch>,
+    pub(crate) remote_changeset_rx: mpsc::Receiver<RemoteBatch>,
+    /// Anti-entropy queries computed by spawned tasks, sent from the main loop.
+    pub(crate) anti_entropy_tx: mpsc::Sender<(libp2p::PeerId, crate::protocol::AntiEntropyQuery)>,
+    pub(crate) anti_entropy_rx: mpsc::Receiver<(libp2p::PeerId, crate::protocol::AntiEntropyQuery)>,
     pub(crate) registry_ready: Arc<Notify>,
     pub(crate) registry_is_ready: bool,
     pub(crate) cmd_rx: mpsc::Receiver<EngineCommand>,
     pub(crate) group_key: Option<GroupKey>,
+    /// The topic the app configured, which `topic_name` is derived from.
+    /// A rotated key derives a new topic from it.
+    pub(crate) user_topic: String,
+    /// Rotation state of `group_key` (see [`crate::rotation`]); `None`
+    /// without a passphrase.
+    pub(crate) key_epoch: Option<crate::rotation::KeyEpoch>,
     /// Relay connection state machine.
     pub(crate) relay_state: RelayState,
     /// Detected NAT status from AutoNAT probes.
@@ -467,12 +821,56 @@ struct EngineRunner {
     pub(crate) verified_peers: std::collections::HashSet<libp2p::PeerId>,
     /// Application-level identity announced by the local peer (ephemeral, session-scoped).
     pub(crate) local_app_id: Option<String>,
-    /// Application-level identities received from remote peers (ephemeral, session-scoped).
-    pub(crate) peer_identities: HashMap<libp2p::PeerId, String>,
+    /// Issuer endorsement of `local_app_id`, announced with it.
+    pub(crate) local_identity_endorsement: Option<crate::identity::IdentityEndorsement>,
+    /// Application-level identities received from remote peers, with how far
+    /// each could be checked (ephemeral, session-scoped).
+    pub(crate) peer_identities:
+        HashMap<libp2p::PeerId, (String, crate::identity::IdentityVerification)>,
     /// Infrastructure peers (relay, rendezvous) — excluded from peer count and sync fan-out.
     pub(crate) infrastructure_peers: std::collections::HashSet<libp2p::PeerId>,
     /// Peers with an in-flight sync request — prevents flooding request-response.
     pub(crate) pending_sync_peers: std::collections::HashSet<libp2p::PeerId>,
+    /// `your_last_db_version` sent with the in-flight sync request to each
+    /// peer, needed to checkpoint the page that comes back.
+    pub(crate) pending_sync_since: HashMap<libp2p::PeerId, u64>,
+    /// Paginated catch-ups still in progress: peer → (base version, cursor
+    /// after the last applied page). Mirrors `_wavesync_catchup_cursors`.
+    pub(crate) catchup_cursors: HashMap<libp2p::PeerId, (u64, crate::protocol::SyncCursor)>,
+    /// Watermarks for other sites' writes (our own is `local_db_version`).
+    /// Mirrors `_wavesync_origin_versions`.
+    pub(crate) origin_versions: crate::protocol::OriginVersions,
+    /// Signs the changes we author with our identity key.
+    pub(crate) signer: crate::signing::ChangeSigner,
+    /// Signing keys of the sites known to sign their changes, ours
+    /// included. Mirrors `_wavesync_site_keys`.
+    pub(crate) site_keys: HashMap<NodeId, [u8; 32]>,
+    /// Devices revoked from the group. Mirrors `_wavesync_revocations`.
+    pub(crate) revocations: crate::revocation::RevocationList,
+    /// Capability grants of restricted devices. Mirrors
+    /// `_wavesync_capability_grants`.
+    pub(crate) capabilities: crate::capability::CapabilityTable,
+    /// Sites whose changes are refused for running their clocks too far
+    /// ahead. Mirrors `_wavesync_quarantine`.
+    pub(crate) quarantined_sites: std::collections::HashSet<NodeId>,
+    /// Rate limits, scores and bans of peers (see [`limits`]).
+    pub(crate) peer_standings: HashMap<libp2p::PeerId, limits::PeerStanding>,
+    /// The open pairing invitation, if any (see [`crate::pairing`]).
+    pub(crate) pairing: Option<crate::pairing::Invitation>,
+    /// `db_version` of our last pushed changeset, sent as the next push's
+    /// `prev_db_version`.
+    pub(crate) last_pushed_db_version: Option<u64>,
+    /// Capability handshake state per connected peer; sync waits for it.
+    pub(crate) peer_handshakes: HashMap<libp2p::PeerId, handshake::Handshake>,
+    /// Replay-protection sessions, keyed by peer; set up by the hellos.
+    pub(crate) sessions: HashMap<libp2p::PeerId, replay::Session>,
+    /// State-snapshot bootstraps in progress, buffering pages until the last.
+    pub(crate) snapshot_bootstraps: HashMap<libp2p::PeerId, bootstrap::SnapshotBootstrap>,
+    /// Peers that couldn't serve a state snapshot — bootstrapped by version
+    /// vector catch-up instead.
+    pub(crate) snapshot_unsupported: std::collections::HashSet<libp2p::PeerId>,
+    /// Changesets recently written or received, so gossip echoes are dropped.
+    pub(crate) seen_changesets: gossip::SeenChangesets,
     /// Peers currently being dialed (not yet connected). Prevents duplicate dials.
     pub(crate) dialing_peers: std::collections::HashSet<libp2p::PeerId>,
     /// Queue of rendezvous-discovered peers waiting to be dialed (rate-limited).
@@ -545,7 +943,13 @@ impl EngineRunner {
                     .or_else(|| self.peer_reported_versions.get(peer_id).copied()),
                 is_bootstrap: self.bootstrap_peers.contains(peer_id),
                 is_group_member: self.verified_peers.contains(peer_id),
-                app_id: self.peer_identities.get(peer_id).cloned(),
+                app_id: self.peer_identities.get(peer_id).map(|(id, _)| id.clone()),
+                identity_verification: self
+                    .peer_identities
+                    .get(peer_id)
+                    .map(|(_, v)| *v)
+                    .unwrap_or_default(),
+                capabilities: self.capabilities.for_peer(peer_id).cloned(),
             })
             .collect();
 
@@ -695,6 +1099,10 @@ impl EngineRunner {
             self.rendezvous_discover();
         }
 
+        if !self.infrastructure_peers.contains(&peer_id) {
+            self.pin_peer_site(&peer_id).await;
+        }
+
         // If this is a bootstrap peer, add to peers and initiate sync
         if self.bootstrap_peers.contains(&peer_id) {
             self.handle_bootstrap_peer_connected(peer_id, endpoint);
@@ -824,6 +1232,8 @@ impl EngineRunner {
                 is_bootstrap: true,
                 is_group_member: false,
                 app_id: None,
+                identity_verification: crate::identity::IdentityVerification::Unverified,
+                capabilities: self.capabilities.for_peer(&peer_id).cloned(),
             },
         ));
         self.update_network_status();
@@ -845,6 +1255,11 @@ impl EngineRunner {
             return;
         }
 
+        // The peer may come back upgraded — handshake again next time.
+        self.peer_handshakes.remove(&peer_id);
+        self.end_session(&peer_id);
+        self.forget_standing(&peer_id);
+
         // Handle relay server disconnect
         if let RelayState::Connected { relay_peer_id, .. } | RelayState::Listening { relay_peer_id } =
             &self.relay_state
@@ -946,6 +1361,15 @@ impl EngineRunner {
             log::warn!("QUIC IPv6 listen failed (non-fatal): {e}");
         }
 
+        #[cfg(feature = "webrtc")]
+        if self.config.webrtc {
+            self.listen_webrtc();
+        }
+        #[cfg(feature = "websocket")]
+        if let Some(port) = self.config.websocket_port {
+            self.listen_websocket(port);
+        }
+
         // If a relay server is configured, dial it. Set state to Connecting
         // first so `try_dial_relay`'s "skip if already Connected/Listening"
         // guard lets the initial dial through but blocks NewListenAddr-driven
@@ -996,7 +1420,18 @@ impl EngineRunner {
         // connects — wasting handshake budget on the others.
         // Multiaddrs without a `/p2p/` suffix fall back to the
         // single-address dial path.
-        let (grouped, suffixless) = group_bootstrap_addrs(self.config.bootstrap_peers.clone());
+        //
+        // Addresses of browser listeners are skipped: pairing hands them
+        // out for browsers, and a native peer dialing one would end up on
+        // WebRTC or WebSocket instead of QUIC.
+        let (grouped, suffixless) = group_bootstrap_addrs(
+            self.config
+                .bootstrap_peers
+                .iter()
+                .filter(|addr| !is_browser_listener(addr))
+                .cloned()
+                .collect(),
+        );
         for peer_id in grouped.keys() {
             self.bootstrap_peers.insert(*peer_id);
         }
@@ -1041,6 +1476,12 @@ impl EngineRunner {
         );
         let has_relay = self.config.relay_server.is_some();
 
+        // First anti-entropy round one interval in: startup catch-up runs first.
+        let mut anti_entropy_interval = tokio::time::interval_at(
+            tokio::time::Instant::now() + self.config.anti_entropy_interval,
+            self.config.anti_entropy_interval,
+        );
+
         loop {
             tokio::select! {
                 Some(changeset) = sync_rx.recv() => {
@@ -1055,6 +1496,14 @@ impl EngineRunner {
                         self.sync_all_known_peers().await;
                     }
                 },
+                _ = anti_entropy_interval.tick() => {
+                    if self.registry_is_ready {
+                        self.start_anti_entropy_rounds();
+                    }
+                },
+                Some((peer, query)) = self.anti_entropy_rx.recv() => {
+                    self.send_anti_entropy(peer, query);
+                },
                 _ = rendezvous_interval.tick(), if has_rendezvous => {
                     self.rendezvous_discover();
                 },
@@ -1066,8 +1515,8 @@ impl EngineRunner {
                         log::error!("Failed to send sync response: {:?}", resp);
                     }
                 },
-                Some(changes) = self.remote_changeset_rx.recv() => {
-                    apply_remote_changeset(&self.db, &self.change_tx, &self.registry, &changes).await;
+                Some(batch) = self.remote_changeset_rx.recv() => {
+                    self.handle_remote_batch(batch).await;
                 },
                 _ = self.registry_ready.notified(), if !self.registry_is_ready => {
                     self.registry_is_ready = true;
@@ -1140,6 +1589,8 @@ impl EngineRunner {
         // Update local db_version
         self.local_db_version = self.local_db_version.max(changeset.db_version);
         self.update_network_status();
+        self.seen_changesets
+            .insert(changeset.site_id, changeset.db_version);
 
         // Fan-out: push changeset to all connected peers via request-response
         let peer_ids: Vec<libp2p::PeerId> = self
@@ -1155,23 +1606,40 @@ impl EngineRunner {
                 "No directly-connected peers; relying on relay push to wake sleeping peers"
             );
         } else {
+            // Signed once; peers that don't verify signatures get them
+            // stripped.
+            let mut signed = changeset.clone();
+            self.signer.sign_own(&mut signed.changes);
+
             for peer_id in &peer_ids {
+                let auth = self.request_auth(peer_id);
+                let mut outgoing = signed.clone();
+                if !self.peer_supports(peer_id, FEATURE_SIGNED_CHANGES) {
+                    crate::signing::strip_signatures(&mut outgoing.changes);
+                }
                 let mut req = SyncRequest::Push {
-                    changeset: changeset.clone(),
+                    changeset: outgoing,
                     topic: self.topic_name.clone(),
+                    hops: None,
+                    prev_db_version: self
+                        .last_pushed_db_version
+                        .filter(|_| self.peer_supports(peer_id, FEATURE_PUSH_SEQUENCE)),
+                    seq: auth.as_ref().and_then(|(_, seq)| *seq),
+                    sealed: None,
                     hmac: None,
                 };
 
-                if let Some(ref gk) = self.group_key {
+                if let Some((ref key, _)) = auth {
                     // Serialize with hmac: None, compute MAC, then set hmac
                     if let Ok(bytes) = serde_json::to_vec(&req) {
-                        let tag = gk.mac(&bytes);
+                        let tag = key.mac(&bytes);
                         let SyncRequest::Push { ref mut hmac, .. } = req else {
                             unreachable!()
                         };
                         *hmac = Some(tag);
                     }
                 }
+                self.seal_request(peer_id, &mut req);
 
                 self.swarm
                     .behaviour_mut()
@@ -1186,6 +1654,10 @@ impl EngineRunner {
             );
         }
 
+        // Peers that weren't connected for this one catch up on connect,
+        // so it counts as pushed either way.
+        self.last_pushed_db_version = Some(changeset.db_version);
+
         // Notify relay to send push notifications to sleeping mobile peers.
         // Must run even when peer_ids is empty — that's the case where both
         // peers are behind NAT with no direct connection, and push is the
@@ -1262,7 +1734,7 @@ impl EngineRunner {
                 self.handle_mdns(event);
             }
             SwarmEvent::Behaviour(WaveSyncBehaviourEvent::Snapshot(event)) => {
-                self.handle_snapshot(event);
+                self.handle_snapshot(event).await;
             }
             SwarmEvent::Behaviour(WaveSyncBehaviourEvent::RelayClient(event)) => {
                 self.handle_relay_client(event);
@@ -1285,9 +1757,15 @@ impl EngineRunner {
             SwarmEvent::Behaviour(WaveSyncBehaviourEvent::AuthResult(event)) => {
                 self.handle_auth_result(event);
             }
+            SwarmEvent::Behaviour(WaveSyncBehaviourEvent::Pairing(event)) => {
+                self.handle_pairing_event(event);
+            }
             SwarmEvent::ConnectionEstablished {
                 peer_id, endpoint, ..
             } => {
+                if self.refuse_revoked(peer_id) || self.refuse_banned(peer_id) {
+                    return;
+                }
                 log::info!("Connection established with {peer_id}");
                 // Count successful peer dials. Infrastructure peers (relay /
                 // rendezvous) are excluded so the rate reflects sync-peer
@@ -1299,17 +1777,21 @@ impl EngineRunner {
 
                     // Cache this (peer_id, multiaddr) so the next cold start
                     // can dial it directly before discovery (#29). Skip the
-                    // relay so the cache stays a sync-peer set.
-                    let db = self.db.clone();
-                    let peer_str = peer_id.to_string();
-                    let addr_str = endpoint.get_remote_address().to_string();
-                    tokio::spawn(async move {
-                        if let Err(e) =
-                            crate::peer_addrs::record_success(&db, &peer_str, &addr_str).await
-                        {
-                            log::debug!("peer_addrs::record_success failed: {e}");
-                        }
-                    });
+                    // relay so the cache stays a sync-peer set, and browsers
+                    // on WebRTC or WebSocket, which can't be dialed back.
+                    let remote = endpoint.get_remote_address();
+                    if !is_browser_listener(remote) {
+                        let db = self.db.clone();
+                        let peer_str = peer_id.to_string();
+                        let addr_str = remote.to_string();
+                        tokio::spawn(async move {
+                            if let Err(e) =
+                                crate::peer_addrs::record_success(&db, &peer_str, &addr_str).await
+                            {
+                                log::debug!("peer_addrs::record_success failed: {e}");
+                            }
+                        });
+                    }
                 }
                 self.handle_connection_established(peer_id, &endpoint).await;
             }
@@ -1630,6 +2112,12 @@ impl EngineRunner {
     }
 }
 
+/// Whether `addr` is one of the listeners meant for browsers, WebRTC or
+/// WebSocket, which native peers don't dial.
+pub(crate) fn is_browser_listener(addr: &Multiaddr) -> bool {
+    webrtc_listener::is_webrtc_direct(addr) || websocket_listener::is_websocket(addr)
+}
+
 /// Partition a flat list of bootstrap multiaddrs into two buckets:
 ///
 ///   * Multiaddrs that end in `/p2p/<peer-id>` are grouped by peer-id so a
@@ -1727,6 +2215,16 @@ mod tests {
         assert_eq!(grouped[&pid], vec![with_pid]);
     }
 
+    #[test]
+    fn is_browser_listener_skips_quic() {
+        let webrtc: Multiaddr = "/ip4/192.168.1.2/udp/4001/webrtc-direct".parse().unwrap();
+        let wss: Multiaddr = "/ip4/192.168.1.2/tcp/4002/tls/ws".parse().unwrap();
+        let quic: Multiaddr = "/ip4/192.168.1.2/udp/4001/quic-v1".parse().unwrap();
+        assert!(is_browser_listener(&webrtc));
+        assert!(is_browser_listener(&wss));
+        assert!(!is_browser_listener(&quic));
+    }
+
     #[test]
     fn group_bootstrap_addrs_empty_input() {
         let (grouped, suffixless) = group_bootstrap_addrs(vec![]);
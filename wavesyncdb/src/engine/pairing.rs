@@ -0,0 +1,246 @@
+//! Pairing new devices into the group (see [`crate::pairing`]).
+//!
+//! The engine answers the inviting side of the exchange on its own swarm.
+//! The joining side runs before the device has any credentials, so
+//! [`join`] dials the inviter from a short-lived swarm of its own.
+
+use super::*;
+
+use libp2p_swarm_derive::NetworkBehaviour;
+
+use crate::network_status::NetworkEvent;
+use crate::pairing::{
+    Invitation, Joiner, PAIRING_PROTOCOL, PairingCodec, PairingCredentials, PairingError,
+    PairingInvite, PairingRequest, PairingResponse, generate_code,
+};
+
+impl EngineRunner {
+    /// Open an invitation valid for `ttl`, replacing any open one.
+    pub(super) fn start_pairing(&mut self, ttl: Duration) -> Result<PairingInvite, String> {
+        let addrs = self.pairing_addresses();
+        if addrs.is_empty() {
+            return Err("the engine isn't listening on any address yet".to_string());
+        }
+        let code = generate_code();
+        self.pairing = Some(Invitation::new(code.clone(), unix_now() + ttl.as_secs()));
+        log::info!("Opened a pairing invitation for {}s", ttl.as_secs());
+        Ok(PairingInvite {
+            peer_id: self.local_peer_id.to_string(),
+            addrs,
+            code,
+        })
+    }
+
+    /// Addresses a new device can reach us on, external ones first, each
+    /// ending in our peer id.
+    fn pairing_addresses(&self) -> Vec<String> {
+        let mut addrs: Vec<String> = Vec::new();
+        for addr in self
+            .swarm
+            .external_addresses()
+            .chain(self.swarm.listeners())
+        {
+            let addr = addr
+                .clone()
+                .with_p2p(self.local_peer_id)
+                .unwrap_or_else(|addr| addr)
+                .to_string();
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+        addrs
+    }
+
+    fn pairing_credentials(&self) -> PairingCredentials {
+        PairingCredentials {
+            topic: self.user_topic.clone(),
+            passphrase: self.key_epoch.as_ref().map(|e| e.passphrase.clone()),
+            group_salt: self.key_epoch.as_ref().and_then(|e| e.salt),
+            relay_server: self.config.relay_server.as_ref().map(ToString::to_string),
+            rendezvous_server: self
+                .config
+                .rendezvous_server
+                .as_ref()
+                .map(ToString::to_string),
+            bootstrap_peers: self.pairing_addresses(),
+        }
+    }
+
+    pub(super) fn handle_pairing_event(
+        &mut self,
+        event: request_response::Event<PairingRequest, PairingResponse>,
+    ) {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message:
+                    request_response::Message::Request {
+                        request, channel, ..
+                    },
+                ..
+            } => {
+                let response = self.answer_pairing(peer, request);
+                if self
+                    .swarm
+                    .behaviour_mut()
+                    .pairing
+                    .send_response(channel, response)
+                    .is_err()
+                {
+                    log::debug!("Pairing peer {peer} went away before our answer");
+                }
+            }
+            request_response::Event::InboundFailure { peer, error, .. } => {
+                log::debug!("Pairing request from {peer} failed: {error}");
+            }
+            _ => {}
+        }
+    }
+
+    fn answer_pairing(&mut self, peer: libp2p::PeerId, request: PairingRequest) -> PairingResponse {
+        let credentials = self.pairing_credentials();
+        let (response, paired) =
+            crate::pairing::answer(&mut self.pairing, unix_now(), peer, request, &credentials);
+        if paired {
+            log::info!("Paired device {peer}");
+            self.emit_network_event(NetworkEvent::DevicePaired(crate::network_status::PeerId(
+                peer.to_string(),
+            )));
+        } else if let PairingResponse::Refused { ref reason } = response {
+            log::warn!("Refused pairing with {peer}: {reason}");
+        }
+        response
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Behaviour of the swarm a joining device pairs from: the pairing
+/// protocol, plus the relay client so an invite's circuit addresses work.
+#[derive(NetworkBehaviour)]
+struct JoinBehaviour {
+    relay_client: relay::client::Behaviour,
+    pairing: request_response::Behaviour<PairingCodec>,
+}
+
+/// Pair with the device `invite` names and return the group credentials.
+/// Dials from a swarm under `keypair` — the device's own identity, so the
+/// inviter sees the peer id it will sync as.
+pub(crate) async fn join(
+    keypair: identity::Keypair,
+    invite: &PairingInvite,
+    timeout: Duration,
+) -> Result<PairingCredentials, PairingError> {
+    let inviter: libp2p::PeerId = invite
+        .peer_id
+        .parse()
+        .map_err(|e| PairingError::InvalidInvite(format!("bad peer id: {e}")))?;
+    let addrs: Vec<Multiaddr> = invite
+        .addrs
+        .iter()
+        .filter_map(|addr| addr.parse().ok())
+        .collect();
+    if addrs.is_empty() {
+        return Err(PairingError::InvalidInvite(
+            "no usable address for the inviting device".to_string(),
+        ));
+    }
+
+    let mut swarm = join_swarm(keypair, timeout)?;
+    for addr in addrs {
+        swarm.add_peer_address(inviter, addr);
+    }
+    tokio::time::timeout(timeout, run_join(&mut swarm, inviter, &invite.code))
+        .await
+        .map_err(|_| PairingError::Timeout)?
+}
+
+fn join_swarm(
+    keypair: identity::Keypair,
+    timeout: Duration,
+) -> Result<libp2p::Swarm<JoinBehaviour>, PairingError> {
+    let behaviour = move |_: &identity::Keypair, relay_client| JoinBehaviour {
+        relay_client,
+        pairing: request_response::Behaviour::new(
+            [(PAIRING_PROTOCOL, request_response::ProtocolSupport::Full)],
+            request_response::Config::default().with_request_timeout(timeout),
+        ),
+    };
+    let setup = |e: String| PairingError::Unreachable(format!("failed to set up the swarm: {e}"));
+
+    let system_result = SwarmBuilder::with_existing_identity(keypair.clone())
+        .with_tokio()
+        .with_quic()
+        .with_dns();
+    let swarm = match system_result {
+        Ok(builder) => builder
+            .with_relay_client(noise::Config::new, yamux::Config::default)
+            .map_err(|e| setup(e.to_string()))?
+            .with_behaviour(behaviour)
+            .map_err(|e| setup(e.to_string()))?
+            .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(30)))
+            .build(),
+        Err(e) => {
+            log::warn!("System DNS resolver failed: {e}. Falling back to Google public DNS.");
+            SwarmBuilder::with_existing_identity(keypair)
+                .with_tokio()
+                .with_quic()
+                .with_dns_config(dns::ResolverConfig::google(), dns::ResolverOpts::default())
+                .with_relay_client(noise::Config::new, yamux::Config::default)
+                .map_err(|e| setup(e.to_string()))?
+                .with_behaviour(behaviour)
+                .map_err(|e| setup(e.to_string()))?
+                .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(30)))
+                .build()
+        }
+    };
+    Ok(swarm)
+}
+
+async fn run_join(
+    swarm: &mut libp2p::Swarm<JoinBehaviour>,
+    inviter: libp2p::PeerId,
+    code: &str,
+) -> Result<PairingCredentials, PairingError> {
+    let (mut joiner, start) = Joiner::start(code);
+    swarm.behaviour_mut().pairing.send_request(&inviter, start);
+
+    loop {
+        let event = match swarm.select_next_some().await {
+            SwarmEvent::Behaviour(JoinBehaviourEvent::Pairing(event)) => event,
+            _ => continue,
+        };
+        match event {
+            request_response::Event::Message {
+                peer,
+                message: request_response::Message::Response { response, .. },
+                ..
+            } if peer == inviter => match response {
+                PairingResponse::Started { spake, confirm } => {
+                    let finish = joiner.confirm(&spake, &confirm)?;
+                    swarm.behaviour_mut().pairing.send_request(&inviter, finish);
+                }
+                PairingResponse::Credentials { nonce, sealed } => {
+                    return joiner.open(&nonce, &sealed);
+                }
+                PairingResponse::Refused { reason } => return Err(PairingError::Refused(reason)),
+            },
+            request_response::Event::OutboundFailure { error, .. } => {
+                return Err(match error {
+                    request_response::OutboundFailure::DialFailure => {
+                        PairingError::Unreachable("no address of the invite answered".to_string())
+                    }
+                    request_response::OutboundFailure::Timeout => PairingError::Timeout,
+                    other => PairingError::Protocol(other.to_string()),
+                });
+            }
+            _ => {}
+        }
+    }
+}
@@ -31,25 +31,57 @@ impl EngineRunner {
         if self.infrastructure_peers.contains(&peer_id) {
             return;
         }
+        if !self.handshake_ready(peer_id) {
+            return;
+        }
+
+        // First contact (or a bootstrap already under way): fetch current
+        // state instead of replaying the peer's whole history.
+        if self.snapshot_bootstraps.contains_key(&peer_id)
+            || (!self.catchup_cursors.contains_key(&peer_id)
+                && self.peer_db_versions.get(&peer_id).copied().unwrap_or(0) == 0
+                && !self.snapshot_unsupported.contains(&peer_id))
+        {
+            self.request_state_snapshot(peer_id);
+            return;
+        }
 
-        let their_last_db_version = self.peer_db_versions.get(&peer_id).copied().unwrap_or(0);
+        // An interrupted paginated catch-up resumes from its checkpoint:
+        // same base version, continuing after the last applied page.
+        let (their_last_db_version, cursor) = match self.catchup_cursors.get(&peer_id) {
+            Some((since, cursor)) => (*since, Some(cursor.clone())),
+            None => (
+                self.peer_db_versions.get(&peer_id).copied().unwrap_or(0),
+                None,
+            ),
+        };
 
         log::info!(
-            "Requesting version vector sync from peer {peer_id} (their last known version: {their_last_db_version})"
+            "Requesting version vector sync from peer {peer_id} (their last known version: {their_last_db_version}, resuming: {})",
+            cursor.is_some()
         );
 
+        let auth = self.request_auth(&peer_id);
         let mut req = SyncRequest::VersionVector {
             my_db_version: self.local_db_version,
             your_last_db_version: their_last_db_version,
             site_id: self.site_id,
             topic: self.topic_name.clone(),
+            page_size: Some(sync_handler::CATCHUP_PAGE_SIZE),
+            cursor,
+            // Only to peers that know the field, so their HMAC check
+            // re-serializes it.
+            origin_versions: self
+                .peer_stamps_origin(&peer_id)
+                .then(|| self.local_origin_versions()),
+            seq: auth.as_ref().and_then(|(_, seq)| *seq),
             hmac: None,
         };
 
-        if let Some(ref gk) = self.group_key {
+        if let Some((ref key, _)) = auth {
             // Serialize with hmac: None, compute MAC, then set hmac
             if let Ok(bytes) = serde_json::to_vec(&req) {
-                let tag = gk.mac(&bytes);
+                let tag = key.mac(&bytes);
                 if let SyncRequest::VersionVector { ref mut hmac, .. } = req {
                     *hmac = Some(tag);
                 }
@@ -62,6 +94,8 @@ impl EngineRunner {
             .snapshot
             .send_request(&peer_id, req);
         self.pending_sync_peers.insert(peer_id);
+        self.pending_sync_since
+            .insert(peer_id, their_last_db_version);
     }
 
     pub(super) fn handle_mdns(&mut self, event: mdns::Event) {
@@ -81,6 +115,11 @@ impl EngineRunner {
                     if self.rejected_peers.contains(&peer_id) {
                         continue;
                     }
+                    // Our WebRTC and WebSocket listeners are for browsers;
+                    // native peers reach each other over QUIC.
+                    if is_browser_listener(&multiaddr) {
+                        continue;
+                    }
                     // Skip dial and address update if already tracked and connected —
                     // avoids duplicate dials from multi-address mDNS discovery
                     // (TCP+QUIC × multiple IPs), but still allow sync initiation below
@@ -116,6 +155,9 @@ impl EngineRunner {
                             is_bootstrap: self.bootstrap_peers.contains(&peer_id),
                             is_group_member: false,
                             app_id: None,
+                            identity_verification:
+                                crate::identity::IdentityVerification::Unverified,
+                            capabilities: self.capabilities.for_peer(&peer_id).cloned(),
                         },
                     ));
                     self.update_network_status();
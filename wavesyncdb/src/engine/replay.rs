@@ -0,0 +1,307 @@
+//! Replay protection for HMAC-authenticated sync messages.
+//!
+//! A group-key MAC alone proves a message came from some group member at
+//! some point, so a captured push could be replayed to any member forever.
+//! When a passphrase is set, each side puts a fresh random nonce in its
+//! [`PeerHello`](crate::protocol::PeerHello). Once both nonces are known,
+//! messages between the two peers are MACed with a session key derived
+//! from the group key, both PeerIds and both nonces (see
+//! [`GroupKey::session_key`]), and requests carry a `seq` counter checked
+//! against a sliding window. A message from another connection fails the
+//! session MAC; one seen before on this connection fails the window.
+//!
+//! Responses travel on the stream of the request they answer, so they are
+//! bound to the session key but carry no counter of their own.
+//!
+//! A session belongs to the peer, not to one connection: `request_response`
+//! spreads a peer's requests over all its open connections, and Noise has
+//! already proven every one of them is that PeerId. A second connection
+//! therefore shares the session's keys and window — a message replayed on
+//! it is caught all the same — and only a new hello nonce starts over.
+//!
+//! Peers that send no nonce have no session. Browser clients, and peers
+//! that announce [`FEATURE_RECEIVER_MACS`], MAC with a key bound to the
+//! receiver's PeerId (see [`GroupKey::receiver_key`]), so a message
+//! captured on its way to one member is refused by every other. Builds
+//! from before that keep the plain group-key MAC, which is still accepted
+//! from them. Both can be replayed to the member they were meant for,
+//! which is why [`EngineConfig::require_sessions`] can refuse messages
+//! outside a session outright.
+
+use super::*;
+
+use crate::protocol::FEATURE_RECEIVER_MACS;
+
+/// How far behind the highest `seq` seen a request may arrive. Requests
+/// run on separate streams, so concurrent ones can overtake each other.
+const REPLAY_WINDOW: u64 = 64;
+
+/// Sliding window over the request counters seen from one peer.
+#[derive(Debug, Default)]
+pub(crate) struct ReplayWindow {
+    highest: u64,
+    /// Bit `i` set: `highest - i` was seen.
+    seen: u64,
+}
+
+impl ReplayWindow {
+    /// Record `seq`. Returns `false` if it was seen already or is too old
+    /// to tell.
+    pub fn accept(&mut self, seq: u64) -> bool {
+        if seq == 0 {
+            return false;
+        }
+        if seq > self.highest {
+            let shift = seq - self.highest;
+            self.seen = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.seen << shift
+            };
+            self.seen |= 1;
+            self.highest = seq;
+            return true;
+        }
+        let age = self.highest - seq;
+        if age >= REPLAY_WINDOW || self.seen & (1 << age) != 0 {
+            return false;
+        }
+        self.seen |= 1 << age;
+        true
+    }
+}
+
+/// Session state with one connected peer.
+#[derive(Debug)]
+pub(crate) struct Session {
+    local_nonce: [u8; 16],
+    remote_nonce: Option<[u8; 16]>,
+    /// (ours → theirs, theirs → ours), once both nonces are known.
+    keys: Option<(GroupKey, GroupKey)>,
+    next_seq: u64,
+    window: ReplayWindow,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            local_nonce: *uuid::Uuid::new_v4().as_bytes(),
+            remote_nonce: None,
+            keys: None,
+            next_seq: 1,
+            window: ReplayWindow::default(),
+        }
+    }
+}
+
+impl EngineRunner {
+    /// Our nonce for the session with `peer`, if a passphrase is set.
+    pub(super) fn session_nonce(&mut self, peer: libp2p::PeerId) -> Option<[u8; 16]> {
+        self.group_key.as_ref()?;
+        Some(
+            self.sessions
+                .entry(peer)
+                .or_insert_with(Session::new)
+                .local_nonce,
+        )
+    }
+
+    /// Take the nonce from `peer`'s hello. A nonce we haven't seen means a
+    /// new connection on their side, so the counters start over.
+    pub(super) fn note_peer_nonce(&mut self, peer: libp2p::PeerId, nonce: Option<[u8; 16]>) {
+        let (Some(gk), Some(nonce)) = (self.group_key.clone(), nonce) else {
+            return;
+        };
+        let local = self.swarm.local_peer_id().to_bytes();
+        let remote = peer.to_bytes();
+        let session = self.sessions.entry(peer).or_insert_with(Session::new);
+        if session.remote_nonce == Some(nonce) {
+            return;
+        }
+        session.keys = Some((
+            gk.session_key(&local, &remote, &session.local_nonce, &nonce),
+            gk.session_key(&remote, &local, &nonce, &session.local_nonce),
+        ));
+        session.remote_nonce = Some(nonce);
+        session.next_seq = 1;
+        session.window = ReplayWindow::default();
+    }
+
+    /// Forget the session with a peer that disconnected.
+    pub(super) fn end_session(&mut self, peer: &libp2p::PeerId) {
+        self.sessions.remove(peer);
+    }
+
+    /// Key and counter for a request to `peer`: the session key and the
+    /// next `seq` once the session is up, [`Self::key_outside_session`] and
+    /// no counter until then. `None` without a passphrase.
+    pub(super) fn request_auth(
+        &mut self,
+        peer: &libp2p::PeerId,
+    ) -> Option<(GroupKey, Option<u64>)> {
+        let gk = self.group_key.clone()?;
+        match self.sessions.get_mut(peer) {
+            Some(Session {
+                keys: Some((outbound, _)),
+                next_seq,
+                ..
+            }) => {
+                let seq = *next_seq;
+                *next_seq += 1;
+                Some((outbound.clone(), Some(seq)))
+            }
+            _ => Some((self.key_outside_session(peer, &gk), None)),
+        }
+    }
+
+    /// Key for a response to `peer`.
+    pub(super) fn response_key(&self, peer: &libp2p::PeerId) -> Option<GroupKey> {
+        let gk = self.group_key.as_ref()?;
+        match self.sessions.get(peer) {
+            Some(Session {
+                keys: Some((outbound, _)),
+                ..
+            }) => Some(outbound.clone()),
+            _ => Some(self.key_outside_session(peer, gk)),
+        }
+    }
+
+    /// Key for a message to `peer` while there's no session with it: bound
+    /// to its PeerId if it accepts that, the plain group key for builds
+    /// that predate receiver-bound MACs.
+    fn key_outside_session(&self, peer: &libp2p::PeerId, gk: &GroupKey) -> GroupKey {
+        if self.peer_supports(peer, FEATURE_RECEIVER_MACS) {
+            gk.receiver_key(&peer.to_bytes())
+        } else {
+            gk.clone()
+        }
+    }
+
+    /// Check a request's tag over `bytes` (serialized with `hmac: None`)
+    /// and its `seq`. Only call with a group key configured.
+    pub(super) fn verify_request(
+        &mut self,
+        peer: libp2p::PeerId,
+        bytes: &[u8],
+        tag: &[u8; 32],
+        seq: Option<u64>,
+    ) -> bool {
+        let Some(gk) = self.group_key.clone() else {
+            return true;
+        };
+        let Some(Session {
+            keys: Some((_, inbound)),
+            window,
+            ..
+        }) = self.sessions.get_mut(&peer)
+        else {
+            return self.verify_outside_session(peer, &gk, bytes, tag);
+        };
+        let authentic = seq.is_some() && inbound.verify(bytes, tag);
+        if authentic && seq.is_some_and(|seq| window.accept(seq)) {
+            return true;
+        }
+        // A group-key tag, or a session tag with a spent counter, is a
+        // genuine message from somewhere else or from earlier.
+        if authentic || gk.verify(bytes, tag) {
+            self.record_replay(peer);
+        }
+        false
+    }
+
+    /// Check a response's tag over `bytes` (serialized with `hmac: None`).
+    /// Only call with a group key configured.
+    pub(super) fn verify_response(
+        &self,
+        peer: libp2p::PeerId,
+        bytes: &[u8],
+        tag: &[u8; 32],
+    ) -> bool {
+        let Some(gk) = self.group_key.clone() else {
+            return true;
+        };
+        let Some(Session {
+            keys: Some((_, inbound)),
+            ..
+        }) = self.sessions.get(&peer)
+        else {
+            return self.verify_outside_session(peer, &gk, bytes, tag);
+        };
+        if inbound.verify(bytes, tag) {
+            return true;
+        }
+        if gk.verify(bytes, tag) {
+            self.record_replay(peer);
+        }
+        false
+    }
+
+    /// Check a tag from `peer`, which has no session with us. It has to be
+    /// made under the group key or, during a rotation's grace window, the
+    /// key it replaced: sessions are only ever set up under the current
+    /// key, so that lets in peers that haven't moved yet.
+    ///
+    /// Peers that announce receiver-bound MACs must bind the tag to us.
+    /// Older builds MAC with the plain key, which is accepted unless
+    /// sessions are required.
+    fn verify_outside_session(
+        &self,
+        peer: libp2p::PeerId,
+        gk: &GroupKey,
+        bytes: &[u8],
+        tag: &[u8; 32],
+    ) -> bool {
+        if self.config.require_sessions {
+            log::debug!("Rejecting message from peer {peer} outside a session");
+            return false;
+        }
+        let local = self.swarm.local_peer_id().to_bytes();
+        if gk.receiver_key(&local).verify(bytes, tag)
+            || self
+                .previous_key()
+                .is_some_and(|previous| previous.receiver_key(&local).verify(bytes, tag))
+        {
+            return true;
+        }
+        let plain = gk.verify(bytes, tag)
+            || self
+                .previous_key()
+                .is_some_and(|previous| previous.verify(bytes, tag));
+        if plain && self.peer_supports(&peer, FEATURE_RECEIVER_MACS) {
+            // The peer binds its own tags, so a plain one was captured on
+            // its way to some other member.
+            self.record_replay(peer);
+            return false;
+        }
+        plain
+    }
+
+    fn record_replay(&self, peer: libp2p::PeerId) {
+        self.diagnostics
+            .replays_rejected
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        log::warn!("Rejecting replayed or cross-connection message from peer {peer}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_window_rejects_repeats_and_stale_counters() {
+        let mut window = ReplayWindow::default();
+        assert!(!window.accept(0));
+        assert!(window.accept(1));
+        assert!(!window.accept(1));
+        // Overtaken requests still get in once.
+        assert!(window.accept(5));
+        assert!(window.accept(3));
+        assert!(!window.accept(3));
+        assert!(window.accept(2));
+
+        assert!(window.accept(5 + REPLAY_WINDOW));
+        assert!(!window.accept(5), "fell out of the window");
+        assert!(window.accept(6));
+    }
+}
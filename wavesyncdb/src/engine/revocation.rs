@@ -0,0 +1,237 @@
+//! Revoking devices and enforcing revocations (see [`crate::revocation`]).
+//!
+//! Revocations are exchanged after every handshake with a peer that
+//! announces them, and a newly learned one is passed on to every connected
+//! peer that does. A revoked device is cut off as soon as we learn of it,
+//! and refused when it connects, says hello, asks for changes or pushes.
+
+use super::*;
+
+use crate::protocol::{FEATURE_REVOCATIONS, SyncResponse};
+use crate::revocation::{Revocation, key_for_peer, peer_for_key};
+
+impl EngineRunner {
+    /// Whether `peer` is a revoked device.
+    pub(super) fn is_revoked_peer(&self, peer: &libp2p::PeerId) -> bool {
+        self.revocations.is_revoked_peer(peer)
+    }
+
+    /// Whether `site`'s changes come from a revoked device.
+    pub(super) fn is_revoked_site(&self, site: &NodeId) -> bool {
+        self.revocations.is_revoked_site(site, &self.site_keys)
+    }
+
+    /// Whether the device with identity `key` may revoke others: it is an
+    /// administrator, or a member we know — verified in a handshake, or
+    /// signing changes we hold. Whether it is revoked itself is up to the
+    /// list.
+    fn may_revoke(&self, key: &[u8; 32]) -> bool {
+        self.config.admin_keys.contains(key)
+            || self.site_keys.values().any(|k| k == key)
+            || self
+                .verified_peers
+                .iter()
+                .any(|p| key_for_peer(p).as_ref() == Some(key))
+    }
+
+    /// If `peer` is a revoked device, drop its connection and return `true`.
+    pub(super) fn refuse_revoked(&mut self, peer: libp2p::PeerId) -> bool {
+        if !self.is_revoked_peer(&peer) {
+            return false;
+        }
+        self.diagnostics
+            .revoked_rejected
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        log::debug!("Refusing revoked device {peer}");
+        let _ = self.swarm.disconnect_peer_id(peer);
+        true
+    }
+
+    /// Revoke `peer` from the group, then rotate the group key to a fresh
+    /// passphrase that the revoked device never sees. The key it holds gets
+    /// no grace window. Returns the new passphrase, for devices that were
+    /// offline to be given by hand.
+    pub(super) async fn revoke_device(
+        &mut self,
+        peer: libp2p::PeerId,
+    ) -> Result<Option<String>, String> {
+        if peer == *self.swarm.local_peer_id() {
+            return Err("a device can't revoke itself".to_string());
+        }
+        let key = key_for_peer(&peer)
+            .ok_or_else(|| format!("peer {peer} has no ed25519 identity key"))?;
+        if self.revocations.is_revoked(&key) {
+            return Err(format!("peer {peer} is already revoked"));
+        }
+
+        let revoked_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        log::info!("Revoking device {peer}");
+        self.learn_revocations(vec![Revocation::issue(&self.signer, key, revoked_at)], None)
+            .await;
+
+        if self.group_key.is_none() {
+            return Ok(None);
+        }
+        let mut random = [0u8; 32];
+        random[..16].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+        random[16..].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+        let passphrase = crate::seal::to_hex(&random);
+        self.rotate_passphrase(&passphrase, Some(0)).await?;
+        Ok(Some(passphrase))
+    }
+
+    /// Add `incoming` to our revocations, then persist, enforce and pass on
+    /// the ones that are new — to every peer but `from`, which sent them.
+    async fn learn_revocations(&mut self, incoming: Vec<Revocation>, from: Option<libp2p::PeerId>) {
+        let mut revocations = std::mem::take(&mut self.revocations);
+        let added = revocations.merge(incoming, |key| self.may_revoke(key));
+        self.revocations = revocations;
+        if added.is_empty() {
+            return;
+        }
+
+        for revocation in &added {
+            if let Err(e) = peer_tracker::record_revocation(&self.db, revocation).await {
+                log::warn!("Failed to persist a revocation: {e}");
+            }
+            if let Some(peer) = peer_for_key(&revocation.key) {
+                log::info!("Device {peer} is revoked from the group");
+                self.emit_network_event(crate::network_status::NetworkEvent::DeviceRevoked(
+                    crate::network_status::PeerId(peer.to_string()),
+                ));
+            }
+        }
+
+        let revoked: Vec<libp2p::PeerId> = self
+            .peers
+            .keys()
+            .copied()
+            .filter(|p| self.is_revoked_peer(p))
+            .collect();
+        for peer in revoked {
+            self.reject_peer(peer);
+            let _ = self.swarm.disconnect_peer_id(peer);
+        }
+
+        let others: Vec<libp2p::PeerId> = self
+            .peers
+            .keys()
+            .copied()
+            .filter(|p| Some(*p) != from && self.peer_supports(p, FEATURE_REVOCATIONS))
+            .collect();
+        for peer in others {
+            self.send_revocations(peer, added.clone());
+        }
+    }
+
+    /// Offer our revocations to `peer` once its handshake has settled. The
+    /// list goes even when empty: the reply carries the peer's.
+    pub(super) fn exchange_revocations(&mut self, peer: libp2p::PeerId) {
+        if self.peer_supports(&peer, FEATURE_REVOCATIONS) {
+            self.send_revocations(peer, self.revocations.entries());
+        }
+    }
+
+    fn send_revocations(&mut self, peer: libp2p::PeerId, revocations: Vec<Revocation>) {
+        let auth = self.request_auth(&peer);
+        let mut req = SyncRequest::Revocations {
+            revocations,
+            seq: auth.as_ref().and_then(|(_, seq)| *seq),
+            hmac: None,
+        };
+
+        if let Some((ref key, _)) = auth
+            && let Ok(bytes) = serde_json::to_vec(&req)
+        {
+            let tag = key.mac(&bytes);
+            if let SyncRequest::Revocations { ref mut hmac, .. } = req {
+                *hmac = Some(tag);
+            }
+        }
+
+        self.swarm.behaviour_mut().snapshot.send_request(&peer, req);
+    }
+
+    /// Verify HMAC, take the revocations we lack and answer with the ones
+    /// the peer lacks.
+    pub(super) async fn handle_revocations_request(
+        &mut self,
+        peer: libp2p::PeerId,
+        channel: request_response::ResponseChannel<SyncResponse>,
+        revocations: Vec<Revocation>,
+        seq: Option<u64>,
+        req_hmac: Option<[u8; 32]>,
+    ) {
+        if self.refuse_revoked(peer) {
+            return;
+        }
+        if self.group_key.is_some() {
+            let Some(tag) = req_hmac else {
+                log::debug!("Rejecting unauthenticated revocations from peer {peer}");
+                return;
+            };
+            let verify_req = SyncRequest::Revocations {
+                revocations: revocations.clone(),
+                seq,
+                hmac: None,
+            };
+            if let Ok(bytes) = serde_json::to_vec(&verify_req)
+                && !self.verify_request(peer, &bytes, &tag, seq)
+            {
+                log::debug!("Rejecting revocations with invalid HMAC from peer {peer}");
+                return;
+            }
+        }
+
+        let mut resp = SyncResponse::Revocations {
+            revocations: self.revocations.missing_from(&revocations),
+            hmac: None,
+        };
+        if let Some(key) = self.response_key(&peer)
+            && let Ok(bytes) = serde_json::to_vec(&resp)
+        {
+            let tag = key.mac(&bytes);
+            if let SyncResponse::Revocations { ref mut hmac, .. } = resp {
+                *hmac = Some(tag);
+            }
+        }
+        let resp_tx = self.snapshot_resp_tx.clone();
+        tokio::spawn(async move {
+            let _ = resp_tx.send((channel, resp)).await;
+        });
+
+        self.learn_revocations(revocations, Some(peer)).await;
+    }
+
+    /// Verify HMAC and take the revocations we lacked.
+    pub(super) async fn handle_revocations_response(
+        &mut self,
+        peer: libp2p::PeerId,
+        revocations: Vec<Revocation>,
+        resp_hmac: Option<[u8; 32]>,
+    ) {
+        if self.refuse_revoked(peer) {
+            return;
+        }
+        if self.group_key.is_some() {
+            let Some(tag) = resp_hmac else {
+                log::debug!("Rejecting unauthenticated revocations response from peer {peer}");
+                return;
+            };
+            let verify_resp = SyncResponse::Revocations {
+                revocations: revocations.clone(),
+                hmac: None,
+            };
+            if let Ok(bytes) = serde_json::to_vec(&verify_resp)
+                && !self.verify_response(peer, &bytes, &tag)
+            {
+                log::debug!("Rejecting revocations response with invalid HMAC from peer {peer}");
+                return;
+            }
+        }
+        self.learn_revocations(revocations, Some(peer)).await;
+    }
+}
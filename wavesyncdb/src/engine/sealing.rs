@@ -0,0 +1,83 @@
+//! Sealing outgoing change payloads and opening incoming ones (see
+//! [`crate::seal`]).
+//!
+//! Sealing happens after a message's HMAC is computed and opening before
+//! it is checked, so the handlers never see an envelope.
+
+use super::*;
+
+use crate::protocol::{FEATURE_SEALED_PAYLOADS, SyncResponse};
+use crate::seal::{PayloadKey, SealError};
+
+impl EngineRunner {
+    /// Key to seal changes for `peer` with, if encryption is on and the
+    /// peer's hello says it can open them.
+    pub(super) fn sealing_key(&self, peer: &libp2p::PeerId) -> Option<PayloadKey> {
+        if !self.config.encrypt_payloads {
+            return None;
+        }
+        let gk = self.group_key.as_ref()?;
+        self.peer_supports(peer, FEATURE_SEALED_PAYLOADS)
+            .then(|| gk.payload_key())
+    }
+
+    /// Seal the changes in a signed request to `peer`.
+    pub(super) fn seal_request(&self, peer: &libp2p::PeerId, req: &mut SyncRequest) {
+        if let Some(key) = self.sealing_key(peer) {
+            req.seal_changes(&key);
+        }
+    }
+
+    /// Open sealed changes in a request from `peer`. Returns `false` if
+    /// the request has to be dropped.
+    pub(super) fn open_request(&self, peer: libp2p::PeerId, req: &mut SyncRequest) -> bool {
+        match self.open_with_keys(req, SyncRequest::open_changes) {
+            Ok(()) => true,
+            Err(e) => {
+                self.record_unopenable(peer, &e);
+                false
+            }
+        }
+    }
+
+    /// Open sealed changes in a response from `peer`. Returns `false` if
+    /// the response has to be dropped.
+    pub(super) fn open_response(&self, peer: libp2p::PeerId, resp: &mut SyncResponse) -> bool {
+        match self.open_with_keys(resp, SyncResponse::open_changes) {
+            Ok(()) => true,
+            Err(e) => {
+                self.record_unopenable(peer, &e);
+                false
+            }
+        }
+    }
+
+    /// Open `msg` with our payload key or, during a key rotation's grace
+    /// window, with the previous one — a peer that hasn't moved yet seals
+    /// with that.
+    fn open_with_keys<M: Clone>(
+        &self,
+        msg: &mut M,
+        open: impl Fn(&mut M, Option<&PayloadKey>) -> Result<(), SealError>,
+    ) -> Result<(), SealError> {
+        let key = self.group_key.as_ref().map(GroupKey::payload_key);
+        let Some(previous) = self.previous_key() else {
+            return open(msg, key.as_ref());
+        };
+        let original = msg.clone();
+        match open(msg, key.as_ref()) {
+            Err(SealError::Decrypt) => {
+                *msg = original;
+                open(msg, Some(&previous.payload_key()))
+            }
+            other => other,
+        }
+    }
+
+    fn record_unopenable(&self, peer: libp2p::PeerId, err: &SealError) {
+        self.diagnostics
+            .sealed_payloads_rejected
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        log::warn!("Dropping message with unopenable sealed changes from peer {peer}: {err}");
+    }
+}
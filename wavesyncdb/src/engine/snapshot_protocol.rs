@@ -1,22 +1,81 @@
 //! libp2p request-response codec for version vector sync protocol.
 //!
 //! Uses length-prefixed serde_json serialization for `SyncRequest` / `SyncResponse`.
+//! Two protocol ids are registered: [`SNAPSHOT_PROTOCOL_COMPRESSED`] is
+//! offered first and frames each payload with a compression flag (see
+//! [`crate::compression`]); [`SNAPSHOT_PROTOCOL`] is the original
+//! uncompressed framing, kept so older peers still negotiate.
 
 use std::io;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use futures::prelude::*;
 use libp2p::StreamProtocol;
 use libp2p::request_response;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 
+use crate::compression;
+use crate::diagnostics::Counters;
 use crate::protocol::{SyncRequest, SyncResponse};
 
 /// Protocol identifier for the sync protocol.
 pub const SNAPSHOT_PROTOCOL: StreamProtocol = StreamProtocol::new("/wavesync/snapshot/3.0.0");
 
+/// Same messages as [`SNAPSHOT_PROTOCOL`], with payloads above
+/// [`compression::COMPRESSION_THRESHOLD`] zstd-compressed.
+pub const SNAPSHOT_PROTOCOL_COMPRESSED: StreamProtocol =
+    StreamProtocol::new("/wavesync/snapshot/3.1.0");
+
 /// Codec for serializing/deserializing sync messages.
+///
+/// Picks the framing from the protocol negotiated for each stream, so one
+/// codec instance serves both compressed and legacy peers.
 #[derive(Debug, Clone, Default)]
-pub struct SnapshotCodec;
+pub struct SnapshotCodec {
+    diagnostics: Option<Arc<Counters>>,
+}
+
+impl SnapshotCodec {
+    /// Codec that records compression ratios into the engine's counters.
+    pub(crate) fn with_diagnostics(diagnostics: Arc<Counters>) -> Self {
+        Self {
+            diagnostics: Some(diagnostics),
+        }
+    }
+
+    async fn read_message<T, M>(&self, protocol: &StreamProtocol, io: &mut T) -> io::Result<M>
+    where
+        T: AsyncRead + Unpin + Send,
+        M: DeserializeOwned,
+    {
+        let mut bytes = read_length_prefixed(io).await?;
+        if *protocol == SNAPSHOT_PROTOCOL_COMPRESSED {
+            bytes = compression::decode(&bytes)?;
+        }
+        serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    async fn write_message<T, M>(
+        &self,
+        protocol: &StreamProtocol,
+        io: &mut T,
+        msg: &M,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+        M: Serialize + Sync,
+    {
+        let bytes =
+            serde_json::to_vec(msg).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if *protocol != SNAPSHOT_PROTOCOL_COMPRESSED {
+            return write_length_prefixed(io, &bytes).await;
+        }
+        let encoded = compression::encode_recorded(&bytes, self.diagnostics.as_deref());
+        write_length_prefixed(io, &encoded).await
+    }
+}
 
 #[async_trait]
 impl request_response::Codec for SnapshotCodec {
@@ -26,54 +85,48 @@ impl request_response::Codec for SnapshotCodec {
 
     async fn read_request<T>(
         &mut self,
-        _protocol: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
     ) -> io::Result<Self::Request>
     where
         T: AsyncRead + Unpin + Send,
     {
-        let bytes = read_length_prefixed(io).await?;
-        serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        self.read_message(protocol, io).await
     }
 
     async fn read_response<T>(
         &mut self,
-        _protocol: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
     ) -> io::Result<Self::Response>
     where
         T: AsyncRead + Unpin + Send,
     {
-        let bytes = read_length_prefixed(io).await?;
-        serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        self.read_message(protocol, io).await
     }
 
     async fn write_request<T>(
         &mut self,
-        _protocol: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
         req: Self::Request,
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send,
     {
-        let bytes =
-            serde_json::to_vec(&req).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        write_length_prefixed(io, &bytes).await
+        self.write_message(protocol, io, &req).await
     }
 
     async fn write_response<T>(
         &mut self,
-        _protocol: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
         res: Self::Response,
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send,
     {
-        let bytes =
-            serde_json::to_vec(&res).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        write_length_prefixed(io, &bytes).await
+        self.write_message(protocol, io, &res).await
     }
 }
 
@@ -137,13 +190,17 @@ mod tests {
 
     #[tokio::test]
     async fn test_codec_request_roundtrip() {
-        let mut codec = SnapshotCodec;
+        let mut codec = SnapshotCodec::default();
         let protocol = SNAPSHOT_PROTOCOL;
         let req = SyncRequest::VersionVector {
             my_db_version: 42,
             your_last_db_version: 10,
             site_id: crate::messages::NodeId([1u8; 16]),
             topic: "test-topic".to_string(),
+            page_size: None,
+            cursor: None,
+            origin_versions: None,
+            seq: None,
             hmac: None,
         };
         let mut buf = Cursor::new(Vec::new());
@@ -166,7 +223,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_codec_response_roundtrip() {
-        let mut codec = SnapshotCodec;
+        let mut codec = SnapshotCodec::default();
         let protocol = SNAPSHOT_PROTOCOL;
         let resp = SyncResponse::ChangesetResponse {
             changes: vec![],
@@ -174,6 +231,9 @@ mod tests {
             your_last_db_version: 50,
             site_id: crate::messages::NodeId([2u8; 16]),
             topic: "test-topic".to_string(),
+            next_cursor: None,
+            origin_versions: None,
+            sealed: None,
             hmac: None,
         };
         let mut buf = Cursor::new(Vec::new());
@@ -199,7 +259,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_codec_read_request_invalid_data() {
-        let mut codec = SnapshotCodec;
+        let mut codec = SnapshotCodec::default();
         let protocol = SNAPSHOT_PROTOCOL;
         let garbage = vec![0xFF, 0xFE, 0xFD, 0xFC];
         let mut buf = Cursor::new(Vec::new());
@@ -210,4 +270,72 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
     }
+
+    fn large_changeset_response() -> SyncResponse {
+        let changes = (0..200)
+            .map(|i| crate::messages::ColumnChange {
+                table: "tasks".into(),
+                pk: format!("task-{i}").into(),
+                cid: "title".into(),
+                val: Some(serde_json::json!("a fairly repetitive task title")),
+                site_id: crate::messages::NodeId([3u8; 16]),
+                col_version: 1,
+                cl: 1,
+                seq: i,
+                db_version: i as u64 + 1,
+                sig: None,
+            })
+            .collect();
+        SyncResponse::ChangesetResponse {
+            changes,
+            my_db_version: 200,
+            your_last_db_version: 0,
+            site_id: crate::messages::NodeId([3u8; 16]),
+            topic: "test-topic".to_string(),
+            next_cursor: None,
+            origin_versions: None,
+            sealed: None,
+            hmac: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compressed_protocol_roundtrip_records_ratio() {
+        let diagnostics = Arc::new(Counters::default());
+        let mut codec = SnapshotCodec::with_diagnostics(Arc::clone(&diagnostics));
+        let protocol = SNAPSHOT_PROTOCOL_COMPRESSED;
+        let mut buf = Cursor::new(Vec::new());
+        codec
+            .write_response(&protocol, &mut buf, large_changeset_response())
+            .await
+            .unwrap();
+        let written = buf.into_inner();
+
+        let snap = diagnostics.snapshot();
+        assert_eq!(snap.payloads_compressed, 1);
+        assert_eq!(snap.compression_output_bytes as usize, written.len() - 4);
+        assert!(snap.compression_ratio().unwrap() > 2.0);
+
+        let mut reader = Cursor::new(written);
+        match codec.read_response(&protocol, &mut reader).await.unwrap() {
+            SyncResponse::ChangesetResponse { changes, .. } => assert_eq!(changes.len(), 200),
+            _ => panic!("Expected ChangesetResponse"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_legacy_protocol_is_never_compressed() {
+        let diagnostics = Arc::new(Counters::default());
+        let mut codec = SnapshotCodec::with_diagnostics(Arc::clone(&diagnostics));
+        let protocol = SNAPSHOT_PROTOCOL;
+        let mut buf = Cursor::new(Vec::new());
+        codec
+            .write_response(&protocol, &mut buf, large_changeset_response())
+            .await
+            .unwrap();
+        let written = buf.into_inner();
+        assert_eq!(diagnostics.snapshot().payloads_compressed, 0);
+        // Plain JSON straight after the length prefix — what a 3.0.0 peer expects.
+        assert_eq!(written[4], b'{');
+    }
 }
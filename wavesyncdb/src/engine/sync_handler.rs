@@ -1,18 +1,111 @@
 //! Sync request handling and remote changeset application.
 
 use super::*;
+use crate::protocol::{FEATURE_ORIGIN_VERSIONS, OriginVersions, SyncCursor};
+use crate::signing::ChangeSigner;
+
+/// Changes per page this engine asks for during catch-up. Small enough that
+/// a page applies in one short transaction and a dropped connection loses
+/// little work; large enough that a 500k-change history is a few hundred
+/// round trips rather than tens of thousands.
+pub(crate) const CATCHUP_PAGE_SIZE: u32 = 2_000;
+
+/// Upper bound on the page size this engine will serve, whatever the
+/// requester asks for. Keeps a single response well under the codec's
+/// 64 MiB frame cap even with large column values.
+pub(crate) const MAX_CATCHUP_PAGE_SIZE: u32 = 10_000;
+
+/// Remote changes queued for sequential application in the main loop.
+pub(crate) struct RemoteBatch {
+    pub changes: Vec<ColumnChange>,
+    /// Set when the batch is one page of a paginated catch-up, so the main
+    /// loop can checkpoint it once it has committed.
+    pub catchup: Option<CatchupPage>,
+    /// The sender's watermarks, set when this batch completes a catch-up
+    /// or snapshot from it: once committed, we hold everything they cover.
+    pub origin_versions: Option<OriginVersions>,
+    /// Set when the batch is a push, which is acknowledged only after it
+    /// has committed.
+    pub push: Option<PendingPush>,
+}
+
+impl From<Vec<ColumnChange>> for RemoteBatch {
+    fn from(changes: Vec<ColumnChange>) -> Self {
+        Self {
+            changes,
+            catchup: None,
+            origin_versions: None,
+            push: None,
+        }
+    }
+}
+
+/// A received push waiting for its changes to commit.
+pub(crate) struct PendingPush {
+    pub peer: libp2p::PeerId,
+    pub channel: request_response::ResponseChannel<crate::protocol::SyncResponse>,
+    /// The sender's own `db_version` for the changeset; `None` when it was
+    /// forwarded from another origin.
+    pub db_version: Option<u64>,
+    /// `Push::prev_db_version`, to detect a push that never arrived.
+    pub prev_db_version: Option<u64>,
+    /// Where the changeset goes once its changes have committed; `None`
+    /// for one we already had.
+    pub forward: Option<ForwardPush>,
+}
+
+/// A pushed changeset's identity, for marking it seen and gossiping the
+/// changes we kept from it once they have committed.
+pub(crate) struct ForwardPush {
+    pub site_id: NodeId,
+    pub db_version: u64,
+    pub hops: Option<u8>,
+}
+
+/// Forget the origin versions on changes from a peer that doesn't stamp
+/// them: such a peer reports the `db_version` it applied a change at, not
+/// the one its origin wrote it at. Only changes written by `sender` itself
+/// (when known) keep theirs — for those the two are the same.
+pub(super) fn strip_origin_versions(changes: &mut [ColumnChange], sender: Option<NodeId>) {
+    for c in changes {
+        if Some(c.site_id) != sender {
+            c.db_version = 0;
+        }
+    }
+}
+
+/// Checkpoint bookkeeping for one catch-up page.
+pub(crate) struct CatchupPage {
+    pub peer: libp2p::PeerId,
+    /// The `your_last_db_version` the catch-up started from.
+    pub since_db_version: u64,
+    /// Where the next page starts; `None` on the last page.
+    pub next_cursor: Option<SyncCursor>,
+}
+
+/// Hand a catch-up page to the main loop. Returns `false` if the queue was
+/// full and the page was dropped: nothing checkpointed it, so asking the
+/// peer again resumes from the same place.
+pub(super) fn queue_catchup_page(tx: &mpsc::Sender<RemoteBatch>, batch: RemoteBatch) -> bool {
+    tx.try_send(batch).is_ok()
+}
 
 impl EngineRunner {
-    pub(super) fn handle_snapshot(
+    pub(super) async fn handle_snapshot(
         &mut self,
         event: request_response::Event<crate::protocol::SyncRequest, crate::protocol::SyncResponse>,
     ) {
         match event {
             request_response::Event::Message { peer, message, .. } => match message {
                 request_response::Message::Request {
-                    request, channel, ..
+                    mut request,
+                    channel,
+                    ..
                 } => {
                     log::info!("Received sync request from peer {peer}: {request:?}");
+                    if !self.admit_request(peer) || !self.open_request(peer, &mut request) {
+                        return;
+                    }
 
                     match request {
                         SyncRequest::VersionVector {
@@ -20,6 +113,10 @@ impl EngineRunner {
                             your_last_db_version,
                             site_id: peer_site_id,
                             topic: peer_topic,
+                            page_size,
+                            cursor,
+                            origin_versions,
+                            seq,
                             hmac: req_hmac,
                         } => {
                             self.handle_version_vector_request(
@@ -29,41 +126,135 @@ impl EngineRunner {
                                 your_last_db_version,
                                 peer_site_id,
                                 peer_topic,
+                                page_size,
+                                cursor,
+                                origin_versions,
+                                seq,
                                 req_hmac,
                             );
                         }
                         SyncRequest::Push {
                             changeset,
                             topic: peer_topic,
+                            hops,
+                            prev_db_version,
+                            seq,
+                            sealed: _,
                             hmac: req_hmac,
                         } => {
                             self.handle_push_request(
-                                peer, channel, changeset, peer_topic, req_hmac,
+                                peer,
+                                channel,
+                                changeset,
+                                peer_topic,
+                                hops,
+                                prev_db_version,
+                                seq,
+                                req_hmac,
                             );
                         }
+                        SyncRequest::StateSnapshot {
+                            site_id: peer_site_id,
+                            topic: peer_topic,
+                            after,
+                            seq,
+                            hmac: req_hmac,
+                        } => {
+                            self.handle_state_snapshot_request(
+                                peer,
+                                channel,
+                                peer_site_id,
+                                peer_topic,
+                                after,
+                                seq,
+                                req_hmac,
+                            );
+                        }
+                        SyncRequest::AntiEntropy {
+                            query,
+                            topic: peer_topic,
+                            seq,
+                            hmac: req_hmac,
+                        } => {
+                            self.handle_anti_entropy_request(
+                                peer, channel, query, peer_topic, seq, req_hmac,
+                            );
+                        }
+                        SyncRequest::Hello {
+                            hello,
+                            topic: peer_topic,
+                            hmac: req_hmac,
+                        } => {
+                            self.handle_hello_request(peer, channel, hello, peer_topic, req_hmac);
+                        }
                         SyncRequest::IdentityAnnounce {
                             app_id,
+                            claim,
+                            seq,
                             hmac: req_hmac,
                         } => {
-                            self.handle_identity_announce_request(peer, channel, app_id, req_hmac);
+                            self.handle_identity_announce_request(
+                                peer, channel, app_id, claim, seq, req_hmac,
+                            );
+                        }
+                        SyncRequest::KeyRotation {
+                            rotation,
+                            grant,
+                            hmac,
+                        } => {
+                            let received = key_rotation::ReceivedRotation {
+                                rotation,
+                                grant,
+                                hmac,
+                            };
+                            self.handle_key_rotation_request(peer, channel, received)
+                                .await;
+                        }
+                        SyncRequest::Revocations {
+                            revocations,
+                            seq,
+                            hmac: req_hmac,
+                        } => {
+                            self.handle_revocations_request(
+                                peer,
+                                channel,
+                                revocations,
+                                seq,
+                                req_hmac,
+                            )
+                            .await;
+                        }
+                        SyncRequest::Capabilities {
+                            grants,
+                            seq,
+                            hmac: req_hmac,
+                        } => {
+                            self.handle_capabilities_request(peer, channel, grants, seq, req_hmac)
+                                .await;
                         }
                     }
                 }
-                request_response::Message::Response { response, .. } => {
+                request_response::Message::Response { mut response, .. } => {
                     self.pending_sync_peers.remove(&peer);
                     log::info!("Received sync response from peer {peer}");
+                    if !self.open_response(peer, &mut response) {
+                        return;
+                    }
 
                     match response {
                         crate::protocol::SyncResponse::ChangesetResponse {
-                            changes,
+                            mut changes,
                             my_db_version,
                             your_last_db_version,
                             site_id: peer_site_id,
                             topic: peer_topic,
+                            next_cursor,
+                            origin_versions,
+                            sealed: _,
                             hmac: resp_hmac,
                         } => {
                             // Verify HMAC if group key is configured
-                            if let Some(ref gk) = self.group_key {
+                            if self.group_key.is_some() {
                                 let tag = match resp_hmac {
                                     Some(t) => t,
                                     None => {
@@ -80,10 +271,13 @@ impl EngineRunner {
                                         your_last_db_version,
                                         site_id: peer_site_id,
                                         topic: peer_topic.clone(),
+                                        next_cursor: next_cursor.clone(),
+                                        origin_versions: origin_versions.clone(),
+                                        sealed: None,
                                         hmac: None,
                                     };
                                 if let Ok(bytes) = serde_json::to_vec(&verify_resp)
-                                    && !gk.verify(&bytes, &tag)
+                                    && !self.verify_response(peer, &bytes, &tag)
                                 {
                                     log::debug!(
                                         "Rejecting sync response with invalid HMAC from peer {peer}"
@@ -93,7 +287,7 @@ impl EngineRunner {
                             }
 
                             // Ignore responses from peers on a different topic
-                            if !peer_topic.is_empty() && peer_topic != self.topic_name {
+                            if !peer_topic.is_empty() && !self.accepts_topic(&peer_topic) {
                                 log::debug!(
                                     "Ignoring sync response from peer {peer}: topic mismatch (theirs={peer_topic}, ours={})",
                                     self.topic_name
@@ -103,6 +297,50 @@ impl EngineRunner {
                                 return;
                             }
 
+                            let since = self.pending_sync_since.remove(&peer).unwrap_or(0);
+                            if !self.admit_changes(peer, &changes) {
+                                return;
+                            }
+                            if origin_versions.is_none() {
+                                strip_origin_versions(&mut changes, Some(peer_site_id));
+                            }
+
+                            // Mid-catch-up page: more history remains, so we
+                            // are not caught up with this peer yet — leave
+                            // peer_db_versions alone and don't report PeerSynced.
+                            // The page is applied, checkpointed, and the next
+                            // one requested by `finish_catchup_page`.
+                            if let Some(cursor) = next_cursor {
+                                let reported = self.peer_reported_versions.entry(peer).or_insert(0);
+                                *reported = (*reported).max(my_db_version);
+                                log::info!(
+                                    "Received catch-up page of {} changes from peer {peer} (since {since}, more pending)",
+                                    changes.len(),
+                                );
+                                let batch = RemoteBatch {
+                                    changes,
+                                    catchup: Some(CatchupPage {
+                                        peer,
+                                        since_db_version: since,
+                                        next_cursor: Some(cursor),
+                                    }),
+                                    origin_versions: None,
+                                    push: None,
+                                };
+                                // Nothing else asks for the next page until
+                                // this one is applied, so a dropped page
+                                // would stall the catch-up until the next
+                                // sync round: ask for it again right away.
+                                if !queue_catchup_page(&self.remote_changeset_tx, batch) {
+                                    log::warn!(
+                                        "Remote changeset queue full, re-requesting catch-up page from peer {peer}"
+                                    );
+                                    self.initiate_sync_for_peer(peer);
+                                }
+                                return;
+                            }
+                            let resumed_catchup = self.catchup_cursors.contains_key(&peer);
+
                             // Update our knowledge of this peer's version
                             self.peer_db_versions.insert(peer, my_db_version);
                             let reported = self.peer_reported_versions.entry(peer).or_insert(0);
@@ -127,6 +365,11 @@ impl EngineRunner {
                                 log::info!(
                                     "Version vector sync with peer {peer}: already up to date"
                                 );
+                                // Nothing to apply, so their watermarks hold
+                                // for us as they are.
+                                if let Some(theirs) = origin_versions {
+                                    self.adopt_origin_versions(theirs);
+                                }
                                 // Still need to persist the Lamport bump even if no changes
                                 if lamport_bump {
                                     let db = self.db.clone();
@@ -134,6 +377,16 @@ impl EngineRunner {
                                         let _ = shadow::set_db_version(&db, my_db_version).await;
                                     });
                                 }
+                                // An empty last page still completes the catch-up.
+                                if resumed_catchup {
+                                    self.catchup_cursors.remove(&peer);
+                                    let db = self.db.clone();
+                                    let peer_str = peer.to_string();
+                                    tokio::spawn(async move {
+                                        let _ = peer_tracker::clear_catchup_cursor(&db, &peer_str)
+                                            .await;
+                                    });
+                                }
                             } else {
                                 log::info!(
                                     "Received {} changes from peer {peer} (their db_version: {})",
@@ -182,25 +435,93 @@ impl EngineRunner {
                                     .await;
                                 });
 
-                                if let Err(e) = self.remote_changeset_tx.try_send(changes) {
+                                let batch = RemoteBatch {
+                                    changes,
+                                    catchup: resumed_catchup.then_some(CatchupPage {
+                                        peer,
+                                        since_db_version: since,
+                                        next_cursor: None,
+                                    }),
+                                    origin_versions,
+                                    push: None,
+                                };
+                                if let Err(e) = self.remote_changeset_tx.try_send(batch) {
                                     log::warn!(
                                         "Remote changeset queue full, dropping sync response: {e}"
                                     );
                                 }
                             }
                         }
+                        crate::protocol::SyncResponse::AntiEntropy {
+                            reply,
+                            sealed: _,
+                            hmac: resp_hmac,
+                        } => {
+                            self.handle_anti_entropy_reply(peer, reply, resp_hmac);
+                        }
+                        crate::protocol::SyncResponse::Hello {
+                            hello,
+                            hmac: resp_hmac,
+                        } => {
+                            self.handle_hello_response(peer, hello, resp_hmac);
+                        }
+                        crate::protocol::SyncResponse::StateSnapshot {
+                            changes,
+                            my_db_version,
+                            site_id: peer_site_id,
+                            topic: peer_topic,
+                            next,
+                            origin_versions,
+                            sealed: _,
+                            hmac: resp_hmac,
+                        } => {
+                            self.handle_state_snapshot_response(
+                                peer,
+                                changes,
+                                my_db_version,
+                                peer_site_id,
+                                peer_topic,
+                                next,
+                                origin_versions,
+                                resp_hmac,
+                            );
+                        }
                         crate::protocol::SyncResponse::PushAck => {
                             log::debug!("Received PushAck from peer {peer}");
                         }
                         crate::protocol::SyncResponse::IdentityAck => {
                             log::debug!("Received IdentityAck from peer {peer}");
                         }
+                        crate::protocol::SyncResponse::KeyRotationAck => {
+                            log::debug!("Received KeyRotationAck from peer {peer}");
+                        }
+                        crate::protocol::SyncResponse::Revocations {
+                            revocations,
+                            hmac: resp_hmac,
+                        } => {
+                            self.handle_revocations_response(peer, revocations, resp_hmac)
+                                .await;
+                        }
+                        crate::protocol::SyncResponse::Capabilities {
+                            grants,
+                            hmac: resp_hmac,
+                        } => {
+                            self.handle_capabilities_response(peer, grants, resp_hmac)
+                                .await;
+                        }
                     }
                 }
             },
-            request_response::Event::OutboundFailure { peer, error, .. } => {
+            request_response::Event::OutboundFailure {
+                peer,
+                request_id,
+                error,
+                ..
+            } => {
                 self.pending_sync_peers.remove(&peer);
                 log::warn!("Sync request to {peer} failed: {error}");
+                self.handle_hello_failure(peer, request_id, &error);
+                self.handle_state_snapshot_failure(peer, request_id, &error);
                 // Connection might be dead — re-dial if we know the peer's address
                 if let Some(addr) = self.peers.get(&peer).cloned()
                     && !self.swarm.is_connected(&peer)
@@ -218,6 +539,11 @@ impl EngineRunner {
 
     /// Verify HMAC + topic, reject mismatched peers, then spawn a task to query
     /// changes since the peer's last known version and send a `ChangesetResponse`.
+    ///
+    /// When the requester sets `page_size`, only one page (starting after
+    /// `cursor`) is sent, with a `next_cursor` if more remain. When it sends
+    /// `origin_versions`, only changes past its per-origin watermarks are
+    /// sent, along with ours.
     #[allow(clippy::too_many_arguments)]
     fn handle_version_vector_request(
         &mut self,
@@ -227,10 +553,17 @@ impl EngineRunner {
         your_last_db_version: u64,
         peer_site_id: NodeId,
         peer_topic: String,
+        page_size: Option<u32>,
+        cursor: Option<SyncCursor>,
+        origin_versions: Option<OriginVersions>,
+        seq: Option<u64>,
         req_hmac: Option<[u8; 32]>,
     ) {
+        if self.refuse_revoked(peer) {
+            return;
+        }
         // Verify HMAC if group key is configured
-        if let Some(ref gk) = self.group_key {
+        if self.group_key.is_some() {
             let tag = match req_hmac {
                 Some(t) => t,
                 None => {
@@ -244,10 +577,14 @@ impl EngineRunner {
                 your_last_db_version,
                 site_id: peer_site_id,
                 topic: peer_topic.clone(),
+                page_size,
+                cursor: cursor.clone(),
+                origin_versions: origin_versions.clone(),
+                seq,
                 hmac: None,
             };
             if let Ok(bytes) = serde_json::to_vec(&verify_req)
-                && !gk.verify(&bytes, &tag)
+                && !self.verify_request(peer, &bytes, &tag, seq)
             {
                 log::debug!("Rejecting sync request with invalid HMAC from peer {peer}");
                 return;
@@ -268,7 +605,7 @@ impl EngineRunner {
         }
 
         // Reject requests from peers on a different topic
-        if !peer_topic.is_empty() && peer_topic != self.topic_name {
+        if !peer_topic.is_empty() && !self.accepts_topic(&peer_topic) {
             log::debug!(
                 "Ignoring sync request from peer {peer}: topic mismatch (theirs={peer_topic}, ours={})",
                 self.topic_name
@@ -303,22 +640,65 @@ impl EngineRunner {
         let local_site_id = self.site_id;
         let change_tx = self.change_tx.clone();
         let topic_name = self.topic_name.clone();
-        let group_key = self.group_key.clone();
+        let group_key = self.response_key(&peer);
+        let sealing_key = self.sealing_key(&peer);
+        let signer = self.change_signer(&peer);
+        // Captured before the changes are read, like local_db_version.
+        let our_origin_versions = origin_versions
+            .as_ref()
+            .map(|_| self.local_origin_versions());
 
         tokio::spawn(async move {
             // Get changes since the peer's last known version of us
-            let changes =
-                match shadow::get_changes_since(&db, &registry, your_last_db_version).await {
-                    Ok(c) => c,
-                    Err(e) => {
-                        log::error!(
-                            "Failed to get changes since {}: {}",
-                            your_last_db_version,
-                            e
-                        );
-                        Vec::new()
+            let (mut changes, next_cursor) = match page_size {
+                Some(n) => {
+                    let limit = n.clamp(1, MAX_CATCHUP_PAGE_SIZE) as usize;
+                    match shadow::get_changes_page(
+                        &db,
+                        &registry,
+                        your_last_db_version,
+                        origin_versions.as_ref(),
+                        cursor.as_ref(),
+                        limit,
+                    )
+                    .await
+                    {
+                        Ok(page) => page,
+                        Err(e) => {
+                            // Don't answer with an empty last page — the
+                            // requester would take that as "caught up".
+                            // Dropping the channel surfaces as an outbound
+                            // failure and it retries from its checkpoint.
+                            log::error!(
+                                "Failed to get changes page since {your_last_db_version}: {e}"
+                            );
+                            return;
+                        }
                     }
-                };
+                }
+                None => {
+                    match shadow::get_changes_since(
+                        &db,
+                        &registry,
+                        your_last_db_version,
+                        origin_versions.as_ref(),
+                    )
+                    .await
+                    {
+                        Ok(c) => (c, None),
+                        Err(e) => {
+                            log::error!(
+                                "Failed to get changes since {}: {}",
+                                your_last_db_version,
+                                e
+                            );
+                            (Vec::new(), None)
+                        }
+                    }
+                }
+            };
+
+            ChangeSigner::sign_for(signer.as_ref(), &mut changes);
 
             let mut resp = crate::protocol::SyncResponse::ChangesetResponse {
                 changes,
@@ -326,6 +706,9 @@ impl EngineRunner {
                 your_last_db_version: my_db_version,
                 site_id: local_site_id,
                 topic: topic_name,
+                next_cursor,
+                origin_versions: our_origin_versions,
+                sealed: None,
                 hmac: None,
             };
 
@@ -340,6 +723,10 @@ impl EngineRunner {
                 }
             }
 
+            if let Some(ref key) = sealing_key {
+                resp.seal_changes(key);
+            }
+
             if let Err(e) = resp_tx.send((channel, resp)).await {
                 log::error!("Failed to queue sync response: {}", e);
             }
@@ -357,17 +744,26 @@ impl EngineRunner {
         });
     }
 
-    /// Verify HMAC + topic, queue changeset for sequential application, send PushAck.
+    /// Verify HMAC + topic, then queue a changeset for sequential
+    /// application. The PushAck, and forwarding to our other peers, happen
+    /// in [`Self::finish_push`] once the batch commits.
+    #[allow(clippy::too_many_arguments)]
     fn handle_push_request(
         &mut self,
         peer: libp2p::PeerId,
         channel: request_response::ResponseChannel<crate::protocol::SyncResponse>,
         changeset: SyncChangeset,
         peer_topic: String,
+        hops: Option<u8>,
+        prev_db_version: Option<u64>,
+        seq: Option<u64>,
         req_hmac: Option<[u8; 32]>,
     ) {
+        if self.refuse_revoked(peer) {
+            return;
+        }
         // Verify HMAC if group key is configured
-        if let Some(ref gk) = self.group_key {
+        if self.group_key.is_some() {
             let tag = match req_hmac {
                 Some(t) => t,
                 None => {
@@ -378,10 +774,14 @@ impl EngineRunner {
             let verify_req = SyncRequest::Push {
                 changeset: changeset.clone(),
                 topic: peer_topic.clone(),
+                hops,
+                prev_db_version,
+                seq,
+                sealed: None,
                 hmac: None,
             };
             if let Ok(bytes) = serde_json::to_vec(&verify_req)
-                && !gk.verify(&bytes, &tag)
+                && !self.verify_request(peer, &bytes, &tag, seq)
             {
                 log::debug!("Rejecting push with invalid HMAC from peer {peer}");
                 return;
@@ -402,7 +802,7 @@ impl EngineRunner {
         }
 
         // Reject pushes from peers on a different topic
-        if !peer_topic.is_empty() && peer_topic != self.topic_name {
+        if !peer_topic.is_empty() && !self.accepts_topic(&peer_topic) {
             log::debug!(
                 "Ignoring push from peer {peer}: topic mismatch (theirs={peer_topic}, ours={})",
                 self.topic_name
@@ -411,45 +811,147 @@ impl EngineRunner {
             return;
         }
 
-        // Track peer's db_version (use max to avoid overwriting with stale values)
-        let entry = self.peer_db_versions.entry(peer).or_insert(0);
-        *entry = (*entry).max(changeset.db_version);
-        let reported = self.peer_reported_versions.entry(peer).or_insert(0);
-        *reported = (*reported).max(changeset.db_version);
+        // A forwarded changeset carries its origin's db_version, which says
+        // nothing about the forwarder's. `peer_db_versions` only advances
+        // once the push has been applied (see `finish_push`).
+        if hops.is_none() {
+            let reported = self.peer_reported_versions.entry(peer).or_insert(0);
+            *reported = (*reported).max(changeset.db_version);
+        }
 
         log::info!(
-            "Received push from peer {peer} with {} changes at db_version {}",
+            "Received push from peer {peer} with {} changes at db_version {} (hops: {})",
             changeset.changes.len(),
             changeset.db_version,
+            hops.unwrap_or(0),
         );
 
-        // Send PushAck immediately via response channel
-        let resp_tx = self.snapshot_resp_tx.clone();
-        tokio::spawn(async move {
-            if let Err(e) = resp_tx
-                .send((channel, crate::protocol::SyncResponse::PushAck))
-                .await
-            {
-                log::error!("Failed to send PushAck: {e}");
-            }
-        });
+        if !self.admit_push(peer, &changeset.changes) {
+            return;
+        }
+
+        // A changeset we already have is still queued, empty, so its ack
+        // waits behind the batch that carried it.
+        let already_seen = self
+            .seen_changesets
+            .contains(changeset.site_id, changeset.db_version);
+        if already_seen {
+            log::debug!(
+                "Already have changeset (site={:?}, db_version={}), not re-applying",
+                changeset.site_id,
+                changeset.db_version
+            );
+        }
+        let batch = RemoteBatch {
+            changes: if already_seen {
+                Vec::new()
+            } else {
+                changeset.changes
+            },
+            catchup: None,
+            origin_versions: None,
+            push: Some(PendingPush {
+                peer,
+                channel,
+                db_version: hops.is_none().then_some(changeset.db_version),
+                prev_db_version,
+                forward: (!already_seen).then_some(ForwardPush {
+                    site_id: changeset.site_id,
+                    db_version: changeset.db_version,
+                    hops,
+                }),
+            }),
+        };
 
-        // Queue changeset for sequential application in the main loop
-        if let Err(e) = self.remote_changeset_tx.try_send(changeset.changes) {
+        // Queue changeset for sequential application in the main loop.
+        // It counts as seen, and is forwarded, only once it has been
+        // checked and committed (see `finish_push`): a dropped or failed
+        // one must be accepted again if another peer forwards it. Dropping
+        // the batch drops the response channel, so the sender sees the push
+        // fail.
+        if let Err(e) = self.remote_changeset_tx.try_send(batch) {
             log::warn!("Remote changeset queue full, dropping push: {e}");
+            self.release_push(peer);
+        }
+    }
+
+    /// Acknowledge a push once its batch has committed, and advance the
+    /// sender's `db_version` — unless the push shows that an earlier one
+    /// never reached us, in which case catch up from what we have instead.
+    /// A push that failed to apply is not acknowledged and leaves the
+    /// watermark alone, so the next catch-up fetches it again.
+    ///
+    /// An applied changeset is marked seen and `kept` — the changes that
+    /// survived the checks in [`Self::handle_remote_batch`] — is forwarded
+    /// to our other peers.
+    pub(super) fn finish_push(&mut self, push: PendingPush, applied: bool, kept: &[ColumnChange]) {
+        let peer = push.peer;
+        self.release_push(peer);
+        if !applied {
+            log::warn!("Failed to apply push from peer {peer}, not acknowledging");
+            return;
+        }
+        // Another peer may have delivered the same changeset while this
+        // one was queued; only the first to commit forwards it. Nothing
+        // from a revoked origin is passed on.
+        if let Some(fwd) = push.forward
+            && self.seen_changesets.insert(fwd.site_id, fwd.db_version)
+            && !kept.is_empty()
+            && !self.is_revoked_site(&fwd.site_id)
+        {
+            let changeset = SyncChangeset {
+                site_id: fwd.site_id,
+                db_version: fwd.db_version,
+                changes: kept.to_vec(),
+            };
+            self.forward_changeset(peer, &changeset, fwd.hops);
+        }
+        if let Err(resp) = self
+            .swarm
+            .behaviour_mut()
+            .snapshot
+            .send_response(push.channel, crate::protocol::SyncResponse::PushAck)
+        {
+            log::debug!("Failed to send PushAck to peer {peer}: {resp:?}");
+        }
+
+        // Without a prior sync a push says nothing about what came before
+        // it; the sync on connect sets the watermark.
+        let (Some(db_version), Some(known)) =
+            (push.db_version, self.peer_db_versions.get(&peer).copied())
+        else {
+            return;
+        };
+        if let Some(prev) = push.prev_db_version
+            && prev > known
+        {
+            self.diagnostics
+                .push_gaps_detected
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            log::info!(
+                "Push from peer {peer} at db_version {db_version} follows one at {prev}, but we only have up to {known}: catching up"
+            );
+            if self.swarm.is_connected(&peer) {
+                self.initiate_sync_for_peer(peer);
+            }
+            return;
         }
+        self.peer_db_versions.insert(peer, known.max(db_version));
     }
 
-    /// Verify HMAC, check peer is verified, store identity, emit event, respond with IdentityAck.
+    /// Verify HMAC, check peer is verified, check the identity's signature,
+    /// store identity, emit event, respond with IdentityAck.
     fn handle_identity_announce_request(
         &mut self,
         peer: libp2p::PeerId,
         channel: request_response::ResponseChannel<crate::protocol::SyncResponse>,
         app_id: String,
+        claim: Option<crate::identity::IdentityClaim>,
+        seq: Option<u64>,
         req_hmac: Option<[u8; 32]>,
     ) {
         // Verify HMAC if group key is configured
-        if let Some(ref gk) = self.group_key {
+        if self.group_key.is_some() {
             let tag = match req_hmac {
                 Some(t) => t,
                 None => {
@@ -459,10 +961,12 @@ impl EngineRunner {
             };
             let verify_req = SyncRequest::IdentityAnnounce {
                 app_id: app_id.clone(),
+                claim: claim.clone(),
+                seq,
                 hmac: None,
             };
             if let Ok(bytes) = serde_json::to_vec(&verify_req)
-                && !gk.verify(&bytes, &tag)
+                && !self.verify_request(peer, &bytes, &tag, seq)
             {
                 log::debug!("Rejecting identity announce with invalid HMAC from peer {peer}");
                 return;
@@ -475,10 +979,34 @@ impl EngineRunner {
             return;
         }
 
-        self.peer_identities.insert(peer, app_id.clone());
+        // A signed claim has to be for the announced identity and signed by
+        // the announcing device itself; unsigned ones are reported as such.
+        let verification = match claim {
+            None => crate::identity::IdentityVerification::Unverified,
+            Some(claim) => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                match claim
+                    .verify(&peer, &self.config.identity_issuers, now)
+                    .filter(|_| claim.app_id == app_id)
+                {
+                    Some(v) => v,
+                    None => {
+                        log::warn!("Rejecting identity announce with a bad signature from {peer}");
+                        return;
+                    }
+                }
+            }
+        };
+
+        self.peer_identities
+            .insert(peer, (app_id.clone(), verification));
         self.emit_network_event(crate::network_status::NetworkEvent::PeerIdentityReceived {
             peer_id: crate::network_status::PeerId(peer.to_string()),
             app_id,
+            verification,
         });
         self.update_network_status();
 
@@ -491,8 +1019,115 @@ impl EngineRunner {
         });
     }
 
+    /// Checkpoint a catch-up page once it has been applied, then request the
+    /// next one. A page that failed to commit leaves the previous checkpoint
+    /// in place, so the next sync round asks for the same page again.
+    ///
+    /// The checkpoint is written after the page's transaction rather than
+    /// inside it: a crash in between re-fetches one page, which is harmless
+    /// because applying the same changes twice is a no-op under the CRDT.
+    pub(super) async fn finish_catchup_page(&mut self, page: CatchupPage, applied: bool) {
+        if !applied {
+            log::warn!(
+                "Catch-up page from peer {} did not commit; will retry from the last checkpoint",
+                page.peer
+            );
+            return;
+        }
+        let peer_str = page.peer.to_string();
+        match page.next_cursor {
+            Some(cursor) => {
+                if let Err(e) = peer_tracker::save_catchup_cursor(
+                    &self.db,
+                    &peer_str,
+                    page.since_db_version,
+                    &cursor,
+                )
+                .await
+                {
+                    log::warn!("Failed to checkpoint catch-up from peer {peer_str}: {e}");
+                }
+                self.catchup_cursors
+                    .insert(page.peer, (page.since_db_version, cursor));
+                if self.swarm.is_connected(&page.peer) {
+                    self.initiate_sync_for_peer(page.peer);
+                }
+            }
+            None => {
+                self.catchup_cursors.remove(&page.peer);
+                if let Err(e) = peer_tracker::clear_catchup_cursor(&self.db, &peer_str).await {
+                    log::warn!("Failed to clear catch-up checkpoint for peer {peer_str}: {e}");
+                }
+            }
+        }
+    }
+
+    /// Check and apply a batch of remote changes, then settle what came
+    /// with it: adopt the sender's watermarks, checkpoint the catch-up
+    /// page, acknowledge the push. Returns how many changes were merged,
+    /// or `None` if applying them failed.
+    pub(super) async fn handle_remote_batch(&mut self, mut batch: RemoteBatch) -> Option<usize> {
+        let mut forged = self.drop_forged_changes(&mut batch.changes).await;
+        forged.extend(self.drop_unauthorized_changes(&mut batch.changes).await);
+        forged.extend(self.drop_inflated_changes(&mut batch.changes).await);
+        forged.extend(self.drop_mistyped_changes(&mut batch.changes));
+        // Empty batches only carry a push ack for a changeset that came in
+        // through another peer (or nothing but forgeries, refused writes,
+        // inflated clocks and mistyped values).
+        let applied = batch.changes.is_empty()
+            || apply_remote_changeset(&self.db, &self.change_tx, &self.registry, &batch.changes)
+                .await;
+        if applied && let Some(mut theirs) = batch.origin_versions {
+            // We don't hold what we refused.
+            for site in &forged {
+                theirs.forget(site);
+            }
+            self.adopt_origin_versions(theirs);
+        }
+        if let Some(page) = batch.catchup {
+            self.finish_catchup_page(page, applied).await;
+        }
+        if let Some(push) = batch.push {
+            self.finish_push(push, applied, &batch.changes);
+        }
+        applied.then_some(batch.changes.len())
+    }
+
+    /// Our watermarks, including our own site's.
+    pub(super) fn local_origin_versions(&self) -> OriginVersions {
+        self.origin_versions
+            .clone()
+            .with(self.site_id, self.local_db_version)
+    }
+
+    /// Merge a peer's watermarks into ours after applying everything it
+    /// sent against them, and persist the ones that moved.
+    pub(super) fn adopt_origin_versions(&mut self, theirs: OriginVersions) {
+        let mut moved = OriginVersions::default();
+        for (site, version) in theirs.iter() {
+            if *site != self.site_id && self.origin_versions.advance(*site, *version) {
+                moved.advance(*site, *version);
+            }
+        }
+        if moved.is_empty() {
+            return;
+        }
+        log::debug!("Origin watermarks advanced: {moved:?}");
+        let db = self.db.clone();
+        tokio::spawn(async move {
+            if let Err(e) = peer_tracker::save_origin_versions(&db, &moved).await {
+                log::warn!("Failed to persist origin watermarks: {e}");
+            }
+        });
+    }
+
+    /// Whether `peer` stamps the changes it sends with origin versions.
+    pub(super) fn peer_stamps_origin(&self, peer: &libp2p::PeerId) -> bool {
+        self.peer_supports(peer, FEATURE_ORIGIN_VERSIONS)
+    }
+
     /// Permanently reject a peer: remove from all tracking sets and emit PeerRejected.
-    fn reject_peer(&mut self, peer: libp2p::PeerId) {
+    pub(super) fn reject_peer(&mut self, peer: libp2p::PeerId) {
         self.rejected_peers.insert(peer);
         self.verified_peers.remove(&peer);
         self.peer_identities.remove(&peer);
@@ -519,19 +1154,22 @@ impl EngineRunner {
 /// `ChangeNotification`s are buffered during the transaction and emitted
 /// only AFTER commit (Rule 2.12 — subscribers must never observe a
 /// notification before its data is durable).
+///
+/// Returns `true` once the transaction has committed (individual changes
+/// may still have lost conflict resolution), `false` if it was rolled back.
 pub(super) async fn apply_remote_changeset(
     db: &DatabaseConnection,
     change_tx: &broadcast::Sender<ChangeNotification>,
     registry: &TableRegistry,
     changes: &[ColumnChange],
-) {
+) -> bool {
     use sea_orm::TransactionTrait;
 
     let txn = match db.begin().await {
         Ok(t) => t,
         Err(e) => {
             log::error!("Failed to begin transaction for remote changeset: {e}");
-            return;
+            return false;
         }
     };
 
@@ -541,7 +1179,7 @@ pub(super) async fn apply_remote_changeset(
         Err(e) => {
             log::error!("Failed to increment db_version: {e}");
             let _ = txn.rollback().await;
-            return;
+            return false;
         }
     };
 
@@ -613,15 +1251,29 @@ pub(super) async fn apply_remote_changeset(
         }
     }
 
+    // Keep the author's signature for every change that won, so it can be
+    // relayed verifiably.
+    for change in changes.iter().filter(|c| c.sig.is_some()) {
+        if let Err(e) = shadow::record_change_signature(&txn, change).await {
+            log::warn!(
+                "Failed to record signature for {}.{} ({}): {e}",
+                change.table,
+                change.cid,
+                change.pk
+            );
+        }
+    }
+
     if let Err(e) = txn.commit().await {
         log::error!("Failed to commit remote changeset transaction: {e}");
         // Notifications are not sent — data was rolled back.
-        return;
+        return false;
     }
 
     for n in pending_notifications {
         let _ = change_tx.send(n);
     }
+    true
 }
 
 /// Apply a remote delete: check conflict resolution, delete row, update shadow.
@@ -664,13 +1316,14 @@ async fn apply_remote_delete(
     }
 
     let _ = shadow::delete_clock_entries(db, table, pk).await;
-    let _ = shadow::insert_tombstone(
+    let _ = shadow::insert_tombstone_with_origin(
         db,
         table,
         pk,
         change.col_version,
         local_db_version,
         &change.site_id,
+        change.db_version,
     )
     .await;
 
@@ -692,7 +1345,9 @@ async fn apply_remote_column_changes(
 ) -> (bool, Vec<(String, serde_json::Value)>) {
     let exists = row_exists(db, table, &meta.primary_key_column, pk).await;
     let mut winning_columns: Vec<(String, sea_orm::Value)> = Vec::new();
-    let mut pending_shadow_updates: Vec<(String, u64, crate::messages::NodeId, u32)> = Vec::new();
+    // (cid, col_version, site_id, seq, origin_version)
+    let mut pending_shadow_updates: Vec<(String, u64, crate::messages::NodeId, u32, u64)> =
+        Vec::new();
     let mut changed_columns: Vec<(String, serde_json::Value)> = Vec::new();
 
     for change in row_changes {
@@ -723,6 +1378,20 @@ async fn apply_remote_column_changes(
             );
             continue;
         }
+        // The value as stored: `change.val` itself stays as signed, so it
+        // can be passed on unchanged.
+        let value = match meta.coerce_value(&change.cid.0, change.val.as_ref()) {
+            Ok(value) => value,
+            Err(e) => {
+                log::warn!(
+                    "Rejecting remote change with a mistyped value: {}/{}/{}: {e}",
+                    table,
+                    pk,
+                    change.cid.0
+                );
+                continue;
+            }
+        };
 
         let (local_cv, local_site) =
             shadow::get_col_version_with_site(db, table, pk, &change.cid.0)
@@ -750,16 +1419,17 @@ async fn apply_remote_column_changes(
         };
 
         if should_apply {
-            winning_columns.push((change.cid.0.clone(), json_to_sea_value(change.val.as_ref())));
+            winning_columns.push((change.cid.0.clone(), json_to_sea_value(value.as_ref())));
             changed_columns.push((
                 change.cid.0.clone(),
-                change.val.clone().unwrap_or(serde_json::Value::Null),
+                value.unwrap_or(serde_json::Value::Null),
             ));
             pending_shadow_updates.push((
                 change.cid.0.clone(),
                 change.col_version,
                 remote_site,
                 change.seq,
+                change.db_version,
             ));
         }
     }
@@ -858,12 +1528,22 @@ async fn flush_shadow_updates(
     db: &impl ConnectionTrait,
     table: &str,
     pk: &str,
-    updates: &[(String, u64, crate::messages::NodeId, u32)],
+    updates: &[(String, u64, crate::messages::NodeId, u32, u64)],
     local_db_version: u64,
 ) {
-    for (cid, cv, site, seq) in updates {
-        let _ =
-            shadow::upsert_clock_entry(db, table, pk, cid, *cv, local_db_version, site, *seq).await;
+    for (cid, cv, site, seq, origin_version) in updates {
+        let _ = shadow::upsert_clock_entry_with_origin(
+            db,
+            table,
+            pk,
+            cid,
+            *cv,
+            local_db_version,
+            site,
+            *seq,
+            *origin_version,
+        )
+        .await;
     }
 }
 
@@ -979,10 +1659,39 @@ mod tests {
             primary_key_column: "id".to_string(),
             columns: vec!["id".to_string(), "title".to_string(), "done".to_string()],
             delete_policy: crate::messages::DeletePolicy::default(),
+            column_types: Default::default(),
         });
         (db, registry)
     }
 
+    // ── queue_catchup_page tests ──
+
+    #[test]
+    fn test_queue_catchup_page_reports_full_queue() {
+        let (tx, mut rx) = mpsc::channel::<RemoteBatch>(1);
+        let peer = libp2p::PeerId::random();
+        let page = || RemoteBatch {
+            changes: Vec::new(),
+            catchup: Some(CatchupPage {
+                peer,
+                since_db_version: 7,
+                next_cursor: None,
+            }),
+            origin_versions: None,
+            push: None,
+        };
+        tx.try_send(RemoteBatch::from(Vec::new())).unwrap();
+
+        assert!(!queue_catchup_page(&tx, page()), "queue is full");
+        assert!(rx.try_recv().unwrap().catchup.is_none());
+        assert!(rx.try_recv().is_err(), "the dropped page is not queued");
+
+        // Once the loop drains the queue, the re-requested page fits.
+        assert!(queue_catchup_page(&tx, page()));
+        let queued = rx.try_recv().unwrap().catchup.unwrap();
+        assert_eq!((queued.peer, queued.since_db_version), (peer, 7));
+    }
+
     // ── strip_returning tests ──
 
     #[test]
@@ -1045,6 +1754,7 @@ mod tests {
             cl: 1,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
 
         apply_remote_changeset(&db, &tx, &registry, &changes).await;
@@ -1081,6 +1791,73 @@ mod tests {
         );
     }
 
+    /// Values that don't fit their column's declared type are refused;
+    /// values that do are stored in the column's form, while the shadow
+    /// clock still advances for them.
+    #[tokio::test]
+    async fn test_apply_checks_declared_column_types() {
+        use crate::registry::{ColumnKind, ColumnType};
+        let (db, registry) = setup_engine_test_db().await;
+        let mut meta = registry.get("tasks").unwrap();
+        meta.column_types = HashMap::from([
+            (
+                "title".to_string(),
+                ColumnType {
+                    kind: ColumnKind::Text,
+                    nullable: false,
+                },
+            ),
+            (
+                "done".to_string(),
+                ColumnType {
+                    kind: ColumnKind::Integer,
+                    nullable: false,
+                },
+            ),
+        ]);
+        registry.register(meta);
+        let (tx, _rx) = broadcast::channel::<ChangeNotification>(16);
+
+        db.execute_unprepared("INSERT INTO tasks (id, title, done) VALUES ('t1', 'before', 0)")
+            .await
+            .unwrap();
+        let change = |cid: &str, val: serde_json::Value| ColumnChange {
+            table: "tasks".into(),
+            pk: "t1".into(),
+            cid: cid.into(),
+            val: Some(val),
+            site_id: NodeId([7u8; 16]),
+            col_version: 1,
+            cl: 1,
+            seq: 0,
+            db_version: 0,
+            sig: None,
+        };
+        let changes = vec![
+            change("title", serde_json::json!({"not": "text"})),
+            change("done", serde_json::json!("1")),
+        ];
+        apply_remote_changeset(&db, &tx, &registry, &changes).await;
+
+        use sea_orm::ConnectionTrait;
+        let row = db
+            .query_one_raw(sea_orm::Statement::from_string(
+                sea_orm::DatabaseBackend::Sqlite,
+                "SELECT title, done, typeof(done) AS ty FROM tasks WHERE id = 't1'".to_string(),
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.try_get::<String>("", "title").unwrap(), "before");
+        assert_eq!(row.try_get::<i64>("", "done").unwrap(), 1);
+        assert_eq!(row.try_get::<String>("", "ty").unwrap(), "integer");
+
+        let (title_cv, _) = shadow::get_col_version_with_site(&db, "tasks", "t1", "title")
+            .await
+            .unwrap();
+        assert_eq!(title_cv, 0, "no clock for the refused value");
+    }
+
     /// REGRESSION — WSDB-PoC-1b (was: PK rewrite via `cid = "id"`).
     ///
     /// `id` is in `meta.columns` (it's a registered column) so the
@@ -1108,6 +1885,7 @@ mod tests {
             cl: 1,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
 
         apply_remote_changeset(&db, &tx, &registry, &changes).await;
@@ -1161,6 +1939,7 @@ mod tests {
             cl: 1,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
 
         apply_remote_changeset(&db, &tx, &registry, &changes).await;
@@ -1193,6 +1972,7 @@ mod tests {
             cl: 1,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
 
         apply_remote_changeset(&db, &tx, &registry, &changes).await;
@@ -1218,6 +1998,7 @@ mod tests {
                 cl: 1,
                 seq: 0,
                 db_version: 0,
+                sig: None,
             },
             ColumnChange {
                 table: "tasks".into(),
@@ -1229,6 +2010,7 @@ mod tests {
                 cl: 1,
                 seq: 1,
                 db_version: 0,
+                sig: None,
             },
             ColumnChange {
                 table: "tasks".into(),
@@ -1240,6 +2022,7 @@ mod tests {
                 cl: 1,
                 seq: 2,
                 db_version: 0,
+                sig: None,
             },
         ];
 
@@ -1282,6 +2065,7 @@ mod tests {
             cl: 10,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
 
         apply_remote_changeset(&db, &tx, &registry, &changes).await;
@@ -1337,6 +2121,7 @@ mod tests {
             cl: 10,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
 
         apply_remote_changeset(&db, &tx, &registry, &changes).await;
@@ -1386,6 +2171,7 @@ mod tests {
             cl: 3,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
 
         apply_remote_changeset(&db, &tx, &registry, &changes).await;
@@ -1440,6 +2226,7 @@ mod tests {
                 cl: 5,
                 seq: 0,
                 db_version: 0,
+                sig: None,
             },
             ColumnChange {
                 table: "tasks".into(),
@@ -1451,6 +2238,7 @@ mod tests {
                 cl: 1,
                 seq: 1,
                 db_version: 0,
+                sig: None,
             },
         ];
 
@@ -1503,6 +2291,7 @@ mod tests {
             cl: 3,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
 
         apply_remote_changeset(&db, &tx, &registry, &changes).await;
@@ -1552,6 +2341,7 @@ mod tests {
             cl: 5,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
 
         apply_remote_changeset(&db, &tx, &registry, &changes).await;
@@ -1572,6 +2362,7 @@ mod tests {
             primary_key_column: "id".to_string(),
             columns: vec!["id".to_string(), "title".to_string(), "done".to_string()],
             delete_policy: crate::messages::DeletePolicy::AddWins,
+            column_types: Default::default(),
         });
 
         db.execute_unprepared("INSERT INTO tasks VALUES ('aw-1', 'Tie Keep', 0)")
@@ -1601,6 +2392,7 @@ mod tests {
             cl: 5,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
 
         apply_remote_changeset(&db, &tx, &registry, &changes).await;
@@ -1642,6 +2434,7 @@ mod tests {
             cl: 5,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
         apply_remote_changeset(&db, &tx, &registry, &delete_changes).await;
         assert!(!row_exists(&db, "tasks", "id", "iad-1").await);
@@ -1658,6 +2451,7 @@ mod tests {
                 cl: 10,
                 seq: 0,
                 db_version: 0,
+                sig: None,
             },
             ColumnChange {
                 table: "tasks".into(),
@@ -1669,6 +2463,7 @@ mod tests {
                 cl: 10,
                 seq: 1,
                 db_version: 0,
+                sig: None,
             },
             ColumnChange {
                 table: "tasks".into(),
@@ -1680,6 +2475,7 @@ mod tests {
                 cl: 10,
                 seq: 2,
                 db_version: 0,
+                sig: None,
             },
         ];
         apply_remote_changeset(&db, &tx, &registry, &insert_changes).await;
@@ -1716,6 +2512,7 @@ mod tests {
                 cl: 1,
                 seq: 0,
                 db_version: 0,
+                sig: None,
             },
             ColumnChange {
                 table: "tasks".into(),
@@ -1727,6 +2524,7 @@ mod tests {
                 cl: 1,
                 seq: 1,
                 db_version: 0,
+                sig: None,
             },
             ColumnChange {
                 table: "tasks".into(),
@@ -1738,6 +2536,7 @@ mod tests {
                 cl: 1,
                 seq: 2,
                 db_version: 0,
+                sig: None,
             },
             ColumnChange {
                 table: "tasks".into(),
@@ -1749,6 +2548,7 @@ mod tests {
                 cl: 1,
                 seq: 0,
                 db_version: 0,
+                sig: None,
             },
             ColumnChange {
                 table: "tasks".into(),
@@ -1760,6 +2560,7 @@ mod tests {
                 cl: 1,
                 seq: 1,
                 db_version: 0,
+                sig: None,
             },
             ColumnChange {
                 table: "tasks".into(),
@@ -1771,6 +2572,7 @@ mod tests {
                 cl: 1,
                 seq: 2,
                 db_version: 0,
+                sig: None,
             },
         ];
 
@@ -1816,6 +2618,7 @@ mod tests {
             cl: 1,
             seq: 1,
             db_version: 0,
+            sig: None,
         }];
         apply_remote_changeset(&db_a, &tx_a, &registry_a, &b_changes).await;
 
@@ -1848,6 +2651,7 @@ mod tests {
             cl: 1,
             seq: 1,
             db_version: 0,
+            sig: None,
         }];
         apply_remote_changeset(&db_b, &tx_b, &registry_b, &a_changes).await;
 
@@ -1919,6 +2723,7 @@ mod tests {
             cl: 2,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
         apply_remote_changeset(&db_a, &tx_a, &registry_a, &b_changes).await;
 
@@ -1933,6 +2738,7 @@ mod tests {
             cl: 3,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
         apply_remote_changeset(&db_b, &tx_b, &registry_b, &a_changes).await;
 
@@ -1984,6 +2790,7 @@ mod tests {
             primary_key_column: "id".to_string(),
             columns: vec!["id".to_string(), "title".to_string(), "done".to_string()],
             delete_policy: crate::messages::DeletePolicy::default(),
+            column_types: Default::default(),
         });
         (db, registry)
     }
@@ -2007,6 +2814,7 @@ mod tests {
             cl: 2,
             seq: 0,
             db_version: 0,
+            sig: None,
         }];
         apply_remote_changeset(&db, &tx, &registry, &update_changes).await;
 
@@ -2040,6 +2848,7 @@ mod tests {
                 cl: 1,
                 seq: 0,
                 db_version: 0,
+                sig: None,
             },
             ColumnChange {
                 table: "tasks".into(),
@@ -2051,6 +2860,7 @@ mod tests {
                 cl: 1,
                 seq: 1,
                 db_version: 0,
+                sig: None,
             },
             ColumnChange {
                 table: "tasks".into(),
@@ -2062,6 +2872,7 @@ mod tests {
                 cl: 1,
                 seq: 2,
                 db_version: 0,
+                sig: None,
             },
         ];
         apply_remote_changeset(&db, &tx, &registry, &insert_changes).await;
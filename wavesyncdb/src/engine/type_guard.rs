@@ -0,0 +1,53 @@
+//! Checking remote values against the local schema (see
+//! [`TableMeta::column_types`](crate::registry::TableMeta::column_types)).
+//!
+//! SQLite stores whatever it is given, so a string written to an INTEGER
+//! column would go in unnoticed and later fail to decode on every device.
+//! Remote changes whose value doesn't fit its column's declared type are
+//! dropped before they are applied. Values that fit are stored in their
+//! column's form when applied, as
+//! [`TableMeta::coerce_value`](crate::registry::TableMeta::coerce_value)
+//! returns them.
+
+use super::*;
+
+use std::collections::HashSet;
+
+impl EngineRunner {
+    /// Remove the remote changes whose value doesn't fit its column.
+    /// Returns the sites whose changes were refused.
+    pub(super) fn drop_mistyped_changes(
+        &mut self,
+        changes: &mut Vec<ColumnChange>,
+    ) -> HashSet<NodeId> {
+        let mut refused_sites = HashSet::new();
+        let mut tables: HashMap<String, Option<crate::registry::TableMeta>> = HashMap::new();
+        let before = changes.len();
+        changes.retain(|c| {
+            let meta = tables
+                .entry(c.table.0.clone())
+                .or_insert_with(|| self.registry.get(&c.table.0));
+            let Some(meta) = meta else {
+                return true;
+            };
+            let Err(e) = meta.coerce_value(&c.cid.0, c.val.as_ref()) else {
+                return true;
+            };
+            log::warn!(
+                "Refusing remote change with a mistyped value: {}/{}/{}: {e}",
+                c.table.0,
+                c.pk.0,
+                c.cid.0
+            );
+            refused_sites.insert(c.site_id);
+            false
+        });
+        let refused = (before - changes.len()) as u64;
+        if refused > 0 {
+            self.diagnostics
+                .mistyped_values_rejected
+                .fetch_add(refused, std::sync::atomic::Ordering::Relaxed);
+        }
+        refused_sites
+    }
+}
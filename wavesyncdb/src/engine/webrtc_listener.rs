@@ -0,0 +1,79 @@
+//! WebRTC listeners for browser peers.
+//!
+//! Browsers can't open QUIC connections, so without these every browser
+//! peer reaches us through a relay circuit, even on the same LAN. With the
+//! `webrtc` feature built in and `EngineConfig::webrtc` on, we also
+//! listen on `webrtc-direct`; the address, certificate hash included,
+//! reaches browsers through identify over their circuit connection, and
+//! they dial it directly. The certificate is kept in `_wavesync_meta` so
+//! the address survives restarts (see
+//! `shadow::get_or_create_webrtc_certificate`).
+//!
+//! Only browser → native connections go direct. Two browsers still talk
+//! through the circuit: libp2p's browser transport dials `webrtc-direct`
+//! listeners but has no relay-signalled browser-to-browser WebRTC.
+//!
+//! Native peers keep talking QUIC to each other and never dial these
+//! addresses.
+
+use super::*;
+
+use libp2p::multiaddr::Protocol;
+
+/// Whether `addr` is a `webrtc-direct` address.
+pub(crate) fn is_webrtc_direct(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| matches!(p, Protocol::WebRTCDirect))
+}
+
+/// The `webrtc-direct` transport, serving `certificate`.
+#[cfg(feature = "webrtc")]
+pub(super) fn transport(
+    key: &identity::Keypair,
+    certificate: libp2p_webrtc::tokio::Certificate,
+) -> libp2p::core::transport::Boxed<(libp2p::PeerId, libp2p::core::muxing::StreamMuxerBox)> {
+    use libp2p::Transport;
+
+    libp2p_webrtc::tokio::Transport::new(key.clone(), certificate)
+        .map(|(peer, conn), _| (peer, libp2p::core::muxing::StreamMuxerBox::new(conn)))
+        .boxed()
+}
+
+#[cfg(feature = "webrtc")]
+impl EngineRunner {
+    /// Listen for browser peers on `webrtc-direct`, alongside QUIC.
+    pub(super) fn listen_webrtc(&mut self) {
+        // Loopback on iOS, as for QUIC (see `run`).
+        #[cfg(target_os = "ios")]
+        let (v4, v6) = (
+            "/ip4/127.0.0.1/udp/0/webrtc-direct",
+            "/ip6/::1/udp/0/webrtc-direct",
+        );
+        #[cfg(not(target_os = "ios"))]
+        let (v4, v6) = (
+            "/ip4/0.0.0.0/udp/0/webrtc-direct",
+            "/ip6/::/udp/0/webrtc-direct",
+        );
+
+        if let Err(e) = self.swarm.listen_on(v4.parse().unwrap()) {
+            log::warn!("WebRTC listen failed (non-fatal, browsers will use the relay): {e}");
+        }
+        if self.config.ipv6
+            && let Err(e) = self.swarm.listen_on(v6.parse().unwrap())
+        {
+            log::warn!("WebRTC IPv6 listen failed (non-fatal): {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_webrtc_direct() {
+        let webrtc: Multiaddr = "/ip4/192.168.1.2/udp/4001/webrtc-direct".parse().unwrap();
+        let quic: Multiaddr = "/ip4/192.168.1.2/udp/4001/quic-v1".parse().unwrap();
+        assert!(is_webrtc_direct(&webrtc));
+        assert!(!is_webrtc_direct(&quic));
+    }
+}
@@ -0,0 +1,140 @@
+//! WebSocket listener for browser peers.
+//!
+//! WebRTC (see `webrtc_listener`) still needs a relay to introduce the
+//! browser. With the `websocket` feature built in and
+//! `EngineConfig::websocket_port` set, we also listen on WebSocket, which
+//! a browser can dial with no relay at all, e.g. a web UI talking to a
+//! desktop companion app on the same machine. With
+//! `EngineConfig::websocket_tls` it serves `wss`, which pages loaded over
+//! HTTPS need.
+//!
+//! The address is advertised over identify and in pairing invitations.
+//! Native peers keep talking QUIC to each other and never dial it (see
+//! [`is_browser_listener`](super::is_browser_listener)).
+
+use super::*;
+
+use libp2p::multiaddr::Protocol;
+
+/// Certificate and key a `wss` listener serves, both DER-encoded.
+#[cfg(feature = "websocket")]
+#[derive(Clone)]
+pub struct WebSocketTls {
+    /// The certificate chain, leaf first.
+    pub certificate_chain: Vec<Vec<u8>>,
+    /// The leaf certificate's private key (PKCS#8 or PKCS#1).
+    pub private_key: Vec<u8>,
+}
+
+#[cfg(feature = "websocket")]
+impl std::fmt::Debug for WebSocketTls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebSocketTls")
+            .field("certificate_chain", &self.certificate_chain.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Whether `addr` is a WebSocket address, `ws` or `wss`.
+pub(crate) fn is_websocket(addr: &Multiaddr) -> bool {
+    addr.iter()
+        .any(|p| matches!(p, Protocol::Ws(_) | Protocol::Wss(_)))
+}
+
+/// The libp2p TLS config for `tls`, checking the certificate and key.
+#[cfg(feature = "websocket")]
+pub(crate) fn tls_config(tls: &WebSocketTls) -> Result<libp2p::websocket::tls::Config, String> {
+    use libp2p::websocket::tls;
+
+    let key = tls::PrivateKey::new(tls.private_key.clone());
+    let chain = tls
+        .certificate_chain
+        .iter()
+        .cloned()
+        .map(tls::Certificate::new);
+    tls::Config::new(key, chain).map_err(|e| e.to_string())
+}
+
+/// WebSocket over TCP, secured with noise and multiplexed with yamux as
+/// the browser's websys transport expects, serving `wss` with `tls`.
+#[cfg(feature = "websocket")]
+pub(super) fn transport(
+    key: &identity::Keypair,
+    tls: Option<libp2p::websocket::tls::Config>,
+) -> Result<
+    libp2p::core::transport::Boxed<(libp2p::PeerId, libp2p::core::muxing::StreamMuxerBox)>,
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    use libp2p::Transport;
+    use libp2p::core::upgrade::Version;
+
+    let tcp = libp2p::tcp::tokio::Transport::new(libp2p::tcp::Config::default());
+    let mut ws = libp2p::websocket::Config::new(tcp);
+    if let Some(tls) = tls {
+        ws.set_tls_config(tls);
+    }
+    Ok(ws
+        .upgrade(Version::V1)
+        .authenticate(noise::Config::new(key)?)
+        .multiplex(yamux::Config::default())
+        .map(|(peer, muxer), _| (peer, libp2p::core::muxing::StreamMuxerBox::new(muxer)))
+        .boxed())
+}
+
+#[cfg(feature = "websocket")]
+impl EngineRunner {
+    /// Listen for browser peers on WebSocket at TCP `port`, alongside QUIC.
+    pub(super) fn listen_websocket(&mut self, port: u16) {
+        // Loopback on iOS, where libp2p-tcp's interface watcher hangs (see
+        // `run`).
+        #[cfg(target_os = "ios")]
+        let (v4, v6) = ("/ip4/127.0.0.1", "/ip6/::1");
+        #[cfg(not(target_os = "ios"))]
+        let (v4, v6) = ("/ip4/0.0.0.0", "/ip6/::");
+        let ws = if self.config.websocket_tls.is_some() {
+            "tls/ws"
+        } else {
+            "ws"
+        };
+
+        let v4 = format!("{v4}/tcp/{port}/{ws}");
+        match self.swarm.listen_on(v4.parse().unwrap()) {
+            Ok(_) => log::info!("Listening for browser peers on {v4}"),
+            Err(e) => log::warn!("WebSocket listen on {v4} failed (non-fatal): {e}"),
+        }
+        if self.config.ipv6 {
+            let v6 = format!("{v6}/tcp/{port}/{ws}");
+            if let Err(e) = self.swarm.listen_on(v6.parse().unwrap()) {
+                log::warn!("WebSocket IPv6 listen on {v6} failed (non-fatal): {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_websocket() {
+        let ws: Multiaddr = "/ip4/192.168.1.2/tcp/4002/ws".parse().unwrap();
+        let wss: Multiaddr = "/ip4/192.168.1.2/tcp/4002/tls/ws".parse().unwrap();
+        let quic: Multiaddr = "/ip4/192.168.1.2/udp/4001/quic-v1".parse().unwrap();
+        assert!(is_websocket(&ws));
+        assert!(is_websocket(&wss));
+        assert!(is_websocket(
+            &"/dns4/example.com/tcp/443/wss".parse().unwrap()
+        ));
+        assert!(!is_websocket(&quic));
+    }
+
+    #[cfg(feature = "websocket")]
+    #[test]
+    fn test_tls_config_rejects_garbage() {
+        let tls = WebSocketTls {
+            certificate_chain: vec![b"not a certificate".to_vec()],
+            private_key: b"not a key".to_vec(),
+        };
+        assert!(tls_config(&tls).is_err());
+    }
+}
@@ -5,9 +5,10 @@
 //! - **C FFI** (`wavesync_background_sync`) — called from iOS Swift via `@_silgen_name`.
 //!   Enable with `features = ["mobile-ffi"]`.
 //!
-//! - **JNI** (`Java_dev_dioxus_main_WaveSyncService_backgroundSync`) — called from
-//!   Android Kotlin via `WaveSyncService` in `dev.dioxus.main`.
-//!   Enable with `features = ["push-sync"]`.
+//! - **JNI** (`Java_dev_dioxus_main_WaveSyncService_backgroundSync`, and
+//!   `..._firebaseOptions` for the Firebase credentials the sync config keeps
+//!   in its secret store) — called from Android Kotlin via `WaveSyncService`
+//!   in `dev.dioxus.main`. Enable with `features = ["push-sync"]`.
 
 use std::ffi::CStr;
 use std::os::raw::c_char;
@@ -183,3 +184,45 @@ pub extern "system" fn Java_dev_dioxus_main_WaveSyncService_backgroundSync(
     );
     run_background_sync(&url, timeout_secs as u32, &peer_addrs)
 }
+
+/// JNI entry point for the Firebase credentials saved with the sync config.
+/// Called from Dioxus-generated `WaveSyncService.firebaseOptions()` to
+/// initialise Firebase on a cold start: the credentials are kept in the
+/// config's secret store, not in `.wavesync_config.json`.
+///
+/// Returns a JSON object with `project_id`, `app_id` and `api_key`, or
+/// `null` if the config doesn't have all three.
+#[cfg(all(target_os = "android", feature = "push-sync"))]
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_dev_dioxus_main_WaveSyncService_firebaseOptions<'local>(
+    mut env: jni::JNIEnv<'local>,
+    _class: jni::objects::JClass<'local>,
+    database_url: jni::objects::JString<'local>,
+) -> jni::sys::jstring {
+    ensure_android_logger();
+
+    let url: String = match env.get_string(&database_url) {
+        Ok(s) => s.into(),
+        Err(_) => return std::ptr::null_mut(),
+    };
+    let config = match crate::SyncConfig::load(&url) {
+        Ok(config) => config,
+        Err(e) => {
+            log::warn!("No Firebase credentials for {url}: {e}");
+            return std::ptr::null_mut();
+        }
+    };
+    let (Some(project_id), Some(app_id), Some(api_key)) =
+        (config.fcm_project_id, config.fcm_app_id, config.fcm_api_key)
+    else {
+        return std::ptr::null_mut();
+    };
+    let json = serde_json::json!({
+        "project_id": project_id,
+        "app_id": app_id,
+        "api_key": api_key,
+    });
+    env.new_string(json.to_string())
+        .map(|s| s.into_raw())
+        .unwrap_or(std::ptr::null_mut())
+}
@@ -0,0 +1,257 @@
+//! Application identities bound to device keys.
+//!
+//! [`WaveSyncDb::set_peer_identity`](crate::WaveSyncDb::set_peer_identity)
+//! labels a device with an app-defined string — a user id, say. On its own
+//! that label is only a claim: any member of the group can announce any
+//! string. Builds announcing
+//! [`FEATURE_SIGNED_IDENTITIES`](crate::protocol::FEATURE_SIGNED_IDENTITIES)
+//! sign it as an [`IdentityClaim`] with the device's identity key (the one
+//! behind its `PeerId`, see [`crate::signing`]), so a peer can't announce a
+//! label under another device's identity. That still lets a device choose
+//! its own label; an app that needs "this really is Alice's phone" has its
+//! backend countersign the device key with an issuer key of its own, as an
+//! [`IdentityEndorsement`], and trusts that issuer on every device with
+//! [`WaveSyncDbBuilder::with_identity_issuer`](crate::WaveSyncDbBuilder::with_identity_issuer).
+//!
+//! Each peer's label is reported with how far it could be checked, as an
+//! [`IdentityVerification`], on
+//! [`PeerInfo::identity_verification`](crate::PeerInfo::identity_verification)
+//! and [`NetworkEvent::PeerIdentityReceived`](crate::NetworkEvent::PeerIdentityReceived).
+
+use libp2p::identity::ed25519;
+use serde::{Deserialize, Serialize};
+
+use crate::revocation::key_for_peer;
+use crate::signing::{ChangeSignature, ChangeSigner};
+
+/// Domain separator prefixed to every signed identity claim.
+const CLAIM_DOMAIN: &[u8] = b"wavesyncdb-identity-v1";
+
+/// Domain separator prefixed to every signed endorsement.
+const ENDORSEMENT_DOMAIN: &[u8] = b"wavesyncdb-identity-endorsement-v1";
+
+/// How far a peer's announced identity could be checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum IdentityVerification {
+    /// Announced without a signature, by a build that doesn't sign
+    /// identities. Any group member could have announced it.
+    #[default]
+    Unverified,
+    /// Signed by the announcing device's own key: the label is the one the
+    /// device chose, but nothing vouches for it.
+    DeviceSigned,
+    /// Also endorsed for this device by the trusted `issuer` key.
+    Endorsed { issuer: [u8; 32] },
+}
+
+/// An application issuer's statement that the device with identity key
+/// `device` is `app_id`.
+///
+/// Issued by the app's own backend once it has authenticated the user, for
+/// the key [`key_for_peer`] reads from the device's
+/// [`NetworkStatus::local_peer_id`](crate::NetworkStatus::local_peer_id).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityEndorsement {
+    /// The endorsed identity.
+    pub app_id: String,
+    /// The endorsed device's identity key.
+    pub device: [u8; 32],
+    /// Unix seconds after which the endorsement no longer counts, if any.
+    pub expires_at: Option<u64>,
+    /// Signature over the above by the issuer key.
+    pub sig: ChangeSignature,
+}
+
+impl IdentityEndorsement {
+    /// Endorse the device with identity key `device` as `app_id`, signed by
+    /// `issuer`.
+    pub fn issue(
+        issuer: &ed25519::Keypair,
+        app_id: &str,
+        device: [u8; 32],
+        expires_at: Option<u64>,
+    ) -> Self {
+        let sig = issuer
+            .sign(&endorsement_bytes(app_id, &device, expires_at))
+            .try_into()
+            .expect("ed25519 signatures are 64 bytes");
+        Self {
+            app_id: app_id.to_string(),
+            device,
+            expires_at,
+            sig: ChangeSignature {
+                key: issuer.public().to_bytes(),
+                sig,
+            },
+        }
+    }
+
+    /// Whether the signature verifies and the endorsement hasn't expired
+    /// at `now` (Unix seconds).
+    pub fn verify(&self, now: u64) -> bool {
+        self.expires_at.is_none_or(|at| now < at)
+            && ed25519::PublicKey::try_from_bytes(&self.sig.key).is_ok_and(|by| {
+                by.verify(
+                    &endorsement_bytes(&self.app_id, &self.device, self.expires_at),
+                    &self.sig.sig,
+                )
+            })
+    }
+}
+
+/// A device's signed claim to an application identity, with an optional
+/// issuer endorsement.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityClaim {
+    /// The claimed identity.
+    pub app_id: String,
+    /// Signature over `app_id` by the claiming device's identity key.
+    pub sig: ChangeSignature,
+    /// The app issuer's endorsement of the claim, if the app has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endorsement: Option<IdentityEndorsement>,
+}
+
+impl IdentityClaim {
+    /// Claim `app_id` for `signer`'s device.
+    pub fn issue(
+        signer: &ChangeSigner,
+        app_id: &str,
+        endorsement: Option<IdentityEndorsement>,
+    ) -> Self {
+        Self {
+            app_id: app_id.to_string(),
+            sig: signer.sign(&claim_bytes(app_id)),
+            endorsement,
+        }
+    }
+
+    /// Check the claim as announced by `peer` at `now`, trusting
+    /// endorsements from `issuers`.
+    ///
+    /// `None` when the claim isn't signed by `peer`'s own key — a claim
+    /// forged or replayed by another device. An endorsement that doesn't
+    /// check out only leaves the claim [`IdentityVerification::DeviceSigned`].
+    pub fn verify(
+        &self,
+        peer: &libp2p::PeerId,
+        issuers: &[[u8; 32]],
+        now: u64,
+    ) -> Option<IdentityVerification> {
+        let device = key_for_peer(peer)?;
+        let signed = self.sig.key == device
+            && ed25519::PublicKey::try_from_bytes(&device)
+                .is_ok_and(|by| by.verify(&claim_bytes(&self.app_id), &self.sig.sig));
+        if !signed {
+            return None;
+        }
+        let endorsed = self.endorsement.as_ref().filter(|e| {
+            e.app_id == self.app_id
+                && e.device == device
+                && issuers.contains(&e.sig.key)
+                && e.verify(now)
+        });
+        Some(match endorsed {
+            Some(e) => IdentityVerification::Endorsed { issuer: e.sig.key },
+            None => IdentityVerification::DeviceSigned,
+        })
+    }
+}
+
+fn claim_bytes(app_id: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(CLAIM_DOMAIN.len() + app_id.len());
+    out.extend_from_slice(CLAIM_DOMAIN);
+    out.extend_from_slice(app_id.as_bytes());
+    out
+}
+
+fn endorsement_bytes(app_id: &str, device: &[u8; 32], expires_at: Option<u64>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(ENDORSEMENT_DOMAIN.len() + 49 + app_id.len());
+    out.extend_from_slice(ENDORSEMENT_DOMAIN);
+    out.extend_from_slice(device);
+    match expires_at {
+        Some(at) => {
+            out.push(1);
+            out.extend_from_slice(&at.to_be_bytes());
+        }
+        None => out.push(0),
+    }
+    out.extend_from_slice(app_id.as_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::site_id_for_key;
+    use libp2p::identity;
+
+    fn device() -> (ChangeSigner, libp2p::PeerId) {
+        let keypair = ed25519::Keypair::generate();
+        let peer = libp2p::PeerId::from_public_key(&identity::PublicKey::from(keypair.public()));
+        let site = site_id_for_key(&keypair.public().to_bytes());
+        (ChangeSigner::new(keypair, site), peer)
+    }
+
+    #[test]
+    fn test_claim_binds_to_announcing_device() {
+        let (alice, alice_peer) = device();
+        let (_, mallory_peer) = device();
+        let claim = IdentityClaim::issue(&alice, "alice", None);
+        assert_eq!(
+            claim.verify(&alice_peer, &[], 0),
+            Some(IdentityVerification::DeviceSigned)
+        );
+
+        // Replayed by another device, or relabelled.
+        assert_eq!(claim.verify(&mallory_peer, &[], 0), None);
+        let mut relabelled = claim.clone();
+        relabelled.app_id = "bob".to_string();
+        assert_eq!(relabelled.verify(&alice_peer, &[], 0), None);
+
+        let json = serde_json::to_string(&claim).unwrap();
+        let back: IdentityClaim = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, claim);
+    }
+
+    #[test]
+    fn test_endorsement_needs_trusted_issuer_and_matching_device() {
+        let (alice, alice_peer) = device();
+        let issuer = ed25519::Keypair::generate();
+        let trusted = [issuer.public().to_bytes()];
+        let endorse = |app_id: &str, device: [u8; 32], expires_at| {
+            IdentityEndorsement::issue(&issuer, app_id, device, expires_at)
+        };
+
+        let claim = IdentityClaim::issue(
+            &alice,
+            "alice",
+            Some(endorse("alice", alice.public_key(), Some(100))),
+        );
+        assert_eq!(
+            claim.verify(&alice_peer, &trusted, 50),
+            Some(IdentityVerification::Endorsed { issuer: trusted[0] })
+        );
+        // Untrusted issuer, or expired.
+        assert_eq!(
+            claim.verify(&alice_peer, &[], 50),
+            Some(IdentityVerification::DeviceSigned)
+        );
+        assert_eq!(
+            claim.verify(&alice_peer, &trusted, 100),
+            Some(IdentityVerification::DeviceSigned)
+        );
+
+        // Endorsed for another identity or another device.
+        for endorsement in [
+            endorse("bob", alice.public_key(), None),
+            endorse("alice", [9u8; 32], None),
+        ] {
+            let claim = IdentityClaim::issue(&alice, "alice", Some(endorsement));
+            assert_eq!(
+                claim.verify(&alice_peer, &trusted, 0),
+                Some(IdentityVerification::DeviceSigned)
+            );
+        }
+    }
+}
@@ -40,12 +40,20 @@
 // These compile on every target — including wasm32 — and form the surface
 // shared with browser builds.
 pub mod auth;
+pub mod capability;
+pub(crate) mod compression;
 pub mod conflict;
 pub mod diagnostics;
+pub mod identity;
 pub mod messages;
 pub mod network_status;
+pub mod pairing;
 pub mod protocol;
 pub mod registry;
+pub mod revocation;
+pub mod rotation;
+pub mod seal;
+pub mod signing;
 pub mod synced_model;
 pub mod synced_table;
 
@@ -57,18 +65,24 @@ pub mod synced_table;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod background_sync;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod bundle;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod connection;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod engine;
 #[cfg(all(not(target_arch = "wasm32"), feature = "mobile-ffi"))]
 mod ffi;
 #[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod merkle;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod peer_addrs;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod peer_tracker;
 #[cfg(not(target_arch = "wasm32"))]
 pub(crate) mod push;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod secret_store;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod shadow;
 
 // Browser/wasm32 engine. Minimal real-time changeset fan-out over a
@@ -101,7 +115,7 @@ pub use messages::{
 pub use network_status::{NatStatus, NetworkEvent, NetworkStatus, PeerId, PeerInfo, RelayStatus};
 #[cfg(not(target_arch = "wasm32"))]
 pub use registry::SyncEntityInfo;
-pub use registry::{TableMeta, TableRegistry};
+pub use registry::{ColumnKind, ColumnType, TableMeta, TableRegistry};
 pub use synced_model::SyncedModel;
 pub use synced_table::SyncedTableEntity;
 
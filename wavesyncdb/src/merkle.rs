@@ -0,0 +1,236 @@
+//! Range hashes over shadow-table clocks, for anti-entropy.
+//!
+//! Version vector sync only ships what `peer_db_versions` says a peer is
+//! missing. If that bookkeeping is ever wrong — a restored backup, a manual
+//! `set_db_version`, a bug — two peers can stay divergent without noticing.
+//! Anti-entropy compares the clocks themselves, independent of `db_version`.
+//!
+//! Every clock `(pk, cid, col_version, site_id)` of a table is hashed and
+//! placed in the bucket addressed by the leading bytes of `blake3(pk)`. A
+//! bucket's hash is the XOR of its entries' hashes, so a parent is the XOR
+//! of its children and the tree needs no upkeep: it is recomputed from a
+//! scan of the shadow table when asked. With a fan-out of 256 and
+//! [`MAX_DEPTH`] levels, a leaf holds about `rows / 65536` rows.
+//!
+//! Column clocks of a row behind a `__deleted` tombstone are left out,
+//! matching what catch-up and state snapshots ship for such rows —
+//! otherwise a peer bootstrapped from a snapshot would look permanently
+//! divergent.
+
+use std::collections::{HashMap, HashSet};
+
+use sea_orm::{ConnectionTrait, DbErr};
+
+use crate::protocol::ClockDigest;
+use crate::shadow::{self, ClockEntry};
+
+/// Number of children under each range.
+pub const FANOUT: usize = 256;
+
+/// Depth of the leaf ranges, in bytes of `blake3(pk)`.
+pub const MAX_DEPTH: usize = 2;
+
+/// XOR-combined hash of the clocks in a range.
+pub type RangeHash = [u8; 32];
+
+fn pk_path(pk: &str) -> [u8; 32] {
+    *blake3::hash(pk.as_bytes()).as_bytes()
+}
+
+fn entry_hash(e: &ClockEntry) -> RangeHash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(e.pk.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(e.cid.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(&e.col_version.to_le_bytes());
+    hasher.update(&e.site_id.0);
+    *hasher.finalize().as_bytes()
+}
+
+fn xor_into(acc: &mut RangeHash, h: &RangeHash) {
+    for (a, b) in acc.iter_mut().zip(h) {
+        *a ^= b;
+    }
+}
+
+/// Clocks of `table` under `prefix`, with their pk paths, minus column
+/// clocks hidden by a tombstone.
+async fn effective_entries(
+    db: &impl ConnectionTrait,
+    table: &str,
+    prefix: &[u8],
+) -> Result<Vec<(ClockEntry, [u8; 32])>, DbErr> {
+    let entries = shadow::get_all_clock_entries(db, table).await?;
+    let tombstoned: HashSet<String> = entries
+        .iter()
+        .filter(|e| e.cid == "__deleted")
+        .map(|e| e.pk.clone())
+        .collect();
+    Ok(entries
+        .into_iter()
+        .filter(|e| e.cid == "__deleted" || !tombstoned.contains(&e.pk))
+        .filter_map(|e| {
+            let path = pk_path(&e.pk);
+            path.starts_with(prefix).then_some((e, path))
+        })
+        .collect())
+}
+
+/// Hash of every clock in `table`.
+pub async fn table_root(db: &impl ConnectionTrait, table: &str) -> Result<RangeHash, DbErr> {
+    let mut root = [0u8; 32];
+    for (e, _) in effective_entries(db, table, &[]).await? {
+        xor_into(&mut root, &entry_hash(&e));
+    }
+    Ok(root)
+}
+
+/// Hashes of the [`FANOUT`] children of the range `prefix` in `table`.
+/// `prefix` must be shorter than [`MAX_DEPTH`].
+pub async fn child_hashes(
+    db: &impl ConnectionTrait,
+    table: &str,
+    prefix: &[u8],
+) -> Result<Vec<RangeHash>, DbErr> {
+    debug_assert!(prefix.len() < MAX_DEPTH);
+    let mut children = vec![[0u8; 32]; FANOUT];
+    for (e, path) in effective_entries(db, table, prefix).await? {
+        xor_into(&mut children[path[prefix.len()] as usize], &entry_hash(&e));
+    }
+    Ok(children)
+}
+
+/// Clocks in the leaf range `prefix` of `table`.
+pub async fn leaf_entries(
+    db: &impl ConnectionTrait,
+    table: &str,
+    prefix: &[u8],
+) -> Result<Vec<ClockEntry>, DbErr> {
+    Ok(effective_entries(db, table, prefix)
+        .await?
+        .into_iter()
+        .map(|(e, _)| e)
+        .collect())
+}
+
+/// The part of a clock the range hashes cover.
+pub fn digest(e: &ClockEntry) -> ClockDigest {
+    ClockDigest {
+        pk: e.pk.clone(),
+        cid: e.cid.clone(),
+        col_version: e.col_version,
+        site_id: e.site_id,
+    }
+}
+
+/// Child indexes whose hashes differ. A malformed `theirs` (wrong length)
+/// counts as differing everywhere.
+pub fn differing_children(ours: &[RangeHash], theirs: &[RangeHash]) -> Vec<u8> {
+    (0..FANOUT)
+        .filter(|&i| ours.get(i) != theirs.get(i))
+        .map(|i| i as u8)
+        .collect()
+}
+
+/// Our clocks that the peer lacks or holds at a different version.
+pub fn entries_missing_from(local: Vec<ClockEntry>, theirs: &[ClockDigest]) -> Vec<ClockEntry> {
+    let theirs: HashMap<(&str, &str), (u64, &crate::messages::NodeId)> = theirs
+        .iter()
+        .map(|d| ((d.pk.as_str(), d.cid.as_str()), (d.col_version, &d.site_id)))
+        .collect();
+    local
+        .into_iter()
+        .filter(|e| {
+            theirs.get(&(e.pk.as_str(), e.cid.as_str())) != Some(&(e.col_version, &e.site_id))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::NodeId;
+    use sea_orm::Database;
+
+    async fn setup() -> sea_orm::DatabaseConnection {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        shadow::create_meta_table(&db).await.unwrap();
+        shadow::create_shadow_table(&db, "tasks").await.unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn test_identical_clocks_hash_equal_and_drift_is_localised() {
+        let a = setup().await;
+        let b = setup().await;
+        let site = NodeId([1u8; 16]);
+        for db in [&a, &b] {
+            for i in 0..50 {
+                let pk = format!("pk{i}");
+                shadow::upsert_clock_entry(db, "tasks", &pk, "title", 1, 1, &site, 0)
+                    .await
+                    .unwrap();
+            }
+        }
+        assert_eq!(
+            table_root(&a, "tasks").await.unwrap(),
+            table_root(&b, "tasks").await.unwrap()
+        );
+
+        // b missed one update
+        shadow::upsert_clock_entry(&a, "tasks", "pk7", "title", 2, 2, &site, 0)
+            .await
+            .unwrap();
+        assert_ne!(
+            table_root(&a, "tasks").await.unwrap(),
+            table_root(&b, "tasks").await.unwrap()
+        );
+
+        let path = pk_path("pk7");
+        let top = differing_children(
+            &child_hashes(&a, "tasks", &[]).await.unwrap(),
+            &child_hashes(&b, "tasks", &[]).await.unwrap(),
+        );
+        assert_eq!(top, vec![path[0]]);
+        let next = differing_children(
+            &child_hashes(&a, "tasks", &path[..1]).await.unwrap(),
+            &child_hashes(&b, "tasks", &path[..1]).await.unwrap(),
+        );
+        assert_eq!(next, vec![path[1]]);
+
+        let theirs: Vec<ClockDigest> = leaf_entries(&b, "tasks", &path[..MAX_DEPTH])
+            .await
+            .unwrap()
+            .iter()
+            .map(digest)
+            .collect();
+        let missing = entries_missing_from(
+            leaf_entries(&a, "tasks", &path[..MAX_DEPTH]).await.unwrap(),
+            &theirs,
+        );
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].pk, "pk7");
+        assert_eq!(missing[0].col_version, 2);
+    }
+
+    #[tokio::test]
+    async fn test_tombstone_hides_column_clocks() {
+        let a = setup().await;
+        let b = setup().await;
+        let site = NodeId([1u8; 16]);
+        // a saw the row before it was deleted, b only ever saw the tombstone
+        shadow::upsert_clock_entry(&a, "tasks", "pk1", "title", 1, 1, &site, 0)
+            .await
+            .unwrap();
+        for db in [&a, &b] {
+            shadow::insert_tombstone(db, "tasks", "pk1", 2, 2, &site)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            table_root(&a, "tasks").await.unwrap(),
+            table_root(&b, "tasks").await.unwrap()
+        );
+    }
+}
@@ -36,7 +36,7 @@ pub struct HmacTag(pub [u8; 32]);
 /// A 16-byte array, typically derived from process ID + timestamp at startup,
 /// or persisted in `_wavesync_meta` for stable identity across restarts.
 /// Used as the final tiebreaker in column-level conflict resolution.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct NodeId(pub [u8; 16]);
 
 // ── From impls for string newtypes ──
@@ -144,10 +144,15 @@ pub struct ColumnChange {
     pub cl: u64,
     /// Ordering within a single `db_version` batch.
     pub seq: u32,
-    /// The db_version at which this change was created.
-    /// Used for correct ordering in `get_changes_since`.
+    /// The db_version at which this change was created on its origin site
+    /// (`site_id`), or 0 if unknown (e.g. relayed by a peer that predates
+    /// origin versions). Used for per-origin watermarks in catch-up.
     #[serde(default)]
     pub db_version: u64,
+    /// The origin site's signature over the change, if it signs its
+    /// changes (see [`crate::signing`]). Kept as-is when relayed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sig: Option<crate::signing::ChangeSignature>,
 }
 
 /// A batch of column-level changes from a single write operation.
@@ -203,6 +208,7 @@ mod tests {
             cl: 5,
             seq: 0,
             db_version: 0,
+            sig: None,
         };
         let json = serde_json::to_string(&change).unwrap();
         let deserialized: ColumnChange = serde_json::from_str(&json).unwrap();
@@ -225,6 +231,7 @@ mod tests {
                     cl: 1,
                     seq: 0,
                     db_version: 0,
+                    sig: None,
                 },
                 ColumnChange {
                     table: "tasks".into(),
@@ -236,6 +243,7 @@ mod tests {
                     cl: 1,
                     seq: 1,
                     db_version: 0,
+                    sig: None,
                 },
             ],
         };
@@ -271,6 +279,7 @@ mod tests {
             cl: 3,
             seq: 0,
             db_version: 0,
+            sig: None,
         };
         assert_eq!(change.cid, "__deleted");
         assert!(change.val.is_none());
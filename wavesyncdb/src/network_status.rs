@@ -7,6 +7,10 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+use crate::capability::Capabilities;
+use crate::identity::IdentityVerification;
+use crate::messages::NodeId;
+
 /// Opaque peer identifier (wraps libp2p PeerId string).
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PeerId(pub String);
@@ -33,6 +37,13 @@ pub struct PeerInfo {
     pub is_group_member: bool,
     /// Application-defined identity announced by this peer (ephemeral, session-scoped).
     pub app_id: Option<String>,
+    /// How far `app_id` could be checked (see [`crate::identity`]).
+    #[serde(default)]
+    pub identity_verification: IdentityVerification,
+    /// What this peer may write, when a grant restricts it (`None` means no
+    /// restrictions; see [`crate::capability`]).
+    #[serde(default)]
+    pub capabilities: Option<Capabilities>,
 }
 
 /// Relay connection status.
@@ -137,8 +148,17 @@ pub enum NetworkEvent {
     PeerRejected(PeerId),
     /// A peer was verified via successful HMAC exchange.
     PeerVerified(PeerId),
-    /// A peer announced its application-level identity.
-    PeerIdentityReceived { peer_id: PeerId, app_id: String },
+    /// A peer's capability handshake showed we can't sync with it (e.g. a
+    /// protocol version or table schema mismatch). The connection is kept,
+    /// but no data is exchanged until one side is upgraded.
+    PeerIncompatible { peer_id: PeerId, reason: String },
+    /// A peer announced its application-level identity, checked as far as
+    /// `verification` says (see [`crate::identity`]).
+    PeerIdentityReceived {
+        peer_id: PeerId,
+        app_id: String,
+        verification: IdentityVerification,
+    },
     /// Relay connection status changed.
     RelayStatusChanged(RelayStatus),
     /// NAT detection status changed.
@@ -147,6 +167,28 @@ pub enum NetworkEvent {
     RendezvousStatusChanged { registered: bool },
     /// Version vector sync completed with a peer.
     PeerSynced { peer_id: PeerId, db_version: u64 },
+    /// The group key was rotated — by this device or by a peer — and the
+    /// engine moved to the new key's topic. `epoch` counts the rotations.
+    GroupKeyRotated { epoch: u64 },
+    /// A device was revoked from the group — by this device or by a peer —
+    /// and is refused from now on.
+    DeviceRevoked(PeerId),
+    /// A new device completed pairing with this one and received the
+    /// group credentials (see [`crate::pairing`]).
+    DevicePaired(PeerId),
+    /// A site's changes are refused from now on: one of them ran a clock
+    /// further ahead of ours than the configured bound allows (see
+    /// [`crate::conflict`]). `peer_id` is the device writing as the site,
+    /// when it is known.
+    SiteQuarantined {
+        site_id: NodeId,
+        peer_id: Option<PeerId>,
+        clock: u64,
+        local_max: u64,
+    },
+    /// A peer kept breaking the inbound limits and is refused until
+    /// `until` (Unix seconds).
+    PeerBanned { peer_id: PeerId, until: u64 },
     /// Local persistent state is loaded — the database is queryable
     /// independently of any peer connectivity. Fired **before**
     /// [`Self::EngineStarted`] so subscribers that only care about
@@ -195,6 +237,8 @@ mod tests {
                     is_bootstrap: false,
                     is_group_member: true,
                     app_id: None,
+                    identity_verification: IdentityVerification::Unverified,
+                    capabilities: None,
                 },
                 PeerInfo {
                     peer_id: PeerId("b".into()),
@@ -203,6 +247,8 @@ mod tests {
                     is_bootstrap: false,
                     is_group_member: false,
                     app_id: None,
+                    identity_verification: IdentityVerification::Unverified,
+                    capabilities: None,
                 },
                 PeerInfo {
                     peer_id: PeerId("c".into()),
@@ -211,6 +257,8 @@ mod tests {
                     is_bootstrap: true,
                     is_group_member: true,
                     app_id: None,
+                    identity_verification: IdentityVerification::Unverified,
+                    capabilities: None,
                 },
             ],
             ..Default::default()
@@ -242,6 +290,8 @@ mod tests {
                 is_bootstrap: false,
                 is_group_member: true,
                 app_id: None,
+                identity_verification: IdentityVerification::Unverified,
+                capabilities: None,
             }],
             topic: "my-topic".into(),
             relay_status: RelayStatus::Connected,
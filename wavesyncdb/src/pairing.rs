@@ -0,0 +1,653 @@
+//! Pairing a new device into a sync group over a short one-time code.
+//!
+//! Handing a device the raw passphrase — in a QR code, a link, a chat
+//! message — leaks the group secret to anyone who sees it. Pairing instead
+//! runs SPAKE2, a password-authenticated key exchange, over a short code
+//! that only works once and expires within minutes. A member of the group
+//! (the *inviter*) shows a [`PairingInvite`]: its peer id, the addresses it
+//! can be reached on, and the code. The new device (the *joiner*) dials it
+//! and runs the exchange over `/wavesync/pair/1.0.0`:
+//!
+//! 1. `Start` — the joiner's SPAKE2 message.
+//! 2. `Started` — the inviter's SPAKE2 message and a confirmation tag that
+//!    proves it derived the same key, i.e. knew the code.
+//! 3. `Finish` — the joiner's confirmation tag.
+//! 4. `Credentials` — the group credentials ([`PairingCredentials`]: topic,
+//!    passphrase, relay and rendezvous servers, addresses to bootstrap
+//!    from), encrypted under the exchanged key.
+//!
+//! An eavesdropper learns nothing about the code or the credentials, and
+//! someone who dials with a wrong code gets one guess: the inviter burns
+//! the code on the first `Start` it answers, whatever the outcome. What an
+//! invite leaks is a code that is already spent or about to expire.
+//!
+//! On native, [`WaveSyncDb::start_pairing`](crate::WaveSyncDb::start_pairing)
+//! invites and [`SyncConfig::pair`](crate::SyncConfig::pair) joins, saving
+//! the credentials as the device's sync config. Browser clients have the
+//! matching `WebSyncClient::start_pairing` and `WebSyncClient::pair`.
+
+use std::io;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p::request_response;
+use libp2p::{PeerId, StreamProtocol};
+use serde::{Deserialize, Serialize};
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+
+use crate::seal::{PayloadKey, from_hex, to_hex};
+
+/// Protocol identifier for the pairing exchange.
+pub const PAIRING_PROTOCOL: StreamProtocol = StreamProtocol::new("/wavesync/pair/1.0.0");
+
+/// How long an invite stays valid unless the caller picks otherwise.
+pub const DEFAULT_PAIRING_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// How long a joining device waits for the whole exchange.
+pub(crate) const JOIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Pairing messages are a few hundred bytes; anything near this is junk.
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+/// Characters a code is made of: no `0`/`O` or `1`/`I` to confuse.
+const CODE_ALPHABET: &[u8] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+/// Characters in a code, 40 bits' worth.
+const CODE_LEN: usize = 8;
+
+const JOINER_ID: &[u8] = b"wavesyncdb-pair-joiner";
+const INVITER_ID: &[u8] = b"wavesyncdb-pair-inviter";
+const CREDENTIALS_AAD: &[u8] = b"wavesyncdb-pairing-credentials-v1";
+
+/// Errors from pairing a device.
+#[derive(Debug, thiserror::Error)]
+pub enum PairingError {
+    #[error("invalid pairing invite: {0}")]
+    InvalidInvite(String),
+    #[error("the pairing code doesn't match")]
+    WrongCode,
+    #[error("pairing refused: {0}")]
+    Refused(String),
+    #[error("pairing protocol error: {0}")]
+    Protocol(String),
+    #[error("could not reach the inviting device: {0}")]
+    Unreachable(String),
+    #[error("pairing timed out")]
+    Timeout,
+    #[error("failed to save the paired config: {0}")]
+    Config(String),
+}
+
+/// A fresh random code, formatted `XXXX-XXXX`.
+pub fn generate_code() -> String {
+    let random = uuid::Uuid::new_v4();
+    let chars: String = random.as_bytes()[..CODE_LEN]
+        .iter()
+        .map(|b| CODE_ALPHABET[(*b as usize) % CODE_ALPHABET.len()] as char)
+        .collect();
+    format!("{}-{}", &chars[..CODE_LEN / 2], &chars[CODE_LEN / 2..])
+}
+
+/// A code as typed by a person: case, dashes and spaces don't matter.
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// What the inviting device shows the new one, as a QR code or a link.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PairingInvite {
+    /// The inviting device's libp2p peer id.
+    pub peer_id: String,
+    /// Multiaddrs it can be reached on, relay circuits included.
+    pub addrs: Vec<String>,
+    /// The one-time code.
+    pub code: String,
+}
+
+impl PairingInvite {
+    /// Encode as a `wavesync://pair?peer=…&addr=…&code=…` URL.
+    pub fn to_url(&self) -> String {
+        let mut url = format!("wavesync://pair?peer={}", percent_encode(&self.peer_id));
+        for addr in &self.addrs {
+            url.push_str("&addr=");
+            url.push_str(&percent_encode(addr));
+        }
+        url.push_str("&code=");
+        url.push_str(&percent_encode(&self.code));
+        url
+    }
+
+    /// Parse a URL made by [`Self::to_url`].
+    pub fn parse(raw: &str) -> Result<Self, PairingError> {
+        let query = raw
+            .trim()
+            .strip_prefix("wavesync://pair?")
+            .ok_or_else(|| PairingError::InvalidInvite("not a wavesync://pair URL".to_string()))?;
+
+        let (mut peer_id, mut addrs, mut code) = (None, Vec::new(), None);
+        for kv in query.split('&') {
+            let Some((k, v)) = kv.split_once('=') else {
+                continue;
+            };
+            let v = percent_decode(v);
+            match k {
+                "peer" => peer_id = Some(v),
+                "addr" => addrs.push(v),
+                "code" => code = Some(v),
+                _ => {}
+            }
+        }
+        let missing = |what: &str| PairingError::InvalidInvite(format!("no {what} in the invite"));
+        Ok(Self {
+            peer_id: peer_id.ok_or_else(|| missing("peer id"))?,
+            addrs,
+            code: code
+                .filter(|c| !normalize_code(c).is_empty())
+                .ok_or_else(|| missing("code"))?,
+        })
+    }
+}
+
+/// What a paired device needs to join the group.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PairingCredentials {
+    /// The topic the group's apps configure (before the passphrase is
+    /// mixed in).
+    pub topic: String,
+    /// The group passphrase currently in use.
+    pub passphrase: Option<String>,
+    /// Salt of the group key, when it is a v2 key (see [`crate::auth`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_salt: Option<crate::auth::GroupSalt>,
+    pub relay_server: Option<String>,
+    pub rendezvous_server: Option<String>,
+    /// Addresses of the inviting device, to reach the group through.
+    #[serde(default)]
+    pub bootstrap_peers: Vec<String>,
+}
+
+/// A message from the joiner.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PairingRequest {
+    /// The joiner's SPAKE2 message.
+    Start { spake: Vec<u8> },
+    /// The joiner's confirmation tag.
+    Finish { confirm: [u8; 32] },
+}
+
+/// A message from the inviter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PairingResponse {
+    /// The inviter's SPAKE2 message and confirmation tag.
+    Started { spake: Vec<u8>, confirm: [u8; 32] },
+    /// The credentials, encrypted under the exchanged key.
+    Credentials { nonce: [u8; 24], sealed: String },
+    /// No invitation is open, or it was already used.
+    Refused { reason: String },
+}
+
+/// Keys derived from the SPAKE2 secret, bound to the messages exchanged.
+struct SessionKeys {
+    inviter_confirm: [u8; 32],
+    joiner_confirm: [u8; 32],
+    credentials: PayloadKey,
+}
+
+impl SessionKeys {
+    fn derive(secret: &[u8], joiner_msg: &[u8], inviter_msg: &[u8]) -> Self {
+        let mut transcript = blake3::Hasher::new_derive_key("wavesyncdb-pairing-transcript-v1");
+        transcript.update(&(joiner_msg.len() as u64).to_be_bytes());
+        transcript.update(joiner_msg);
+        transcript.update(inviter_msg);
+        let transcript = transcript.finalize();
+
+        let confirm = |context: &str| {
+            *blake3::keyed_hash(&blake3::derive_key(context, secret), transcript.as_bytes())
+                .as_bytes()
+        };
+        Self {
+            inviter_confirm: confirm("wavesyncdb-pairing-inviter-v1"),
+            joiner_confirm: confirm("wavesyncdb-pairing-joiner-v1"),
+            credentials: PayloadKey::from_bytes(blake3::derive_key(
+                "wavesyncdb-pairing-credentials-v1",
+                secret,
+            )),
+        }
+    }
+}
+
+/// Compare tags in constant time.
+fn tags_match(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    blake3::Hash::from(*a) == blake3::Hash::from(*b)
+}
+
+/// The new device's side of the exchange.
+pub struct Joiner {
+    spake: Option<Spake2<Ed25519Group>>,
+    start: Vec<u8>,
+    keys: Option<SessionKeys>,
+}
+
+impl Joiner {
+    /// Begin pairing with `code`; send the returned `Start` to the inviter.
+    pub fn start(code: &str) -> (Self, PairingRequest) {
+        let (spake, msg) = Spake2::<Ed25519Group>::start_a(
+            &Password::new(normalize_code(code).as_bytes()),
+            &Identity::new(JOINER_ID),
+            &Identity::new(INVITER_ID),
+        );
+        let joiner = Self {
+            spake: Some(spake),
+            start: msg.clone(),
+            keys: None,
+        };
+        (joiner, PairingRequest::Start { spake: msg })
+    }
+
+    /// Check the inviter's `Started` and answer with `Finish`. Fails with
+    /// [`PairingError::WrongCode`] when the inviter holds another code.
+    pub fn confirm(
+        &mut self,
+        inviter_msg: &[u8],
+        confirm: &[u8; 32],
+    ) -> Result<PairingRequest, PairingError> {
+        let spake = self
+            .spake
+            .take()
+            .ok_or_else(|| PairingError::Protocol("exchange already confirmed".to_string()))?;
+        let secret = spake
+            .finish(inviter_msg)
+            .map_err(|e| PairingError::Protocol(format!("bad SPAKE2 message: {e:?}")))?;
+        let keys = SessionKeys::derive(&secret, &self.start, inviter_msg);
+        if !tags_match(&keys.inviter_confirm, confirm) {
+            return Err(PairingError::WrongCode);
+        }
+        let finish = PairingRequest::Finish {
+            confirm: keys.joiner_confirm,
+        };
+        self.keys = Some(keys);
+        Ok(finish)
+    }
+
+    /// Decrypt the inviter's `Credentials`.
+    pub fn open(&self, nonce: &[u8; 24], sealed: &str) -> Result<PairingCredentials, PairingError> {
+        let keys = self
+            .keys
+            .as_ref()
+            .ok_or_else(|| PairingError::Protocol("credentials before confirmation".to_string()))?;
+        let ciphertext = from_hex(sealed)
+            .ok_or_else(|| PairingError::Protocol("credentials are not hex".to_string()))?;
+        let json = keys
+            .credentials
+            .decrypt(nonce, &ciphertext, CREDENTIALS_AAD)
+            .ok_or_else(|| PairingError::Protocol("credentials failed to decrypt".to_string()))?;
+        serde_json::from_slice(&json).map_err(|e| PairingError::Protocol(e.to_string()))
+    }
+}
+
+/// The inviting device's side of one exchange.
+pub struct InviterSession {
+    keys: SessionKeys,
+}
+
+impl InviterSession {
+    /// Answer a joiner's `Start` under the invitation's `code`.
+    pub fn respond(code: &str, joiner_msg: &[u8]) -> Result<(Self, PairingResponse), PairingError> {
+        let (spake, msg) = Spake2::<Ed25519Group>::start_b(
+            &Password::new(normalize_code(code).as_bytes()),
+            &Identity::new(JOINER_ID),
+            &Identity::new(INVITER_ID),
+        );
+        let secret = spake
+            .finish(joiner_msg)
+            .map_err(|e| PairingError::Protocol(format!("bad SPAKE2 message: {e:?}")))?;
+        let keys = SessionKeys::derive(&secret, joiner_msg, &msg);
+        let started = PairingResponse::Started {
+            spake: msg,
+            confirm: keys.inviter_confirm,
+        };
+        Ok((Self { keys }, started))
+    }
+
+    /// Check the joiner's `Finish` and, if it proves the code, seal
+    /// `credentials` for it.
+    pub fn finish(
+        &self,
+        confirm: &[u8; 32],
+        credentials: &PairingCredentials,
+    ) -> Result<PairingResponse, PairingError> {
+        if !tags_match(&self.keys.joiner_confirm, confirm) {
+            return Err(PairingError::WrongCode);
+        }
+        let json = serde_json::to_vec(credentials).expect("credentials serialize to JSON");
+        let (nonce, ciphertext) = self.keys.credentials.encrypt(&json, CREDENTIALS_AAD);
+        Ok(PairingResponse::Credentials {
+            nonce,
+            sealed: to_hex(&ciphertext),
+        })
+    }
+}
+
+/// An invitation open on the inviting device's engine.
+pub(crate) struct Invitation {
+    /// The code, until the first joiner spends it.
+    code: Option<String>,
+    /// Unix seconds after which the invitation is closed.
+    expires_at: u64,
+    /// The joiner that spent the code, and the exchange with it.
+    session: Option<(PeerId, InviterSession)>,
+}
+
+impl Invitation {
+    pub(crate) fn new(code: String, expires_at: u64) -> Self {
+        Self {
+            code: Some(code),
+            expires_at,
+            session: None,
+        }
+    }
+}
+
+/// Answer `request` from `peer` under the invitation in `slot` at `now`
+/// (unix seconds), closing it once it is used up or expired. A joiner
+/// that proves the code is sent `credentials`; the flag returned is
+/// whether that happened.
+pub(crate) fn answer(
+    slot: &mut Option<Invitation>,
+    now: u64,
+    peer: PeerId,
+    request: PairingRequest,
+    credentials: &PairingCredentials,
+) -> (PairingResponse, bool) {
+    let refuse = |reason: &str| {
+        let response = PairingResponse::Refused {
+            reason: reason.to_string(),
+        };
+        (response, false)
+    };
+    if slot.as_ref().is_some_and(|inv| inv.expires_at <= now) {
+        *slot = None;
+    }
+    let Some(invitation) = slot.as_mut() else {
+        return refuse("no pairing invitation is open");
+    };
+
+    match request {
+        PairingRequest::Start { spake } => {
+            // One guess per code: it is spent whatever the outcome.
+            let Some(code) = invitation.code.take() else {
+                return refuse("the pairing code was already used");
+            };
+            match InviterSession::respond(&code, &spake) {
+                Ok((session, started)) => {
+                    invitation.session = Some((peer, session));
+                    (started, false)
+                }
+                Err(e) => {
+                    *slot = None;
+                    refuse(&e.to_string())
+                }
+            }
+        }
+        PairingRequest::Finish { confirm } => {
+            let Some((_, session)) = invitation.session.take_if(|(p, _)| *p == peer) else {
+                return refuse("no pairing exchange in progress with this device");
+            };
+            *slot = None;
+            match session.finish(&confirm, credentials) {
+                Ok(sealed) => (sealed, true),
+                Err(e) => refuse(&e.to_string()),
+            }
+        }
+    }
+}
+
+/// Length-prefixed serde_json codec for the pairing protocol.
+#[derive(Debug, Clone, Default)]
+pub struct PairingCodec;
+
+#[async_trait]
+impl request_response::Codec for PairingCodec {
+    type Protocol = StreamProtocol;
+    type Request = PairingRequest;
+    type Response = PairingResponse;
+
+    async fn read_request<T>(
+        &mut self,
+        _p: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io).await
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _p: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _p: &Self::Protocol,
+        io: &mut T,
+        req: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, &req).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _p: &Self::Protocol,
+        io: &mut T,
+        res: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, &res).await
+    }
+}
+
+async fn read_message<T, M>(io: &mut T) -> io::Result<M>
+where
+    T: AsyncRead + Unpin + Send,
+    M: serde::de::DeserializeOwned,
+{
+    let mut len_buf = [0u8; 4];
+    io.read_exact(&mut len_buf).await?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("pairing message too large: {len}"),
+        ));
+    }
+    let mut buf = vec![0u8; len];
+    io.read_exact(&mut buf).await?;
+    serde_json::from_slice(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+async fn write_message<T, M>(io: &mut T, msg: &M) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + Send,
+    M: Serialize,
+{
+    let bytes =
+        serde_json::to_vec(msg).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    io.write_all(&(bytes.len() as u32).to_be_bytes()).await?;
+    io.write_all(&bytes).await?;
+    io.flush().await
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters.
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+/// Inverse of [`percent_encode`]; malformed escapes are kept as-is.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = s.get(i + 1..i + 3)
+            && let Ok(b) = u8::from_str_radix(hex, 16)
+        {
+            out.push(b);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials() -> PairingCredentials {
+        PairingCredentials {
+            topic: "notes".to_string(),
+            passphrase: Some("correct horse".to_string()),
+            group_salt: Some(crate::auth::GroupSalt([9u8; 16])),
+            relay_server: Some("/dns4/relay.example.com/udp/4001/quic-v1".to_string()),
+            rendezvous_server: None,
+            bootstrap_peers: vec!["/ip4/192.168.1.2/udp/4001/quic-v1".to_string()],
+        }
+    }
+
+    fn exchange(inviter_code: &str, joiner_code: &str) -> Result<PairingCredentials, PairingError> {
+        let (mut joiner, start) = Joiner::start(joiner_code);
+        let PairingRequest::Start { spake } = start else {
+            panic!("Expected Start");
+        };
+        let (session, started) = InviterSession::respond(inviter_code, &spake)?;
+        let PairingResponse::Started { spake, confirm } = started else {
+            panic!("Expected Started");
+        };
+        let PairingRequest::Finish { confirm } = joiner.confirm(&spake, &confirm)? else {
+            panic!("Expected Finish");
+        };
+        match session.finish(&confirm, &credentials())? {
+            PairingResponse::Credentials { nonce, sealed } => joiner.open(&nonce, &sealed),
+            _ => panic!("Expected Credentials"),
+        }
+    }
+
+    #[test]
+    fn test_exchange_transfers_credentials() {
+        let code = generate_code();
+        assert_eq!(code.len(), CODE_LEN + 1);
+        // Typed by hand: lowercase, no dash.
+        let typed = code.replace('-', "").to_lowercase();
+        assert_eq!(exchange(&code, &typed).unwrap(), credentials());
+    }
+
+    #[test]
+    fn test_wrong_code_is_detected() {
+        assert!(matches!(
+            exchange("ABCD-EFGH", "ABCD-EFGJ"),
+            Err(PairingError::WrongCode)
+        ));
+    }
+
+    #[test]
+    fn test_inviter_refuses_unconfirmed_joiner() {
+        let (_, start) = Joiner::start("ABCD-EFGH");
+        let PairingRequest::Start { spake } = start else {
+            panic!("Expected Start");
+        };
+        let (session, _) = InviterSession::respond("ABCD-EFGH", &spake).unwrap();
+        assert!(matches!(
+            session.finish(&[0u8; 32], &credentials()),
+            Err(PairingError::WrongCode)
+        ));
+    }
+
+    #[test]
+    fn test_code_is_spent_by_the_first_joiner() {
+        let random_peer = || {
+            libp2p::identity::Keypair::generate_ed25519()
+                .public()
+                .to_peer_id()
+        };
+        let joiner_peer = random_peer();
+        let mut slot = Some(Invitation::new("ABCD-EFGH".to_string(), 100));
+
+        // A wrong guess spends the code, so the right one comes too late.
+        let (_, start) = Joiner::start("ABCD-EFGJ");
+        let (response, _) = answer(&mut slot, 10, random_peer(), start, &credentials());
+        assert!(matches!(response, PairingResponse::Started { .. }));
+        let (_, start) = Joiner::start("ABCD-EFGH");
+        let (response, _) = answer(&mut slot, 10, joiner_peer, start, &credentials());
+        assert!(matches!(response, PairingResponse::Refused { .. }));
+
+        // A fresh invitation pairs, and closes behind the joiner.
+        let mut slot = Some(Invitation::new("ABCD-EFGH".to_string(), 100));
+        let (mut joiner, start) = Joiner::start("abcd efgh");
+        let (started, _) = answer(&mut slot, 10, joiner_peer, start, &credentials());
+        let PairingResponse::Started { spake, confirm } = started else {
+            panic!("Expected Started");
+        };
+        let finish = joiner.confirm(&spake, &confirm).unwrap();
+        let (sealed, paired) = answer(&mut slot, 10, joiner_peer, finish, &credentials());
+        assert!(paired);
+        assert!(slot.is_none());
+        let PairingResponse::Credentials { nonce, sealed } = sealed else {
+            panic!("Expected Credentials");
+        };
+        assert_eq!(joiner.open(&nonce, &sealed).unwrap(), credentials());
+
+        // An expired invitation answers nobody.
+        let mut slot = Some(Invitation::new("ABCD-EFGH".to_string(), 100));
+        let (_, start) = Joiner::start("ABCD-EFGH");
+        let (response, _) = answer(&mut slot, 100, joiner_peer, start, &credentials());
+        assert!(matches!(response, PairingResponse::Refused { .. }));
+        assert!(slot.is_none());
+    }
+
+    #[test]
+    fn test_invite_url_roundtrip() {
+        let invite = PairingInvite {
+            peer_id: "12D3KooWP6oyorVZmZvTRPHdxsEyE2V8mR1dTiAZJYoNsMNX19KW".to_string(),
+            addrs: vec![
+                "/ip4/192.168.1.2/udp/4001/quic-v1".to_string(),
+                "/dns4/relay.example.com/tcp/443/wss/p2p/12D3KooWRelay/p2p-circuit".to_string(),
+            ],
+            code: generate_code(),
+        };
+        let url = invite.to_url();
+        assert!(!url.contains("/ip4"));
+        assert_eq!(PairingInvite::parse(&url).unwrap(), invite);
+
+        assert!(PairingInvite::parse("wavesync://?peer=a&topic=b&pass=c").is_err());
+        assert!(PairingInvite::parse("wavesync://pair?peer=a&code=-").is_err());
+    }
+}
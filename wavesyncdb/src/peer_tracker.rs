@@ -3,12 +3,37 @@
 //! Maintains a `_wavesync_peer_versions` table that tracks known peers and
 //! their last-known `db_version`. This allows efficient incremental sync:
 //! when a peer reconnects, we only send changes since their last known version.
+//!
+//! A second table, `_wavesync_catchup_cursors`, checkpoints paginated
+//! catch-up: after each page is applied, the continuation cursor is stored
+//! so a sync interrupted by a dropped connection or an app restart resumes
+//! from the last applied page instead of starting over.
+//!
+//! A third, `_wavesync_origin_versions`, persists this node's
+//! [`OriginVersions`] watermarks for other sites.
+//!
+//! A fourth, `_wavesync_site_keys`, pins the signing key first seen for
+//! each site whose id isn't derived from its key (see [`crate::signing`]).
+//!
+//! A fifth, `_wavesync_revocations`, holds the devices revoked from the
+//! group (see [`crate::revocation`]).
+//!
+//! `_wavesync_capability_grants` holds the capability grants of restricted
+//! devices, and `_wavesync_capability_denials` the remote changes refused
+//! under them (see [`crate::capability`]).
+//!
+//! `_wavesync_quarantine` holds the sites whose changes are refused for
+//! running their clocks too far ahead (see [`crate::conflict`]).
 
 use std::collections::HashMap;
 
 use sea_orm::{ConnectionTrait, DbErr, ExecResult, FromQueryResult, Statement};
 
-use crate::messages::NodeId;
+use crate::capability::{CapabilityGrant, Operation};
+use crate::messages::{ColumnChange, NodeId};
+use crate::protocol::{OriginVersions, SyncCursor};
+use crate::revocation::Revocation;
+use crate::signing::ChangeSignature;
 
 /// Create the `_wavesync_peer_versions` table if it does not already exist.
 pub async fn create_peer_versions_table(db: &impl ConnectionTrait) -> Result<ExecResult, DbErr> {
@@ -94,6 +119,48 @@ pub async fn get_all_peer_versions(
         .collect())
 }
 
+/// A peer we have synced with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerVersion {
+    pub peer_id: String,
+    /// Its site, if it was recorded.
+    pub site_id: Option<NodeId>,
+    /// Its `db_version` as of our last sync with it.
+    pub db_version: u64,
+    /// Unix seconds at which it was last seen.
+    pub last_seen: u64,
+}
+
+/// Every peer we have synced with, most recently seen first.
+pub async fn get_peers(db: &impl ConnectionTrait) -> Result<Vec<PeerVersion>, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct PeerRow {
+        peer_id: String,
+        site_id: Option<Vec<u8>>,
+        db_version: i64,
+        last_seen: i64,
+    }
+
+    let rows = PeerRow::find_by_statement(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "SELECT peer_id, site_id, db_version, last_seen FROM _wavesync_peer_versions
+         ORDER BY last_seen DESC, peer_id",
+        [],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| PeerVersion {
+            peer_id: r.peer_id,
+            site_id: r.site_id.and_then(|s| s.try_into().ok()).map(NodeId),
+            db_version: r.db_version as u64,
+            last_seen: r.last_seen as u64,
+        })
+        .collect())
+}
+
 /// Update the last_seen timestamp for a peer.
 pub async fn update_last_seen(
     db: &impl ConnectionTrait,
@@ -108,6 +175,528 @@ pub async fn update_last_seen(
     .await
 }
 
+/// Create the `_wavesync_origin_versions` table if it does not already exist.
+pub async fn create_origin_versions_table(db: &impl ConnectionTrait) -> Result<ExecResult, DbErr> {
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS _wavesync_origin_versions (
+            site_id     BLOB PRIMARY KEY,
+            db_version  INTEGER NOT NULL
+        )",
+    )
+    .await
+}
+
+/// Persist watermarks. Each only ever moves forward, so a stale write
+/// racing a newer one can't lower it.
+pub async fn save_origin_versions(
+    db: &impl ConnectionTrait,
+    versions: &OriginVersions,
+) -> Result<(), DbErr> {
+    for (site, version) in versions.iter() {
+        db.execute_raw(Statement::from_sql_and_values(
+            sea_orm::DatabaseBackend::Sqlite,
+            "INSERT INTO _wavesync_origin_versions (site_id, db_version)
+             VALUES ($1, $2)
+             ON CONFLICT(site_id) DO UPDATE SET
+                db_version = MAX(db_version, excluded.db_version)",
+            [site.0.to_vec().into(), (*version as i64).into()],
+        ))
+        .await?;
+    }
+    Ok(())
+}
+
+/// Load the persisted watermarks.
+pub async fn get_origin_versions(db: &impl ConnectionTrait) -> Result<OriginVersions, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct OriginRow {
+        site_id: Vec<u8>,
+        db_version: i64,
+    }
+
+    let rows = OriginRow::find_by_statement(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "SELECT site_id, db_version FROM _wavesync_origin_versions",
+        [],
+    ))
+    .all(db)
+    .await?;
+
+    let mut versions = OriginVersions::default();
+    for r in rows {
+        let mut id = [0u8; 16];
+        let len = r.site_id.len().min(16);
+        id[..len].copy_from_slice(&r.site_id[..len]);
+        versions.advance(NodeId(id), r.db_version as u64);
+    }
+    Ok(versions)
+}
+
+/// Create the `_wavesync_site_keys` table if it does not already exist.
+pub async fn create_site_keys_table(db: &impl ConnectionTrait) -> Result<ExecResult, DbErr> {
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS _wavesync_site_keys (
+            site_id     BLOB PRIMARY KEY,
+            public_key  BLOB NOT NULL
+        )",
+    )
+    .await
+}
+
+/// Pin `key` as the signing key of `site`, replacing any earlier pin.
+pub async fn pin_site_key(
+    db: &impl ConnectionTrait,
+    site: &NodeId,
+    key: &[u8; 32],
+) -> Result<(), DbErr> {
+    db.execute_raw(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "INSERT OR REPLACE INTO _wavesync_site_keys (site_id, public_key) VALUES ($1, $2)",
+        [site.0.to_vec().into(), key.to_vec().into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Load the pinned signing keys.
+pub async fn get_site_keys(db: &impl ConnectionTrait) -> Result<HashMap<NodeId, [u8; 32]>, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct KeyRow {
+        site_id: Vec<u8>,
+        public_key: Vec<u8>,
+    }
+
+    let rows = KeyRow::find_by_statement(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "SELECT site_id, public_key FROM _wavesync_site_keys",
+        [],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|r| {
+            let site: [u8; 16] = r.site_id.try_into().ok()?;
+            let key: [u8; 32] = r.public_key.try_into().ok()?;
+            Some((NodeId(site), key))
+        })
+        .collect())
+}
+
+/// Create the `_wavesync_revocations` table if it does not already exist.
+pub async fn create_revocations_table(db: &impl ConnectionTrait) -> Result<ExecResult, DbErr> {
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS _wavesync_revocations (
+            public_key  BLOB PRIMARY KEY,
+            revoked_at  INTEGER NOT NULL,
+            signature   BLOB NOT NULL
+        )",
+    )
+    .await
+}
+
+/// Persist a revocation. A device is only ever revoked once, so an
+/// existing row is kept.
+pub async fn record_revocation(
+    db: &impl ConnectionTrait,
+    revocation: &Revocation,
+) -> Result<(), DbErr> {
+    db.execute_raw(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "INSERT OR IGNORE INTO _wavesync_revocations (public_key, revoked_at, signature)
+         VALUES ($1, $2, $3)",
+        [
+            revocation.key.to_vec().into(),
+            (revocation.revoked_at as i64).into(),
+            revocation.sig.to_bytes().into(),
+        ],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Load every revocation.
+pub async fn get_revocations(db: &impl ConnectionTrait) -> Result<Vec<Revocation>, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct RevocationRow {
+        public_key: Vec<u8>,
+        revoked_at: i64,
+        signature: Vec<u8>,
+    }
+
+    let rows = RevocationRow::find_by_statement(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "SELECT public_key, revoked_at, signature FROM _wavesync_revocations",
+        [],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|r| {
+            Some(Revocation {
+                key: r.public_key.try_into().ok()?,
+                revoked_at: r.revoked_at as u64,
+                sig: ChangeSignature::from_bytes(&r.signature)?,
+            })
+        })
+        .collect())
+}
+
+/// Create the `_wavesync_capability_grants` and
+/// `_wavesync_capability_denials` tables if they do not already exist.
+pub async fn create_capability_tables(db: &impl ConnectionTrait) -> Result<ExecResult, DbErr> {
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS _wavesync_capability_grants (
+            subject     BLOB PRIMARY KEY,
+            grant_json  TEXT NOT NULL
+        )",
+    )
+    .await?;
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS _wavesync_capability_denials (
+            denied_at   INTEGER NOT NULL,
+            site_id     BLOB NOT NULL,
+            role        TEXT NOT NULL,
+            table_name  TEXT NOT NULL,
+            pk          TEXT NOT NULL,
+            cid         TEXT NOT NULL,
+            operation   TEXT NOT NULL
+        )",
+    )
+    .await
+}
+
+/// Persist `grant`, replacing the device's previous one.
+pub async fn save_capability_grant(
+    db: &impl ConnectionTrait,
+    grant: &CapabilityGrant,
+) -> Result<(), DbErr> {
+    let json = serde_json::to_string(grant).map_err(|e| DbErr::Custom(e.to_string()))?;
+    db.execute_raw(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "INSERT OR REPLACE INTO _wavesync_capability_grants (subject, grant_json) VALUES ($1, $2)",
+        [grant.subject.to_vec().into(), json.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Load every capability grant.
+pub async fn get_capability_grants(
+    db: &impl ConnectionTrait,
+) -> Result<Vec<CapabilityGrant>, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct GrantRow {
+        grant_json: String,
+    }
+
+    let rows = GrantRow::find_by_statement(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "SELECT grant_json FROM _wavesync_capability_grants",
+        [],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|r| serde_json::from_str(&r.grant_json).ok())
+        .collect())
+}
+
+/// A remote change refused because its author's grant didn't allow it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct CapabilityDenial {
+    /// Unix seconds at which the change was refused.
+    pub denied_at: u64,
+    /// The site that wrote the change.
+    pub site_id: NodeId,
+    /// The role its grant gave it.
+    pub role: String,
+    pub table: String,
+    pub pk: String,
+    pub cid: String,
+    pub operation: Operation,
+}
+
+/// Record that `change`, written under `role`, was refused.
+pub async fn record_capability_denial(
+    db: &impl ConnectionTrait,
+    change: &ColumnChange,
+    role: &str,
+    operation: Operation,
+) -> Result<(), DbErr> {
+    db.execute_raw(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "INSERT INTO _wavesync_capability_denials
+            (denied_at, site_id, role, table_name, pk, cid, operation)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        [
+            (now_secs() as i64).into(),
+            change.site_id.0.to_vec().into(),
+            role.into(),
+            change.table.0.clone().into(),
+            change.pk.0.clone().into(),
+            change.cid.0.clone().into(),
+            operation.as_str().into(),
+        ],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// The most recent `limit` capability denials, newest first.
+pub async fn get_capability_denials(
+    db: &impl ConnectionTrait,
+    limit: u64,
+) -> Result<Vec<CapabilityDenial>, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct DenialRow {
+        denied_at: i64,
+        site_id: Vec<u8>,
+        role: String,
+        table_name: String,
+        pk: String,
+        cid: String,
+        operation: String,
+    }
+
+    let rows = DenialRow::find_by_statement(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "SELECT denied_at, site_id, role, table_name, pk, cid, operation
+         FROM _wavesync_capability_denials ORDER BY rowid DESC LIMIT $1",
+        [(limit as i64).into()],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|r| {
+            Some(CapabilityDenial {
+                denied_at: r.denied_at as u64,
+                site_id: NodeId(r.site_id.try_into().ok()?),
+                role: r.role,
+                table: r.table_name,
+                pk: r.pk,
+                cid: r.cid,
+                operation: serde_json::from_value(serde_json::Value::String(r.operation)).ok()?,
+            })
+        })
+        .collect())
+}
+
+/// Create the `_wavesync_quarantine` table if it does not already exist.
+pub async fn create_quarantine_table(db: &impl ConnectionTrait) -> Result<ExecResult, DbErr> {
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS _wavesync_quarantine (
+            site_id         BLOB PRIMARY KEY,
+            quarantined_at  INTEGER NOT NULL,
+            table_name      TEXT NOT NULL,
+            pk              TEXT NOT NULL,
+            cid             TEXT NOT NULL,
+            clock           BLOB NOT NULL,
+            local_max       BLOB NOT NULL
+        )",
+    )
+    .await
+}
+
+/// A site whose remote changes are refused because one of them ran a
+/// clock further past ours than the bound allows.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Quarantine {
+    /// The site that wrote the offending change.
+    pub site_id: NodeId,
+    /// Unix seconds at which it was quarantined.
+    pub quarantined_at: u64,
+    /// Where the offending change was written.
+    pub table: String,
+    pub pk: String,
+    pub cid: String,
+    /// The change's clock.
+    pub clock: u64,
+    /// The highest clock we held for the row at the time.
+    pub local_max: u64,
+}
+
+/// Quarantine the site that wrote `change`, whose `clock` ran past
+/// `local_max`. A site already quarantined keeps its first record.
+pub async fn quarantine_site(
+    db: &impl ConnectionTrait,
+    change: &ColumnChange,
+    clock: u64,
+    local_max: u64,
+) -> Result<Quarantine, DbErr> {
+    let record = Quarantine {
+        site_id: change.site_id,
+        quarantined_at: now_secs(),
+        table: change.table.0.clone(),
+        pk: change.pk.0.clone(),
+        cid: change.cid.0.clone(),
+        clock,
+        local_max,
+    };
+    // Clocks are stored as big-endian bytes: SQLite integers stop at
+    // i64::MAX, and the offending ones are exactly those past it.
+    db.execute_raw(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "INSERT OR IGNORE INTO _wavesync_quarantine
+            (site_id, quarantined_at, table_name, pk, cid, clock, local_max)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        [
+            record.site_id.0.to_vec().into(),
+            (record.quarantined_at as i64).into(),
+            record.table.clone().into(),
+            record.pk.clone().into(),
+            record.cid.clone().into(),
+            clock.to_be_bytes().to_vec().into(),
+            local_max.to_be_bytes().to_vec().into(),
+        ],
+    ))
+    .await?;
+    Ok(record)
+}
+
+/// Every quarantined site, oldest first.
+pub async fn get_quarantined_sites(db: &impl ConnectionTrait) -> Result<Vec<Quarantine>, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct QuarantineRow {
+        site_id: Vec<u8>,
+        quarantined_at: i64,
+        table_name: String,
+        pk: String,
+        cid: String,
+        clock: Vec<u8>,
+        local_max: Vec<u8>,
+    }
+
+    let rows = QuarantineRow::find_by_statement(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "SELECT site_id, quarantined_at, table_name, pk, cid, clock, local_max
+         FROM _wavesync_quarantine ORDER BY quarantined_at, rowid",
+        [],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|r| {
+            Some(Quarantine {
+                site_id: NodeId(r.site_id.try_into().ok()?),
+                quarantined_at: r.quarantined_at as u64,
+                table: r.table_name,
+                pk: r.pk,
+                cid: r.cid,
+                clock: u64::from_be_bytes(r.clock.try_into().ok()?),
+                local_max: u64::from_be_bytes(r.local_max.try_into().ok()?),
+            })
+        })
+        .collect())
+}
+
+/// Lift the quarantine of `site`. Returns whether it was quarantined.
+pub async fn release_quarantine(db: &impl ConnectionTrait, site: &NodeId) -> Result<bool, DbErr> {
+    let result = db
+        .execute_raw(Statement::from_sql_and_values(
+            sea_orm::DatabaseBackend::Sqlite,
+            "DELETE FROM _wavesync_quarantine WHERE site_id = $1",
+            [site.0.to_vec().into()],
+        ))
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Create the `_wavesync_catchup_cursors` table if it does not already exist.
+pub async fn create_catchup_cursors_table(db: &impl ConnectionTrait) -> Result<ExecResult, DbErr> {
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS _wavesync_catchup_cursors (
+            peer_id           TEXT PRIMARY KEY,
+            since_db_version  INTEGER NOT NULL,
+            cursor            TEXT NOT NULL,
+            updated_at        INTEGER NOT NULL
+        )",
+    )
+    .await
+}
+
+/// Checkpoint an in-progress paginated catch-up from `peer_id`.
+///
+/// `since_db_version` is the `your_last_db_version` the catch-up started
+/// from; it must be sent unchanged with every page, so it is stored with
+/// the cursor.
+pub async fn save_catchup_cursor(
+    db: &impl ConnectionTrait,
+    peer_id: &str,
+    since_db_version: u64,
+    cursor: &SyncCursor,
+) -> Result<ExecResult, DbErr> {
+    let cursor_json = serde_json::to_string(cursor).map_err(|e| DbErr::Custom(e.to_string()))?;
+    db.execute_raw(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "INSERT INTO _wavesync_catchup_cursors (peer_id, since_db_version, cursor, updated_at)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT(peer_id) DO UPDATE SET
+            since_db_version = excluded.since_db_version,
+            cursor = excluded.cursor,
+            updated_at = excluded.updated_at",
+        [
+            peer_id.into(),
+            (since_db_version as i64).into(),
+            cursor_json.into(),
+            (now_secs() as i64).into(),
+        ],
+    ))
+    .await
+}
+
+/// Drop the catch-up checkpoint for `peer_id` (last page applied, or a
+/// full re-sync was requested).
+pub async fn clear_catchup_cursor(
+    db: &impl ConnectionTrait,
+    peer_id: &str,
+) -> Result<ExecResult, DbErr> {
+    db.execute_raw(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "DELETE FROM _wavesync_catchup_cursors WHERE peer_id = $1",
+        [peer_id.into()],
+    ))
+    .await
+}
+
+/// Load every catch-up checkpoint as `peer_id → (since_db_version, cursor)`.
+/// Rows whose cursor no longer parses are skipped.
+pub async fn get_all_catchup_cursors(
+    db: &impl ConnectionTrait,
+) -> Result<HashMap<String, (u64, SyncCursor)>, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct CursorRow {
+        peer_id: String,
+        since_db_version: i64,
+        cursor: String,
+    }
+
+    let rows = CursorRow::find_by_statement(Statement::from_sql_and_values(
+        sea_orm::DatabaseBackend::Sqlite,
+        "SELECT peer_id, since_db_version, cursor FROM _wavesync_catchup_cursors",
+        [],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|r| {
+            let cursor = serde_json::from_str(&r.cursor).ok()?;
+            Some((r.peer_id, (r.since_db_version as u64, cursor)))
+        })
+        .collect())
+}
+
 fn now_secs() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -124,6 +713,12 @@ mod tests {
     async fn setup_db() -> sea_orm::DatabaseConnection {
         let db = Database::connect("sqlite::memory:").await.unwrap();
         create_peer_versions_table(&db).await.unwrap();
+        create_catchup_cursors_table(&db).await.unwrap();
+        create_origin_versions_table(&db).await.unwrap();
+        create_site_keys_table(&db).await.unwrap();
+        create_revocations_table(&db).await.unwrap();
+        create_capability_tables(&db).await.unwrap();
+        create_quarantine_table(&db).await.unwrap();
         db
     }
 
@@ -177,6 +772,28 @@ mod tests {
         assert_eq!(versions["peer-2"], 20);
     }
 
+    #[tokio::test]
+    async fn test_get_peers() {
+        let db = setup_db().await;
+        upsert_peer_version(&db, "peer-1", &NodeId([1u8; 16]), 10)
+            .await
+            .unwrap();
+        db.execute_unprepared(
+            "INSERT INTO _wavesync_peer_versions (peer_id, db_version, last_seen)
+             VALUES ('peer-0', 3, 1)",
+        )
+        .await
+        .unwrap();
+
+        let peers = get_peers(&db).await.unwrap();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].peer_id, "peer-1");
+        assert_eq!(peers[0].site_id, Some(NodeId([1u8; 16])));
+        assert_eq!(peers[0].db_version, 10);
+        assert_eq!(peers[1].site_id, None);
+        assert_eq!(peers[1].last_seen, 1);
+    }
+
     #[tokio::test]
     async fn test_update_last_seen() {
         let db = setup_db().await;
@@ -189,4 +806,151 @@ mod tests {
         let version = get_peer_version(&db, "peer-1").await.unwrap();
         assert_eq!(version, Some(42));
     }
+
+    #[tokio::test]
+    async fn test_catchup_cursor_save_load_clear() {
+        let db = setup_db().await;
+        let cursor = SyncCursor {
+            db_version: 12,
+            seq: 3,
+            table: "tasks".to_string(),
+            pk: "pk-1".to_string(),
+            cid: "title".to_string(),
+        };
+        save_catchup_cursor(&db, "peer-1", 4, &cursor)
+            .await
+            .unwrap();
+
+        let mut advanced = cursor.clone();
+        advanced.db_version = 20;
+        save_catchup_cursor(&db, "peer-1", 4, &advanced)
+            .await
+            .unwrap();
+
+        let cursors = get_all_catchup_cursors(&db).await.unwrap();
+        assert_eq!(cursors.len(), 1);
+        assert_eq!(cursors["peer-1"], (4, advanced));
+
+        clear_catchup_cursor(&db, "peer-1").await.unwrap();
+        assert!(get_all_catchup_cursors(&db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_origin_versions_persist_and_never_regress() {
+        let db = setup_db().await;
+        let a = NodeId([1u8; 16]);
+        let b = NodeId([2u8; 16]);
+        save_origin_versions(&db, &OriginVersions::default().with(a, 10).with(b, 3))
+            .await
+            .unwrap();
+        save_origin_versions(&db, &OriginVersions::default().with(a, 6))
+            .await
+            .unwrap();
+
+        let loaded = get_origin_versions(&db).await.unwrap();
+        assert_eq!(loaded.get(&a), 10);
+        assert_eq!(loaded.get(&b), 3);
+    }
+
+    #[tokio::test]
+    async fn test_site_keys_roundtrip() {
+        let db = setup_db().await;
+        let a = NodeId([1u8; 16]);
+        let b = NodeId([2u8; 16]);
+        pin_site_key(&db, &a, &[1u8; 32]).await.unwrap();
+        pin_site_key(&db, &b, &[2u8; 32]).await.unwrap();
+        pin_site_key(&db, &b, &[3u8; 32]).await.unwrap();
+        let keys = get_site_keys(&db).await.unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[&a], [1u8; 32]);
+        assert_eq!(keys[&b], [3u8; 32]);
+    }
+
+    #[tokio::test]
+    async fn test_revocations_roundtrip() {
+        let db = setup_db().await;
+        let keypair = libp2p::identity::ed25519::Keypair::generate();
+        let site = crate::signing::site_id_for_key(&keypair.public().to_bytes());
+        let signer = crate::signing::ChangeSigner::new(keypair, site);
+        let r = Revocation::issue(&signer, [9u8; 32], 42);
+        record_revocation(&db, &r).await.unwrap();
+        record_revocation(&db, &r).await.unwrap();
+        let loaded = get_revocations(&db).await.unwrap();
+        assert_eq!(loaded, vec![r]);
+        assert!(loaded[0].verify());
+    }
+
+    #[tokio::test]
+    async fn test_capability_grants_and_denials_roundtrip() {
+        use crate::capability::Capabilities;
+
+        let db = setup_db().await;
+        let keypair = libp2p::identity::ed25519::Keypair::generate();
+        let site = crate::signing::site_id_for_key(&keypair.public().to_bytes());
+        let signer = crate::signing::ChangeSigner::new(keypair, site);
+        let first = CapabilityGrant::issue(&signer, [1u8; 32], Capabilities::read_only("kiosk"), 1);
+        let second = CapabilityGrant::issue(&signer, [1u8; 32], Capabilities::full("kiosk"), 2);
+        save_capability_grant(&db, &first).await.unwrap();
+        save_capability_grant(&db, &second).await.unwrap();
+        assert_eq!(get_capability_grants(&db).await.unwrap(), vec![second]);
+
+        let change = ColumnChange {
+            table: "tasks".into(),
+            pk: "pk-1".into(),
+            cid: "title".into(),
+            val: Some(serde_json::json!("a")),
+            site_id: NodeId([2u8; 16]),
+            col_version: 1,
+            cl: 1,
+            seq: 0,
+            db_version: 0,
+            sig: None,
+        };
+        record_capability_denial(&db, &change, "kiosk", Operation::Insert)
+            .await
+            .unwrap();
+        record_capability_denial(&db, &change, "kiosk", Operation::Update)
+            .await
+            .unwrap();
+        let denials = get_capability_denials(&db, 10).await.unwrap();
+        assert_eq!(denials.len(), 2);
+        assert_eq!(denials[0].operation, Operation::Update);
+        assert_eq!(denials[0].site_id, NodeId([2u8; 16]));
+        assert_eq!(denials[1].table, "tasks");
+        assert_eq!(get_capability_denials(&db, 1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_quarantine_roundtrip() {
+        let db = setup_db().await;
+        let mut change = ColumnChange {
+            table: "tasks".into(),
+            pk: "pk-1".into(),
+            cid: "title".into(),
+            val: Some(serde_json::json!("a")),
+            site_id: NodeId([3u8; 16]),
+            col_version: u64::MAX,
+            cl: u64::MAX,
+            seq: 0,
+            db_version: 0,
+            sig: None,
+        };
+        quarantine_site(&db, &change, u64::MAX, 4).await.unwrap();
+        // The first record is kept.
+        change.cid = "done".into();
+        quarantine_site(&db, &change, u64::MAX - 1, 9)
+            .await
+            .unwrap();
+
+        let held = get_quarantined_sites(&db).await.unwrap();
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].site_id, NodeId([3u8; 16]));
+        assert_eq!(held[0].cid, "title");
+        assert_eq!(held[0].clock, u64::MAX);
+        assert_eq!(held[0].local_max, 4);
+
+        assert!(release_quarantine(&db, &NodeId([3u8; 16])).await.unwrap());
+        assert!(!release_quarantine(&db, &NodeId([3u8; 16])).await.unwrap());
+        assert!(get_quarantined_sites(&db).await.unwrap().is_empty());
+    }
 }
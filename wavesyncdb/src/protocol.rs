@@ -3,10 +3,105 @@
 //! Peers exchange version vectors to determine what changes they need.
 //! A single round trip suffices: "I have db_version X, last I heard you were at Y"
 //! → peer responds with all changes since Y.
+//!
+//! Large histories are paged: a requester that sets `page_size` gets at most
+//! that many changes back plus a [`SyncCursor`] to continue from, and keeps
+//! asking until a response arrives without one.
+//!
+//! A peer that has never synced with a responder skips the history
+//! entirely: it asks for a [`SyncRequest::StateSnapshot`] — the current
+//! rows with their winning clocks — installs it, and then continues with
+//! version vector sync from the snapshot's `db_version`.
+//!
+//! Each side also keeps [`OriginVersions`]: for every site whose writes it
+//! holds, the `db_version` of that site up to which it holds all of them.
+//! The requester sends its watermarks with each version vector request and
+//! the responder ships only the changes past them, whichever peer they
+//! arrived through; once the catch-up is applied the requester adopts the
+//! responder's watermarks. Clocks written before a node tracked origins
+//! (`origin_version = 0`) still go by the per-peer `your_last_db_version`.
+//!
+//! Before any of that, both sides of a new connection exchange a
+//! [`PeerHello`] describing what they speak. Peers that can't sync with us
+//! (protocol version out of range, diverging table schemas) are reported
+//! with a `PeerIncompatible` network event rather than left to fail on
+//! undecodable frames.
+
+use std::collections::BTreeMap;
+use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
+use crate::capability::CapabilityGrant;
 use crate::messages::{ColumnChange, NodeId, SyncChangeset};
+use crate::registry::TableRegistry;
+use crate::revocation::Revocation;
+use crate::rotation::{KeyGrant, SignedRotation};
+use crate::seal::{self, PayloadKey, SealError, SealedChanges};
+
+/// Version of the sync wire protocol spoken by this build. Bumped when a
+/// change can't be bridged with optional fields.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Oldest protocol version this build can still sync with.
+pub const MIN_PROTOCOL_VERSION: u32 = 3;
+
+/// Feature flag: serves paged version vector catch-up (`page_size`/`cursor`).
+pub const FEATURE_PAGED_CATCHUP: &str = "paged-catchup";
+
+/// Feature flag: serves [`SyncRequest::StateSnapshot`].
+pub const FEATURE_STATE_SNAPSHOT: &str = "state-snapshot";
+
+/// Feature flag: answers [`SyncRequest::AntiEntropy`] range-hash rounds.
+pub const FEATURE_ANTI_ENTROPY: &str = "anti-entropy";
+
+/// Feature flag: accepts and forwards gossiped pushes (`Push::hops`).
+pub const FEATURE_GOSSIP: &str = "gossip";
+
+/// Feature flag: stamps changes with their origin's `db_version` and
+/// honours [`OriginVersions`] in version vector requests.
+pub const FEATURE_ORIGIN_VERSIONS: &str = "origin-versions";
+
+/// Feature flag: understands `Push::prev_db_version` and acknowledges a
+/// push only once it has been applied.
+pub const FEATURE_PUSH_SEQUENCE: &str = "push-sequence";
+
+/// Feature flag: opens [`SealedChanges`] envelopes, so changes may be sent
+/// to it encrypted.
+pub const FEATURE_SEALED_PAYLOADS: &str = "sealed-payloads";
+
+/// Feature flag: verifies [`ChangeSignature`](crate::signing::ChangeSignature)s,
+/// so changes may be sent to it signed.
+pub const FEATURE_SIGNED_CHANGES: &str = "signed-changes";
+
+/// Feature flag: accepts [`SyncRequest::KeyRotation`] and moves to the
+/// rotated group key.
+pub const FEATURE_KEY_ROTATION: &str = "key-rotation";
+
+/// Feature flag: exchanges [`SyncRequest::Revocations`] and refuses
+/// revoked devices.
+pub const FEATURE_REVOCATIONS: &str = "revocations";
+
+/// Feature flag: exchanges [`SyncRequest::Capabilities`] and refuses
+/// remote changes their author's grant doesn't allow.
+pub const FEATURE_CAPABILITIES: &str = "capabilities";
+
+/// Feature flag: derives v2 group keys (Argon2id with a [`GroupSalt`]), so
+/// it can be sent [`SyncRequest::KeyRotation`]s to one.
+///
+/// [`GroupSalt`]: crate::auth::GroupSalt
+pub const FEATURE_ARGON2ID_KDF: &str = "kdf-argon2id";
+
+/// Feature flag: signs [`SyncRequest::IdentityAnnounce`]s with the device
+/// key and checks the signatures of others (see [`crate::identity`]).
+pub const FEATURE_SIGNED_IDENTITIES: &str = "signed-identities";
+
+/// Feature flag: MACs messages it sends outside a session with the
+/// receiving peer's [`GroupKey::receiver_key`], and accepts such messages.
+/// Peers without it use the plain group key.
+///
+/// [`GroupKey::receiver_key`]: crate::auth::GroupKey::receiver_key
+pub const FEATURE_RECEIVER_MACS: &str = "receiver-macs";
 
 /// A sync request sent by a peer.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +117,23 @@ pub enum SyncRequest {
         /// The sync topic name — requests with a mismatched topic are rejected.
         #[serde(default)]
         topic: String,
+        /// Maximum number of changes the requester wants per response. `None`
+        /// asks for the legacy single response carrying every change.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        page_size: Option<u32>,
+        /// Continuation token from the previous page's `next_cursor`. `None`
+        /// starts from `your_last_db_version`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cursor: Option<SyncCursor>,
+        /// The requester's per-origin watermarks. When set, changes with a
+        /// known origin are filtered by these instead of
+        /// `your_last_db_version`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        origin_versions: Option<OriginVersions>,
+        /// Message counter on the sender's session with us, present once
+        /// both hellos carried a session nonce (see [`PeerHello::session_nonce`]).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
         /// HMAC tag for group authentication (present when a passphrase is configured).
         #[serde(default)]
         hmac: Option<[u8; 32]>,
@@ -32,6 +144,65 @@ pub enum SyncRequest {
         changeset: SyncChangeset,
         /// The sync topic name — requests with a mismatched topic are rejected.
         topic: String,
+        /// Times this changeset has been forwarded since leaving its origin.
+        /// `None` on the origin's own fan-out.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hops: Option<u8>,
+        /// `db_version` of the sender's previous push of its own writes, so
+        /// the receiver can tell it missed one. `None` on the first push of
+        /// a session and on forwarded pushes.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        prev_db_version: Option<u64>,
+        /// The changeset's changes, encrypted, when the sender sealed them (see
+        /// [`crate::seal`]); the plaintext list is then empty.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sealed: Option<SealedChanges>,
+        /// Message counter on the sender's session with us, present once
+        /// both hellos carried a session nonce (see [`PeerHello::session_nonce`]).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        /// HMAC tag for group authentication (present when a passphrase is configured).
+        #[serde(default)]
+        hmac: Option<[u8; 32]>,
+    },
+    /// Bootstrap request: "send me your current state, not your history."
+    StateSnapshot {
+        /// The requesting peer's site_id.
+        site_id: NodeId,
+        /// The sync topic name — requests with a mismatched topic are rejected.
+        topic: String,
+        /// Continuation token from the previous page's `next`. `None` asks
+        /// for the first page.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        after: Option<SnapshotCursor>,
+        /// Message counter on the sender's session with us, present once
+        /// both hellos carried a session nonce (see [`PeerHello::session_nonce`]).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        /// HMAC tag for group authentication (present when a passphrase is configured).
+        #[serde(default)]
+        hmac: Option<[u8; 32]>,
+    },
+    /// One step of an anti-entropy round (see `crate::merkle`).
+    AntiEntropy {
+        /// The requester's side of the comparison.
+        query: AntiEntropyQuery,
+        /// The sync topic name — requests with a mismatched topic are rejected.
+        topic: String,
+        /// Message counter on the sender's session with us, present once
+        /// both hellos carried a session nonce (see [`PeerHello::session_nonce`]).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        /// HMAC tag for group authentication (present when a passphrase is configured).
+        #[serde(default)]
+        hmac: Option<[u8; 32]>,
+    },
+    /// Capability handshake, sent once per connection before syncing.
+    Hello {
+        /// What the requester speaks.
+        hello: PeerHello,
+        /// The sync topic name — requests with a mismatched topic are rejected.
+        topic: String,
         /// HMAC tag for group authentication (present when a passphrase is configured).
         #[serde(default)]
         hmac: Option<[u8; 32]>,
@@ -40,12 +211,316 @@ pub enum SyncRequest {
     IdentityAnnounce {
         /// Opaque application-defined identity string.
         app_id: String,
+        /// `app_id` signed by the sender's device key. Only sent to peers
+        /// announcing [`FEATURE_SIGNED_IDENTITIES`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        claim: Option<crate::identity::IdentityClaim>,
+        /// Message counter on the sender's session with us, present once
+        /// both hellos carried a session nonce (see [`PeerHello::session_nonce`]).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        /// HMAC tag for group authentication (present when a passphrase is configured).
+        #[serde(default)]
+        hmac: Option<[u8; 32]>,
+    },
+    /// Announce a rotation of the group key (see [`crate::rotation`]).
+    /// Authenticated with the key being replaced, not a session key, so it
+    /// reaches peers whichever epoch their session was set up under.
+    /// Rotations to a v2 key only go to peers announcing
+    /// [`FEATURE_ARGON2ID_KDF`].
+    KeyRotation {
+        /// The rotation, signed by the member that issued it.
+        rotation: SignedRotation,
+        /// The new passphrase, sealed to the receiving device.
+        grant: KeyGrant,
+        /// HMAC tag under the key being replaced.
+        #[serde(default)]
+        hmac: Option<[u8; 32]>,
+    },
+    /// Share the devices revoked from the group (see [`crate::revocation`]).
+    /// Sent after each handshake and whenever a revocation is learned; the
+    /// response carries the ones the sender didn't.
+    Revocations {
+        revocations: Vec<Revocation>,
+        /// Message counter on the sender's session with us, present once
+        /// both hellos carried a session nonce (see [`PeerHello::session_nonce`]).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        /// HMAC tag for group authentication (present when a passphrase is configured).
+        #[serde(default)]
+        hmac: Option<[u8; 32]>,
+    },
+    /// Share the capability grants of restricted devices (see
+    /// [`crate::capability`]). Sent after each handshake and whenever a
+    /// grant is learned; the response carries the ones the sender lacked
+    /// or held an older one of.
+    Capabilities {
+        grants: Vec<CapabilityGrant>,
+        /// Message counter on the sender's session with us, present once
+        /// both hellos carried a session nonce (see [`PeerHello::session_nonce`]).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
         /// HMAC tag for group authentication (present when a passphrase is configured).
         #[serde(default)]
         hmac: Option<[u8; 32]>,
     },
 }
 
+/// Continuation token for paginated catch-up.
+///
+/// Identifies the last change the responder included in a page. Pages are
+/// ordered by `(db_version, seq)` — the responder's shadow-table order —
+/// with `(table, pk, cid)` as a tiebreak, because a multi-row write shares
+/// one `db_version` and restarts `seq` for each row.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncCursor {
+    pub db_version: u64,
+    pub seq: u32,
+    pub table: String,
+    pub pk: String,
+    pub cid: String,
+}
+
+/// Capabilities a peer announces in the [`SyncRequest::Hello`] handshake.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerHello {
+    /// The sender's [`PROTOCOL_VERSION`].
+    pub protocol_version: u32,
+    /// The sender's [`MIN_PROTOCOL_VERSION`].
+    pub min_protocol_version: u32,
+    /// Payload encodings the sender can decode (`"json"`).
+    pub codecs: Vec<String>,
+    /// Payload compression schemes the sender can decode (`"zstd"`).
+    pub compression: Vec<String>,
+    /// [`TableMeta::schema_hash`](crate::registry::TableMeta::schema_hash)
+    /// of every table the sender syncs, keyed by table name.
+    pub schema_hashes: BTreeMap<String, String>,
+    /// Application version, if the app configured one. Informational only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_version: Option<String>,
+    /// Optional protocol features the sender serves (`FEATURE_*`).
+    pub features: Vec<String>,
+    /// Fresh random nonce for this connection, sent when a passphrase is
+    /// configured. Once both sides have the other's, every authenticated
+    /// message between them is MACed with a key bound to both PeerIds and
+    /// both nonces, and requests carry a `seq` counter, so messages can't
+    /// be replayed on another connection or twice on the same one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_nonce: Option<[u8; 16]>,
+}
+
+impl PeerHello {
+    /// The hello a native engine sends, describing the tables in `registry`.
+    pub fn local(registry: &TableRegistry, app_version: Option<String>) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            codecs: vec!["json".to_string()],
+            compression: vec!["zstd".to_string()],
+            schema_hashes: registry
+                .all_tables()
+                .iter()
+                .map(|meta| (meta.table_name.clone(), meta.schema_hash()))
+                .collect(),
+            app_version,
+            features: vec![
+                FEATURE_PAGED_CATCHUP.to_string(),
+                FEATURE_STATE_SNAPSHOT.to_string(),
+                FEATURE_ANTI_ENTROPY.to_string(),
+                FEATURE_GOSSIP.to_string(),
+                FEATURE_ORIGIN_VERSIONS.to_string(),
+                FEATURE_PUSH_SEQUENCE.to_string(),
+                FEATURE_SEALED_PAYLOADS.to_string(),
+                FEATURE_SIGNED_CHANGES.to_string(),
+                FEATURE_KEY_ROTATION.to_string(),
+                FEATURE_REVOCATIONS.to_string(),
+                FEATURE_CAPABILITIES.to_string(),
+                FEATURE_ARGON2ID_KDF.to_string(),
+                FEATURE_SIGNED_IDENTITIES.to_string(),
+                FEATURE_RECEIVER_MACS.to_string(),
+            ],
+            session_nonce: None,
+        }
+    }
+
+    /// Whether the peer announced `feature`.
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// Check whether we (`self`) can sync with a peer that sent `theirs`.
+    pub fn check_compatible(&self, theirs: &PeerHello) -> Result<(), Incompatibility> {
+        if theirs.protocol_version < self.min_protocol_version
+            || self.protocol_version < theirs.min_protocol_version
+        {
+            return Err(Incompatibility::ProtocolVersion {
+                ours: self.protocol_version,
+                theirs: theirs.protocol_version,
+            });
+        }
+        if !self.codecs.iter().any(|c| theirs.codecs.contains(c)) {
+            return Err(Incompatibility::NoCommonCodec {
+                theirs: theirs.codecs.clone(),
+            });
+        }
+        let tables: Vec<String> = self
+            .schema_hashes
+            .iter()
+            .filter(|(table, hash)| theirs.schema_hashes.get(*table).is_some_and(|h| h != *hash))
+            .map(|(table, _)| table.clone())
+            .collect();
+        if !tables.is_empty() {
+            return Err(Incompatibility::SchemaMismatch { tables });
+        }
+        Ok(())
+    }
+}
+
+/// Why a peer's [`PeerHello`] rules out syncing with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Incompatibility {
+    /// Neither side's protocol version is within the other's supported range.
+    ProtocolVersion { ours: u32, theirs: u32 },
+    /// The peer can't decode any payload encoding we send.
+    NoCommonCodec { theirs: Vec<String> },
+    /// Tables both sides sync but with different primary key / columns.
+    SchemaMismatch { tables: Vec<String> },
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ProtocolVersion { ours, theirs } => write!(
+                f,
+                "protocol version mismatch (ours={ours}, theirs={theirs})"
+            ),
+            Self::NoCommonCodec { theirs } => {
+                write!(f, "no common payload codec (theirs={theirs:?})")
+            }
+            Self::SchemaMismatch { tables } => {
+                write!(f, "schema differs for tables {}", tables.join(", "))
+            }
+        }
+    }
+}
+
+/// The requester's hashes or clocks for one anti-entropy step. The responder
+/// compares them with its own and answers with the matching
+/// [`AntiEntropyReply`], so the requester never has to hold a round's state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AntiEntropyQuery {
+    /// Root hash of every table the requester syncs.
+    Roots { roots: BTreeMap<String, [u8; 32]> },
+    /// The requester's child hashes under one range of a table.
+    Children {
+        table: String,
+        prefix: Vec<u8>,
+        hashes: Vec<[u8; 32]>,
+    },
+    /// The requester's clocks in some leaf ranges of a table.
+    Clocks {
+        table: String,
+        leaves: Vec<(Vec<u8>, Vec<ClockDigest>)>,
+    },
+}
+
+/// The responder's answer to an [`AntiEntropyQuery`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AntiEntropyReply {
+    /// Tables whose root hash differs from the requester's.
+    Roots { differing: Vec<String> },
+    /// Child indexes under `prefix` whose hash differs.
+    Children {
+        table: String,
+        prefix: Vec<u8>,
+        differing: Vec<u8>,
+    },
+    /// Responder clocks the requester lacks or has a different version of,
+    /// with current values.
+    Clocks { changes: Vec<ColumnChange> },
+}
+
+/// A clock as compared during anti-entropy — what the range hashes cover.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockDigest {
+    pub pk: String,
+    pub cid: String,
+    pub col_version: u64,
+    pub site_id: NodeId,
+}
+
+/// Per-origin version vector: for each site, the highest of its
+/// `db_version`s up to which every change it made is held (or superseded).
+///
+/// A watermark only moves when that is known to be true — for our own site
+/// on every write, for others by adopting a responder's watermarks after
+/// applying a complete catch-up from it. Pushes can arrive out of order and
+/// never move it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<(NodeId, u64)>", into = "Vec<(NodeId, u64)>")]
+pub struct OriginVersions(BTreeMap<NodeId, u64>);
+
+impl OriginVersions {
+    /// Watermark for `site` (0 = nothing known).
+    pub fn get(&self, site: &NodeId) -> u64 {
+        self.0.get(site).copied().unwrap_or(0)
+    }
+
+    /// Raise the watermark for `site` to `version`. Returns whether it moved.
+    pub fn advance(&mut self, site: NodeId, version: u64) -> bool {
+        let entry = self.0.entry(site).or_insert(0);
+        if version > *entry {
+            *entry = version;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drop the watermark for `site`.
+    pub fn forget(&mut self, site: &NodeId) {
+        self.0.remove(site);
+    }
+
+    /// These watermarks with `site` raised to `version`.
+    pub fn with(mut self, site: NodeId, version: u64) -> Self {
+        self.advance(site, version);
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&NodeId, &u64)> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<(NodeId, u64)>> for OriginVersions {
+    fn from(entries: Vec<(NodeId, u64)>) -> Self {
+        let mut versions = Self::default();
+        for (site, version) in entries {
+            versions.advance(site, version);
+        }
+        versions
+    }
+}
+
+impl From<OriginVersions> for Vec<(NodeId, u64)> {
+    fn from(versions: OriginVersions) -> Self {
+        versions.0.into_iter().collect()
+    }
+}
+
+/// Continuation token for a paged [`SyncRequest::StateSnapshot`]: the last
+/// row the responder included. Rows are ordered by table name, then pk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotCursor {
+    pub table: String,
+    pub pk: String,
+}
+
 /// The response to a [`SyncRequest`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SyncResponse {
@@ -62,6 +537,65 @@ pub enum SyncResponse {
         /// The sync topic name — responses with a mismatched topic are ignored.
         #[serde(default)]
         topic: String,
+        /// Set when more changes remain: pass it back as the request's
+        /// `cursor` to fetch the next page.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        next_cursor: Option<SyncCursor>,
+        /// The responder's watermarks when this page was read, set when it
+        /// honoured the request's `origin_versions`. Adopted by the
+        /// requester once the last page is applied.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        origin_versions: Option<OriginVersions>,
+        /// The changes, encrypted, when the sender sealed them (see
+        /// [`crate::seal`]); the plaintext list is then empty.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sealed: Option<SealedChanges>,
+        /// HMAC tag for group authentication (present when a passphrase is configured).
+        #[serde(default)]
+        hmac: Option<[u8; 32]>,
+    },
+    /// Reply to a [`SyncRequest::AntiEntropy`] step.
+    AntiEntropy {
+        reply: AntiEntropyReply,
+        /// The changes of a `Clocks` reply, encrypted, when the sender sealed them (see
+        /// [`crate::seal`]); the plaintext list is then empty.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sealed: Option<SealedChanges>,
+        /// HMAC tag for group authentication (present when a passphrase is configured).
+        #[serde(default)]
+        hmac: Option<[u8; 32]>,
+    },
+    /// Reply to a [`SyncRequest::Hello`] with the responder's capabilities.
+    Hello {
+        /// What the responder speaks.
+        hello: PeerHello,
+        /// HMAC tag for group authentication (present when a passphrase is configured).
+        #[serde(default)]
+        hmac: Option<[u8; 32]>,
+    },
+    /// One page of a [`SyncRequest::StateSnapshot`].
+    StateSnapshot {
+        /// The winning clock of every live column, plus the `__deleted`
+        /// tombstone (and nothing else) for deleted rows.
+        changes: Vec<ColumnChange>,
+        /// The responder's db_version when this page was read. The
+        /// requester resumes version vector sync from the first page's value.
+        my_db_version: u64,
+        /// The responder's site_id.
+        site_id: NodeId,
+        /// The sync topic name — responses with a mismatched topic are ignored.
+        topic: String,
+        /// Set when more rows remain: pass it back as the request's `after`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        next: Option<SnapshotCursor>,
+        /// The responder's watermarks when this page was read. The
+        /// requester adopts the first page's once the snapshot is installed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        origin_versions: Option<OriginVersions>,
+        /// The changes, encrypted, when the sender sealed them (see
+        /// [`crate::seal`]); the plaintext list is then empty.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sealed: Option<SealedChanges>,
         /// HMAC tag for group authentication (present when a passphrase is configured).
         #[serde(default)]
         hmac: Option<[u8; 32]>,
@@ -70,6 +604,89 @@ pub enum SyncResponse {
     PushAck,
     /// Acknowledgement for a [`SyncRequest::IdentityAnnounce`].
     IdentityAck,
+    /// Acknowledgement for a [`SyncRequest::KeyRotation`].
+    KeyRotationAck,
+    /// Response to a [`SyncRequest::Revocations`]: the responder's
+    /// revocations the request didn't carry.
+    Revocations {
+        revocations: Vec<Revocation>,
+        /// HMAC tag for group authentication (present when a passphrase is configured).
+        #[serde(default)]
+        hmac: Option<[u8; 32]>,
+    },
+    /// Response to a [`SyncRequest::Capabilities`]: the responder's grants
+    /// that are newer than the request's.
+    Capabilities {
+        grants: Vec<CapabilityGrant>,
+        /// HMAC tag for group authentication (present when a passphrase is configured).
+        #[serde(default)]
+        hmac: Option<[u8; 32]>,
+    },
+}
+
+impl SyncRequest {
+    /// Encrypt the changes this request carries. Call after the HMAC is set.
+    pub fn seal_changes(&mut self, key: &PayloadKey) {
+        if let Self::Push {
+            changeset, sealed, ..
+        } = self
+        {
+            seal::seal_into(key, &mut changeset.changes, sealed);
+        }
+    }
+
+    /// Decrypt sealed changes back into place. Call before checking the HMAC.
+    pub fn open_changes(&mut self, key: Option<&PayloadKey>) -> Result<(), SealError> {
+        match self {
+            Self::Push {
+                changeset, sealed, ..
+            } => seal::open_into(key, &mut changeset.changes, sealed),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl SyncResponse {
+    /// Encrypt the changes this response carries. Call after the HMAC is set.
+    pub fn seal_changes(&mut self, key: &PayloadKey) {
+        match self {
+            Self::ChangesetResponse {
+                changes, sealed, ..
+            }
+            | Self::StateSnapshot {
+                changes, sealed, ..
+            }
+            | Self::AntiEntropy {
+                reply: AntiEntropyReply::Clocks { changes },
+                sealed,
+                ..
+            } => seal::seal_into(key, changes, sealed),
+            _ => {}
+        }
+    }
+
+    /// Decrypt sealed changes back into place. Call before checking the HMAC.
+    pub fn open_changes(&mut self, key: Option<&PayloadKey>) -> Result<(), SealError> {
+        match self {
+            Self::ChangesetResponse {
+                changes, sealed, ..
+            }
+            | Self::StateSnapshot {
+                changes, sealed, ..
+            }
+            | Self::AntiEntropy {
+                reply: AntiEntropyReply::Clocks { changes },
+                sealed,
+                ..
+            } => seal::open_into(key, changes, sealed),
+            Self::AntiEntropy {
+                sealed: Some(_), ..
+            } => Err(SealError::Malformed(
+                "sealed changes on a reply without clocks".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -84,6 +701,10 @@ mod tests {
             your_last_db_version: 5,
             site_id: NodeId([1u8; 16]),
             topic: "my-topic".to_string(),
+            page_size: None,
+            cursor: None,
+            origin_versions: None,
+            seq: None,
             hmac: Some([0xAB; 32]),
         };
         let json = serde_json::to_string(&req).unwrap();
@@ -118,11 +739,15 @@ mod tests {
                 cl: 3,
                 seq: 0,
                 db_version: 0,
+                sig: None,
             }],
             my_db_version: 20,
             your_last_db_version: 10,
             site_id: NodeId([2u8; 16]),
             topic: "test".to_string(),
+            next_cursor: None,
+            origin_versions: None,
+            sealed: None,
             hmac: None,
         };
         let json = serde_json::to_string(&resp).unwrap();
@@ -154,6 +779,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_paging_fields_roundtrip_and_stay_off_the_wire_when_unused() {
+        let cursor = SyncCursor {
+            db_version: 9,
+            seq: 2,
+            table: "tasks".to_string(),
+            pk: "pk-7".to_string(),
+            cid: "title".to_string(),
+        };
+        let req = SyncRequest::VersionVector {
+            my_db_version: 1,
+            your_last_db_version: 0,
+            site_id: NodeId([1u8; 16]),
+            topic: "t".to_string(),
+            page_size: Some(500),
+            cursor: Some(cursor.clone()),
+            origin_versions: None,
+            seq: None,
+            hmac: None,
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        match serde_json::from_str::<SyncRequest>(&json).unwrap() {
+            SyncRequest::VersionVector {
+                page_size,
+                cursor: got,
+                ..
+            } => {
+                assert_eq!(page_size, Some(500));
+                assert_eq!(got, Some(cursor));
+            }
+            _ => panic!("Expected VersionVector"),
+        }
+
+        // Unpaged requests serialize exactly like pre-pagination builds, so
+        // HMACs computed by older peers still verify.
+        let legacy = SyncRequest::VersionVector {
+            my_db_version: 1,
+            your_last_db_version: 0,
+            site_id: NodeId([1u8; 16]),
+            topic: "t".to_string(),
+            page_size: None,
+            cursor: None,
+            origin_versions: None,
+            seq: None,
+            hmac: None,
+        };
+        let json = serde_json::to_string(&legacy).unwrap();
+        assert!(!json.contains("page_size"));
+        assert!(!json.contains("cursor"));
+        assert!(!json.contains("origin_versions"));
+    }
+
     #[test]
     fn test_sync_response_empty_changes() {
         let resp = SyncResponse::ChangesetResponse {
@@ -162,6 +839,9 @@ mod tests {
             your_last_db_version: 0,
             site_id: NodeId([0u8; 16]),
             topic: String::new(),
+            next_cursor: None,
+            origin_versions: None,
+            sealed: None,
             hmac: None,
         };
         let json = serde_json::to_string(&resp).unwrap();
@@ -184,6 +864,10 @@ mod tests {
                 changes: vec![],
             },
             topic: "push-topic".to_string(),
+            hops: Some(2),
+            prev_db_version: Some(5),
+            seq: None,
+            sealed: None,
             hmac: Some([0xCD; 32]),
         };
         let json = serde_json::to_string(&req).unwrap();
@@ -192,16 +876,234 @@ mod tests {
             SyncRequest::Push {
                 changeset,
                 topic,
+                hops,
+                prev_db_version,
+                seq,
+                sealed,
                 hmac,
             } => {
                 assert_eq!(changeset.db_version, 7);
                 assert_eq!(topic, "push-topic");
+                assert_eq!(hops, Some(2));
+                assert_eq!(prev_db_version, Some(5));
+                assert_eq!(seq, None);
+                assert_eq!(sealed, None);
                 assert_eq!(hmac, Some([0xCD; 32]));
             }
             _ => panic!("Expected Push"),
         }
     }
 
+    #[test]
+    fn test_sync_request_key_rotation_roundtrip() {
+        let keypair = libp2p::identity::ed25519::Keypair::generate();
+        let site = crate::signing::site_id_for_key(&keypair.public().to_bytes());
+        let signer = crate::signing::ChangeSigner::new(keypair, site);
+        let new = crate::auth::GroupKey::from_passphrase("new");
+        let rotation = SignedRotation::issue(&signer, 3, &new, None, None);
+        let req = SyncRequest::KeyRotation {
+            rotation: rotation.clone(),
+            grant: KeyGrant::seal(&signer.public_key(), 3, "new").unwrap(),
+            hmac: Some([0x11; 32]),
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(!json.contains("grace_secs"));
+        assert!(!json.contains("salt"));
+        match serde_json::from_str(&json).unwrap() {
+            SyncRequest::KeyRotation {
+                rotation: back,
+                grant,
+                hmac,
+            } => {
+                assert_eq!(back, rotation);
+                assert!(back.verify());
+                assert_eq!(grant.open(&signer, 3).as_deref(), Some("new"));
+                assert_eq!(hmac, Some([0x11; 32]));
+            }
+            _ => panic!("Expected KeyRotation"),
+        }
+    }
+
+    #[test]
+    fn test_sync_request_revocations_roundtrip() {
+        let keypair = libp2p::identity::ed25519::Keypair::generate();
+        let site = crate::signing::site_id_for_key(&keypair.public().to_bytes());
+        let signer = crate::signing::ChangeSigner::new(keypair, site);
+        let revocation = Revocation::issue(&signer, [5u8; 32], 9);
+        let req = SyncRequest::Revocations {
+            revocations: vec![revocation.clone()],
+            seq: Some(4),
+            hmac: Some([0x22; 32]),
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        match serde_json::from_str(&json).unwrap() {
+            SyncRequest::Revocations {
+                revocations,
+                seq,
+                hmac,
+            } => {
+                assert_eq!(revocations, vec![revocation]);
+                assert!(revocations[0].verify());
+                assert_eq!(seq, Some(4));
+                assert_eq!(hmac, Some([0x22; 32]));
+            }
+            _ => panic!("Expected Revocations"),
+        }
+    }
+
+    #[test]
+    fn test_sync_response_capabilities_roundtrip() {
+        use crate::capability::{Capabilities, Operation};
+
+        let keypair = libp2p::identity::ed25519::Keypair::generate();
+        let site = crate::signing::site_id_for_key(&keypair.public().to_bytes());
+        let signer = crate::signing::ChangeSigner::new(keypair, site);
+        let grant = CapabilityGrant::issue(
+            &signer,
+            [6u8; 32],
+            Capabilities::read_only("guest").allow("comments", &[Operation::Insert]),
+            12,
+        );
+        let resp = SyncResponse::Capabilities {
+            grants: vec![grant.clone()],
+            hmac: Some([0x33; 32]),
+        };
+        let json = serde_json::to_string(&resp).unwrap();
+        match serde_json::from_str(&json).unwrap() {
+            SyncResponse::Capabilities { grants, hmac } => {
+                assert_eq!(grants, vec![grant]);
+                assert!(grants[0].verify());
+                assert_eq!(hmac, Some([0x33; 32]));
+            }
+            _ => panic!("Expected Capabilities"),
+        }
+    }
+
+    #[test]
+    fn test_origin_push_omits_hops() {
+        // Older peers MAC the request they re-serialize; an origin push must
+        // stay byte-identical to what they produce.
+        let req = SyncRequest::Push {
+            changeset: crate::messages::SyncChangeset {
+                site_id: NodeId([5u8; 16]),
+                db_version: 7,
+                changes: vec![],
+            },
+            topic: "push-topic".to_string(),
+            hops: None,
+            prev_db_version: None,
+            seq: None,
+            sealed: None,
+            hmac: None,
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(!json.contains("hops"));
+        assert!(!json.contains("prev_db_version"));
+        assert!(!json.contains("seq"));
+    }
+
+    #[test]
+    fn test_sealed_push_keeps_hmac_over_plaintext() {
+        let gk = crate::auth::GroupKey::from_passphrase("secret");
+        let change = ColumnChange {
+            table: "tasks".into(),
+            pk: "pk-1".into(),
+            cid: "title".into(),
+            val: Some(serde_json::json!("private")),
+            site_id: NodeId([5u8; 16]),
+            col_version: 1,
+            cl: 1,
+            seq: 0,
+            db_version: 7,
+            sig: None,
+        };
+        let plain = SyncRequest::Push {
+            changeset: crate::messages::SyncChangeset {
+                site_id: NodeId([5u8; 16]),
+                db_version: 7,
+                changes: vec![change.clone()],
+            },
+            topic: "push-topic".to_string(),
+            hops: None,
+            prev_db_version: None,
+            seq: None,
+            sealed: None,
+            hmac: None,
+        };
+        let plain_bytes = serde_json::to_vec(&plain).unwrap();
+
+        let mut req = plain.clone();
+        req.seal_changes(&gk.payload_key());
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(!json.contains("private"));
+
+        let mut received: SyncRequest = serde_json::from_str(&json).unwrap();
+        received.open_changes(Some(&gk.payload_key())).unwrap();
+        assert_eq!(serde_json::to_vec(&received).unwrap(), plain_bytes);
+
+        let mut no_key: SyncRequest = serde_json::from_str(&json).unwrap();
+        assert!(no_key.open_changes(None).is_err());
+    }
+
+    #[test]
+    fn test_sealed_anti_entropy_clocks_roundtrip() {
+        let key = crate::auth::GroupKey::from_passphrase("secret").payload_key();
+        let changes = vec![ColumnChange {
+            table: "tasks".into(),
+            pk: "pk-1".into(),
+            cid: "title".into(),
+            val: Some(serde_json::json!("private")),
+            site_id: NodeId([5u8; 16]),
+            col_version: 1,
+            cl: 1,
+            seq: 0,
+            db_version: 0,
+            sig: None,
+        }];
+        let mut resp = SyncResponse::AntiEntropy {
+            reply: AntiEntropyReply::Clocks {
+                changes: changes.clone(),
+            },
+            sealed: None,
+            hmac: None,
+        };
+        resp.seal_changes(&key);
+        assert!(matches!(
+            &resp,
+            SyncResponse::AntiEntropy {
+                reply: AntiEntropyReply::Clocks { changes },
+                sealed: Some(_),
+                ..
+            } if changes.is_empty()
+        ));
+        resp.open_changes(Some(&key)).unwrap();
+        assert!(matches!(
+            resp,
+            SyncResponse::AntiEntropy {
+                reply: AntiEntropyReply::Clocks { changes: opened },
+                sealed: None,
+                ..
+            } if opened == changes
+        ));
+    }
+
+    #[test]
+    fn test_origin_versions_only_advance_and_roundtrip() {
+        let a = NodeId([1u8; 16]);
+        let b = NodeId([2u8; 16]);
+        let mut versions = OriginVersions::default().with(a, 5);
+        assert!(!versions.advance(a, 3), "a watermark never moves back");
+        assert!(versions.advance(b, 7));
+        assert_eq!(versions.get(&a), 5);
+        assert_eq!(versions.get(&NodeId([9u8; 16])), 0);
+
+        // NodeId keys can't be JSON object keys, so the map goes over the
+        // wire as a list of pairs.
+        let json = serde_json::to_string(&versions).unwrap();
+        let back: OriginVersions = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, versions);
+    }
+
     #[test]
     fn test_sync_response_push_ack_roundtrip() {
         let resp = SyncResponse::PushAck;
@@ -209,4 +1111,122 @@ mod tests {
         let deserialized: SyncResponse = serde_json::from_str(&json).unwrap();
         assert!(matches!(deserialized, SyncResponse::PushAck));
     }
+
+    #[test]
+    fn test_state_snapshot_roundtrip() {
+        let req = SyncRequest::StateSnapshot {
+            site_id: NodeId([3u8; 16]),
+            topic: "t".to_string(),
+            after: Some(SnapshotCursor {
+                table: "tasks".to_string(),
+                pk: "pk-9".to_string(),
+            }),
+            seq: None,
+            hmac: None,
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        match serde_json::from_str::<SyncRequest>(&json).unwrap() {
+            SyncRequest::StateSnapshot { after, .. } => {
+                assert_eq!(after.unwrap().pk, "pk-9");
+            }
+            _ => panic!("Expected StateSnapshot"),
+        }
+
+        let resp = SyncResponse::StateSnapshot {
+            changes: Vec::new(),
+            my_db_version: 42,
+            site_id: NodeId([3u8; 16]),
+            topic: "t".to_string(),
+            next: None,
+            origin_versions: None,
+            sealed: None,
+            hmac: None,
+        };
+        let json = serde_json::to_string(&resp).unwrap();
+        assert!(
+            !json.contains("next"),
+            "an absent cursor stays off the wire"
+        );
+        match serde_json::from_str::<SyncResponse>(&json).unwrap() {
+            SyncResponse::StateSnapshot {
+                my_db_version,
+                next,
+                ..
+            } => {
+                assert_eq!(my_db_version, 42);
+                assert!(next.is_none());
+            }
+            _ => panic!("Expected StateSnapshot"),
+        }
+    }
+
+    fn hello() -> PeerHello {
+        let registry = TableRegistry::new();
+        registry.register(crate::registry::TableMeta {
+            table_name: "tasks".to_string(),
+            primary_key_column: "id".to_string(),
+            columns: vec!["id".to_string(), "title".to_string()],
+            delete_policy: crate::messages::DeletePolicy::default(),
+            column_types: Default::default(),
+        });
+        PeerHello::local(&registry, Some("1.2.0".to_string()))
+    }
+
+    #[test]
+    fn test_hello_roundtrip_and_self_compatible() {
+        let ours = hello();
+        let req = SyncRequest::Hello {
+            hello: ours.clone(),
+            topic: "t".to_string(),
+            hmac: None,
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        match serde_json::from_str::<SyncRequest>(&json).unwrap() {
+            SyncRequest::Hello { hello, .. } => assert_eq!(hello, ours),
+            _ => panic!("Expected Hello"),
+        }
+        assert!(ours.supports(FEATURE_STATE_SNAPSHOT));
+        assert_eq!(ours.check_compatible(&ours), Ok(()));
+    }
+
+    #[test]
+    fn test_hello_incompatibilities() {
+        let ours = hello();
+
+        let mut old = ours.clone();
+        old.protocol_version = MIN_PROTOCOL_VERSION - 1;
+        old.min_protocol_version = MIN_PROTOCOL_VERSION - 1;
+        assert!(matches!(
+            ours.check_compatible(&old),
+            Err(Incompatibility::ProtocolVersion { .. })
+        ));
+
+        let mut future = ours.clone();
+        future.protocol_version = PROTOCOL_VERSION + 2;
+        future.min_protocol_version = PROTOCOL_VERSION + 1;
+        assert!(ours.check_compatible(&future).is_err());
+
+        let mut cbor_only = ours.clone();
+        cbor_only.codecs = vec!["cbor".to_string()];
+        assert!(matches!(
+            ours.check_compatible(&cbor_only),
+            Err(Incompatibility::NoCommonCodec { .. })
+        ));
+
+        let mut drifted = ours.clone();
+        drifted
+            .schema_hashes
+            .insert("tasks".to_string(), "other".to_string());
+        drifted
+            .schema_hashes
+            .insert("notes".to_string(), "only-theirs".to_string());
+        let err = ours.check_compatible(&drifted).unwrap_err();
+        assert_eq!(
+            err,
+            Incompatibility::SchemaMismatch {
+                tables: vec!["tasks".to_string()]
+            }
+        );
+        assert_eq!(err.to_string(), "schema differs for tables tasks");
+    }
 }
@@ -24,6 +24,151 @@ pub struct TableMeta {
     pub columns: Vec<String>,
     /// How to resolve delete vs. non-delete conflicts for this table.
     pub delete_policy: DeletePolicy,
+    /// Declared type of each column, used to check remote values before
+    /// they are written. Columns missing here take any value.
+    pub column_types: HashMap<String, ColumnType>,
+}
+
+impl TableMeta {
+    /// Stable fingerprint of the synced shape of this table — primary key
+    /// and column set, independent of column order. Exchanged in the
+    /// capability handshake so peers with diverging schemas for the same
+    /// table refuse to sync instead of failing to apply each other's rows.
+    pub fn schema_hash(&self) -> String {
+        let mut columns: Vec<&str> = self.columns.iter().map(String::as_str).collect();
+        columns.sort_unstable();
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(self.primary_key_column.as_bytes());
+        for col in columns {
+            hasher.update(b"\0");
+            hasher.update(col.as_bytes());
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+
+    /// Check a remote `value` for `column` against its declared type, and
+    /// return it in the form it is stored in. Columns without a declared
+    /// type take any value.
+    pub fn coerce_value(
+        &self,
+        column: &str,
+        value: Option<&serde_json::Value>,
+    ) -> Result<Option<serde_json::Value>, String> {
+        match self.column_types.get(column) {
+            Some(ty) => ty.coerce(value),
+            None => Ok(value.cloned()),
+        }
+    }
+}
+
+/// Storage class of a synced column, as far as remote values are checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+    /// Whole numbers. Integral floats, numeric strings and booleans (as
+    /// 0/1) are accepted and stored as integers.
+    Integer,
+    /// Floating-point numbers. Numeric strings are accepted.
+    Real,
+    /// `true`/`false`. The integers 0 and 1 are accepted.
+    Boolean,
+    /// Strings, including dates, times and UUIDs.
+    Text,
+    /// Binary data, carried as a hex string.
+    Blob,
+    /// JSON documents: any value.
+    Json,
+    /// Not checked.
+    Any,
+}
+
+/// Declared type of a synced column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnType {
+    pub kind: ColumnKind,
+    /// Whether the column takes `NULL`.
+    pub nullable: bool,
+}
+
+impl ColumnType {
+    /// Map a SeaORM column definition to the type its values are checked
+    /// against.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_column_def(def: &sea_orm::ColumnDef) -> Self {
+        use sea_orm::ColumnType as Sea;
+        let kind = match def.get_column_type() {
+            Sea::TinyInteger
+            | Sea::SmallInteger
+            | Sea::Integer
+            | Sea::BigInteger
+            | Sea::TinyUnsigned
+            | Sea::SmallUnsigned
+            | Sea::Unsigned
+            | Sea::BigUnsigned => ColumnKind::Integer,
+            Sea::Float | Sea::Double => ColumnKind::Real,
+            Sea::Boolean => ColumnKind::Boolean,
+            Sea::Char(_)
+            | Sea::String(_)
+            | Sea::Text
+            | Sea::Date
+            | Sea::Time
+            | Sea::DateTime
+            | Sea::Timestamp
+            | Sea::TimestampWithTimeZone
+            | Sea::Uuid
+            | Sea::Enum { .. } => ColumnKind::Text,
+            Sea::Blob | Sea::Binary(_) | Sea::VarBinary(_) => ColumnKind::Blob,
+            Sea::Json | Sea::JsonBinary => ColumnKind::Json,
+            _ => ColumnKind::Any,
+        };
+        Self {
+            kind,
+            nullable: def.is_null(),
+        }
+    }
+
+    /// Check `value` against this type and return it in the form it is
+    /// stored in, or why it doesn't fit.
+    pub fn coerce(
+        &self,
+        value: Option<&serde_json::Value>,
+    ) -> Result<Option<serde_json::Value>, String> {
+        use serde_json::Value;
+        let value = match value {
+            None | Some(Value::Null) if self.nullable => return Ok(value.cloned()),
+            None | Some(Value::Null) => return Err("NULL in a NOT NULL column".to_string()),
+            Some(v) => v,
+        };
+        let coerced = match (self.kind, value) {
+            (ColumnKind::Any | ColumnKind::Json, v) => Some(v.clone()),
+            (ColumnKind::Integer, Value::Number(n)) if n.is_i64() => Some(value.clone()),
+            (ColumnKind::Integer, Value::Number(n)) => n
+                .as_f64()
+                .filter(|f| f.fract() == 0.0 && f.abs() < i64::MAX as f64)
+                .map(|f| Value::from(f as i64)),
+            (ColumnKind::Integer, Value::Bool(b)) => Some(Value::from(*b as i64)),
+            (ColumnKind::Integer, Value::String(s)) => {
+                s.trim().parse::<i64>().ok().map(Value::from)
+            }
+            (ColumnKind::Real, Value::Number(_)) => Some(value.clone()),
+            (ColumnKind::Real, Value::String(s)) => s
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number),
+            (ColumnKind::Boolean, Value::Bool(_)) => Some(value.clone()),
+            (ColumnKind::Boolean, Value::Number(n)) => match n.as_i64() {
+                Some(0) => Some(Value::Bool(false)),
+                Some(1) => Some(Value::Bool(true)),
+                _ => None,
+            },
+            (ColumnKind::Text | ColumnKind::Blob, Value::String(_)) => Some(value.clone()),
+            _ => None,
+        };
+        coerced
+            .map(Some)
+            .ok_or_else(|| format!("{value} is not a valid {:?} value", self.kind))
+    }
 }
 
 /// Metadata submitted by `#[derive(SyncEntity)]` at link time.
@@ -97,9 +242,51 @@ mod tests {
             primary_key_column: pk.to_string(),
             columns: cols.iter().map(|c| c.to_string()).collect(),
             delete_policy: DeletePolicy::default(),
+            column_types: HashMap::new(),
         }
     }
 
+    #[test]
+    fn test_schema_hash_ignores_column_order_but_not_columns() {
+        let a = make_meta("tasks", "id", &["id", "title", "done"]);
+        let b = make_meta("tasks", "id", &["done", "id", "title"]);
+        let c = make_meta("tasks", "id", &["id", "title"]);
+        let d = make_meta("tasks", "title", &["id", "title", "done"]);
+        assert_eq!(a.schema_hash(), b.schema_hash());
+        assert_ne!(a.schema_hash(), c.schema_hash());
+        assert_ne!(a.schema_hash(), d.schema_hash());
+    }
+
+    #[test]
+    fn test_coerce_value_checks_declared_types() {
+        use serde_json::json;
+        let mut meta = make_meta("tasks", "id", &["id", "title", "done", "count", "note"]);
+        let ty = |kind, nullable| ColumnType { kind, nullable };
+        meta.column_types = HashMap::from([
+            ("title".to_string(), ty(ColumnKind::Text, false)),
+            ("done".to_string(), ty(ColumnKind::Boolean, false)),
+            ("count".to_string(), ty(ColumnKind::Integer, true)),
+        ]);
+        let coerce = |col: &str, v: serde_json::Value| meta.coerce_value(col, Some(&v));
+
+        assert_eq!(coerce("title", json!("a")), Ok(Some(json!("a"))));
+        assert!(coerce("title", json!(1)).is_err());
+        assert!(meta.coerce_value("title", None).is_err(), "NOT NULL");
+
+        assert_eq!(coerce("done", json!(1)), Ok(Some(json!(true))));
+        assert!(coerce("done", json!(2)).is_err());
+        assert!(coerce("done", json!({"x": 1})).is_err());
+
+        assert_eq!(coerce("count", json!(3.0)), Ok(Some(json!(3))));
+        assert_eq!(coerce("count", json!(" 42 ")), Ok(Some(json!(42))));
+        assert_eq!(coerce("count", json!(null)), Ok(Some(json!(null))));
+        assert!(coerce("count", json!(3.5)).is_err());
+        assert!(coerce("count", json!("many")).is_err());
+
+        // Columns without a declared type take anything.
+        assert_eq!(coerce("note", json!([1, 2])), Ok(Some(json!([1, 2]))));
+    }
+
     #[test]
     fn test_new_creates_empty() {
         let registry = TableRegistry::new();
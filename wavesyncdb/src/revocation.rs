@@ -0,0 +1,292 @@
+//! Revoking devices from a sync group.
+//!
+//! A lost or compromised device still holds the passphrase, so it has to be
+//! shut out twice: by identity, and by key. A [`Revocation`] names the
+//! device's identity key — the ed25519 key behind its libp2p `PeerId`, and
+//! behind the signatures on its changes (see [`crate::signing`]) — and is
+//! signed by the member that issued it. Revocations are synced to every
+//! member and persisted in `_wavesync_revocations`; a member holding one
+//! refuses connections, version vector requests and pushes from the device,
+//! and changes written under its site id. The issuing member then rotates
+//! the group key with no grace window (see [`crate::rotation`]), so the
+//! device can neither authenticate nor decrypt under the new key, nor join
+//! again under a fresh identity.
+//!
+//! Not every key may revoke. A member takes a revocation only if it is
+//! signed by an administrator of the group — a key the app configures with
+//! [`WaveSyncDbBuilder::with_admin_key`](crate::WaveSyncDbBuilder::with_admin_key)
+//! — or by a member it knows: a device it has verified, or whose signed
+//! changes it holds. Anyone else holding the passphrase could otherwise
+//! mint an identity and revoke the group one device at a time. A
+//! revocation from an unknown member is offered again with every exchange,
+//! and taken once its issuer is known.
+//!
+//! The list only grows. A revocation signed by a device already revoked is
+//! refused; two members revoking each other at the same time may both end
+//! up revoked, depending on which revocation each member saw first.
+//!
+//! Builds that enforce revocations announce
+//! [`FEATURE_REVOCATIONS`](crate::protocol::FEATURE_REVOCATIONS).
+
+use std::collections::HashMap;
+
+use libp2p::identity::{self, ed25519};
+use serde::{Deserialize, Serialize};
+
+use crate::messages::NodeId;
+use crate::signing::{ChangeSignature, ChangeSigner, site_id_for_key};
+
+/// Domain separator prefixed to every signed revocation.
+const REVOCATION_DOMAIN: &[u8] = b"wavesyncdb-revocation-v1";
+
+/// A device removed from the group, signed by the member that removed it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revocation {
+    /// The revoked device's identity key.
+    pub key: [u8; 32],
+    /// Unix seconds at which it was revoked.
+    pub revoked_at: u64,
+    /// Signature over the above by the revoking member's identity key.
+    pub sig: ChangeSignature,
+}
+
+impl Revocation {
+    /// Revoke the device with identity `key`, signed by `signer`.
+    pub fn issue(signer: &ChangeSigner, key: [u8; 32], revoked_at: u64) -> Self {
+        Self {
+            key,
+            revoked_at,
+            sig: signer.sign(&signing_bytes(&key, revoked_at)),
+        }
+    }
+
+    /// Whether the signature verifies.
+    pub fn verify(&self) -> bool {
+        ed25519::PublicKey::try_from_bytes(&self.sig.key)
+            .is_ok_and(|by| by.verify(&signing_bytes(&self.key, self.revoked_at), &self.sig.sig))
+    }
+}
+
+fn signing_bytes(key: &[u8; 32], revoked_at: u64) -> Vec<u8> {
+    let mut out = Vec::with_capacity(REVOCATION_DOMAIN.len() + 40);
+    out.extend_from_slice(REVOCATION_DOMAIN);
+    out.extend_from_slice(key);
+    out.extend_from_slice(&revoked_at.to_be_bytes());
+    out
+}
+
+/// The identity key behind `peer`, if it is an ed25519 one.
+pub fn key_for_peer(peer: &libp2p::PeerId) -> Option<[u8; 32]> {
+    // Ed25519 peer ids inline the encoded public key as an identity
+    // multihash: code 0, then the length, then the key.
+    let bytes = peer.to_bytes();
+    let (&[0, len], encoded) = bytes.split_first_chunk::<2>()? else {
+        return None;
+    };
+    if encoded.len() != len as usize {
+        return None;
+    }
+    let key = identity::PublicKey::try_decode_protobuf(encoded).ok()?;
+    Some(key.try_into_ed25519().ok()?.to_bytes())
+}
+
+/// The `PeerId` of the device with identity `key`.
+pub fn peer_for_key(key: &[u8; 32]) -> Option<libp2p::PeerId> {
+    let key = ed25519::PublicKey::try_from_bytes(key).ok()?;
+    Some(libp2p::PeerId::from_public_key(&identity::PublicKey::from(
+        key,
+    )))
+}
+
+/// The revocations a member holds.
+#[derive(Debug, Clone, Default)]
+pub struct RevocationList {
+    entries: HashMap<[u8; 32], Revocation>,
+}
+
+impl RevocationList {
+    /// A list of revocations loaded from storage, checked when they arrived.
+    pub fn from_entries(entries: impl IntoIterator<Item = Revocation>) -> Self {
+        Self {
+            entries: entries.into_iter().map(|r| (r.key, r)).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Every revocation held.
+    pub fn entries(&self) -> Vec<Revocation> {
+        self.entries.values().cloned().collect()
+    }
+
+    /// Whether the device with identity `key` is revoked.
+    pub fn is_revoked(&self, key: &[u8; 32]) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Whether `peer` is a revoked device.
+    pub fn is_revoked_peer(&self, peer: &libp2p::PeerId) -> bool {
+        !self.is_empty() && key_for_peer(peer).is_some_and(|k| self.is_revoked(&k))
+    }
+
+    /// Whether `site`'s changes come from a revoked device: its id is
+    /// derived from a revoked key, or it is pinned to one in `site_keys`.
+    pub fn is_revoked_site(&self, site: &NodeId, site_keys: &HashMap<NodeId, [u8; 32]>) -> bool {
+        !self.is_empty()
+            && (site_keys.get(site).is_some_and(|k| self.is_revoked(k))
+                || self.entries.keys().any(|k| site_id_for_key(k) == *site))
+    }
+
+    /// Add the revocations in `incoming` that verify and were signed by a
+    /// key `may_revoke` accepts that isn't revoked. Returns the ones that
+    /// are new.
+    pub fn merge(
+        &mut self,
+        incoming: impl IntoIterator<Item = Revocation>,
+        may_revoke: impl Fn(&[u8; 32]) -> bool,
+    ) -> Vec<Revocation> {
+        let mut added = Vec::new();
+        for r in incoming {
+            if self.is_revoked(&r.key)
+                || self.is_revoked(&r.sig.key)
+                || !may_revoke(&r.sig.key)
+                || !r.verify()
+            {
+                continue;
+            }
+            self.entries.insert(r.key, r.clone());
+            added.push(r);
+        }
+        added
+    }
+
+    /// The revocations held that aren't in `theirs`.
+    pub fn missing_from(&self, theirs: &[Revocation]) -> Vec<Revocation> {
+        self.entries
+            .values()
+            .filter(|r| !theirs.iter().any(|t| t.key == r.key))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer() -> ChangeSigner {
+        let keypair = ed25519::Keypair::generate();
+        let site = site_id_for_key(&keypair.public().to_bytes());
+        ChangeSigner::new(keypair, site)
+    }
+
+    #[test]
+    fn test_revocation_verifies_and_roundtrips() {
+        let by = signer();
+        let r = Revocation::issue(&by, [7u8; 32], 100);
+        assert!(r.verify());
+
+        let json = serde_json::to_string(&r).unwrap();
+        let back: Revocation = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, r);
+
+        let mut tampered = r.clone();
+        tampered.key = [8u8; 32];
+        assert!(!tampered.verify());
+    }
+
+    #[test]
+    fn test_key_for_peer_matches_identity() {
+        let keypair = ed25519::Keypair::generate();
+        let public = keypair.public().to_bytes();
+        let peer = libp2p::PeerId::from_public_key(&identity::PublicKey::from(keypair.public()));
+        assert_eq!(key_for_peer(&peer), Some(public));
+        assert_eq!(peer_for_key(&public), Some(peer));
+    }
+
+    #[test]
+    fn test_merge_refuses_forged_and_revoked_signers() {
+        let a = signer();
+        let b = signer();
+        let mut list = RevocationList::default();
+
+        let anyone = |_: &[u8; 32]| true;
+
+        let mut forged = Revocation::issue(&a, [1u8; 32], 1);
+        forged.revoked_at = 2;
+        assert!(list.merge([forged], anyone).is_empty());
+
+        let revoke_b = Revocation::issue(&a, b.public_key(), 1);
+        assert_eq!(
+            list.merge([revoke_b.clone()], anyone),
+            vec![revoke_b.clone()]
+        );
+        // Known already.
+        assert!(list.merge([revoke_b], anyone).is_empty());
+
+        // A revoked device can't revoke anyone else.
+        assert!(
+            list.merge([Revocation::issue(&b, a.public_key(), 2)], anyone)
+                .is_empty()
+        );
+        assert!(!list.is_revoked(&a.public_key()));
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn test_revoked_sites_and_peers() {
+        let a = signer();
+        let b = signer();
+        let mut list = RevocationList::default();
+        list.merge([Revocation::issue(&a, b.public_key(), 1)], |_| true);
+
+        let b_peer = peer_for_key(&b.public_key()).unwrap();
+        assert!(list.is_revoked_peer(&b_peer));
+
+        let mut site_keys = HashMap::new();
+        assert!(list.is_revoked_site(&site_id_for_key(&b.public_key()), &site_keys));
+        assert!(!list.is_revoked_site(&site_id_for_key(&a.public_key()), &site_keys));
+
+        // A legacy site pinned to the revoked key.
+        let legacy = NodeId([3u8; 16]);
+        assert!(!list.is_revoked_site(&legacy, &site_keys));
+        site_keys.insert(legacy, b.public_key());
+        assert!(list.is_revoked_site(&legacy, &site_keys));
+
+        assert_eq!(list.missing_from(&[]).len(), 1);
+        assert!(list.missing_from(&list.entries()).is_empty());
+    }
+
+    #[test]
+    fn test_merge_takes_only_known_members_and_admins() {
+        let (member, admin, stranger, target) = (signer(), signer(), signer(), signer());
+        let known = |key: &[u8; 32]| *key == member.public_key() || *key == admin.public_key();
+        let mut list = RevocationList::default();
+
+        // A fresh identity minted by someone holding the passphrase.
+        let from_stranger = Revocation::issue(&stranger, target.public_key(), 1);
+        assert!(list.merge([from_stranger.clone()], known).is_empty());
+        assert!(!list.is_revoked(&target.public_key()));
+
+        // Taken once its issuer is known.
+        let known_now = |key: &[u8; 32]| known(key) || *key == stranger.public_key();
+        assert_eq!(list.merge([from_stranger], known_now).len(), 1);
+
+        assert_eq!(
+            list.merge([Revocation::issue(&admin, member.public_key(), 2)], known)
+                .len(),
+            1
+        );
+        // A revoked member's key no longer counts.
+        assert!(
+            list.merge([Revocation::issue(&member, admin.public_key(), 3)], known)
+                .is_empty()
+        );
+    }
+}
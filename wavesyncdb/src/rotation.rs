@@ -0,0 +1,620 @@
+//! Rotating the group passphrase without splitting the group.
+//!
+//! The passphrase decides the sync topic, the rendezvous namespace and
+//! every MAC, so a device that simply starts using a new one is on its own.
+//! A rotation is instead announced to the group under the key it replaces.
+//! The announcement is a [`SignedRotation`] — the new epoch and key
+//! fingerprint, signed by the identity key of the member that issued it —
+//! and, for each member it is sent to, a [`KeyGrant`]: the new passphrase
+//! sealed to that member's identity key. Each peer that opens its grant
+//! moves to the new key, topic and namespace, and passes the rotation on
+//! with a grant sealed to the next member. A device that only holds the
+//! old passphrase learns nothing from a grant sealed to someone else, and a
+//! rotation issued by a revoked device (see [`crate::revocation`]) is
+//! refused however it arrives.
+//!
+//! Rotations are numbered. Each one bumps the epoch; two devices rotating
+//! from the same epoch at once are settled by [`supersedes`], so the group
+//! converges on one key. For a grace window after a rotation the previous
+//! key is still accepted, so devices that were offline can still be reached
+//! and brought over.
+//!
+//! The current state is a [`KeyEpoch`] in `_wavesync_meta`. It outlives the
+//! passphrase the app passes to the builder: an app that still configures
+//! a passphrase that has since been rotated away from keeps the rotated one.
+//! Only the state's public half is written there — epoch, salts, grace
+//! deadline, key fingerprints and the signed rotation; the passphrases go to
+//! the app's [`SecretStore`](crate::secret_store::SecretStore).
+//!
+//! A rotation also carries the [`GroupSalt`] of the new key, when it is a
+//! v2 key (see [`crate::auth`]). That is how a v1 group moves to v2: it
+//! rotates to a salted key, which only goes to peers announcing
+//! [`FEATURE_ARGON2ID_KDF`](crate::protocol::FEATURE_ARGON2ID_KDF).
+//!
+//! Builds that accept rotations announce
+//! [`FEATURE_KEY_ROTATION`](crate::protocol::FEATURE_KEY_ROTATION).
+
+use chacha20poly1305::aead::OsRng;
+use chacha20poly1305::aead::rand_core::RngCore;
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use libp2p::identity::ed25519;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::{GroupKey, GroupSalt};
+use crate::seal::{PayloadKey, from_hex, to_hex};
+use crate::signing::{ChangeSignature, ChangeSigner};
+
+/// Associated data for a grant, followed by its epoch and recipient.
+const GRANT_AAD: &[u8] = b"wavesyncdb-key-rotation-v2";
+
+/// Domain separator prefixed to every signed rotation.
+const ROTATION_DOMAIN: &[u8] = b"wavesyncdb-rotation-v1";
+
+/// A rotation's new passphrase, sealed to one member's identity key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyGrant {
+    /// The sender's one-off X25519 public key.
+    pub ephemeral: [u8; 32],
+    /// Random XChaCha20 nonce.
+    pub nonce: [u8; 24],
+    /// Hex-encoded ciphertext with the Poly1305 tag appended.
+    pub ciphertext: String,
+}
+
+impl KeyGrant {
+    /// Seal `passphrase` to the device with identity `recipient`. The grant
+    /// is bound to `epoch`, so it can't be replayed as a later rotation.
+    /// `None` if `recipient` isn't an ed25519 key.
+    pub fn seal(recipient: &[u8; 32], epoch: u64, passphrase: &str) -> Option<Self> {
+        let theirs = x25519_public(recipient)?;
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        let ephemeral = MontgomeryPoint::mul_base_clamped(secret);
+        let key = grant_key(&theirs.mul_clamped(secret), &ephemeral, recipient)?;
+        let (nonce, ciphertext) = key.encrypt(passphrase.as_bytes(), &grant_aad(epoch, recipient));
+        Some(Self {
+            ephemeral: ephemeral.to_bytes(),
+            nonce,
+            ciphertext: to_hex(&ciphertext),
+        })
+    }
+
+    /// The passphrase, if the grant was sealed to `signer`'s identity for
+    /// `epoch`.
+    pub fn open(&self, signer: &ChangeSigner, epoch: u64) -> Option<String> {
+        let recipient = signer.public_key();
+        let ephemeral = MontgomeryPoint(self.ephemeral);
+        let key = grant_key(
+            &ephemeral.mul_clamped(signer.x25519_secret()),
+            &ephemeral,
+            &recipient,
+        )?;
+        let ciphertext = from_hex(&self.ciphertext)?;
+        let plaintext = key.decrypt(&self.nonce, &ciphertext, &grant_aad(epoch, &recipient))?;
+        String::from_utf8(plaintext).ok()
+    }
+}
+
+/// The X25519 form of the ed25519 identity key `key`.
+fn x25519_public(key: &[u8; 32]) -> Option<MontgomeryPoint> {
+    Some(CompressedEdwardsY(*key).decompress()?.to_montgomery())
+}
+
+/// The key a grant is sealed with, from the X25519 `shared` secret. `None`
+/// for the all-zero secret a small-order point gives.
+fn grant_key(
+    shared: &MontgomeryPoint,
+    ephemeral: &MontgomeryPoint,
+    recipient: &[u8; 32],
+) -> Option<PayloadKey> {
+    if shared.as_bytes() == &[0u8; 32] {
+        return None;
+    }
+    let mut hasher = blake3::Hasher::new_derive_key("wavesyncdb-key-grant-v2");
+    hasher.update(shared.as_bytes());
+    hasher.update(ephemeral.as_bytes());
+    hasher.update(recipient);
+    Some(PayloadKey::from_bytes(*hasher.finalize().as_bytes()))
+}
+
+fn grant_aad(epoch: u64, recipient: &[u8; 32]) -> Vec<u8> {
+    let mut aad = GRANT_AAD.to_vec();
+    aad.extend_from_slice(&epoch.to_be_bytes());
+    aad.extend_from_slice(recipient);
+    aad
+}
+
+/// A rotation of the group key, signed by the member that issued it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedRotation {
+    /// The epoch the new key starts.
+    pub epoch: u64,
+    /// Fingerprint of the new key.
+    pub fingerprint: [u8; 32],
+    /// The salt of the new key when it is a v2 key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt: Option<GroupSalt>,
+    /// How long the replaced key stays accepted, when the rotation sets
+    /// it: `Some(0)` after a revocation, whose device holds that key.
+    /// Otherwise each peer uses its own grace window.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grace_secs: Option<u64>,
+    /// Signature over the above by the issuer's identity key.
+    pub sig: ChangeSignature,
+}
+
+impl SignedRotation {
+    /// Rotate to `key` (derived under `salt`) at `epoch`, signed by `signer`.
+    pub fn issue(
+        signer: &ChangeSigner,
+        epoch: u64,
+        key: &GroupKey,
+        salt: Option<GroupSalt>,
+        grace_secs: Option<u64>,
+    ) -> Self {
+        let fingerprint = key.fingerprint();
+        Self {
+            epoch,
+            fingerprint,
+            salt,
+            grace_secs,
+            sig: signer.sign(&rotation_bytes(epoch, &fingerprint, salt, grace_secs)),
+        }
+    }
+
+    /// Whether the signature verifies.
+    pub fn verify(&self) -> bool {
+        ed25519::PublicKey::try_from_bytes(&self.sig.key).is_ok_and(|by| {
+            by.verify(
+                &rotation_bytes(self.epoch, &self.fingerprint, self.salt, self.grace_secs),
+                &self.sig.sig,
+            )
+        })
+    }
+
+    /// Identity key of the member that issued it.
+    pub fn issuer(&self) -> [u8; 32] {
+        self.sig.key
+    }
+
+    /// Whether it rotates to `key`.
+    pub fn is_for(&self, key: &GroupKey) -> bool {
+        key.fingerprint() == self.fingerprint
+    }
+}
+
+fn rotation_bytes(
+    epoch: u64,
+    fingerprint: &[u8; 32],
+    salt: Option<GroupSalt>,
+    grace_secs: Option<u64>,
+) -> Vec<u8> {
+    let mut out = Vec::with_capacity(ROTATION_DOMAIN.len() + 66);
+    out.extend_from_slice(ROTATION_DOMAIN);
+    out.extend_from_slice(&epoch.to_be_bytes());
+    out.extend_from_slice(fingerprint);
+    match salt {
+        Some(salt) => {
+            out.push(1);
+            out.extend_from_slice(&salt.0);
+        }
+        None => out.push(0),
+    }
+    match grace_secs {
+        Some(secs) => {
+            out.push(1);
+            out.extend_from_slice(&secs.to_be_bytes());
+        }
+        None => out.push(0),
+    }
+    out
+}
+
+/// Whether `new` replaces the rotation that brought us to `epoch`
+/// (`current`, or none at epoch 0): a later epoch always does, and of two
+/// rotations to the same epoch the one whose issuer has the higher identity
+/// key wins. The issuer is bound by the signature, while the key a
+/// rotation moves to is whatever its issuer picks: ordering by the key
+/// would let a member retry passphrases until its rotation wins.
+pub fn supersedes(new: &SignedRotation, epoch: u64, current: Option<&SignedRotation>) -> bool {
+    new.epoch > epoch
+        || (new.epoch == epoch && Some(new.issuer()) > current.map(SignedRotation::issuer))
+}
+
+/// The rotation state of the group key.
+///
+/// What is serialized — and persisted in `_wavesync_meta` — identifies keys
+/// by fingerprint only. The passphrases are kept in memory and in the
+/// secret store, under [`PASSPHRASE`](crate::secret_store::PASSPHRASE) and
+/// [`PREVIOUS_PASSPHRASE`](crate::secret_store::PREVIOUS_PASSPHRASE), and
+/// so are the keys derived from them: a v2 key takes an Argon2id run, which
+/// is done once, off the engine's task, rather than on every message.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KeyEpoch {
+    /// Rotations since the passphrase the group started with.
+    pub epoch: u64,
+    /// The salt the key in use is derived with; `None` for a v1 key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt: Option<GroupSalt>,
+    /// Hex fingerprint of the key in use.
+    pub fingerprint: String,
+    /// The salt of the key it replaced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_salt: Option<GroupSalt>,
+    /// Hex fingerprint of the key it replaced, still accepted until
+    /// `grace_until`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_fingerprint: Option<String>,
+    /// Unix seconds at which the previous key stops being accepted.
+    #[serde(default)]
+    pub grace_until: u64,
+    /// Hex fingerprints of every key rotated away from.
+    #[serde(default)]
+    pub superseded: Vec<String>,
+    /// The rotation to `epoch`; `None` at epoch 0. Passed on to peers
+    /// still on the previous key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<SignedRotation>,
+    /// The passphrase in use. Never serialized.
+    #[serde(skip)]
+    pub passphrase: String,
+    /// The passphrase it replaced, while known. Never serialized.
+    #[serde(skip)]
+    pub previous: Option<String>,
+    /// The key derived from `passphrase`.
+    #[serde(skip)]
+    key: Option<GroupKey>,
+    /// The key derived from `previous`.
+    #[serde(skip)]
+    previous_key: Option<GroupKey>,
+}
+
+impl std::fmt::Debug for KeyEpoch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyEpoch")
+            .field("epoch", &self.epoch)
+            .field("fingerprint", &self.fingerprint)
+            .field("previous_fingerprint", &self.previous_fingerprint)
+            .field("grace_until", &self.grace_until)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KeyEpoch {
+    /// The state of a group that has never rotated, on `key` — derived
+    /// from `passphrase` under `salt`.
+    pub fn new(passphrase: &str, salt: Option<GroupSalt>, key: GroupKey) -> Self {
+        Self {
+            epoch: 0,
+            salt,
+            fingerprint: to_hex(&key.fingerprint()),
+            previous_salt: None,
+            previous_fingerprint: None,
+            grace_until: 0,
+            superseded: Vec::new(),
+            rotation: None,
+            passphrase: passphrase.to_string(),
+            previous: None,
+            key: Some(key),
+            previous_key: None,
+        }
+    }
+
+    /// Attach the keys derived from `passphrase` and `previous` to a state
+    /// read back without them.
+    pub fn with_keys(mut self, key: GroupKey, previous_key: Option<GroupKey>) -> Self {
+        self.fingerprint = to_hex(&key.fingerprint());
+        if let Some(ref previous) = previous_key {
+            self.previous_fingerprint = Some(to_hex(&previous.fingerprint()));
+        }
+        self.key = Some(key);
+        self.previous_key = previous_key;
+        self
+    }
+
+    /// Whether `key` is the one in use.
+    pub fn is_key(&self, key: &GroupKey) -> bool {
+        to_hex(&key.fingerprint()) == self.fingerprint
+    }
+
+    /// Whether `key` is the one the last rotation replaced.
+    pub fn is_previous_key(&self, key: &GroupKey) -> bool {
+        self.previous_fingerprint.as_deref() == Some(to_hex(&key.fingerprint()).as_str())
+    }
+
+    /// Whether this state applies to an app configured with the passphrase
+    /// `configured` derives from: it's either the key in use or one rotated
+    /// away from. Any other passphrase is a deliberate change, and starts
+    /// over at epoch 0.
+    pub fn applies_to(&self, configured: &GroupKey) -> bool {
+        self.is_key(configured) || self.superseded.contains(&to_hex(&configured.fingerprint()))
+    }
+
+    /// The key in use, once attached.
+    pub fn key(&self) -> Option<GroupKey> {
+        self.key.clone()
+    }
+
+    /// The previous key, while its grace window lasts.
+    pub fn previous_key(&self) -> Option<GroupKey> {
+        self.previous_key
+            .clone()
+            .filter(|_| now_secs() < self.grace_until)
+    }
+
+    /// The state after `rotation` to `passphrase`, which `key` is derived
+    /// from, keeping the key in use now acceptable for `grace_secs`.
+    ///
+    /// A rotation that beat ours for the same epoch was made from the same
+    /// key we rotated from, so that key stays the previous one.
+    pub fn advance(
+        &self,
+        passphrase: &str,
+        key: GroupKey,
+        rotation: SignedRotation,
+        grace_secs: u64,
+    ) -> Self {
+        let (epoch, salt) = (rotation.epoch, rotation.salt);
+        let mut superseded = self.superseded.clone();
+        superseded.push(self.fingerprint.clone());
+        let mut next = if epoch == self.epoch {
+            Self {
+                superseded,
+                ..self.clone()
+            }
+        } else {
+            Self {
+                epoch,
+                previous_salt: self.salt,
+                previous_fingerprint: Some(self.fingerprint.clone()),
+                grace_until: now_secs().saturating_add(grace_secs),
+                superseded,
+                previous: Some(self.passphrase.clone()),
+                previous_key: self.key.clone(),
+                ..self.clone()
+            }
+        };
+        next.salt = salt;
+        next.fingerprint = to_hex(&key.fingerprint());
+        next.passphrase = passphrase.to_string();
+        next.key = Some(key);
+        next.rotation = Some(rotation);
+        next
+    }
+}
+
+/// A [`KeyEpoch`] as versions before the secret store persisted it, with
+/// the passphrases in it. Only [`crate::shadow`] reads those, so native
+/// builds only.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Deserialize)]
+struct LegacyKeyEpoch {
+    epoch: u64,
+    passphrase: String,
+    #[serde(default)]
+    salt: Option<GroupSalt>,
+    #[serde(default)]
+    previous: Option<String>,
+    #[serde(default)]
+    previous_salt: Option<GroupSalt>,
+    #[serde(default)]
+    grace_until: u64,
+    #[serde(default)]
+    superseded: Vec<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl KeyEpoch {
+    /// Parse a state persisted with its passphrases, which come back
+    /// filled in — but without fingerprints, until
+    /// [`KeyEpoch::with_keys`]. `None` if `json` isn't one.
+    pub(crate) fn from_legacy(json: &[u8]) -> Option<Self> {
+        let legacy: LegacyKeyEpoch = serde_json::from_slice(json).ok()?;
+        Some(Self {
+            epoch: legacy.epoch,
+            salt: legacy.salt,
+            fingerprint: String::new(),
+            previous_salt: legacy.previous_salt,
+            previous_fingerprint: None,
+            grace_until: legacy.grace_until,
+            superseded: legacy.superseded,
+            rotation: None,
+            passphrase: legacy.passphrase,
+            previous: legacy.previous,
+            key: None,
+            previous_key: None,
+        })
+    }
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer() -> ChangeSigner {
+        let keypair = ed25519::Keypair::generate();
+        let site = crate::signing::site_id_for_key(&keypair.public().to_bytes());
+        ChangeSigner::new(keypair, site)
+    }
+
+    fn v1(passphrase: &str) -> GroupKey {
+        GroupKey::from_passphrase(passphrase)
+    }
+
+    fn fingerprint(key: Option<GroupKey>) -> Option<[u8; 32]> {
+        key.map(|k| k.fingerprint())
+    }
+
+    /// `state` rotated to the v1 key of `passphrase` at `epoch` by `by`.
+    fn rotate(state: &KeyEpoch, by: &ChangeSigner, passphrase: &str, epoch: u64) -> KeyEpoch {
+        let rotation = SignedRotation::issue(by, epoch, &v1(passphrase), None, None);
+        state.advance(passphrase, v1(passphrase), rotation, 60)
+    }
+
+    #[test]
+    fn test_grant_opens_only_for_its_recipient_and_epoch() {
+        let member = signer();
+        let grant = KeyGrant::seal(&member.public_key(), 1, "new").unwrap();
+        assert_eq!(grant.open(&member, 1).as_deref(), Some("new"));
+        assert_eq!(grant.open(&member, 2), None);
+        assert_eq!(grant.open(&signer(), 1), None);
+
+        let json = serde_json::to_string(&grant).unwrap();
+        assert!(!json.contains("new"));
+        let back: KeyGrant = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, grant);
+    }
+
+    #[test]
+    fn test_rotation_signature_covers_key_and_grace() {
+        let by = signer();
+        let rotation = SignedRotation::issue(&by, 2, &v1("new"), None, Some(0));
+        assert!(rotation.verify());
+        assert!(rotation.is_for(&v1("new")));
+        assert_eq!(rotation.issuer(), by.public_key());
+
+        let mut tampered = rotation.clone();
+        tampered.grace_secs = Some(3600);
+        assert!(!tampered.verify());
+        let mut tampered = rotation;
+        tampered.fingerprint = v1("other").fingerprint();
+        assert!(!tampered.verify());
+    }
+
+    #[test]
+    fn test_concurrent_rotations_settle_on_one_issuer() {
+        let (a, b) = (signer(), signer());
+        let from_a = SignedRotation::issue(&a, 1, &v1("a"), None, None);
+        let from_b = SignedRotation::issue(&b, 1, &v1("b"), None, None);
+        let later = SignedRotation::issue(&a, 2, &v1("a2"), None, None);
+        assert!(supersedes(&later, 1, Some(&from_b)));
+        assert!(!supersedes(&from_a, 2, Some(&later)));
+        assert!(supersedes(&from_a, 0, None));
+        // Exactly one of two rotations to the same epoch wins, from either side.
+        assert_ne!(
+            supersedes(&from_a, 1, Some(&from_b)),
+            supersedes(&from_b, 1, Some(&from_a))
+        );
+        assert!(!supersedes(&from_a, 1, Some(&from_a)));
+        // Whatever key the loser rotates to.
+        let (winner, loser) = if a.public_key() > b.public_key() {
+            (&from_a, &b)
+        } else {
+            (&from_b, &a)
+        };
+        for passphrase in ["x", "y", "z"] {
+            let retry = SignedRotation::issue(loser, 1, &v1(passphrase), None, None);
+            assert!(!supersedes(&retry, 1, Some(winner)));
+        }
+    }
+
+    #[test]
+    fn test_advance_keeps_previous_key_for_grace() {
+        let by = signer();
+        let start = KeyEpoch::new("one", None, v1("one"));
+        let next = rotate(&start, &by, "two", 1);
+        assert_eq!(next.epoch, 1);
+        assert_eq!(next.previous.as_deref(), Some("one"));
+        assert_eq!(fingerprint(next.previous_key()), fingerprint(start.key()));
+        assert_eq!(
+            next.rotation.as_ref().map(|r| r.issuer()),
+            Some(by.public_key())
+        );
+
+        // Losing a same-epoch race keeps the key both sides rotated from.
+        let raced = rotate(&next, &signer(), "three", 1);
+        assert_eq!(raced.previous.as_deref(), Some("one"));
+        assert_eq!(raced.grace_until, next.grace_until);
+        assert_eq!(fingerprint(raced.previous_key()), fingerprint(start.key()));
+
+        let rotation = SignedRotation::issue(&by, 1, &v1("two"), None, Some(0));
+        let no_grace = start.advance("two", v1("two"), rotation, 0);
+        assert!(no_grace.previous_key().is_none());
+    }
+
+    #[test]
+    fn test_rotated_state_applies_to_old_passphrases_only() {
+        let by = signer();
+        let state = rotate(
+            &rotate(&KeyEpoch::new("one", None, v1("one")), &by, "two", 1),
+            &by,
+            "three",
+            2,
+        );
+        assert!(state.applies_to(&v1("one")));
+        assert!(state.applies_to(&v1("two")));
+        assert!(state.applies_to(&v1("three")));
+        assert!(!state.applies_to(&v1("unrelated")));
+
+        // Persisted, it names its keys by fingerprint only.
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(!json.contains("two") && !json.contains("three"));
+        let back: KeyEpoch = serde_json::from_str(&json).unwrap();
+        assert!(back.passphrase.is_empty() && back.previous.is_none());
+        assert!(back.key().is_none());
+        assert!(back.is_key(&v1("three")));
+        assert!(back.is_previous_key(&v1("two")));
+        assert!(back.applies_to(&v1("one")));
+        assert_eq!(back.rotation, state.rotation);
+    }
+
+    #[test]
+    fn test_legacy_state_keeps_its_passphrases() {
+        let legacy = br#"{"epoch":1,"passphrase":"two","previous":"one","grace_until":9,
+            "superseded":["ab"]}"#;
+        let state = KeyEpoch::from_legacy(legacy).unwrap();
+        assert_eq!(state.passphrase, "two");
+        assert_eq!(state.previous.as_deref(), Some("one"));
+        assert_eq!(state.superseded, vec!["ab".to_string()]);
+        let state = state.with_keys(v1("two"), Some(v1("one")));
+        assert!(state.is_key(&v1("two")));
+        assert!(state.is_previous_key(&v1("one")));
+
+        // Current rows don't carry passphrases.
+        let current = serde_json::to_vec(&state).unwrap();
+        assert!(KeyEpoch::from_legacy(&current).is_none());
+    }
+
+    #[test]
+    fn test_upgrade_to_salted_key_keeps_v1_key_for_grace() {
+        let salt = GroupSalt([3u8; 16]);
+        let salted = GroupKey::from_passphrase_salted("one", &salt);
+        let start = KeyEpoch::new("one", None, v1("one"));
+        let rotation = SignedRotation::issue(&signer(), 1, &salted, Some(salt), None);
+        let upgraded = start.advance("one", salted.clone(), rotation, 60);
+        assert_eq!(fingerprint(upgraded.key()), Some(salted.fingerprint()));
+        assert_eq!(
+            fingerprint(upgraded.previous_key()),
+            fingerprint(start.key())
+        );
+        assert!(upgraded.applies_to(&v1("one")));
+
+        let json = serde_json::to_vec(&upgraded).unwrap();
+        let back: KeyEpoch = serde_json::from_slice(&json).unwrap();
+        assert!(back.is_key(&salted));
+        assert_eq!(back.salt, Some(salt));
+    }
+
+    #[tokio::test]
+    async fn test_derive_blocking_matches_derive() {
+        let salt = GroupSalt([5u8; 16]);
+        assert_eq!(
+            GroupKey::derive_blocking("one", Some(&salt))
+                .await
+                .fingerprint(),
+            GroupKey::from_passphrase_salted("one", &salt).fingerprint()
+        );
+        assert_eq!(
+            GroupKey::derive_blocking("one", None).await.fingerprint(),
+            v1("one").fingerprint()
+        );
+    }
+}
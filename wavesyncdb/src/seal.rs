@@ -0,0 +1,272 @@
+//! Payload encryption for the column changes carried by sync messages.
+//!
+//! The group key MACs every message, but a MAC hides nothing: a relay
+//! holding a store-and-forward push, a push-notification payload hint or an
+//! exported bundle would all carry row values in the clear. When a
+//! passphrase is set, the `ColumnChange`s of a message are therefore moved
+//! into a [`SealedChanges`] envelope — XChaCha20-Poly1305 under a
+//! [`PayloadKey`] derived from the [`GroupKey`](crate::auth::GroupKey) with
+//! its own KDF context, so the MAC key and the encryption key never
+//! coincide.
+//!
+//! ## Order of operations
+//!
+//! The sender MACs the message with its changes in place and seals it
+//! afterwards; the receiver opens it before checking the MAC. The HMAC
+//! paths therefore see exactly the bytes they always did, and a sealed
+//! envelope lifted into another message opens fine but fails that
+//! message's MAC.
+//!
+//! ## Framing
+//!
+//! The plaintext is the serde_json of the change list, framed by
+//! [`compression::encode`](crate::compression) before encryption since
+//! ciphertext no longer compresses on the wire. The ciphertext travels as
+//! a hex string — JSON number arrays would triple it.
+//!
+//! Builds that can open envelopes announce
+//! [`FEATURE_SEALED_PAYLOADS`](crate::protocol::FEATURE_SEALED_PAYLOADS);
+//! peers that don't keep receiving plaintext.
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+
+use crate::compression;
+use crate::messages::ColumnChange;
+
+/// Associated data for every envelope, so a ciphertext made under the
+/// payload key for some other purpose can't be passed off as changes.
+const CHANGES_AAD: &[u8] = b"wavesyncdb-changes-v1";
+
+/// Errors from opening a [`SealedChanges`] envelope.
+#[derive(Debug, thiserror::Error)]
+pub enum SealError {
+    #[error("sealed payload received without a passphrase configured")]
+    NoKey,
+    #[error("sealed payload failed to decrypt")]
+    Decrypt,
+    #[error("message carries both sealed and plaintext changes")]
+    Mixed,
+    #[error("sealed payload is malformed: {0}")]
+    Malformed(String),
+}
+
+/// Symmetric key for [`SealedChanges`], derived with
+/// [`GroupKey::payload_key`](crate::auth::GroupKey::payload_key).
+#[derive(Clone)]
+pub struct PayloadKey(XChaCha20Poly1305);
+
+impl PayloadKey {
+    pub(crate) fn from_bytes(key: [u8; 32]) -> Self {
+        Self(XChaCha20Poly1305::new(&key.into()))
+    }
+
+    /// Encrypt `changes` under a fresh random nonce.
+    pub fn seal(&self, changes: &[ColumnChange]) -> SealedChanges {
+        let json = serde_json::to_vec(changes).expect("ColumnChange serializes to JSON");
+        let (nonce, ciphertext) = self.encrypt(&compression::encode(&json).bytes, CHANGES_AAD);
+        SealedChanges {
+            nonce,
+            ciphertext: to_hex(&ciphertext),
+        }
+    }
+
+    /// Decrypt and decode an envelope produced by [`PayloadKey::seal`].
+    pub fn open(&self, sealed: &SealedChanges) -> Result<Vec<ColumnChange>, SealError> {
+        let ciphertext = from_hex(&sealed.ciphertext)
+            .ok_or_else(|| SealError::Malformed("ciphertext is not hex".to_string()))?;
+        let plaintext = self
+            .decrypt(&sealed.nonce, &ciphertext, CHANGES_AAD)
+            .ok_or(SealError::Decrypt)?;
+        let json =
+            compression::decode(&plaintext).map_err(|e| SealError::Malformed(e.to_string()))?;
+        serde_json::from_slice(&json).map_err(|e| SealError::Malformed(e.to_string()))
+    }
+
+    /// Encrypt `msg` under a fresh random nonce, bound to `aad`. Each use
+    /// of the payload key passes its own `aad`, so ciphertexts can't be
+    /// moved between them.
+    pub(crate) fn encrypt(&self, msg: &[u8], aad: &[u8]) -> ([u8; 24], Vec<u8>) {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, Payload { msg, aad })
+            .expect("XChaCha20-Poly1305 encryption is infallible for in-memory buffers");
+        (nonce.into(), ciphertext)
+    }
+
+    /// Inverse of [`PayloadKey::encrypt`]; `None` if the ciphertext was made
+    /// under another key or `aad`, or was tampered with.
+    pub(crate) fn decrypt(
+        &self,
+        nonce: &[u8; 24],
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> Option<Vec<u8>> {
+        self.0
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .ok()
+    }
+}
+
+impl std::fmt::Debug for PayloadKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PayloadKey").finish_non_exhaustive()
+    }
+}
+
+/// A list of [`ColumnChange`]s encrypted with a [`PayloadKey`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedChanges {
+    /// Random XChaCha20 nonce.
+    pub nonce: [u8; 24],
+    /// Hex-encoded ciphertext with the Poly1305 tag appended.
+    pub ciphertext: String,
+}
+
+/// Move `changes` into a sealed envelope in `sealed`, leaving `changes`
+/// empty. Nothing to hide means nothing to seal.
+pub(crate) fn seal_into(
+    key: &PayloadKey,
+    changes: &mut Vec<ColumnChange>,
+    sealed: &mut Option<SealedChanges>,
+) {
+    if changes.is_empty() {
+        return;
+    }
+    *sealed = Some(key.seal(changes));
+    changes.clear();
+}
+
+/// Undo [`seal_into`]: open `sealed`, if set, back into `changes`.
+pub(crate) fn open_into(
+    key: Option<&PayloadKey>,
+    changes: &mut Vec<ColumnChange>,
+    sealed: &mut Option<SealedChanges>,
+) -> Result<(), SealError> {
+    let Some(envelope) = sealed.take() else {
+        return Ok(());
+    };
+    if !changes.is_empty() {
+        return Err(SealError::Mixed);
+    }
+    *changes = key.ok_or(SealError::NoKey)?.open(&envelope)?;
+    Ok(())
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+            let _ = write!(s, "{b:02x}");
+            s
+        })
+}
+
+pub(crate) fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 == 1 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::GroupKey;
+    use crate::messages::NodeId;
+
+    fn change(val: &str) -> ColumnChange {
+        ColumnChange {
+            table: "tasks".into(),
+            pk: "pk-1".into(),
+            cid: "title".into(),
+            val: Some(serde_json::json!(val)),
+            site_id: NodeId([1u8; 16]),
+            col_version: 1,
+            cl: 1,
+            seq: 0,
+            db_version: 1,
+            sig: None,
+        }
+    }
+
+    #[test]
+    fn test_seal_roundtrip_hides_values() {
+        let key = GroupKey::from_passphrase("secret").payload_key();
+        let changes = vec![change("top secret title")];
+        let sealed = key.seal(&changes);
+        let json = serde_json::to_string(&sealed).unwrap();
+        assert!(!json.contains("top secret"));
+        assert!(!json.contains("tasks"));
+        assert_eq!(key.open(&sealed).unwrap(), changes);
+    }
+
+    #[test]
+    fn test_seal_uses_fresh_nonces() {
+        let key = GroupKey::from_passphrase("secret").payload_key();
+        let changes = vec![change("a")];
+        assert_ne!(key.seal(&changes), key.seal(&changes));
+    }
+
+    #[test]
+    fn test_open_rejects_wrong_key_and_tampering() {
+        let key = GroupKey::from_passphrase("secret").payload_key();
+        let other = GroupKey::from_passphrase("other").payload_key();
+        let mut sealed = key.seal(&[change("a")]);
+        assert!(matches!(other.open(&sealed), Err(SealError::Decrypt)));
+
+        let flipped = if sealed.ciphertext.starts_with('0') {
+            "1"
+        } else {
+            "0"
+        };
+        sealed.ciphertext.replace_range(0..1, flipped);
+        assert!(matches!(key.open(&sealed), Err(SealError::Decrypt)));
+    }
+
+    #[test]
+    fn test_open_into_requires_key_and_no_plaintext() {
+        let key = GroupKey::from_passphrase("secret").payload_key();
+        let mut changes = vec![change("a")];
+        let mut sealed = None;
+        seal_into(&key, &mut changes, &mut sealed);
+        assert!(changes.is_empty());
+        assert!(sealed.is_some());
+
+        let mut no_key = sealed.clone();
+        assert!(matches!(
+            open_into(None, &mut Vec::new(), &mut no_key),
+            Err(SealError::NoKey)
+        ));
+        let mut mixed = sealed.clone();
+        assert!(matches!(
+            open_into(Some(&key), &mut vec![change("b")], &mut mixed),
+            Err(SealError::Mixed)
+        ));
+
+        open_into(Some(&key), &mut changes, &mut sealed).unwrap();
+        assert_eq!(changes, vec![change("a")]);
+        assert!(sealed.is_none());
+    }
+
+    #[test]
+    fn test_hex_roundtrip() {
+        let bytes = [0x00, 0x7f, 0xff, 0x10];
+        assert_eq!(to_hex(&bytes), "007fff10");
+        assert_eq!(from_hex("007fff10").unwrap(), bytes);
+        assert!(from_hex("abc").is_none());
+        assert!(from_hex("zz").is_none());
+    }
+}
@@ -0,0 +1,313 @@
+//! Where [`SyncConfig`](crate::SyncConfig) keeps its secrets.
+//!
+//! The config file next to the database is read by background services and
+//! picked up by desktop backups and folder-sync tools, so the passphrase,
+//! the managed-relay API key and the Firebase credentials are not written
+//! into it. They go through a [`SecretStore`] instead: [`FileSecretStore`]
+//! by default, or any other backend passed to
+//! [`WaveSyncDbBuilder::with_secret_store`](crate::WaveSyncDbBuilder::with_secret_store).
+//!
+//! [`FileSecretStore`] only keeps the secrets out of the config file. Its
+//! key file sits on the same disk as the ciphertext, so anything that can
+//! read both — a backup of the app directory, say — can read the secrets.
+//! Protecting them from that takes a platform keystore (Android Keystore,
+//! the iOS or macOS Keychain, a desktop keyring) behind a [`SecretStore`].
+//!
+//! Configs saved by older versions still carry the secrets in plaintext;
+//! [`SyncConfig::load`](crate::SyncConfig::load) moves them into the store
+//! and rewrites the file without them the first time it reads one. The
+//! Android service reads the Firebase credentials back through
+//! `WaveSyncService.firebaseOptions` to initialise Firebase on a cold start.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chacha20poly1305::XChaCha20Poly1305;
+use chacha20poly1305::aead::{KeyInit, OsRng};
+use serde::{Deserialize, Serialize};
+
+use crate::seal::{PayloadKey, from_hex, to_hex};
+
+/// Name under which the group passphrase is stored.
+pub const PASSPHRASE: &str = "passphrase";
+/// Name under which the passphrase replaced by the last key rotation is
+/// stored, while the group still accepts it (see [`crate::rotation`]).
+pub const PREVIOUS_PASSPHRASE: &str = "previous_passphrase";
+/// Name under which the managed-relay API key is stored.
+pub const API_KEY: &str = "api_key";
+/// Name under which the Firebase project ID is stored.
+pub const FCM_PROJECT_ID: &str = "fcm_project_id";
+/// Name under which the Firebase application ID is stored.
+pub const FCM_APP_ID: &str = "fcm_app_id";
+/// Name under which the Firebase API key is stored.
+pub const FCM_API_KEY: &str = "fcm_api_key";
+
+/// Prefix of the associated data binding each ciphertext to its name, so
+/// one secret's ciphertext can't be moved under another's name.
+const SECRET_AAD: &[u8] = b"wavesyncdb-secret-v1:";
+
+/// Errors from a [`SecretStore`].
+#[derive(Debug, thiserror::Error)]
+pub enum SecretStoreError {
+    #[error("secret store I/O failed: {0}")]
+    Io(String),
+    #[error("secret store is corrupt: {0}")]
+    Corrupt(String),
+    #[error("secret store backend failed: {0}")]
+    Backend(String),
+}
+
+/// Storage for the secrets of one database's sync config.
+///
+/// Names are the constants in this module. An implementation serves a
+/// single database; one backed by a shared OS keyring should fold the
+/// database path into its entry names.
+pub trait SecretStore: Send + Sync {
+    /// The secret stored under `name`, if any.
+    fn get(&self, name: &str) -> Result<Option<String>, SecretStoreError>;
+    /// Store `value` under `name`, replacing any previous value.
+    fn set(&self, name: &str, value: &str) -> Result<(), SecretStoreError>;
+    /// Remove the secret under `name`; removing a missing one is not an error.
+    fn delete(&self, name: &str) -> Result<(), SecretStoreError>;
+}
+
+/// One encrypted entry of a [`FileSecretStore`].
+#[derive(Serialize, Deserialize)]
+struct SealedSecret {
+    nonce: String,
+    ciphertext: String,
+}
+
+/// The default [`SecretStore`]: secrets encrypted with XChaCha20-Poly1305
+/// in a JSON file, under a random 32-byte key kept in a separate file.
+///
+/// This keeps the secrets out of the config file, not away from anyone who
+/// can read the disk: the key file is as readable as the ciphertext, and
+/// [`FileSecretStore::for_database`] puts both next to the database, where
+/// a backup of the directory picks up the pair. Where the secrets must not
+/// leave the device, implement [`SecretStore`] over a platform keystore.
+pub struct FileSecretStore {
+    secrets_path: PathBuf,
+    key_path: PathBuf,
+    /// Serialises read-modify-write cycles on the secrets file within this
+    /// process (the app and its engine both write through one store).
+    lock: Mutex<()>,
+}
+
+impl FileSecretStore {
+    /// A store keeping its secrets in `secrets_path` and its key in
+    /// `key_path`. The key file is created on first write.
+    pub fn new(secrets_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        Self {
+            secrets_path: secrets_path.into(),
+            key_path: key_path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// The store next to the database at `database_url`:
+    /// `.wavesync_secrets.json` and `.wavesync_secret.key`.
+    pub fn for_database(database_url: &str) -> Option<Self> {
+        let dir = crate::SyncConfig::config_path(database_url)?
+            .parent()?
+            .to_path_buf();
+        Some(Self::new(
+            dir.join(".wavesync_secrets.json"),
+            dir.join(".wavesync_secret.key"),
+        ))
+    }
+
+    /// Read the key file, or create it when `create` is set.
+    fn key(&self, create: bool) -> Result<Option<PayloadKey>, SecretStoreError> {
+        match std::fs::read(&self.key_path) {
+            Ok(bytes) => {
+                let bytes: [u8; 32] = bytes.try_into().map_err(|_| {
+                    SecretStoreError::Corrupt(format!(
+                        "key file {} is not 32 bytes",
+                        self.key_path.display()
+                    ))
+                })?;
+                Ok(Some(PayloadKey::from_bytes(bytes)))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && create => {
+                let key: [u8; 32] = XChaCha20Poly1305::generate_key(&mut OsRng).into();
+                write_private(&self.key_path, &key)?;
+                Ok(Some(PayloadKey::from_bytes(key)))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(SecretStoreError::Io(format!(
+                "failed to read {}: {e}",
+                self.key_path.display()
+            ))),
+        }
+    }
+
+    fn entries(&self) -> Result<BTreeMap<String, SealedSecret>, SecretStoreError> {
+        match std::fs::read_to_string(&self.secrets_path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| {
+                SecretStoreError::Corrupt(format!("{}: {e}", self.secrets_path.display()))
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(SecretStoreError::Io(format!(
+                "failed to read {}: {e}",
+                self.secrets_path.display()
+            ))),
+        }
+    }
+
+    fn save_entries(
+        &self,
+        entries: &BTreeMap<String, SealedSecret>,
+    ) -> Result<(), SecretStoreError> {
+        let json = serde_json::to_vec_pretty(entries)
+            .map_err(|e| SecretStoreError::Io(format!("failed to serialize secrets: {e}")))?;
+        write_private(&self.secrets_path, &json)
+    }
+}
+
+impl SecretStore for FileSecretStore {
+    fn get(&self, name: &str) -> Result<Option<String>, SecretStoreError> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let entries = self.entries()?;
+        let Some(sealed) = entries.get(name) else {
+            return Ok(None);
+        };
+        let key = self.key(false)?.ok_or_else(|| {
+            SecretStoreError::Corrupt(format!("key file {} is missing", self.key_path.display()))
+        })?;
+        let nonce: [u8; 24] = from_hex(&sealed.nonce)
+            .and_then(|n| n.try_into().ok())
+            .ok_or_else(|| SecretStoreError::Corrupt(format!("bad nonce for '{name}'")))?;
+        let ciphertext = from_hex(&sealed.ciphertext)
+            .ok_or_else(|| SecretStoreError::Corrupt(format!("bad ciphertext for '{name}'")))?;
+        let plaintext = key
+            .decrypt(&nonce, &ciphertext, &aad(name))
+            .ok_or_else(|| {
+                SecretStoreError::Corrupt(format!("'{name}' doesn't decrypt under the key file"))
+            })?;
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|e| SecretStoreError::Corrupt(format!("'{name}' is not UTF-8: {e}")))
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), SecretStoreError> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entries = self.entries()?;
+        let key = self
+            .key(true)?
+            .expect("key() creates the key file when asked to");
+        let (nonce, ciphertext) = key.encrypt(value.as_bytes(), &aad(name));
+        entries.insert(
+            name.to_string(),
+            SealedSecret {
+                nonce: to_hex(&nonce),
+                ciphertext: to_hex(&ciphertext),
+            },
+        );
+        self.save_entries(&entries)
+    }
+
+    fn delete(&self, name: &str) -> Result<(), SecretStoreError> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entries = self.entries()?;
+        if entries.remove(name).is_some() {
+            self.save_entries(&entries)?;
+        }
+        Ok(())
+    }
+}
+
+fn aad(name: &str) -> Vec<u8> {
+    [SECRET_AAD, name.as_bytes()].concat()
+}
+
+/// Write `contents` to `path` atomically, readable by the owner only.
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), SecretStoreError> {
+    use std::io::Write;
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(&tmp)
+        .and_then(|mut file| file.write_all(contents).and_then(|()| file.sync_all()))
+        .and_then(|()| std::fs::rename(&tmp, path))
+        .map_err(|e| SecretStoreError::Io(format!("failed to write {}: {e}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> (PathBuf, FileSecretStore) {
+        let dir = std::env::temp_dir().join(format!(
+            "wavesync_secret_store_{name}_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = FileSecretStore::new(dir.join("secrets.json"), dir.join("secret.key"));
+        (dir, store)
+    }
+
+    #[test]
+    fn test_set_get_delete() {
+        let (dir, store) = temp_store("roundtrip");
+        assert_eq!(store.get(PASSPHRASE).unwrap(), None);
+
+        store.set(PASSPHRASE, "correct horse").unwrap();
+        store.set(API_KEY, "wsc_live_123").unwrap();
+        assert_eq!(
+            store.get(PASSPHRASE).unwrap().as_deref(),
+            Some("correct horse")
+        );
+        assert_eq!(store.get(API_KEY).unwrap().as_deref(), Some("wsc_live_123"));
+
+        store.delete(PASSPHRASE).unwrap();
+        store.delete(PASSPHRASE).unwrap();
+        assert_eq!(store.get(PASSPHRASE).unwrap(), None);
+        assert_eq!(store.get(API_KEY).unwrap().as_deref(), Some("wsc_live_123"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_secrets_are_not_stored_in_plaintext() {
+        let (dir, store) = temp_store("plaintext");
+        store.set(PASSPHRASE, "correct horse").unwrap();
+        let on_disk = std::fs::read_to_string(dir.join("secrets.json")).unwrap();
+        assert!(!on_disk.contains("correct horse"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_wrong_key_or_moved_entry_fails() {
+        let (dir, store) = temp_store("tamper");
+        store.set(PASSPHRASE, "correct horse").unwrap();
+
+        // An entry copied under another name doesn't open.
+        let mut entries = store.entries().unwrap();
+        let sealed = entries.remove(PASSPHRASE).unwrap();
+        entries.insert(API_KEY.to_string(), sealed);
+        store.save_entries(&entries).unwrap();
+        assert!(matches!(
+            store.get(API_KEY),
+            Err(SecretStoreError::Corrupt(_))
+        ));
+
+        // Nor does anything once the key file is gone.
+        std::fs::remove_file(dir.join("secret.key")).unwrap();
+        assert!(matches!(
+            store.get(API_KEY),
+            Err(SecretStoreError::Corrupt(_))
+        ));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -6,11 +6,23 @@
 //!
 //! Shadow tables replace the old `_wavesync_log` — metadata lives alongside
 //! current state and overwrites in place, so no compaction is needed.
+//!
+//! Besides the local `db_version` a clock was written at, each clock keeps
+//! `origin_version`: the `db_version` its origin site wrote it at. That is
+//! what [`OriginVersions`] watermarks are compared against; `0` marks clocks
+//! whose origin version isn't known (written before it was tracked, or
+//! received from a build that doesn't report it).
+//!
+//! Clocks adopted from a site that signs its changes also keep the author's
+//! signature, in `_wavesync_change_sigs`, so they can be passed on to other
+//! peers still verifiable (see [`crate::signing`]).
 
 use sea_orm::{ConnectionTrait, DatabaseBackend, DbErr, ExecResult, FromQueryResult, Statement};
 
 use crate::messages::{ColumnChange, NodeId};
+use crate::protocol::{OriginVersions, SnapshotCursor, SyncCursor};
 use crate::registry::TableRegistry;
+use crate::signing::ChangeSignature;
 
 /// A single clock entry from a shadow table.
 #[derive(Debug, Clone)]
@@ -21,6 +33,7 @@ pub struct ClockEntry {
     pub db_version: u64,
     pub site_id: NodeId,
     pub seq: u32,
+    pub origin_version: u64,
 }
 
 /// Create the `_wavesync_meta` key-value table.
@@ -48,12 +61,35 @@ pub async fn create_shadow_table(
             db_version  INTEGER NOT NULL,
             site_id     BLOB NOT NULL,
             seq         INTEGER NOT NULL DEFAULT 0,
+            origin_version INTEGER NOT NULL DEFAULT 0,
             PRIMARY KEY (pk, cid)
         )",
         shadow_name
     );
     db.execute_unprepared(&sql).await?;
 
+    // Shadow tables created before origin versions were tracked: existing
+    // clocks keep origin_version 0 and go on syncing by per-peer watermark.
+    #[derive(Debug, FromQueryResult)]
+    struct CountRow {
+        cnt: i64,
+    }
+    let has_origin = CountRow::find_by_statement(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "SELECT COUNT(*) AS cnt FROM pragma_table_info($1) WHERE name = 'origin_version'",
+        [shadow_name.clone().into()],
+    ))
+    .one(db)
+    .await?
+    .is_some_and(|r| r.cnt > 0);
+    if !has_origin {
+        db.execute_unprepared(&format!(
+            "ALTER TABLE \"{}\" ADD COLUMN origin_version INTEGER NOT NULL DEFAULT 0",
+            shadow_name
+        ))
+        .await?;
+    }
+
     // Index on db_version for efficient get_changes_since queries
     let idx_sql = format!(
         "CREATE INDEX IF NOT EXISTS \"idx_{}_db_version\" ON \"{}\" (db_version)",
@@ -115,7 +151,7 @@ pub async fn max_db_version_across_shadow_tables(db: &impl ConnectionTrait) -> R
     let tables = TableName::find_by_statement(Statement::from_sql_and_values(
         DatabaseBackend::Sqlite,
         "SELECT name FROM sqlite_master \
-         WHERE type = 'table' AND name LIKE '_wavesync_%_clock'",
+         WHERE type = 'table' AND name LIKE '\\_wavesync\\_%\\_clock' ESCAPE '\\'",
         [],
     ))
     .all(db)
@@ -200,6 +236,29 @@ pub async fn set_db_version(db: &impl ConnectionTrait, version: u64) -> Result<(
 pub async fn get_or_create_libp2p_keypair(
     db: &impl ConnectionTrait,
 ) -> Result<libp2p::identity::Keypair, DbErr> {
+    if let Some(keypair) = find_libp2p_keypair(db).await? {
+        return Ok(keypair);
+    }
+
+    let keypair = libp2p::identity::Keypair::generate_ed25519();
+    let bytes = keypair
+        .to_protobuf_encoding()
+        .map_err(|e| DbErr::Custom(format!("failed to encode libp2p keypair: {e}")))?;
+    db.execute_raw(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "INSERT OR REPLACE INTO _wavesync_meta (key, value) VALUES ($1, $2)",
+        ["libp2p_keypair".into(), bytes.into()],
+    ))
+    .await?;
+    Ok(keypair)
+}
+
+/// Load the persisted libp2p keypair without generating one, for readers
+/// that mustn't write. `None` if there is none yet, or it no longer parses
+/// (in which case [`get_or_create_libp2p_keypair`] replaces it).
+pub async fn find_libp2p_keypair(
+    db: &impl ConnectionTrait,
+) -> Result<Option<libp2p::identity::Keypair>, DbErr> {
     #[derive(Debug, FromQueryResult)]
     struct MetaRow {
         value: Vec<u8>,
@@ -213,33 +272,74 @@ pub async fn get_or_create_libp2p_keypair(
     .one(db)
     .await?;
 
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    match libp2p::identity::Keypair::from_protobuf_encoding(&row.value) {
+        Ok(kp) => Ok(Some(kp)),
+        Err(e) => {
+            log::warn!("stored libp2p keypair is unparseable ({e}); PeerId will change once");
+            Ok(None)
+        }
+    }
+}
+
+/// Load the certificate of our WebRTC listener from `_wavesync_meta`, or
+/// generate and persist one on first launch.
+///
+/// Its hash is part of the listener's `/webrtc-direct/certhash/...`
+/// address, so a stable certificate keeps the addresses browsers cached
+/// from an earlier session dialable.
+#[cfg(feature = "webrtc")]
+pub async fn get_or_create_webrtc_certificate(
+    db: &impl ConnectionTrait,
+) -> Result<libp2p_webrtc::tokio::Certificate, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct MetaRow {
+        value: Vec<u8>,
+    }
+
+    let row = MetaRow::find_by_statement(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "SELECT value FROM _wavesync_meta WHERE key = $1",
+        ["webrtc_certificate".into()],
+    ))
+    .one(db)
+    .await?;
+
     if let Some(row) = row {
-        match libp2p::identity::Keypair::from_protobuf_encoding(&row.value) {
-            Ok(kp) => return Ok(kp),
+        match std::str::from_utf8(&row.value)
+            .map_err(|e| e.to_string())
+            .and_then(|pem| {
+                libp2p_webrtc::tokio::Certificate::from_pem(pem).map_err(|e| e.to_string())
+            }) {
+            Ok(cert) => return Ok(cert),
             Err(e) => {
                 log::warn!(
-                    "stored libp2p keypair is unparseable ({e}); regenerating. \
-                     PeerId will change once."
+                    "stored WebRTC certificate is unparseable ({e}); regenerating. \
+                     Browsers will need the new listen address."
                 );
             }
         }
     }
 
-    let keypair = libp2p::identity::Keypair::generate_ed25519();
-    let bytes = keypair
-        .to_protobuf_encoding()
-        .map_err(|e| DbErr::Custom(format!("failed to encode libp2p keypair: {e}")))?;
+    let cert = libp2p_webrtc::tokio::Certificate::generate(&mut rand::thread_rng())
+        .map_err(|e| DbErr::Custom(format!("failed to generate WebRTC certificate: {e}")))?;
     db.execute_raw(Statement::from_sql_and_values(
         DatabaseBackend::Sqlite,
         "INSERT OR REPLACE INTO _wavesync_meta (key, value) VALUES ($1, $2)",
-        ["libp2p_keypair".into(), bytes.into()],
+        [
+            "webrtc_certificate".into(),
+            cert.serialize_pem().into_bytes().into(),
+        ],
     ))
     .await?;
-    Ok(keypair)
+    Ok(cert)
 }
 
-/// Get or generate a persistent site_id.
-pub async fn get_site_id(db: &impl ConnectionTrait) -> Result<NodeId, DbErr> {
+/// Load the persisted site_id without generating one, for readers that
+/// mustn't write.
+pub async fn find_site_id(db: &impl ConnectionTrait) -> Result<Option<NodeId>, DbErr> {
     #[derive(Debug, FromQueryResult)]
     struct MetaRow {
         value: Vec<u8>,
@@ -253,24 +353,35 @@ pub async fn get_site_id(db: &impl ConnectionTrait) -> Result<NodeId, DbErr> {
     .one(db)
     .await?;
 
-    if let Some(row) = row
-        && row.value.len() == 16
-    {
-        let mut id = [0u8; 16];
-        id.copy_from_slice(&row.value);
-        return Ok(NodeId(id));
+    Ok(row
+        .and_then(|row| <[u8; 16]>::try_from(row.value).ok())
+        .map(NodeId))
+}
+
+/// Get or generate a persistent site_id.
+pub async fn get_site_id(db: &impl ConnectionTrait) -> Result<NodeId, DbErr> {
+    if let Some(id) = find_site_id(db).await? {
+        return Ok(id);
     }
 
-    // Generate new site_id
-    let mut id = [0u8; 16];
-    let pid = std::process::id().to_le_bytes();
-    id[..4].copy_from_slice(&pid);
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_nanos()
-        .to_le_bytes();
-    id[4..].copy_from_slice(&now[..12]);
+    // A new site takes its id from its signing key, so its signatures speak
+    // for it without being pinned first (see `crate::signing`). Sites
+    // created before that keep the random id persisted above.
+    let id = match get_or_create_libp2p_keypair(db).await?.try_into_ed25519() {
+        Ok(keypair) => crate::signing::site_id_for_key(&keypair.public().to_bytes()).0,
+        Err(_) => {
+            let mut id = [0u8; 16];
+            let pid = std::process::id().to_le_bytes();
+            id[..4].copy_from_slice(&pid);
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+                .to_le_bytes();
+            id[4..].copy_from_slice(&now[..12]);
+            id
+        }
+    };
 
     // Persist it
     db.execute_raw(Statement::from_sql_and_values(
@@ -283,6 +394,63 @@ pub async fn get_site_id(db: &impl ConnectionTrait) -> Result<NodeId, DbErr> {
     Ok(NodeId(id))
 }
 
+/// Load the group key's rotation state (see [`crate::rotation`]), if the
+/// database was ever opened with a passphrase, without its passphrases. A
+/// corrupt value reads as missing, which falls back to the configured
+/// passphrase.
+///
+/// A state written by a version that kept the passphrases in it comes back
+/// with them filled in, for the caller to move into the secret store and
+/// rewrite with [`set_key_epoch`].
+pub async fn get_key_epoch(
+    db: &impl ConnectionTrait,
+) -> Result<Option<crate::rotation::KeyEpoch>, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct MetaRow {
+        value: Vec<u8>,
+    }
+
+    let row = MetaRow::find_by_statement(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "SELECT value FROM _wavesync_meta WHERE key = $1",
+        ["key_epoch".into()],
+    ))
+    .one(db)
+    .await?;
+
+    Ok(row.and_then(|row| {
+        if let Some(legacy) = crate::rotation::KeyEpoch::from_legacy(&row.value) {
+            return Some(legacy);
+        }
+        match serde_json::from_slice(&row.value) {
+            Ok(state) => Some(state),
+            Err(e) => {
+                log::warn!(
+                    "stored key epoch is unparseable ({e}); using the configured passphrase"
+                );
+                None
+            }
+        }
+    }))
+}
+
+/// Persist the group key's rotation state. Its passphrases are not
+/// written; they belong in the secret store.
+pub async fn set_key_epoch(
+    db: &impl ConnectionTrait,
+    state: &crate::rotation::KeyEpoch,
+) -> Result<(), DbErr> {
+    let bytes = serde_json::to_vec(state)
+        .map_err(|e| DbErr::Custom(format!("failed to encode key epoch: {e}")))?;
+    db.execute_raw(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "INSERT OR REPLACE INTO _wavesync_meta (key, value) VALUES ($1, $2)",
+        ["key_epoch".into(), bytes.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
 /// Get the current col_version for a specific column of a row.
 pub async fn get_col_version(
     db: &impl ConnectionTrait,
@@ -350,7 +518,8 @@ pub async fn get_col_version_with_site(
     }
 }
 
-/// Insert or replace a clock entry in the shadow table.
+/// Insert or replace a clock entry in the shadow table, for a change made
+/// at `db_version` by this node (its origin version is `db_version`).
 #[allow(clippy::too_many_arguments)]
 pub async fn upsert_clock_entry(
     db: &impl ConnectionTrait,
@@ -361,11 +530,39 @@ pub async fn upsert_clock_entry(
     db_version: u64,
     site_id: &NodeId,
     seq: u32,
+) -> Result<ExecResult, DbErr> {
+    upsert_clock_entry_with_origin(
+        db,
+        table,
+        pk,
+        cid,
+        col_version,
+        db_version,
+        site_id,
+        seq,
+        db_version,
+    )
+    .await
+}
+
+/// Insert or replace a clock entry received from another node, recording
+/// the `db_version` its origin wrote it at (0 if unknown).
+#[allow(clippy::too_many_arguments)]
+pub async fn upsert_clock_entry_with_origin(
+    db: &impl ConnectionTrait,
+    table: &str,
+    pk: &str,
+    cid: &str,
+    col_version: u64,
+    db_version: u64,
+    site_id: &NodeId,
+    seq: u32,
+    origin_version: u64,
 ) -> Result<ExecResult, DbErr> {
     let shadow_name = format!("_wavesync_{}_clock", table);
     let sql = format!(
-        "INSERT OR REPLACE INTO \"{}\" (pk, cid, col_version, db_version, site_id, seq)
-         VALUES ($1, $2, $3, $4, $5, $6)",
+        "INSERT OR REPLACE INTO \"{}\" (pk, cid, col_version, db_version, site_id, seq, origin_version)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
         shadow_name
     );
 
@@ -379,6 +576,7 @@ pub async fn upsert_clock_entry(
             (db_version as i64).into(),
             site_id.0.to_vec().into(),
             (seq as i32).into(),
+            (origin_version as i64).into(),
         ],
     ))
     .await
@@ -418,14 +616,17 @@ pub async fn upsert_clock_entries_batch(
             placeholders.push(',');
         }
         let base = i * 6;
+        // origin_version reuses the db_version placeholder: a local write's
+        // origin is this node.
         placeholders.push_str(&format!(
-            "(${},${},${},${},${},${})",
+            "(${},${},${},${},${},${},${})",
             base + 1,
             base + 2,
             base + 3,
             base + 4,
             base + 5,
             base + 6,
+            base + 4,
         ));
         values.push(pk.into());
         values.push(cid.clone().into());
@@ -438,13 +639,14 @@ pub async fn upsert_clock_entries_batch(
     }
 
     let sql = format!(
-        r#"INSERT INTO "{shadow}" (pk, cid, col_version, db_version, site_id, seq)
+        r#"INSERT INTO "{shadow}" (pk, cid, col_version, db_version, site_id, seq, origin_version)
            VALUES {values}
            ON CONFLICT(pk, cid) DO UPDATE SET
                col_version = "{shadow}".col_version + 1,
                db_version = excluded.db_version,
                site_id    = excluded.site_id,
-               seq        = excluded.seq
+               seq        = excluded.seq,
+               origin_version = excluded.origin_version
            RETURNING cid, col_version"#,
         shadow = shadow_name,
         values = placeholders,
@@ -471,25 +673,44 @@ pub async fn upsert_clock_entries_batch(
     Ok(out)
 }
 
+/// Clock row as stored in a shadow table.
+#[derive(Debug, FromQueryResult)]
+struct ClockRow {
+    pk: String,
+    cid: String,
+    col_version: i64,
+    db_version: i64,
+    site_id: Vec<u8>,
+    seq: i32,
+    origin_version: i64,
+}
+
+impl From<ClockRow> for ClockEntry {
+    fn from(r: ClockRow) -> Self {
+        let mut id = [0u8; 16];
+        let len = r.site_id.len().min(16);
+        id[..len].copy_from_slice(&r.site_id[..len]);
+        ClockEntry {
+            pk: r.pk,
+            cid: r.cid,
+            col_version: r.col_version as u64,
+            db_version: r.db_version as u64,
+            site_id: NodeId(id),
+            seq: r.seq as u32,
+            origin_version: r.origin_version as u64,
+        }
+    }
+}
+
 /// Get all clock entries for a specific row.
 pub async fn get_clock_entries_for_row(
     db: &impl ConnectionTrait,
     table: &str,
     pk: &str,
 ) -> Result<Vec<ClockEntry>, DbErr> {
-    #[derive(Debug, FromQueryResult)]
-    struct ClockRow {
-        pk: String,
-        cid: String,
-        col_version: i64,
-        db_version: i64,
-        site_id: Vec<u8>,
-        seq: i32,
-    }
-
     let shadow_name = format!("_wavesync_{}_clock", table);
     let sql = format!(
-        "SELECT pk, cid, col_version, db_version, site_id, seq FROM \"{}\" WHERE pk = $1",
+        "SELECT pk, cid, col_version, db_version, site_id, seq, origin_version FROM \"{}\" WHERE pk = $1",
         shadow_name
     );
 
@@ -501,200 +722,700 @@ pub async fn get_clock_entries_for_row(
     .all(db)
     .await?;
 
-    Ok(rows
-        .into_iter()
-        .map(|r| {
-            let mut id = [0u8; 16];
-            let len = r.site_id.len().min(16);
-            id[..len].copy_from_slice(&r.site_id[..len]);
-            ClockEntry {
-                pk: r.pk,
-                cid: r.cid,
-                col_version: r.col_version as u64,
-                db_version: r.db_version as u64,
-                site_id: NodeId(id),
-                seq: r.seq as u32,
-            }
-        })
-        .collect())
+    Ok(rows.into_iter().map(ClockEntry::from).collect())
+}
+
+/// Get every clock entry of a table.
+pub async fn get_all_clock_entries(
+    db: &impl ConnectionTrait,
+    table: &str,
+) -> Result<Vec<ClockEntry>, DbErr> {
+    let shadow_name = format!("_wavesync_{}_clock", table);
+    let sql = format!(
+        "SELECT pk, cid, col_version, db_version, site_id, seq, origin_version FROM \"{}\"",
+        shadow_name
+    );
+
+    let rows = ClockRow::find_by_statement(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        &sql,
+        [],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(rows.into_iter().map(ClockEntry::from).collect())
+}
+
+/// Clock row as read from a shadow table by the catch-up queries.
+#[derive(Debug, FromQueryResult)]
+struct ChangeRow {
+    pk: String,
+    cid: String,
+    col_version: i64,
+    db_version: i64,
+    seq: i32,
+    site_id: Vec<u8>,
+    origin_version: i64,
+}
+
+/// `WHERE` condition for the clocks a requester is missing. Without
+/// `origins`, everything written locally after `since` (bound as `$1`).
+/// With them, clocks past the requester's watermark for their origin site;
+/// clocks of unknown origin still go by `since`. Binds its parameters after
+/// those already in `values`.
+fn missing_clause(origins: Option<&OriginVersions>, values: &mut Vec<sea_orm::Value>) -> String {
+    let Some(origins) = origins else {
+        return "db_version > $1".to_string();
+    };
+    let watermark = if origins.is_empty() {
+        "0".to_string()
+    } else {
+        let mut case = "CASE site_id".to_string();
+        for (site, version) in origins.iter() {
+            values.push(site.0.to_vec().into());
+            values.push((*version as i64).into());
+            case.push_str(&format!(
+                " WHEN ${} THEN ${}",
+                values.len() - 1,
+                values.len()
+            ));
+        }
+        case.push_str(" ELSE 0 END");
+        case
+    };
+    format!(
+        "(CASE WHEN origin_version = 0 THEN db_version > $1 ELSE origin_version > ({watermark}) END)"
+    )
 }
 
 /// Get all changes since a given db_version across all shadow tables.
 ///
 /// Joins shadow clock tables with actual user tables to get current column values.
-/// Returns changes ordered by (db_version, seq).
+/// Returns changes in the order they were written here, (db_version, seq).
+/// With `origins`, only changes past the requester's per-origin watermarks
+/// (see [`missing_clause`]).
 pub async fn get_changes_since(
     db: &impl ConnectionTrait,
     registry: &TableRegistry,
     since_db_version: u64,
+    origins: Option<&OriginVersions>,
 ) -> Result<Vec<ColumnChange>, DbErr> {
-    let mut all_changes = Vec::new();
+    let mut keyed: Vec<(crate::registry::TableMeta, ChangeRow)> = Vec::new();
 
     for meta in registry.all_tables() {
         let shadow_name = format!("_wavesync_{}_clock", meta.table_name);
-        let pk_col = &meta.primary_key_column;
-
-        // Get all clock entries newer than since_db_version
-        #[derive(Debug, FromQueryResult)]
-        struct ChangeRow {
-            pk: String,
-            cid: String,
-            col_version: i64,
-            db_version: i64,
-            seq: i32,
-            site_id: Vec<u8>,
-        }
 
+        let mut values: Vec<sea_orm::Value> = vec![(since_db_version as i64).into()];
+        let missing = missing_clause(origins, &mut values);
         let sql = format!(
-            "SELECT pk, cid, col_version, db_version, seq, site_id FROM \"{}\" WHERE db_version > $1 ORDER BY db_version, seq",
-            shadow_name
+            "SELECT pk, cid, col_version, db_version, seq, site_id, origin_version FROM \"{}\" WHERE {} ORDER BY db_version, seq",
+            shadow_name, missing
         );
 
         let rows = ChangeRow::find_by_statement(Statement::from_sql_and_values(
             DatabaseBackend::Sqlite,
             &sql,
-            [(since_db_version as i64).into()],
+            values,
         ))
         .all(db)
         .await?;
 
-        for row in rows {
-            // For __deleted entries, val is None
-            let val = if row.cid == "__deleted" {
-                None
-            } else {
-                // Look up the current value from the actual table.
-                // Use json_object to get the value as a properly typed JSON value.
-                let val_result = db
-                    .query_one_raw(Statement::from_sql_and_values(
-                        DatabaseBackend::Sqlite,
-                        format!(
-                            "SELECT json_object('v', \"{}\") as json_val FROM \"{}\" WHERE \"{}\" = $1",
-                            row.cid, meta.table_name, pk_col
-                        ),
-                        [row.pk.clone().into()],
-                    ))
-                    .await?;
-
-                match val_result {
-                    Some(qr) => {
-                        let raw: Option<String> = qr.try_get("", "json_val").ok();
-                        raw.and_then(|s| {
-                            let obj: serde_json::Value = serde_json::from_str(&s).ok()?;
-                            Some(obj.get("v")?.clone())
-                        })
-                    }
-                    None => None,
-                }
-            };
+        keyed.extend(rows.into_iter().map(|r| (meta.clone(), r)));
+    }
 
-            // Skip non-delete entries where the row was concurrently deleted.
-            // The __deleted tombstone handles the delete correctly.
-            if val.is_none() && row.cid != "__deleted" {
-                continue;
-            }
+    // Sort by (db_version, seq) for correct causal ordering across tables.
+    // This is the local db_version; the changes carry their origin's.
+    keyed.sort_by_key(|(_, r)| (r.db_version, r.seq));
 
-            let mut id = [0u8; 16];
-            let len = row.site_id.len().min(16);
-            id[..len].copy_from_slice(&row.site_id[..len]);
-
-            all_changes.push(ColumnChange {
-                table: meta.table_name.clone().into(),
-                pk: row.pk.into(),
-                cid: row.cid.into(),
-                val,
-                site_id: NodeId(id),
-                col_version: row.col_version as u64,
-                cl: row.col_version as u64, // causal length = col_version for non-deletes
-                seq: row.seq as u32,
-                db_version: row.db_version as u64,
-            });
+    let mut all_changes = Vec::with_capacity(keyed.len());
+    for (meta, row) in keyed {
+        if let Some(change) = resolve_change_row(db, &meta, row).await? {
+            all_changes.push(change);
         }
     }
 
-    // Sort by (db_version, seq) for correct causal ordering across tables
-    all_changes.sort_by_key(|c| (c.db_version, c.seq));
-
     Ok(all_changes)
 }
 
-/// Insert a tombstone entry in the shadow table.
-pub async fn insert_tombstone(
-    db: &impl ConnectionTrait,
-    table: &str,
-    pk: &str,
-    col_version: u64,
-    db_version: u64,
-    site_id: &NodeId,
-) -> Result<ExecResult, DbErr> {
-    upsert_clock_entry(
-        db,
-        table,
-        pk,
-        "__deleted",
-        col_version,
-        db_version,
-        site_id,
-        0,
-    )
-    .await
-}
-
-/// Remove just the `__deleted` tombstone sentinel for a row.
+/// Get one page of changes since `since_db_version`, resuming after `after`.
 ///
-/// Used when a row is re-inserted or updated after deletion — preserves
-/// per-column clock entries so that CRDT col_versions continue from their
-/// previous values instead of resetting to 0.
-pub async fn clear_tombstone(
+/// Paginated counterpart of [`get_changes_since`] for catch-up of large
+/// histories. Returns at most `limit` changes plus the cursor to pass back
+/// for the next page, or `None` once the last page has been returned.
+///
+/// Changes are ordered by `(db_version, seq, table, pk, cid)`. The
+/// `(table, pk, cid)` tiebreak is what makes the cursor exact: a multi-row
+/// write shares one `db_version` and restarts `seq` for every row, so
+/// `(db_version, seq)` alone would either skip or repeat rows at a page
+/// boundary.
+///
+/// Rows whose value has since been deleted are skipped (same as
+/// [`get_changes_since`]) but still advance the cursor, so a page may hold
+/// fewer than `limit` changes while more remain.
+pub async fn get_changes_page(
     db: &impl ConnectionTrait,
-    table: &str,
-    pk: &str,
-) -> Result<ExecResult, DbErr> {
-    let shadow_name = format!("_wavesync_{}_clock", table);
-    let sql = format!(
-        "DELETE FROM \"{}\" WHERE pk = $1 AND cid = '__deleted'",
-        shadow_name
-    );
+    registry: &TableRegistry,
+    since_db_version: u64,
+    origins: Option<&OriginVersions>,
+    after: Option<&SyncCursor>,
+    limit: usize,
+) -> Result<(Vec<ColumnChange>, Option<SyncCursor>), DbErr> {
+    let limit = limit.max(1);
+    let mut keyed: Vec<(crate::registry::TableMeta, ChangeRow)> = Vec::new();
 
-    db.execute_raw(Statement::from_sql_and_values(
-        DatabaseBackend::Sqlite,
-        &sql,
-        [pk.into()],
-    ))
-    .await
-}
+    for meta in registry.all_tables() {
+        let shadow_name = format!("_wavesync_{}_clock", meta.table_name);
 
-/// Remove all shadow clock entries for a row (used when delete wins).
-pub async fn delete_clock_entries(
-    db: &impl ConnectionTrait,
-    table: &str,
-    pk: &str,
-) -> Result<ExecResult, DbErr> {
-    let shadow_name = format!("_wavesync_{}_clock", table);
-    let sql = format!("DELETE FROM \"{}\" WHERE pk = $1", shadow_name);
+        let mut values: Vec<sea_orm::Value> = vec![(since_db_version as i64).into()];
+        let cursor_clause = match after {
+            None => String::new(),
+            Some(c) => {
+                values.push((c.db_version as i64).into());
+                values.push((c.seq as i32).into());
+                match meta.table_name.as_str().cmp(c.table.as_str()) {
+                    // Tables sorting before the cursor's table already sent
+                    // every row at the cursor's (db_version, seq).
+                    std::cmp::Ordering::Less => " AND (db_version, seq) > ($2, $3)".to_string(),
+                    std::cmp::Ordering::Equal => {
+                        values.push(c.pk.clone().into());
+                        values.push(c.cid.clone().into());
+                        " AND (db_version, seq, pk, cid) > ($2, $3, $4, $5)".to_string()
+                    }
+                    std::cmp::Ordering::Greater => " AND (db_version, seq) >= ($2, $3)".to_string(),
+                }
+            }
+        };
 
-    db.execute_raw(Statement::from_sql_and_values(
-        DatabaseBackend::Sqlite,
-        &sql,
-        [pk.into()],
-    ))
-    .await
-}
+        let missing = missing_clause(origins, &mut values);
 
-/// Check if a shadow table exists for the given table name.
-pub async fn shadow_table_exists(
-    db: &impl ConnectionTrait,
-    table_name: &str,
-) -> Result<bool, DbErr> {
-    #[derive(Debug, FromQueryResult)]
-    struct CountRow {
-        cnt: i64,
-    }
+        // `limit + 1` per table: if any table alone has more than `limit`
+        // rows left, the merged set is larger than `limit` and we know to
+        // hand out a cursor.
+        let sql = format!(
+            "SELECT pk, cid, col_version, db_version, seq, site_id, origin_version FROM \"{}\" \
+             WHERE {}{} ORDER BY db_version, seq, pk, cid LIMIT {}",
+            shadow_name,
+            missing,
+            cursor_clause,
+            limit + 1
+        );
 
-    let shadow_name = format!("_wavesync_{}_clock", table_name);
-    let row = CountRow::find_by_statement(Statement::from_sql_and_values(
-        DatabaseBackend::Sqlite,
-        "SELECT COUNT(*) as cnt FROM sqlite_master WHERE type='table' AND name=$1",
+        let rows = ChangeRow::find_by_statement(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            &sql,
+            values,
+        ))
+        .all(db)
+        .await?;
+
+        keyed.extend(rows.into_iter().map(|r| (meta.clone(), r)));
+    }
+
+    keyed.sort_by(|(ma, a), (mb, b)| {
+        (a.db_version, a.seq, &ma.table_name, &a.pk, &a.cid).cmp(&(
+            b.db_version,
+            b.seq,
+            &mb.table_name,
+            &b.pk,
+            &b.cid,
+        ))
+    });
+
+    let next_cursor = if keyed.len() > limit {
+        keyed.truncate(limit);
+        keyed.last().map(|(meta, row)| SyncCursor {
+            db_version: row.db_version as u64,
+            seq: row.seq as u32,
+            table: meta.table_name.clone(),
+            pk: row.pk.clone(),
+            cid: row.cid.clone(),
+        })
+    } else {
+        None
+    };
+
+    let mut changes = Vec::with_capacity(keyed.len());
+    for (meta, row) in keyed {
+        if let Some(change) = resolve_change_row(db, &meta, row).await? {
+            changes.push(change);
+        }
+    }
+
+    Ok((changes, next_cursor))
+}
+
+/// SQLite caps a function call at 127 arguments, so `json_object` is built
+/// over at most this many columns at a time.
+const JSON_OBJECT_MAX_COLUMNS: usize = 60;
+
+/// Read one page of the compacted current state, for snapshot bootstrap.
+///
+/// Walks rows in `(table, pk)` order starting after `after` and returns, for
+/// each row, the winning clock of every column with its current value — or
+/// only the `__deleted` tombstone if the row is deleted. Unlike
+/// [`get_changes_since`] this looks up values once per row rather than once
+/// per column, and never ships column clocks a tombstone has superseded.
+///
+/// A page covers at most `limit_rows` rows; the returned cursor is `None`
+/// once every table has been walked.
+pub async fn get_state_page(
+    db: &impl ConnectionTrait,
+    registry: &TableRegistry,
+    after: Option<&SnapshotCursor>,
+    limit_rows: usize,
+) -> Result<(Vec<ColumnChange>, Option<SnapshotCursor>), DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct PkRow {
+        pk: String,
+    }
+
+    let limit_rows = limit_rows.max(1);
+    let mut tables = registry.all_tables();
+    tables.sort_by(|a, b| a.table_name.cmp(&b.table_name));
+
+    let mut changes = Vec::new();
+    let mut rows_taken = 0;
+
+    for meta in tables {
+        let after_pk = match after {
+            None => None,
+            Some(c) => match meta.table_name.as_str().cmp(c.table.as_str()) {
+                std::cmp::Ordering::Less => continue,
+                std::cmp::Ordering::Equal => Some(c.pk.clone()),
+                std::cmp::Ordering::Greater => None,
+            },
+        };
+
+        let shadow_name = format!("_wavesync_{}_clock", meta.table_name);
+        let remaining = limit_rows - rows_taken;
+        let (filter, values): (&str, Vec<sea_orm::Value>) = match after_pk {
+            Some(pk) => ("WHERE pk > $1 ", vec![pk.into()]),
+            None => ("", Vec::new()),
+        };
+        let sql = format!(
+            "SELECT DISTINCT pk FROM \"{}\" {}ORDER BY pk LIMIT {}",
+            shadow_name,
+            filter,
+            remaining + 1
+        );
+        let mut pks = PkRow::find_by_statement(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            &sql,
+            values,
+        ))
+        .all(db)
+        .await?;
+
+        let more_in_table = pks.len() > remaining;
+        pks.truncate(remaining);
+        rows_taken += pks.len();
+        let last_pk = pks.last().map(|r| r.pk.clone());
+
+        for PkRow { pk } in pks {
+            let mut entries = get_clock_entries_for_row(db, &meta.table_name, &pk).await?;
+            if let Some(i) = entries.iter().position(|e| e.cid == "__deleted") {
+                let entry = entries.swap_remove(i);
+                let db_version = entry.db_version;
+                let mut change = clock_entry_to_change(&meta, entry, None);
+                attach_signature(db, &mut change, db_version).await;
+                changes.push(change);
+                continue;
+            }
+            let Some(row) = get_row_json(db, &meta, &pk).await? else {
+                // Deleted after the pk scan; incremental sync carries the tombstone.
+                continue;
+            };
+            for entry in entries {
+                if let Some(val) = row.get(&entry.cid) {
+                    let val = Some(val.clone());
+                    let db_version = entry.db_version;
+                    let mut change = clock_entry_to_change(&meta, entry, val);
+                    attach_signature(db, &mut change, db_version).await;
+                    changes.push(change);
+                }
+            }
+        }
+
+        if more_in_table || rows_taken == limit_rows {
+            return Ok((
+                changes,
+                last_pk.map(|pk| SnapshotCursor {
+                    table: meta.table_name.clone(),
+                    pk,
+                }),
+            ));
+        }
+    }
+
+    Ok((changes, None))
+}
+
+/// Current values of a row as a column → JSON map, or `None` if the row
+/// does not exist.
+async fn get_row_json(
+    db: &impl ConnectionTrait,
+    meta: &crate::registry::TableMeta,
+    pk: &str,
+) -> Result<Option<serde_json::Map<String, serde_json::Value>>, DbErr> {
+    let mut out = serde_json::Map::new();
+    for chunk in meta.columns.chunks(JSON_OBJECT_MAX_COLUMNS) {
+        let args = chunk
+            .iter()
+            .map(|c| format!("'{c}', \"{c}\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let result = db
+            .query_one_raw(Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                format!(
+                    "SELECT json_object({}) as json_val FROM \"{}\" WHERE \"{}\" = $1",
+                    args, meta.table_name, meta.primary_key_column
+                ),
+                [pk.into()],
+            ))
+            .await?;
+        let Some(qr) = result else {
+            return Ok(None);
+        };
+        let raw: Option<String> = qr.try_get("", "json_val").ok();
+        if let Some(serde_json::Value::Object(obj)) =
+            raw.and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+        {
+            out.extend(obj);
+        }
+    }
+    Ok(Some(out))
+}
+
+fn clock_entry_to_change(
+    meta: &crate::registry::TableMeta,
+    entry: ClockEntry,
+    val: Option<serde_json::Value>,
+) -> ColumnChange {
+    ColumnChange {
+        table: meta.table_name.clone().into(),
+        pk: entry.pk.into(),
+        cid: entry.cid.into(),
+        val,
+        site_id: entry.site_id,
+        col_version: entry.col_version,
+        cl: entry.col_version, // causal length = col_version for non-deletes
+        seq: entry.seq,
+        db_version: entry.origin_version,
+        sig: None,
+    }
+}
+
+/// Turn specific clock entries of `meta`'s table into [`ColumnChange`]s
+/// carrying current values, skipping entries [`get_changes_since`] would
+/// skip.
+pub async fn changes_for_entries(
+    db: &impl ConnectionTrait,
+    meta: &crate::registry::TableMeta,
+    entries: Vec<ClockEntry>,
+) -> Result<Vec<ColumnChange>, DbErr> {
+    let mut changes = Vec::with_capacity(entries.len());
+    for e in entries {
+        let row = ChangeRow {
+            pk: e.pk,
+            cid: e.cid,
+            col_version: e.col_version as i64,
+            db_version: e.db_version as i64,
+            seq: e.seq as i32,
+            site_id: e.site_id.0.to_vec(),
+            origin_version: e.origin_version as i64,
+        };
+        if let Some(change) = resolve_change_row(db, meta, row).await? {
+            changes.push(change);
+        }
+    }
+    Ok(changes)
+}
+
+/// Turn a shadow clock row into a [`ColumnChange`] carrying the column's
+/// current value. Returns `None` for a non-delete entry whose row has been
+/// deleted concurrently — the `__deleted` tombstone covers it.
+async fn resolve_change_row(
+    db: &impl ConnectionTrait,
+    meta: &crate::registry::TableMeta,
+    row: ChangeRow,
+) -> Result<Option<ColumnChange>, DbErr> {
+    // For __deleted entries, val is None
+    let val = if row.cid == "__deleted" {
+        None
+    } else {
+        // Look up the current value from the actual table.
+        // Use json_object to get the value as a properly typed JSON value.
+        let val_result = db
+            .query_one_raw(Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                format!(
+                    "SELECT json_object('v', \"{}\") as json_val FROM \"{}\" WHERE \"{}\" = $1",
+                    row.cid, meta.table_name, meta.primary_key_column
+                ),
+                [row.pk.clone().into()],
+            ))
+            .await?;
+
+        match val_result {
+            Some(qr) => {
+                let raw: Option<String> = qr.try_get("", "json_val").ok();
+                raw.and_then(|s| {
+                    let obj: serde_json::Value = serde_json::from_str(&s).ok()?;
+                    Some(obj.get("v")?.clone())
+                })
+            }
+            None => None,
+        }
+    };
+
+    // Skip non-delete entries where the row was concurrently deleted.
+    // The __deleted tombstone handles the delete correctly.
+    if val.is_none() && row.cid != "__deleted" {
+        return Ok(None);
+    }
+
+    let mut id = [0u8; 16];
+    let len = row.site_id.len().min(16);
+    id[..len].copy_from_slice(&row.site_id[..len]);
+
+    let mut change = ColumnChange {
+        table: meta.table_name.clone().into(),
+        pk: row.pk.into(),
+        cid: row.cid.into(),
+        val,
+        site_id: NodeId(id),
+        col_version: row.col_version as u64,
+        cl: row.col_version as u64, // causal length = col_version for non-deletes
+        seq: row.seq as u32,
+        db_version: row.origin_version as u64,
+        sig: None,
+    };
+    attach_signature(db, &mut change, row.db_version as u64).await;
+    Ok(Some(change))
+}
+
+/// Insert a tombstone entry in the shadow table, for a delete made at
+/// `db_version` by this node.
+pub async fn insert_tombstone(
+    db: &impl ConnectionTrait,
+    table: &str,
+    pk: &str,
+    col_version: u64,
+    db_version: u64,
+    site_id: &NodeId,
+) -> Result<ExecResult, DbErr> {
+    insert_tombstone_with_origin(db, table, pk, col_version, db_version, site_id, db_version).await
+}
+
+/// Insert a tombstone received from another node, recording the
+/// `db_version` its origin deleted the row at (0 if unknown).
+pub async fn insert_tombstone_with_origin(
+    db: &impl ConnectionTrait,
+    table: &str,
+    pk: &str,
+    col_version: u64,
+    db_version: u64,
+    site_id: &NodeId,
+    origin_version: u64,
+) -> Result<ExecResult, DbErr> {
+    upsert_clock_entry_with_origin(
+        db,
+        table,
+        pk,
+        "__deleted",
+        col_version,
+        db_version,
+        site_id,
+        0,
+        origin_version,
+    )
+    .await
+}
+
+/// Remove just the `__deleted` tombstone sentinel for a row.
+///
+/// Used when a row is re-inserted or updated after deletion — preserves
+/// per-column clock entries so that CRDT col_versions continue from their
+/// previous values instead of resetting to 0.
+pub async fn clear_tombstone(
+    db: &impl ConnectionTrait,
+    table: &str,
+    pk: &str,
+) -> Result<ExecResult, DbErr> {
+    let shadow_name = format!("_wavesync_{}_clock", table);
+    let sql = format!(
+        "DELETE FROM \"{}\" WHERE pk = $1 AND cid = '__deleted'",
+        shadow_name
+    );
+
+    db.execute_raw(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        &sql,
+        [pk.into()],
+    ))
+    .await
+}
+
+/// Remove all shadow clock entries for a row (used when delete wins).
+pub async fn delete_clock_entries(
+    db: &impl ConnectionTrait,
+    table: &str,
+    pk: &str,
+) -> Result<ExecResult, DbErr> {
+    let shadow_name = format!("_wavesync_{}_clock", table);
+    let sql = format!("DELETE FROM \"{}\" WHERE pk = $1", shadow_name);
+
+    db.execute_raw(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        &sql,
+        [pk.into()],
+    ))
+    .await
+}
+
+/// Rewrite every clock of `table` above `ceiling` to `ceiling`, undoing
+/// clocks inflated by a peer (see [`crate::conflict`]). Clocks stored past
+/// `i64::MAX` read back negative from SQLite and count as above it.
+/// Returns how many clocks were rewritten.
+pub async fn clamp_clocks(
+    db: &impl ConnectionTrait,
+    table: &str,
+    ceiling: u64,
+) -> Result<u64, DbErr> {
+    let shadow_name = format!("_wavesync_{}_clock", table);
+    let ceiling = ceiling.min(i64::MAX as u64) as i64;
+    let sql = format!(
+        "UPDATE \"{}\" SET col_version = $1 WHERE col_version > $1 OR col_version < 0",
+        shadow_name
+    );
+    let result = db
+        .execute_raw(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            &sql,
+            [ceiling.into()],
+        ))
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Create the `_wavesync_change_sigs` table, which keeps the signature and
+/// signed value of each clock this node adopted from a signing site, so the
+/// change can be relayed verifiably (see [`crate::signing`]).
+///
+/// Rows are keyed to the clock's local `db_version`: once the clock is
+/// overwritten, its row no longer matches and is ignored.
+pub async fn create_change_sigs_table(db: &impl ConnectionTrait) -> Result<ExecResult, DbErr> {
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS _wavesync_change_sigs (
+            tbl        TEXT NOT NULL,
+            pk         TEXT NOT NULL,
+            cid        TEXT NOT NULL,
+            db_version INTEGER NOT NULL,
+            val        TEXT NOT NULL,
+            sig        BLOB NOT NULL,
+            PRIMARY KEY (tbl, pk, cid)
+        )",
+    )
+    .await
+}
+
+/// Record `change`'s signature if its clock is the one now stored for its
+/// column — i.e. it won conflict resolution. Does nothing for unsigned
+/// changes or ones that lost.
+pub async fn record_change_signature(
+    db: &impl ConnectionTrait,
+    change: &ColumnChange,
+) -> Result<(), DbErr> {
+    let Some(sig) = &change.sig else {
+        return Ok(());
+    };
+    let val = serde_json::to_string(&change.val).map_err(|e| DbErr::Custom(e.to_string()))?;
+    let sql = format!(
+        "INSERT OR REPLACE INTO _wavesync_change_sigs (tbl, pk, cid, db_version, val, sig)
+         SELECT $1, pk, cid, db_version, $2, $3 FROM \"_wavesync_{}_clock\"
+         WHERE pk = $4 AND cid = $5 AND col_version = $6 AND site_id = $7",
+        change.table.0
+    );
+    db.execute_raw(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        &sql,
+        [
+            change.table.0.clone().into(),
+            val.into(),
+            sig.to_bytes().into(),
+            change.pk.0.clone().into(),
+            change.cid.0.clone().into(),
+            (change.col_version as i64).into(),
+            change.site_id.0.to_vec().into(),
+        ],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// The signature and signed value recorded for the clock of `table` at
+/// (`pk`, `cid`) written locally at `db_version`, if any.
+async fn get_change_signature(
+    db: &impl ConnectionTrait,
+    table: &str,
+    pk: &str,
+    cid: &str,
+    db_version: u64,
+) -> Result<Option<(Option<serde_json::Value>, ChangeSignature)>, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct SigRow {
+        val: String,
+        sig: Vec<u8>,
+    }
+
+    let found = SigRow::find_by_statement(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "SELECT val, sig FROM _wavesync_change_sigs
+         WHERE tbl = $1 AND pk = $2 AND cid = $3 AND db_version = $4",
+        [
+            table.into(),
+            pk.into(),
+            cid.into(),
+            (db_version as i64).into(),
+        ],
+    ))
+    .one(db)
+    .await?;
+
+    Ok(found.and_then(|r| {
+        let val = serde_json::from_str(&r.val).ok()?;
+        Some((val, ChangeSignature::from_bytes(&r.sig)?))
+    }))
+}
+
+/// Give `change`, read from a clock written locally at `db_version`, its
+/// author's signature and the value exactly as signed, if one was recorded.
+/// Databases that predate the table simply have nothing recorded.
+async fn attach_signature(db: &impl ConnectionTrait, change: &mut ColumnChange, db_version: u64) {
+    if let Ok(Some((val, sig))) =
+        get_change_signature(db, &change.table.0, &change.pk.0, &change.cid.0, db_version).await
+    {
+        change.val = val;
+        change.sig = Some(sig);
+    }
+}
+
+/// Check if a shadow table exists for the given table name.
+pub async fn shadow_table_exists(
+    db: &impl ConnectionTrait,
+    table_name: &str,
+) -> Result<bool, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct CountRow {
+        cnt: i64,
+    }
+
+    let shadow_name = format!("_wavesync_{}_clock", table_name);
+    let row = CountRow::find_by_statement(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "SELECT COUNT(*) as cnt FROM sqlite_master WHERE type='table' AND name=$1",
         [shadow_name.into()],
     ))
     .one(db)
@@ -703,6 +1424,62 @@ pub async fn shadow_table_exists(
     Ok(row.is_some_and(|r| r.cnt > 0))
 }
 
+/// Rebuild the registry of synced tables from the database schema alone:
+/// every table with a shadow table, its columns and primary key as SQLite
+/// reports them. For tools that read a database without the app's
+/// entities. Delete policies and column types aren't recorded in the
+/// database, so every table gets the defaults.
+pub async fn registry_from_schema(db: &impl ConnectionTrait) -> Result<TableRegistry, DbErr> {
+    #[derive(Debug, FromQueryResult)]
+    struct TableName {
+        name: String,
+    }
+    #[derive(Debug, FromQueryResult)]
+    struct ColumnInfo {
+        name: String,
+        pk: i64,
+    }
+
+    let shadows = TableName::find_by_statement(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "SELECT name FROM sqlite_master \
+         WHERE type = 'table' AND name LIKE '\\_wavesync\\_%\\_clock' ESCAPE '\\'",
+        [],
+    ))
+    .all(db)
+    .await?;
+
+    let registry = TableRegistry::new();
+    for shadow in shadows {
+        let Some(table) = shadow
+            .name
+            .strip_prefix("_wavesync_")
+            .and_then(|n| n.strip_suffix("_clock"))
+        else {
+            continue;
+        };
+        let columns = ColumnInfo::find_by_statement(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "SELECT name, pk FROM pragma_table_info($1) ORDER BY cid",
+            [table.into()],
+        ))
+        .all(db)
+        .await?;
+        let Some(pk) = columns.iter().find(|c| c.pk == 1) else {
+            // Not a table of ours, or its user table is gone.
+            continue;
+        };
+        registry.register(crate::registry::TableMeta {
+            table_name: table.to_string(),
+            primary_key_column: pk.name.clone(),
+            columns: columns.iter().map(|c| c.name.clone()).collect(),
+            delete_policy: Default::default(),
+            column_types: Default::default(),
+        });
+    }
+    Ok(registry)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -764,6 +1541,164 @@ mod tests {
         assert_ne!(id1, NodeId([0u8; 16]), "site_id should be non-zero");
     }
 
+    #[tokio::test]
+    async fn test_find_site_id_and_keypair_never_generate() {
+        let db = setup_db().await;
+        assert_eq!(find_site_id(&db).await.unwrap(), None);
+        assert!(find_libp2p_keypair(&db).await.unwrap().is_none());
+        assert_eq!(find_site_id(&db).await.unwrap(), None);
+
+        let site_id = get_site_id(&db).await.unwrap();
+        assert_eq!(find_site_id(&db).await.unwrap(), Some(site_id));
+        let keypair = find_libp2p_keypair(&db).await.unwrap().unwrap();
+        assert_eq!(
+            keypair.public(),
+            get_or_create_libp2p_keypair(&db).await.unwrap().public()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_new_site_id_is_bound_to_keypair() {
+        let db = setup_db().await;
+        let site_id = get_site_id(&db).await.unwrap();
+        let keypair = get_or_create_libp2p_keypair(&db)
+            .await
+            .unwrap()
+            .try_into_ed25519()
+            .unwrap();
+        assert_eq!(
+            site_id,
+            crate::signing::site_id_for_key(&keypair.public().to_bytes())
+        );
+    }
+
+    #[cfg(feature = "webrtc")]
+    #[tokio::test]
+    async fn test_webrtc_certificate_is_persisted() {
+        let db = setup_db().await;
+        let first = get_or_create_webrtc_certificate(&db).await.unwrap();
+        let second = get_or_create_webrtc_certificate(&db).await.unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_key_epoch_roundtrip() {
+        use crate::rotation::KeyEpoch;
+
+        let db = setup_db().await;
+        assert!(get_key_epoch(&db).await.unwrap().is_none());
+
+        let v1 = crate::auth::GroupKey::from_passphrase;
+        let keypair = libp2p::identity::ed25519::Keypair::generate();
+        let site = crate::signing::site_id_for_key(&keypair.public().to_bytes());
+        let signer = crate::signing::ChangeSigner::new(keypair, site);
+        let rotation = crate::rotation::SignedRotation::issue(&signer, 1, &v1("two"), None, None);
+        let state = KeyEpoch::new("one", None, v1("one")).advance("two", v1("two"), rotation, 60);
+        set_key_epoch(&db, &state).await.unwrap();
+        // The passphrases stay out of the database.
+        let loaded = get_key_epoch(&db).await.unwrap().unwrap();
+        assert!(loaded.passphrase.is_empty() && loaded.previous.is_none());
+        assert_eq!(
+            serde_json::to_value(&state).unwrap(),
+            serde_json::to_value(&loaded).unwrap()
+        );
+        assert!(loaded.is_key(&v1("two")) && loaded.is_previous_key(&v1("one")));
+    }
+
+    #[tokio::test]
+    async fn test_change_signature_follows_its_clock() {
+        use crate::registry::TableMeta;
+        use crate::signing::ChangeSigner;
+
+        let db = setup_with_shadow().await;
+        create_change_sigs_table(&db).await.unwrap();
+        db.execute_unprepared("INSERT INTO tasks (id, title, done) VALUES ('t1', 'Signed', 0)")
+            .await
+            .unwrap();
+
+        let signer = ChangeSigner::new(
+            libp2p::identity::ed25519::Keypair::generate(),
+            NodeId([3u8; 16]),
+        );
+        let mut change = ColumnChange {
+            table: "tasks".into(),
+            pk: "t1".into(),
+            cid: "title".into(),
+            val: Some(serde_json::json!("Signed")),
+            site_id: NodeId([3u8; 16]),
+            col_version: 2,
+            cl: 2,
+            seq: 0,
+            db_version: 9,
+            sig: None,
+        };
+        signer.sign_own(std::slice::from_mut(&mut change));
+
+        // A change that lost conflict resolution records nothing.
+        upsert_clock_entry(&db, "tasks", "t1", "title", 3, 4, &NodeId([1u8; 16]), 0)
+            .await
+            .unwrap();
+        record_change_signature(&db, &change).await.unwrap();
+        let meta = TableMeta {
+            table_name: "tasks".to_string(),
+            primary_key_column: "id".to_string(),
+            columns: vec!["id".to_string(), "title".to_string(), "done".to_string()],
+            delete_policy: crate::messages::DeletePolicy::default(),
+            column_types: Default::default(),
+        };
+        let entries = get_clock_entries_for_row(&db, "tasks", "t1").await.unwrap();
+        let served = changes_for_entries(&db, &meta, entries).await.unwrap();
+        assert!(served[0].sig.is_none());
+
+        // Once it wins, it is served with its signature...
+        upsert_clock_entry_with_origin(&db, "tasks", "t1", "title", 2, 5, &NodeId([3u8; 16]), 0, 9)
+            .await
+            .unwrap();
+        record_change_signature(&db, &change).await.unwrap();
+        let entries = get_clock_entries_for_row(&db, "tasks", "t1").await.unwrap();
+        let served = changes_for_entries(&db, &meta, entries).await.unwrap();
+        assert_eq!(served[0].sig, change.sig);
+        assert!(crate::signing::verify_change(&served[0], None).is_ok());
+
+        // ...until the clock is written again.
+        upsert_clock_entry(&db, "tasks", "t1", "title", 3, 6, &NodeId([3u8; 16]), 0)
+            .await
+            .unwrap();
+        let entries = get_clock_entries_for_row(&db, "tasks", "t1").await.unwrap();
+        let served = changes_for_entries(&db, &meta, entries).await.unwrap();
+        assert!(served[0].sig.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_clamp_clocks() {
+        let db = setup_with_shadow().await;
+        let site = NodeId([1u8; 16]);
+        upsert_clock_entry(&db, "tasks", "t1", "title", 7, 1, &site, 0)
+            .await
+            .unwrap();
+        upsert_clock_entry(&db, "tasks", "t1", "done", u64::MAX, 2, &site, 0)
+            .await
+            .unwrap();
+        upsert_clock_entry(&db, "tasks", "t2", "title", 5_000, 3, &site, 0)
+            .await
+            .unwrap();
+
+        assert_eq!(clamp_clocks(&db, "tasks", 1_000).await.unwrap(), 2);
+        assert_eq!(
+            get_col_version(&db, "tasks", "t1", "title").await.unwrap(),
+            7
+        );
+        assert_eq!(
+            get_col_version(&db, "tasks", "t1", "done").await.unwrap(),
+            1_000
+        );
+        assert_eq!(
+            get_col_version(&db, "tasks", "t2", "title").await.unwrap(),
+            1_000
+        );
+        assert_eq!(clamp_clocks(&db, "tasks", 1_000).await.unwrap(), 0);
+    }
+
     #[tokio::test]
     async fn test_create_shadow_table() {
         let db = setup_db().await;
@@ -771,6 +1706,33 @@ mod tests {
         assert!(shadow_table_exists(&db, "tasks").await.unwrap());
     }
 
+    #[tokio::test]
+    async fn test_registry_from_schema() {
+        let db = setup_with_shadow().await;
+        // A shadow table whose user table is gone is skipped, and so is a
+        // table that only matches `_` as a wildcard.
+        create_shadow_table(&db, "dropped").await.unwrap();
+        db.execute_unprepared("CREATE TABLE xwavesyncxtasks_clock (id TEXT PRIMARY KEY)")
+            .await
+            .unwrap();
+        upsert_clock_entry(&db, "tasks", "t1", "title", 1, 1, &NodeId([1u8; 16]), 0)
+            .await
+            .unwrap();
+        db.execute_unprepared("INSERT INTO tasks (id, title, done) VALUES ('t1', 'Read', 0)")
+            .await
+            .unwrap();
+
+        let registry = registry_from_schema(&db).await.unwrap();
+        assert_eq!(registry.all_tables().len(), 1);
+        max_db_version_across_shadow_tables(&db).await.unwrap();
+        let meta = registry.get("tasks").unwrap();
+        assert_eq!(meta.primary_key_column, "id");
+        assert_eq!(meta.columns, ["id", "title", "done"]);
+        let changes = get_changes_since(&db, &registry, 0, None).await.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].val, Some(serde_json::json!("Read")));
+    }
+
     #[tokio::test]
     async fn test_shadow_table_idempotent() {
         let db = setup_db().await;
@@ -969,16 +1931,228 @@ mod tests {
             primary_key_column: "id".to_string(),
             columns: vec!["id".to_string(), "title".to_string(), "done".to_string()],
             delete_policy: crate::messages::DeletePolicy::default(),
+            column_types: Default::default(),
         });
 
         // Get changes since db_version 1 (should only get pk2's change at db_version 3)
-        let changes = get_changes_since(&db, &registry, 1).await.unwrap();
+        let changes = get_changes_since(&db, &registry, 1, None).await.unwrap();
         assert_eq!(changes.len(), 1);
         assert_eq!(changes[0].pk, "pk2");
         assert_eq!(changes[0].cid, "title");
 
         // Get all changes (since 0)
-        let all_changes = get_changes_since(&db, &registry, 0).await.unwrap();
+        let all_changes = get_changes_since(&db, &registry, 0, None).await.unwrap();
         assert_eq!(all_changes.len(), 3);
     }
+
+    #[tokio::test]
+    async fn test_create_shadow_table_adds_origin_version_to_old_tables() {
+        let db = setup_db().await;
+        db.execute_unprepared(
+            "CREATE TABLE \"_wavesync_tasks_clock\" (
+                pk TEXT NOT NULL, cid TEXT NOT NULL, col_version INTEGER NOT NULL,
+                db_version INTEGER NOT NULL, site_id BLOB NOT NULL,
+                seq INTEGER NOT NULL DEFAULT 0, PRIMARY KEY (pk, cid))",
+        )
+        .await
+        .unwrap();
+        db.execute_unprepared(
+            "INSERT INTO \"_wavesync_tasks_clock\" VALUES ('pk1', 'title', 1, 7, x'01', 0)",
+        )
+        .await
+        .unwrap();
+
+        create_shadow_table(&db, "tasks").await.unwrap();
+        // Idempotent once migrated.
+        create_shadow_table(&db, "tasks").await.unwrap();
+
+        let entries = get_clock_entries_for_row(&db, "tasks", "pk1")
+            .await
+            .unwrap();
+        assert_eq!(entries[0].db_version, 7);
+        assert_eq!(
+            entries[0].origin_version, 0,
+            "pre-existing origin is unknown"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_changes_since_by_origin_watermark() {
+        let db = setup_with_shadow().await;
+        let local = NodeId([1u8; 16]);
+        let a = NodeId([2u8; 16]);
+        let b = NodeId([3u8; 16]);
+
+        for pk in ["pk1", "pk2", "pk3", "pk4"] {
+            db.execute_unprepared(&format!("INSERT INTO tasks VALUES ('{pk}', 'x', 0)"))
+                .await
+                .unwrap();
+        }
+        // Received from a at its version 4 and from b at its version 9,
+        // both applied here at local version 2; one written locally at 3;
+        // one of unknown origin applied at local version 5.
+        upsert_clock_entry_with_origin(&db, "tasks", "pk1", "title", 1, 2, &a, 0, 4)
+            .await
+            .unwrap();
+        upsert_clock_entry_with_origin(&db, "tasks", "pk2", "title", 1, 2, &b, 0, 9)
+            .await
+            .unwrap();
+        upsert_clock_entry(&db, "tasks", "pk3", "title", 1, 3, &local, 0)
+            .await
+            .unwrap();
+        upsert_clock_entry_with_origin(&db, "tasks", "pk4", "title", 1, 5, &b, 0, 0)
+            .await
+            .unwrap();
+
+        let registry = TableRegistry::new();
+        registry.register(crate::registry::TableMeta {
+            table_name: "tasks".to_string(),
+            primary_key_column: "id".to_string(),
+            columns: vec!["id".to_string(), "title".to_string(), "done".to_string()],
+            delete_policy: crate::messages::DeletePolicy::default(),
+            column_types: Default::default(),
+        });
+
+        // The requester holds a's writes up to 4 and b's up to 5, and has
+        // seen our local versions up to 5.
+        let origins = OriginVersions::default().with(a, 4).with(b, 5);
+        let changes = get_changes_since(&db, &registry, 5, Some(&origins))
+            .await
+            .unwrap();
+        let pks: Vec<&str> = changes.iter().map(|c| c.pk.0.as_str()).collect();
+        assert_eq!(pks, vec!["pk2", "pk3"]);
+        assert_eq!(
+            changes[0].db_version, 9,
+            "changes carry their origin version"
+        );
+
+        // Unknown-origin clocks fall back to the per-peer watermark.
+        let changes = get_changes_since(&db, &registry, 4, Some(&origins))
+            .await
+            .unwrap();
+        assert!(changes.iter().any(|c| c.pk == "pk4" && c.db_version == 0));
+    }
+
+    #[tokio::test]
+    async fn test_get_changes_page_walks_full_history() {
+        let db = setup_with_shadow().await;
+        let site_id = NodeId([1u8; 16]);
+
+        // A multi-row write shares one db_version and restarts seq per row,
+        // so (db_version, seq) alone cannot position a cursor.
+        for pk in ["pk1", "pk2", "pk3"] {
+            db.execute_unprepared(&format!("INSERT INTO tasks VALUES ('{pk}', 'T', 0)"))
+                .await
+                .unwrap();
+            upsert_clock_entry(&db, "tasks", pk, "title", 1, 1, &site_id, 0)
+                .await
+                .unwrap();
+            upsert_clock_entry(&db, "tasks", pk, "done", 1, 1, &site_id, 1)
+                .await
+                .unwrap();
+        }
+        upsert_clock_entry(&db, "tasks", "pk1", "title", 2, 2, &site_id, 0)
+            .await
+            .unwrap();
+
+        let registry = TableRegistry::new();
+        registry.register(crate::registry::TableMeta {
+            table_name: "tasks".to_string(),
+            primary_key_column: "id".to_string(),
+            columns: vec!["id".to_string(), "title".to_string(), "done".to_string()],
+            delete_policy: crate::messages::DeletePolicy::default(),
+            column_types: Default::default(),
+        });
+
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        let mut pages = 0;
+        loop {
+            let (page, next) = get_changes_page(&db, &registry, 0, None, cursor.as_ref(), 2)
+                .await
+                .unwrap();
+            assert!(page.len() <= 2);
+            seen.extend(page.into_iter().map(|c| (c.db_version, c.pk.0, c.cid.0)));
+            pages += 1;
+            match next {
+                Some(c) => cursor = Some(c),
+                None => break,
+            }
+        }
+
+        // pk1.title moved to db_version 2, so 6 clock rows in total, each once
+        assert_eq!(seen.len(), 6);
+        assert_eq!(pages, 3);
+        let mut dedup = seen.clone();
+        dedup.sort();
+        dedup.dedup();
+        assert_eq!(dedup.len(), 6, "no change may be delivered twice");
+        assert_eq!(seen.last().unwrap().0, 2, "pages follow db_version order");
+
+        // A page that exactly exhausts the history reports no next cursor
+        let (all, next) = get_changes_page(&db, &registry, 0, None, None, 6)
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 6);
+        assert!(next.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_state_page_compacts_deleted_rows_and_pages_by_row() {
+        let db = setup_with_shadow().await;
+        let site_id = NodeId([1u8; 16]);
+
+        for pk in ["pk1", "pk2", "pk3"] {
+            db.execute_unprepared(&format!("INSERT INTO tasks VALUES ('{pk}', 'T {pk}', 0)"))
+                .await
+                .unwrap();
+            upsert_clock_entry(&db, "tasks", pk, "title", 1, 1, &site_id, 0)
+                .await
+                .unwrap();
+            upsert_clock_entry(&db, "tasks", pk, "done", 1, 1, &site_id, 1)
+                .await
+                .unwrap();
+        }
+        // pk2 is deleted: its column clocks stay behind the tombstone
+        db.execute_unprepared("DELETE FROM tasks WHERE id = 'pk2'")
+            .await
+            .unwrap();
+        insert_tombstone(&db, "tasks", "pk2", 2, 2, &site_id)
+            .await
+            .unwrap();
+
+        let registry = TableRegistry::new();
+        registry.register(crate::registry::TableMeta {
+            table_name: "tasks".to_string(),
+            primary_key_column: "id".to_string(),
+            columns: vec!["id".to_string(), "title".to_string(), "done".to_string()],
+            delete_policy: crate::messages::DeletePolicy::default(),
+            column_types: Default::default(),
+        });
+
+        let (all, next) = get_state_page(&db, &registry, None, 10).await.unwrap();
+        assert!(next.is_none());
+        assert_eq!(all.len(), 5, "2 live rows × 2 columns + 1 tombstone");
+        let pk2: Vec<_> = all.iter().filter(|c| c.pk.0 == "pk2").collect();
+        assert_eq!(pk2.len(), 1);
+        assert_eq!(pk2[0].cid.0, "__deleted");
+        assert!(pk2[0].val.is_none());
+        let title = all
+            .iter()
+            .find(|c| c.pk.0 == "pk3" && c.cid.0 == "title")
+            .unwrap();
+        assert_eq!(title.val, Some(serde_json::json!("T pk3")));
+
+        // Two rows per page: pk1 + pk2, then pk3
+        let (first, cursor) = get_state_page(&db, &registry, None, 2).await.unwrap();
+        let cursor = cursor.expect("a row remains");
+        assert_eq!(cursor.pk, "pk2");
+        assert_eq!(first.len(), 3);
+        let (second, next) = get_state_page(&db, &registry, Some(&cursor), 2)
+            .await
+            .unwrap();
+        assert_eq!(second.len(), 2);
+        assert!(second.iter().all(|c| c.pk.0 == "pk3"));
+        assert!(next.is_none());
+    }
 }
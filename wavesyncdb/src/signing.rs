@@ -0,0 +1,357 @@
+//! Per-site signatures on column changes.
+//!
+//! The group key proves a message came from *some* member of the group, but
+//! `ColumnChange::site_id` is only a claim: any member could write changes
+//! under another device's id and win its conflicts. Each site therefore
+//! signs the changes it authored with the ed25519 key behind its libp2p
+//! identity, and the signature travels with the change — through pushes,
+//! catch-up, snapshots, anti-entropy and gossip — so a change relayed by a
+//! third peer is still checked against its author.
+//!
+//! ## Binding site ids to keys
+//!
+//! A site created by this build takes its id from its public key
+//! ([`site_id_for_key`]), so a signature from that key is all it takes to
+//! speak for the site. A peer's key-derived site is also pinned to its key
+//! as soon as the peer connects, so no member can slip in unsigned changes
+//! under it before the peer's first signed one arrives. Sites whose id
+//! predates that (random ids persisted by older builds) are bound on first
+//! sight instead: the first key seen signing for them is pinned, and from
+//! then on only that key is accepted. Once a site is known to sign,
+//! unsigned changes claiming it are refused. Unsigned changes for other
+//! sites are still taken from any peer, signing or not: browsers and older
+//! builds don't sign, and their changes reach us relayed by peers that do.
+//!
+//! ## What is signed
+//!
+//! [`signing_bytes`] covers everything that decides what a change does to a
+//! row — table, pk, column, value, site and clocks — but not `seq` or the
+//! origin `db_version`, which are catch-up bookkeeping and get rewritten on
+//! the way through peers that don't track them. The value is signed as
+//! serialized JSON, so a relay has to pass on the value exactly as the
+//! author sent it; the shadow tables keep it next to the signature for that.
+//!
+//! Builds that verify signatures announce
+//! [`FEATURE_SIGNED_CHANGES`](crate::protocol::FEATURE_SIGNED_CHANGES);
+//! peers that don't get their changes without them.
+
+use libp2p::identity::ed25519;
+use serde::{Deserialize, Serialize};
+
+use crate::messages::{ColumnChange, NodeId};
+use crate::seal::{from_hex, to_hex};
+
+/// Domain separator prefixed to every signed message.
+const SIGNING_DOMAIN: &[u8] = b"wavesyncdb-change-v1";
+
+/// Why a remote change was refused.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ForgeryError {
+    #[error("signature does not verify")]
+    BadSignature,
+    #[error("signed by a key other than the one bound to its site")]
+    WrongKey,
+    #[error("unsigned change for a site that signs its changes")]
+    Unsigned,
+}
+
+/// An ed25519 signature over [`signing_bytes`], with the key that made it.
+///
+/// Serialized as a single hex string (key, then signature).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ChangeSignature {
+    pub key: [u8; 32],
+    pub sig: [u8; 64],
+}
+
+impl ChangeSignature {
+    /// Key and signature concatenated, as stored in the shadow tables.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(96);
+        out.extend_from_slice(&self.key);
+        out.extend_from_slice(&self.sig);
+        out
+    }
+
+    /// Inverse of [`ChangeSignature::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 96 {
+            return None;
+        }
+        let mut key = [0u8; 32];
+        let mut sig = [0u8; 64];
+        key.copy_from_slice(&bytes[..32]);
+        sig.copy_from_slice(&bytes[32..]);
+        Some(Self { key, sig })
+    }
+}
+
+impl TryFrom<String> for ChangeSignature {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        from_hex(&s)
+            .as_deref()
+            .and_then(Self::from_bytes)
+            .ok_or_else(|| "change signature must be 96 hex-encoded bytes".to_string())
+    }
+}
+
+impl From<ChangeSignature> for String {
+    fn from(sig: ChangeSignature) -> Self {
+        to_hex(&sig.to_bytes())
+    }
+}
+
+/// The site id a site created with `key` takes.
+pub fn site_id_for_key(key: &[u8; 32]) -> NodeId {
+    let hash = blake3::derive_key("wavesyncdb-site-id-v1", key);
+    let mut id = [0u8; 16];
+    id.copy_from_slice(&hash[..16]);
+    NodeId(id)
+}
+
+/// The bytes a [`ChangeSignature`] is made over.
+pub fn signing_bytes(change: &ColumnChange) -> Vec<u8> {
+    fn field(out: &mut Vec<u8>, bytes: &[u8]) {
+        out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        out.extend_from_slice(bytes);
+    }
+
+    let val = serde_json::to_vec(&change.val).expect("serde_json::Value serializes to JSON");
+    let mut out = Vec::with_capacity(SIGNING_DOMAIN.len() + 64 + val.len());
+    out.extend_from_slice(SIGNING_DOMAIN);
+    field(&mut out, change.table.0.as_bytes());
+    field(&mut out, change.pk.0.as_bytes());
+    field(&mut out, change.cid.0.as_bytes());
+    field(&mut out, &val);
+    out.extend_from_slice(&change.site_id.0);
+    out.extend_from_slice(&change.col_version.to_be_bytes());
+    out.extend_from_slice(&change.cl.to_be_bytes());
+    out
+}
+
+/// Check `change`'s signature, if it has one, against the key `pinned` for
+/// its site (if any).
+///
+/// Returns the key that signed it — for the caller to pin if it isn't
+/// already — or `None` for an unsigned change from a site not known to sign.
+pub fn verify_change(
+    change: &ColumnChange,
+    pinned: Option<&[u8; 32]>,
+) -> Result<Option<[u8; 32]>, ForgeryError> {
+    let Some(sig) = &change.sig else {
+        return match pinned {
+            Some(_) => Err(ForgeryError::Unsigned),
+            None => Ok(None),
+        };
+    };
+    let key =
+        ed25519::PublicKey::try_from_bytes(&sig.key).map_err(|_| ForgeryError::BadSignature)?;
+    if !key.verify(&signing_bytes(change), &sig.sig) {
+        return Err(ForgeryError::BadSignature);
+    }
+    // A site id derived from the key needs no pin; anything else has to
+    // match the key first seen for the site.
+    if site_id_for_key(&sig.key) == change.site_id || pinned.is_none_or(|p| *p == sig.key) {
+        Ok(Some(sig.key))
+    } else {
+        Err(ForgeryError::WrongKey)
+    }
+}
+
+/// Drop the signatures from `changes`, for a peer that doesn't know them.
+pub fn strip_signatures(changes: &mut [ColumnChange]) {
+    for c in changes {
+        c.sig = None;
+    }
+}
+
+/// Signs the changes this site authored.
+#[derive(Clone)]
+pub struct ChangeSigner {
+    keypair: ed25519::Keypair,
+    site_id: NodeId,
+}
+
+impl ChangeSigner {
+    pub fn new(keypair: ed25519::Keypair, site_id: NodeId) -> Self {
+        Self { keypair, site_id }
+    }
+
+    /// Our public key.
+    pub fn public_key(&self) -> [u8; 32] {
+        self.keypair.public().to_bytes()
+    }
+
+    /// The X25519 secret of our identity key, for opening what was sealed
+    /// to it (see [`crate::rotation::KeyGrant`]): the clamped half of the
+    /// ed25519 secret's SHA-512, as ed25519 itself uses it.
+    pub(crate) fn x25519_secret(&self) -> [u8; 32] {
+        use sha2::Digest;
+        let hash = sha2::Sha512::digest(self.keypair.secret().as_ref());
+        let mut scalar = [0u8; 32];
+        scalar.copy_from_slice(&hash[..32]);
+        scalar
+    }
+
+    /// Sign `msg` with our identity key.
+    pub fn sign(&self, msg: &[u8]) -> ChangeSignature {
+        let sig = self
+            .keypair
+            .sign(msg)
+            .try_into()
+            .expect("ed25519 signatures are 64 bytes");
+        ChangeSignature {
+            key: self.public_key(),
+            sig,
+        }
+    }
+
+    /// Sign every change in `changes` written by this site. Changes from
+    /// other sites keep whatever signature their author gave them.
+    pub fn sign_own(&self, changes: &mut [ColumnChange]) {
+        for c in changes.iter_mut().filter(|c| c.site_id == self.site_id) {
+            c.sig = Some(self.sign(&signing_bytes(c)));
+        }
+    }
+
+    /// Prepare `changes` for a peer: signed if it verifies signatures,
+    /// stripped of them if it doesn't.
+    pub fn sign_for(signer: Option<&Self>, changes: &mut [ColumnChange]) {
+        match signer {
+            Some(s) => s.sign_own(changes),
+            None => strip_signatures(changes),
+        }
+    }
+}
+
+impl std::fmt::Debug for ChangeSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChangeSigner")
+            .field("site_id", &self.site_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(site_id: NodeId, val: &str) -> ColumnChange {
+        ColumnChange {
+            table: "tasks".into(),
+            pk: "pk-1".into(),
+            cid: "title".into(),
+            val: Some(serde_json::json!(val)),
+            site_id,
+            col_version: 2,
+            cl: 2,
+            seq: 0,
+            db_version: 7,
+            sig: None,
+        }
+    }
+
+    fn bound_signer() -> ChangeSigner {
+        let keypair = ed25519::Keypair::generate();
+        let site = site_id_for_key(&keypair.public().to_bytes());
+        ChangeSigner::new(keypair, site)
+    }
+
+    #[test]
+    fn test_signature_serde_roundtrip() {
+        let signer = bound_signer();
+        let mut changes = vec![change(signer.site_id, "a")];
+        signer.sign_own(&mut changes);
+        let json = serde_json::to_string(&changes[0]).unwrap();
+        let back: ColumnChange = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, changes[0]);
+
+        let unsigned = serde_json::to_string(&change(signer.site_id, "a")).unwrap();
+        assert!(!unsigned.contains("sig"));
+    }
+
+    #[test]
+    fn test_derived_site_verifies_without_pin() {
+        let signer = bound_signer();
+        let mut changes = vec![change(signer.site_id, "a")];
+        signer.sign_own(&mut changes);
+        assert_eq!(
+            verify_change(&changes[0], None),
+            Ok(Some(signer.public_key()))
+        );
+        // The derived binding outranks a pin made by someone else first.
+        assert!(verify_change(&changes[0], Some(&[9u8; 32])).is_ok());
+    }
+
+    #[test]
+    fn test_tampered_change_is_rejected() {
+        let signer = bound_signer();
+        let mut changes = vec![change(signer.site_id, "a")];
+        signer.sign_own(&mut changes);
+
+        let mut tampered = changes[0].clone();
+        tampered.val = Some(serde_json::json!("b"));
+        assert_eq!(
+            verify_change(&tampered, None),
+            Err(ForgeryError::BadSignature)
+        );
+
+        let mut bumped = changes[0].clone();
+        bumped.col_version += 1;
+        assert_eq!(
+            verify_change(&bumped, None),
+            Err(ForgeryError::BadSignature)
+        );
+
+        // Catch-up bookkeeping isn't signed.
+        let mut restamped = changes[0].clone();
+        restamped.db_version = 0;
+        restamped.seq = 3;
+        assert!(verify_change(&restamped, None).is_ok());
+    }
+
+    #[test]
+    fn test_legacy_site_is_pinned_to_first_key() {
+        let legacy_site = NodeId([4u8; 16]);
+        let owner = ChangeSigner::new(ed25519::Keypair::generate(), legacy_site);
+        let forger = ChangeSigner::new(ed25519::Keypair::generate(), legacy_site);
+
+        let mut ours = vec![change(legacy_site, "a")];
+        owner.sign_own(&mut ours);
+        let pinned = verify_change(&ours[0], None).unwrap().unwrap();
+        assert_eq!(verify_change(&ours[0], Some(&pinned)), Ok(Some(pinned)));
+
+        let mut forged = vec![change(legacy_site, "b")];
+        forger.sign_own(&mut forged);
+        assert_eq!(
+            verify_change(&forged[0], Some(&pinned)),
+            Err(ForgeryError::WrongKey)
+        );
+    }
+
+    #[test]
+    fn test_unsigned_change_rejected_once_site_signs() {
+        let site = NodeId([5u8; 16]);
+        assert_eq!(verify_change(&change(site, "a"), None), Ok(None));
+        assert_eq!(
+            verify_change(&change(site, "a"), Some(&[1u8; 32])),
+            Err(ForgeryError::Unsigned)
+        );
+    }
+
+    #[test]
+    fn test_sign_own_leaves_other_sites_alone() {
+        let signer = bound_signer();
+        let other = NodeId([6u8; 16]);
+        let mut changes = vec![change(signer.site_id, "a"), change(other, "b")];
+        signer.sign_own(&mut changes);
+        assert!(changes[0].sig.is_some());
+        assert!(changes[1].sig.is_none());
+
+        ChangeSigner::sign_for(None, &mut changes);
+        assert!(changes.iter().all(|c| c.sig.is_none()));
+    }
+}
@@ -3,8 +3,23 @@
 //!
 //! This is a parallel, browser-only sibling to [`crate::engine`]. It shares
 //! the same wire format ([`SyncRequest::Push`] / [`SyncResponse::PushAck`],
-//! protocol id `/wavesync/snapshot/3.0.0`, length-prefixed serde_json) so a
-//! browser peer can talk to a native peer without protocol changes.
+//! protocol ids `/wavesync/snapshot/3.1.0` and `/wavesync/snapshot/3.0.0`,
+//! length-prefixed serde_json) so a browser peer can talk to a native peer
+//! without protocol changes.
+//!
+//! ## Transports
+//!
+//! The browser reaches the relay over WebSocket and other peers through
+//! circuits on it. A native peer that listens on `webrtc-direct` (both
+//! sides built with the `webrtc` feature) or WebSocket (see
+//! `WaveSyncDbBuilder::with_webrtc` and `with_websocket_listener` on
+//! native) advertises that address over
+//! identify; the browser then dials it and syncs over the direct
+//! connection. A native WebSocket listener can also be dialed with no
+//! relay at all, through [`WebSyncClient::connect_persistent`]. Browser ↔
+//! browser traffic still goes through the circuit: the websys WebRTC
+//! transport only dials `webrtc-direct`, and has no relay-signalled
+//! browser-to-browser mode.
 //!
 //! ## Two flavours
 //!
@@ -59,31 +74,59 @@ use tokio::sync::{Mutex, Notify, broadcast, mpsc, oneshot, watch};
 
 use crate::auth::GroupKey;
 use crate::conflict;
+use crate::diagnostics::{Counters, Snapshot};
 use crate::messages::{ColumnChange, ColumnName, NodeId, PrimaryKey, SyncChangeset, TableName};
-use crate::protocol::{SyncRequest, SyncResponse};
+use crate::pairing::{
+    Invitation, JOIN_TIMEOUT, Joiner, PAIRING_PROTOCOL, PairingCodec, PairingCredentials,
+    PairingError, PairingInvite, PairingRequest, PairingResponse,
+};
+use crate::protocol::{
+    FEATURE_RECEIVER_MACS, FEATURE_SEALED_PAYLOADS, PeerHello, SyncRequest, SyncResponse,
+};
+use crate::seal::PayloadKey;
 use crate::web_entity::BrowserEntity;
 use crate::web_store::{BrowserStore, ShadowRow};
 
 // Re-use the native engine's snapshot codec — same wire format, same
-// protocol id. Cargo gates engine/* away from wasm32, so we cannot pull
-// the codec from there. The codec is small (~80 LoC) and pure
+// protocol ids. Cargo gates engine/* away from wasm32, so we cannot pull
+// the codec from there. The codec is small (~100 LoC) and pure
 // `futures::AsyncRead/Write`, so we duplicate it here behind the wasm32
-// gate. Keeping the byte-for-byte protocol id `/wavesync/snapshot/3.0.0`
+// gate. Keeping the byte-for-byte protocol ids `/wavesync/snapshot/3.1.0`
+// (compressed, see `crate::compression`) and `/wavesync/snapshot/3.0.0`
 // is what guarantees a browser client can talk to a native peer.
 mod snapshot_codec {
     use std::io;
+    use std::sync::Arc;
 
     use async_trait::async_trait;
     use futures::prelude::*;
     use libp2p::StreamProtocol;
     use libp2p::request_response;
+    use serde::Serialize;
+    use serde::de::DeserializeOwned;
 
+    use crate::compression;
+    use crate::diagnostics::Counters;
     use crate::protocol::{SyncRequest, SyncResponse};
 
     pub const SNAPSHOT_PROTOCOL: StreamProtocol = StreamProtocol::new("/wavesync/snapshot/3.0.0");
+    pub const SNAPSHOT_PROTOCOL_COMPRESSED: StreamProtocol =
+        StreamProtocol::new("/wavesync/snapshot/3.1.0");
 
     #[derive(Debug, Clone, Default)]
-    pub struct SnapshotCodec;
+    pub struct SnapshotCodec {
+        diagnostics: Option<Arc<Counters>>,
+    }
+
+    impl SnapshotCodec {
+        /// Codec that records compression ratios into the client's
+        /// counters, as the native one does.
+        pub fn with_diagnostics(diagnostics: Arc<Counters>) -> Self {
+            Self {
+                diagnostics: Some(diagnostics),
+            }
+        }
+    }
 
     #[async_trait]
     impl request_response::Codec for SnapshotCodec {
@@ -93,55 +136,78 @@ mod snapshot_codec {
 
         async fn read_request<T>(
             &mut self,
-            _p: &Self::Protocol,
+            p: &Self::Protocol,
             io: &mut T,
         ) -> io::Result<Self::Request>
         where
             T: AsyncRead + Unpin + Send,
         {
-            let bytes = read_lp(io).await?;
-            serde_json::from_slice(&bytes)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            read_message(p, io).await
         }
 
         async fn read_response<T>(
             &mut self,
-            _p: &Self::Protocol,
+            p: &Self::Protocol,
             io: &mut T,
         ) -> io::Result<Self::Response>
         where
             T: AsyncRead + Unpin + Send,
         {
-            let bytes = read_lp(io).await?;
-            serde_json::from_slice(&bytes)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            read_message(p, io).await
         }
 
         async fn write_request<T>(
             &mut self,
-            _p: &Self::Protocol,
+            p: &Self::Protocol,
             io: &mut T,
             req: Self::Request,
         ) -> io::Result<()>
         where
             T: AsyncWrite + Unpin + Send,
         {
-            let bytes = serde_json::to_vec(&req)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-            write_lp(io, &bytes).await
+            write_message(p, io, &req, self.diagnostics.as_deref()).await
         }
 
         async fn write_response<T>(
             &mut self,
-            _p: &Self::Protocol,
+            p: &Self::Protocol,
             io: &mut T,
             res: Self::Response,
         ) -> io::Result<()>
         where
             T: AsyncWrite + Unpin + Send,
         {
-            let bytes = serde_json::to_vec(&res)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            write_message(p, io, &res, self.diagnostics.as_deref()).await
+        }
+    }
+
+    async fn read_message<T, M>(p: &StreamProtocol, io: &mut T) -> io::Result<M>
+    where
+        T: AsyncRead + Unpin + Send,
+        M: DeserializeOwned,
+    {
+        let mut bytes = read_lp(io).await?;
+        if *p == SNAPSHOT_PROTOCOL_COMPRESSED {
+            bytes = compression::decode(&bytes)?;
+        }
+        serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    async fn write_message<T, M>(
+        p: &StreamProtocol,
+        io: &mut T,
+        msg: &M,
+        diagnostics: Option<&Counters>,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+        M: Serialize + Sync,
+    {
+        let bytes =
+            serde_json::to_vec(msg).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if *p == SNAPSHOT_PROTOCOL_COMPRESSED {
+            write_lp(io, &compression::encode_recorded(&bytes, diagnostics)).await
+        } else {
             write_lp(io, &bytes).await
         }
     }
@@ -168,7 +234,7 @@ mod snapshot_codec {
     }
 }
 
-use snapshot_codec::{SNAPSHOT_PROTOCOL, SnapshotCodec};
+use snapshot_codec::{SNAPSHOT_PROTOCOL, SNAPSHOT_PROTOCOL_COMPRESSED, SnapshotCodec};
 
 // Inline copy of `engine/push_protocol.rs` — needed here because
 // `engine/*` is gated to `not(target_arch = "wasm32")`. Wire shape MUST
@@ -328,6 +394,8 @@ pub enum WebSyncError {
     Store(String),
     #[error("identity decode failed: {0}")]
     Identity(String),
+    #[error("pairing failed: {0}")]
+    Pairing(PairingError),
 }
 
 #[derive(NetworkBehaviour)]
@@ -337,6 +405,14 @@ struct WebBehaviour {
     relay_client: libp2p::relay::client::Behaviour,
     identify: identify::Behaviour,
     ping: ping::Behaviour,
+    pairing: request_response::Behaviour<PairingCodec>,
+}
+
+/// Behaviour of the short-lived swarm [`WebSyncClient::pair`] runs.
+#[derive(NetworkBehaviour)]
+struct PairBehaviour {
+    relay_client: libp2p::relay::client::Behaviour,
+    pairing: request_response::Behaviour<PairingCodec>,
 }
 
 enum Command {
@@ -352,6 +428,11 @@ enum Command {
         columns: Vec<(String, serde_json::Value)>,
         ack: oneshot::Sender<Result<u64, WebSyncError>>,
     },
+    /// Open a pairing invitation (see [`WebSyncClient::start_pairing`]).
+    StartPairing {
+        ttl: Duration,
+        reply: oneshot::Sender<Result<PairingInvite, WebSyncError>>,
+    },
 }
 
 /// Browser-side sync client.
@@ -373,6 +454,8 @@ pub struct WebSyncClient {
     /// the latest value is what matters; subscribers that fall behind
     /// just get the most recent value next time they read.
     status_rx: watch::Receiver<WebSyncStatus>,
+    /// Counters the swarm task updates; read via [`Self::diagnostics`].
+    diagnostics: Arc<Counters>,
 }
 
 /// Live debug snapshot exposed by [`WebSyncClient::subscribe_status`].
@@ -463,25 +546,7 @@ impl WebSyncClient {
             .await
             .map_err(|e| WebSyncError::Store(e.to_string()))?;
 
-        let keypair = match store
-            .get_keypair()
-            .await
-            .map_err(|e| WebSyncError::Store(e.to_string()))?
-        {
-            Some(bytes) => identity::Keypair::from_protobuf_encoding(&bytes)
-                .map_err(|e| WebSyncError::Identity(e.to_string()))?,
-            None => {
-                let kp = identity::Keypair::generate_ed25519();
-                let bytes = kp
-                    .to_protobuf_encoding()
-                    .map_err(|e| WebSyncError::Identity(e.to_string()))?;
-                store
-                    .put_keypair(&bytes)
-                    .await
-                    .map_err(|e| WebSyncError::Store(e.to_string()))?;
-                kp
-            }
-        };
+        let keypair = restore_keypair(&store).await?;
 
         let site_id = match store
             .get_site_id()
@@ -579,25 +644,7 @@ impl WebSyncClient {
             .await
             .map_err(|e| WebSyncError::Store(e.to_string()))?;
 
-        let keypair = match store
-            .get_keypair()
-            .await
-            .map_err(|e| WebSyncError::Store(e.to_string()))?
-        {
-            Some(bytes) => identity::Keypair::from_protobuf_encoding(&bytes)
-                .map_err(|e| WebSyncError::Identity(e.to_string()))?,
-            None => {
-                let kp = identity::Keypair::generate_ed25519();
-                let bytes = kp
-                    .to_protobuf_encoding()
-                    .map_err(|e| WebSyncError::Identity(e.to_string()))?;
-                store
-                    .put_keypair(&bytes)
-                    .await
-                    .map_err(|e| WebSyncError::Store(e.to_string()))?;
-                kp
-            }
-        };
+        let keypair = restore_keypair(&store).await?;
 
         let site_id = match store
             .get_site_id()
@@ -694,13 +741,11 @@ impl WebSyncClient {
             .map_err(|e: libp2p::multiaddr::Error| WebSyncError::InvalidMultiaddr(e.to_string()))?;
 
         let local_peer_id = keypair.public().to_peer_id();
+        let diagnostics = Arc::new(Counters::default());
 
-        // `with_other_transport`'s closure must return either a bare
-        // Transport or a `Result<T, Box<dyn Error + Send + Sync>>` — the
-        // two `TryIntoTransport` impls in libp2p 0.56. Anything else
-        // (incl. our own error type) compile-fails with a confusing
-        // `Result<...>: Transport not satisfied` error. Box noise errors
-        // explicitly to land in the documented happy path.
+        // WebSocket to the relay, WebRTC (with the `webrtc` feature)
+        // straight to native peers that listen on `webrtc-direct` (see
+        // `browser_transport`).
         // The `.with_relay_client(...)` step after `with_other_transport`
         // wraps our base WebSocket transport with libp2p's circuit-relay
         // client. After that, `swarm.dial(/p2p/<relay>/p2p-circuit/p2p/<peer>)`
@@ -711,24 +756,23 @@ impl WebSyncClient {
         // wired into our `WebBehaviour`.
         let mut swarm = SwarmBuilder::with_existing_identity(keypair)
             .with_wasm_bindgen()
-            .with_other_transport(|key| {
-                use libp2p::Transport;
-                use libp2p::core::upgrade::Version;
-                let ws = libp2p::websocket_websys::Transport::default();
-                let auth = noise::Config::new(key)
-                    .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
-                Ok::<_, Box<dyn std::error::Error + Send + Sync>>(
-                    ws.upgrade(Version::V1)
-                        .authenticate(auth)
-                        .multiplex(yamux::Config::default()),
-                )
-            })
+            .with_other_transport(browser_transport)
             .map_err(|e| WebSyncError::Setup(format!("transport: {e}")))?
             .with_relay_client(noise::Config::new, yamux::Config::default)
             .map_err(|e| WebSyncError::Setup(format!("relay client: {e}")))?
             .with_behaviour(|key, relay_client| WebBehaviour {
-                snapshot: request_response::Behaviour::new(
-                    [(SNAPSHOT_PROTOCOL, request_response::ProtocolSupport::Full)],
+                snapshot: request_response::Behaviour::with_codec(
+                    SnapshotCodec::with_diagnostics(Arc::clone(&diagnostics)),
+                    // Compressed framing first, legacy second — same
+                    // order as the native swarm so browser ↔ native
+                    // settles on 3.1.0 when both sides support it.
+                    [
+                        (
+                            SNAPSHOT_PROTOCOL_COMPRESSED,
+                            request_response::ProtocolSupport::Full,
+                        ),
+                        (SNAPSHOT_PROTOCOL, request_response::ProtocolSupport::Full),
+                    ],
                     // Match the native side (engine/behaviour.rs): a
                     // catch-up `ChangesetResponse` against a peer with
                     // months of history can take well over the default
@@ -748,6 +792,10 @@ impl WebSyncClient {
                     key.public(),
                 )),
                 ping: ping::Behaviour::default(),
+                pairing: request_response::Behaviour::new(
+                    [(PAIRING_PROTOCOL, request_response::ProtocolSupport::Full)],
+                    request_response::Config::default(),
+                ),
             })
             .map_err(|e| WebSyncError::Setup(format!("behaviour: {e}")))?
             // Default in libp2p 0.55+ is 10 seconds — far too short for a
@@ -794,8 +842,15 @@ impl WebSyncClient {
             inbound_tx: inbound_tx.clone(),
             resolved_tx: resolved_tx.clone(),
             relay_peer_id,
+            relay_addr: relay_peer_id.map(|_| target.clone()),
+            user_topic: user_topic.to_string(),
+            passphrase: passphrase.map(str::to_string),
+            pairing: Mutex::new(None),
             cached_peer_addrs,
             status_tx,
+            sealing_peers: Mutex::new(HashSet::new()),
+            receiver_mac_peers: Mutex::new(HashSet::new()),
+            direct_dialed: Mutex::new(HashSet::new()),
         };
 
         wasm_bindgen_futures::spawn_local(run_swarm(swarm, cmd_rx, state));
@@ -806,6 +861,7 @@ impl WebSyncClient {
             resolved_tx,
             store,
             status_rx,
+            diagnostics,
         })
     }
 
@@ -906,6 +962,12 @@ impl WebSyncClient {
         self.store.clone()
     }
 
+    /// Snapshot the client's diagnostics counters. Only the compression
+    /// counters are kept in the browser; the rest stay at zero.
+    pub fn diagnostics(&self) -> Snapshot {
+        self.diagnostics.snapshot()
+    }
+
     /// Connect via an in-process loopback channel. **Demo/test path only.**
     ///
     /// Two clients constructed with [`LoopbackPair::new`] and crossed
@@ -1008,8 +1070,15 @@ impl WebSyncClient {
             inbound_tx: inbound_tx.clone(),
             resolved_tx: resolved_tx.clone(),
             relay_peer_id: None, // loopback transport has no notion of a relay
+            relay_addr: None,
+            user_topic: user_topic.to_string(),
+            passphrase: passphrase.map(str::to_string),
+            pairing: Mutex::new(None),
             cached_peer_addrs: Vec::new(),
             status_tx,
+            sealing_peers: Mutex::new(HashSet::new()),
+            receiver_mac_peers: Mutex::new(HashSet::new()),
+            direct_dialed: Mutex::new(HashSet::new()),
         };
 
         wasm_bindgen_futures::spawn_local(run_loopback(end, cmd_rx, state));
@@ -1020,6 +1089,7 @@ impl WebSyncClient {
             resolved_tx,
             store: Some(store_arc),
             status_rx,
+            diagnostics: Arc::new(Counters::default()),
         })
     }
 
@@ -1031,6 +1101,94 @@ impl WebSyncClient {
     pub fn subscribe_status(&self) -> watch::Receiver<WebSyncStatus> {
         self.status_rx.clone()
     }
+
+    /// Invite a new device into the group (see [`crate::pairing`]).
+    ///
+    /// A browser has no address of its own, so this needs a client built
+    /// with [`Self::connect_via_relay`]: the invite points at a circuit
+    /// through that relay, reserved on the first call. The code works once
+    /// and expires after `ttl`; a new invite replaces an open one.
+    pub async fn start_pairing(&self, ttl: Duration) -> Result<PairingInvite, WebSyncError> {
+        let (reply, rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::StartPairing { ttl, reply })
+            .map_err(|_| WebSyncError::NotRunning)?;
+        rx.await.map_err(|_| WebSyncError::NotRunning)?
+    }
+
+    /// Join a group by pairing with the device that showed `invite`,
+    /// reached through the relay at `relay_addr` (see [`crate::pairing`]).
+    ///
+    /// Runs under the identity persisted in the `store_name` IndexedDB
+    /// store — the one [`Self::connect_via_relay`] will present — and saves
+    /// the received credentials there, readable again through
+    /// [`BrowserStore::get_pairing_credentials`]. Connect with them
+    /// afterwards.
+    pub async fn pair(
+        relay_addr: &str,
+        invite: &PairingInvite,
+        store_name: &str,
+    ) -> Result<PairingCredentials, WebSyncError> {
+        let relay: Multiaddr = relay_addr
+            .parse()
+            .map_err(|e: libp2p::multiaddr::Error| WebSyncError::InvalidMultiaddr(e.to_string()))?;
+        let inviter: LibPeerId = invite.peer_id.parse().map_err(|e| {
+            WebSyncError::Pairing(PairingError::InvalidInvite(format!("bad peer id: {e}")))
+        })?;
+
+        let store = BrowserStore::open(store_name)
+            .await
+            .map_err(|e| WebSyncError::Store(e.to_string()))?;
+        let keypair = restore_keypair(&store).await?;
+
+        let mut swarm = SwarmBuilder::with_existing_identity(keypair)
+            .with_wasm_bindgen()
+            .with_other_transport(browser_transport)
+            .map_err(|e| WebSyncError::Setup(format!("transport: {e}")))?
+            .with_relay_client(noise::Config::new, yamux::Config::default)
+            .map_err(|e| WebSyncError::Setup(format!("relay client: {e}")))?
+            .with_behaviour(|_, relay_client| PairBehaviour {
+                relay_client,
+                pairing: request_response::Behaviour::new(
+                    [(PAIRING_PROTOCOL, request_response::ProtocolSupport::Full)],
+                    request_response::Config::default().with_request_timeout(JOIN_TIMEOUT),
+                ),
+            })
+            .map_err(|e| WebSyncError::Setup(format!("behaviour: {e}")))?
+            .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(30)))
+            .build();
+
+        // The circuit through our relay first; whatever else the invite
+        // lists is tried alongside, for inviters this transport can reach.
+        swarm.add_peer_address(inviter, circuit_addr(&relay, inviter));
+        for addr in invite.addrs.iter().filter_map(|a| a.parse().ok()) {
+            swarm.add_peer_address(inviter, addr);
+        }
+
+        let exchange = run_pairing(&mut swarm, inviter, &invite.code);
+        let timeout = gloo_timers::future::sleep(JOIN_TIMEOUT);
+        futures::pin_mut!(exchange, timeout);
+        let credentials = match futures::future::select(exchange, timeout).await {
+            futures::future::Either::Left((result, _)) => result.map_err(WebSyncError::Pairing)?,
+            futures::future::Either::Right(_) => {
+                return Err(WebSyncError::Pairing(PairingError::Timeout));
+            }
+        };
+        // Browser clients only derive v1 keys so far (see `crate::auth`).
+        if credentials.group_salt.is_some() {
+            return Err(WebSyncError::Pairing(PairingError::Protocol(
+                "the group key is derived with Argon2id, which browser clients can't join yet"
+                    .to_string(),
+            )));
+        }
+
+        store
+            .put_pairing_credentials(&credentials)
+            .await
+            .map_err(|e| WebSyncError::Store(e.to_string()))?;
+        log::info!("WebSyncClient: paired with {inviter}");
+        Ok(credentials)
+    }
 }
 
 /// One end of an in-process loopback transport. See
@@ -1148,10 +1306,29 @@ struct EngineState {
     /// `connect_loopback` clients that aren't using relay-mediated
     /// discovery.
     relay_peer_id: Option<LibPeerId>,
+    /// Multiaddr of that relay, which a pairing invitation is reached
+    /// through: a browser has no address of its own.
+    relay_addr: Option<Multiaddr>,
+    /// The topic and passphrase the app configured, handed to a device
+    /// this one pairs (see [`crate::pairing`]).
+    user_topic: String,
+    passphrase: Option<String>,
+    /// The open pairing invitation, if any.
+    pairing: Mutex<Option<Invitation>>,
     /// Watch channel sender for live debug status. Engine pushes a
     /// fresh `WebSyncStatus` after every connection lifecycle event;
     /// UIs read it via [`WebSyncClient::subscribe_status`].
     status_tx: watch::Sender<WebSyncStatus>,
+    /// Peers whose hello asked for sealed changes (see [`crate::seal`]).
+    /// Filled from the hello requests native peers send on connect.
+    sealing_peers: Mutex<HashSet<LibPeerId>>,
+    /// Peers whose hello says they accept MACs bound to their PeerId. The
+    /// rest are builds that predate them and get the plain group key.
+    receiver_mac_peers: Mutex<HashSet<LibPeerId>>,
+    /// Peers we're connected to, or have dialed, other than through a
+    /// circuit, so identify doesn't dial them again. Cleared when the
+    /// peer is gone.
+    direct_dialed: Mutex<HashSet<LibPeerId>>,
 }
 
 /// Recompute the watch-channel snapshot from the current connected
@@ -1254,7 +1431,10 @@ async fn run_loopback(
             cmd = cmd_rx.recv() => {
                 match cmd {
                     Some(Command::Publish(changeset)) => {
-                        let req = build_push_request(&state.topic, state.group_key.as_ref(), changeset);
+                        let req = seal_for_loopback(
+                            &state,
+                            build_push_request(&state.topic, state.group_key.as_ref(), changeset),
+                        );
                         if link.is_online() {
                             let _ = end.out_tx.send(req);
                         } else {
@@ -1274,6 +1454,11 @@ async fn run_loopback(
                         .await;
                         let _ = ack.send(result);
                     }
+                    Some(Command::StartPairing { reply, .. }) => {
+                        let _ = reply.send(Err(WebSyncError::Pairing(PairingError::Unreachable(
+                            "a loopback client has no network to pair over".to_string(),
+                        ))));
+                    }
                     None => {
                         log::info!("WebSyncClient (loopback): command channel closed");
                         return;
@@ -1330,6 +1515,10 @@ async fn send_version_vector(state: &EngineState, out_tx: &mpsc::UnboundedSender
         your_last_db_version: last_seen,
         site_id: state.site_id,
         topic: state.topic.clone(),
+        page_size: None,
+        cursor: None,
+        origin_versions: None,
+        seq: None,
         hmac: None,
     };
     if let Some(gk) = &state.group_key {
@@ -1391,9 +1580,13 @@ async fn send_version_vector_swarm(
         your_last_db_version: last_seen,
         site_id: state.site_id,
         topic: state.topic.clone(),
+        page_size: None,
+        cursor: None,
+        origin_versions: None,
+        seq: None,
         hmac: None,
     };
-    if let Some(gk) = &state.group_key {
+    if let Some(gk) = key_for_peer(state, &peer).await {
         if let Ok(bytes) = serde_json::to_vec(&req) {
             let tag = gk.mac(&bytes);
             if let SyncRequest::VersionVector { ref mut hmac, .. } = req {
@@ -1471,6 +1664,7 @@ async fn handle_submit_local_loopback(
             cl: next_cl,
             seq: seq as u32,
             db_version: new_db_version,
+            sig: None,
         });
     }
 
@@ -1490,7 +1684,10 @@ async fn handle_submit_local_loopback(
         changes,
     };
 
-    let req = build_push_request(&state.topic, state.group_key.as_ref(), changeset);
+    let req = seal_for_loopback(
+        state,
+        build_push_request(&state.topic, state.group_key.as_ref(), changeset),
+    );
     if link.is_online() {
         let _ = end.out_tx.send(req);
     } else {
@@ -1503,6 +1700,29 @@ async fn handle_submit_local_loopback(
     Ok(new_db_version)
 }
 
+/// The capability hello the browser client answers with: same protocol
+/// and codecs as native, none of the optional sync features, and MACs
+/// bound to the receiver. With a passphrase it asks for sealed changes.
+fn browser_hello(sealing: bool) -> PeerHello {
+    PeerHello {
+        protocol_version: crate::protocol::PROTOCOL_VERSION,
+        min_protocol_version: crate::protocol::MIN_PROTOCOL_VERSION,
+        codecs: vec!["json".to_string()],
+        compression: vec!["zstd".to_string()],
+        schema_hashes: Default::default(),
+        app_version: None,
+        features: if sealing {
+            vec![
+                FEATURE_SEALED_PAYLOADS.to_string(),
+                FEATURE_RECEIVER_MACS.to_string(),
+            ]
+        } else {
+            vec![FEATURE_RECEIVER_MACS.to_string()]
+        },
+        session_nonce: None,
+    }
+}
+
 /// Loopback equivalent of [`handle_snapshot_event`] for incoming
 /// `SyncRequest`s.
 ///
@@ -1513,14 +1733,22 @@ async fn handle_submit_local_loopback(
 /// is real-time fan-out or catch-up reply, but it doesn't need to: the
 /// merge logic is identical either way, courtesy of CRDT.
 async fn handle_loopback_request(
-    req: SyncRequest,
+    mut req: SyncRequest,
     state: &EngineState,
     out_tx: &mpsc::UnboundedSender<SyncRequest>,
 ) {
+    if let Err(e) = req.open_changes(payload_key(state).as_ref()) {
+        log::debug!("loopback: dropping request with unopenable changes — {e}");
+        return;
+    }
     match req {
         SyncRequest::Push {
             changeset,
             topic,
+            hops,
+            prev_db_version,
+            seq,
+            sealed: _,
             hmac,
         } => {
             if topic != state.topic {
@@ -1531,6 +1759,10 @@ async fn handle_loopback_request(
                 let verify = SyncRequest::Push {
                     changeset: changeset.clone(),
                     topic: topic.clone(),
+                    hops,
+                    prev_db_version,
+                    seq,
+                    sealed: None,
                     hmac: None,
                 };
                 let bytes = match serde_json::to_vec(&verify) {
@@ -1557,6 +1789,10 @@ async fn handle_loopback_request(
             your_last_db_version: since,
             site_id: peer_site,
             topic,
+            page_size,
+            cursor,
+            origin_versions,
+            seq,
             hmac,
         } => {
             if topic != state.topic {
@@ -1569,6 +1805,10 @@ async fn handle_loopback_request(
                     your_last_db_version: since,
                     site_id: peer_site,
                     topic: topic.clone(),
+                    page_size,
+                    cursor,
+                    origin_versions,
+                    seq,
                     hmac: None,
                 };
                 let bytes = match serde_json::to_vec(&verify) {
@@ -1611,15 +1851,37 @@ async fn handle_loopback_request(
                     db_version: my_db_version,
                     changes,
                 };
-                let push = build_push_request(&state.topic, state.group_key.as_ref(), changeset);
+                let push = seal_for_loopback(
+                    state,
+                    build_push_request(&state.topic, state.group_key.as_ref(), changeset),
+                );
                 let _ = out_tx.send(push);
             }
         }
+        SyncRequest::Hello { .. } => {
+            // Loopback peers are always the same build; nothing to negotiate.
+        }
+        SyncRequest::AntiEntropy { .. } => {
+            // Only sent to peers whose hello announced anti-entropy.
+        }
+        SyncRequest::StateSnapshot { .. } => {
+            // Loopback peers catch up through the VersionVector → Push
+            // path above and never ask for snapshots.
+        }
         SyncRequest::IdentityAnnounce { .. } => {
             // Identity announce is a presence signal in the native
             // engine; loopback has no concept of presence beyond the
             // online flag, so we ignore.
         }
+        SyncRequest::KeyRotation { .. } => {
+            // Only sent to peers whose hello announced key rotation.
+        }
+        SyncRequest::Revocations { .. } => {
+            // Only sent to peers whose hello announced revocations.
+        }
+        SyncRequest::Capabilities { .. } => {
+            // Only sent to peers whose hello announced capabilities.
+        }
     }
 }
 
@@ -1733,6 +1995,9 @@ async fn run_swarm(
     // dials would otherwise fail-fast and bump fail_count for working
     // entries.
     let mut cached_predial_done = false;
+    // Whether we asked the relay for a circuit reservation. Only done
+    // once a pairing invitation needs us to be reachable.
+    let mut circuit_listening = false;
 
     loop {
         for peer in pending_announces.drain(..) {
@@ -1753,15 +2018,24 @@ async fn run_swarm(
             cmd = cmd_rx.recv() => {
                 match cmd {
                     Some(Command::Publish(changeset)) => {
-                        let req = build_push_request(&state.topic, state.group_key.as_ref(), changeset);
                         for peer in connected.iter().copied().collect::<Vec<_>>() {
-                            swarm.behaviour_mut().snapshot.send_request(&peer, req.clone());
+                            let req = build_push_request(
+                                &state.topic,
+                                key_for_peer(&state, &peer).await.as_ref(),
+                                changeset.clone(),
+                            );
+                            let req = seal_for_peer(&state, &peer, req).await;
+                            swarm.behaviour_mut().snapshot.send_request(&peer, req);
                         }
                     }
                     Some(Command::SubmitLocal { table, pk, columns, ack }) => {
                         let result = handle_submit_local(&state, &mut swarm, &connected, table, pk, columns).await;
                         let _ = ack.send(result);
                     }
+                    Some(Command::StartPairing { ttl, reply }) => {
+                        let result = start_pairing(&state, &mut swarm, &mut circuit_listening, ttl).await;
+                        let _ = reply.send(result);
+                    }
                     None => {
                         log::info!("WebSyncClient: command channel closed, exiting");
                         return;
@@ -1886,6 +2160,7 @@ async fn handle_submit_local(
             cl: next_cl,
             seq: seq as u32,
             db_version: new_db_version,
+            sig: None,
         });
     }
 
@@ -1902,7 +2177,6 @@ async fn handle_submit_local(
         changes,
     };
 
-    let req = build_push_request(&state.topic, state.group_key.as_ref(), changeset);
     let peers: Vec<LibPeerId> = connected.iter().copied().collect();
     log::info!(
         "WebSyncClient: pushing changeset (db_v={new_db_version}) to {} peer(s): {:?}",
@@ -1910,10 +2184,13 @@ async fn handle_submit_local(
         peers.iter().map(|p| p.to_string()).collect::<Vec<_>>()
     );
     for peer in &peers {
-        let id = swarm
-            .behaviour_mut()
-            .snapshot
-            .send_request(peer, req.clone());
+        let req = build_push_request(
+            &state.topic,
+            key_for_peer(state, peer).await.as_ref(),
+            changeset.clone(),
+        );
+        let req = seal_for_peer(state, peer, req).await;
+        let id = swarm.behaviour_mut().snapshot.send_request(peer, req);
         log::debug!("WebSyncClient: send_request → peer {peer} req_id={id:?}");
     }
     Ok(new_db_version)
@@ -1958,7 +2235,16 @@ async fn handle_event(
                 *relay_connected = true;
                 pending_announces.push(peer_id);
             } else {
-                connected.insert(peer_id);
+                // A second connection — the direct one once the circuit
+                // is up — needs no second catch-up.
+                let first = connected.insert(peer_id);
+                let circuit = endpoint
+                    .get_remote_address()
+                    .iter()
+                    .any(|p| matches!(p, libp2p::multiaddr::Protocol::P2pCircuit));
+                if !circuit {
+                    state.direct_dialed.lock().await.insert(peer_id);
+                }
                 log::info!(
                     "WebSyncClient: connected to peer {peer_id} (connected count={})",
                     connected.len()
@@ -1994,16 +2280,28 @@ async fn handle_event(
                 // path. Without this trigger every browser tab would
                 // permanently miss any history the peer already had
                 // — see issue #57.
-                pending_version_vectors.push(peer_id);
+                if first {
+                    pending_version_vectors.push(peer_id);
+                }
             }
             push_status(state, connected, *relay_connected);
         }
-        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+        SwarmEvent::ConnectionClosed {
+            peer_id,
+            cause,
+            num_established,
+            ..
+        } => {
             if Some(peer_id) == state.relay_peer_id {
                 log::info!("WebSyncClient: disconnected from relay {peer_id} (cause={cause:?})");
                 *relay_connected = false;
+            } else if num_established > 0 {
+                // The circuit closed, the direct connection lives on
+                // (or the other way round).
+                log::debug!("WebSyncClient: one connection to {peer_id} closed (cause={cause:?})");
             } else {
                 connected.remove(&peer_id);
+                state.direct_dialed.lock().await.remove(&peer_id);
                 log::info!(
                     "WebSyncClient: disconnected from peer {peer_id} (connected count={}, cause={cause:?})",
                     connected.len()
@@ -2038,12 +2336,55 @@ async fn handle_event(
             handle_push_event(ev, state, swarm);
         }
         SwarmEvent::Behaviour(WebBehaviourEvent::Ping(_)) => {}
+        SwarmEvent::Behaviour(WebBehaviourEvent::Identify(identify::Event::Received {
+            peer_id,
+            info,
+            ..
+        })) => {
+            dial_direct(peer_id, &info.listen_addrs, state, swarm).await;
+        }
         SwarmEvent::Behaviour(WebBehaviourEvent::Identify(_)) => {}
         SwarmEvent::Behaviour(WebBehaviourEvent::RelayClient(_)) => {}
+        SwarmEvent::Behaviour(WebBehaviourEvent::Pairing(ev)) => {
+            handle_pairing_event(ev, state, swarm).await;
+        }
         _ => {}
     }
 }
 
+/// Dial `peer` over `webrtc-direct` or WebSocket if it listens there, so
+/// the sync traffic stops going through the relay. The circuit stays open until
+/// the relay's limits close it; both connections carry the same peer.
+async fn dial_direct(
+    peer: LibPeerId,
+    listen_addrs: &[Multiaddr],
+    state: &EngineState,
+    swarm: &mut Swarm<WebBehaviour>,
+) {
+    use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
+
+    if Some(peer) == state.relay_peer_id {
+        return;
+    }
+    let addrs = direct_addrs(peer, listen_addrs);
+    if addrs.is_empty() || !state.direct_dialed.lock().await.insert(peer) {
+        return;
+    }
+    log::info!(
+        "WebSyncClient: dialing {peer} directly ({} address(es))",
+        addrs.len()
+    );
+    // `Always`: we're already connected through the circuit, which the
+    // default condition would take as reason not to dial.
+    let opts = DialOpts::peer_id(peer)
+        .addresses(addrs)
+        .condition(PeerCondition::Always)
+        .build();
+    if let Err(e) = swarm.dial(opts) {
+        log::debug!("WebSyncClient: direct dial to {peer} failed: {e}");
+    }
+}
+
 /// Handle inbound and outbound `push` request_response events.
 ///
 /// Two cases that matter:
@@ -2265,12 +2606,259 @@ fn peer_id_from_multiaddr(addr: &Multiaddr) -> Option<LibPeerId> {
     last
 }
 
+/// Restore the identity persisted in `store`, creating it on first use.
+async fn restore_keypair(store: &BrowserStore) -> Result<identity::Keypair, WebSyncError> {
+    match store
+        .get_keypair()
+        .await
+        .map_err(|e| WebSyncError::Store(e.to_string()))?
+    {
+        Some(bytes) => identity::Keypair::from_protobuf_encoding(&bytes)
+            .map_err(|e| WebSyncError::Identity(e.to_string())),
+        None => {
+            let kp = identity::Keypair::generate_ed25519();
+            let bytes = kp
+                .to_protobuf_encoding()
+                .map_err(|e| WebSyncError::Identity(e.to_string()))?;
+            store
+                .put_keypair(&bytes)
+                .await
+                .map_err(|e| WebSyncError::Store(e.to_string()))?;
+            Ok(kp)
+        }
+    }
+}
+
+/// The browser's base transport: WebSocket (noise + yamux) for the
+/// relay, and with the `webrtc` feature WebRTC for `webrtc-direct`
+/// addresses of native peers, which secures and multiplexes on its own.
+///
+/// `with_other_transport` takes either a bare Transport or a
+/// `Result<T, Box<dyn Error + Send + Sync>>` — the two `TryIntoTransport`
+/// impls in libp2p 0.56. Anything else (incl. our own error type)
+/// compile-fails with a confusing `Result<...>: Transport not satisfied`
+/// error, hence the boxed error here.
+fn browser_transport(
+    key: &identity::Keypair,
+) -> Result<
+    libp2p::core::transport::Boxed<(LibPeerId, libp2p::core::muxing::StreamMuxerBox)>,
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    use libp2p::Transport;
+    use libp2p::core::muxing::StreamMuxerBox;
+    use libp2p::core::upgrade::Version;
+
+    let transport = libp2p::websocket_websys::Transport::default()
+        .upgrade(Version::V1)
+        .authenticate(noise::Config::new(key)?)
+        .multiplex(yamux::Config::default())
+        .map(|(peer, conn), _| (peer, StreamMuxerBox::new(conn)))
+        .boxed();
+    #[cfg(feature = "webrtc")]
+    let transport = libp2p::webrtc_websys::Transport::new(libp2p::webrtc_websys::Config::new(key))
+        .map(|(peer, conn), _| (peer, StreamMuxerBox::new(conn)))
+        .or_transport(transport)
+        .map(|either, _| either.into_inner())
+        .boxed();
+    Ok(transport)
+}
+
+/// The `webrtc-direct` (with the `webrtc` feature) and WebSocket addresses
+/// among those a peer told us it listens on, ready to dial: not through a
+/// circuit, not a wildcard.
+fn direct_addrs(peer: LibPeerId, listen_addrs: &[Multiaddr]) -> Vec<Multiaddr> {
+    use libp2p::multiaddr::Protocol;
+
+    listen_addrs
+        .iter()
+        .filter(|addr| {
+            let mut direct = false;
+            for p in addr.iter() {
+                match p {
+                    Protocol::WebRTCDirect if cfg!(feature = "webrtc") => direct = true,
+                    Protocol::Ws(_) | Protocol::Wss(_) => direct = true,
+                    Protocol::P2pCircuit => return false,
+                    Protocol::Ip4(ip) if ip.is_unspecified() => return false,
+                    Protocol::Ip6(ip) if ip.is_unspecified() => return false,
+                    _ => {}
+                }
+            }
+            direct
+        })
+        .map(|addr| {
+            if peer_id_from_multiaddr(addr).is_some() {
+                addr.clone()
+            } else {
+                addr.clone().with(Protocol::P2p(peer))
+            }
+        })
+        .collect()
+}
+
+/// `peer`'s address through a circuit on `relay`.
+fn circuit_addr(relay: &Multiaddr, peer: LibPeerId) -> Multiaddr {
+    relay
+        .clone()
+        .with(libp2p::multiaddr::Protocol::P2pCircuit)
+        .with(libp2p::multiaddr::Protocol::P2p(peer))
+}
+
+fn unix_now_secs() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+/// Open a pairing invitation reached through a circuit on our relay,
+/// reserving the circuit the first time.
+async fn start_pairing(
+    state: &EngineState,
+    swarm: &mut Swarm<WebBehaviour>,
+    circuit_listening: &mut bool,
+    ttl: Duration,
+) -> Result<PairingInvite, WebSyncError> {
+    let Some(relay) = state.relay_addr.as_ref() else {
+        return Err(WebSyncError::Pairing(PairingError::Unreachable(
+            "a browser can only be reached through a relay; connect with `connect_via_relay`"
+                .to_string(),
+        )));
+    };
+    if !*circuit_listening {
+        swarm
+            .listen_on(relay.clone().with(libp2p::multiaddr::Protocol::P2pCircuit))
+            .map_err(|e| WebSyncError::Setup(format!("relay circuit: {e}")))?;
+        *circuit_listening = true;
+    }
+
+    let local = *swarm.local_peer_id();
+    let code = crate::pairing::generate_code();
+    *state.pairing.lock().await = Some(Invitation::new(
+        code.clone(),
+        unix_now_secs() + ttl.as_secs(),
+    ));
+    log::info!(
+        "WebSyncClient: opened a pairing invitation for {}s",
+        ttl.as_secs()
+    );
+    Ok(PairingInvite {
+        peer_id: local.to_string(),
+        addrs: vec![circuit_addr(relay, local).to_string()],
+        code,
+    })
+}
+
+/// Answer a joining device under the open pairing invitation.
+async fn handle_pairing_event(
+    event: request_response::Event<PairingRequest, PairingResponse>,
+    state: &EngineState,
+    swarm: &mut Swarm<WebBehaviour>,
+) {
+    let request_response::Event::Message {
+        peer,
+        message: request_response::Message::Request {
+            request, channel, ..
+        },
+        ..
+    } = event
+    else {
+        return;
+    };
+
+    let local = *swarm.local_peer_id();
+    let credentials = PairingCredentials {
+        topic: state.user_topic.clone(),
+        passphrase: state.passphrase.clone(),
+        group_salt: None,
+        relay_server: state.relay_addr.as_ref().map(ToString::to_string),
+        rendezvous_server: None,
+        bootstrap_peers: state
+            .relay_addr
+            .iter()
+            .map(|relay| circuit_addr(relay, local).to_string())
+            .collect(),
+    };
+    let (response, paired) = crate::pairing::answer(
+        &mut *state.pairing.lock().await,
+        unix_now_secs(),
+        peer,
+        request,
+        &credentials,
+    );
+    if paired {
+        log::info!("WebSyncClient: paired device {peer}");
+    } else if let PairingResponse::Refused { ref reason } = response {
+        log::warn!("WebSyncClient: refused pairing with {peer}: {reason}");
+    }
+    let _ = swarm
+        .behaviour_mut()
+        .pairing
+        .send_response(channel, response);
+}
+
+/// Run the joining side of a pairing exchange with `inviter`.
+async fn run_pairing(
+    swarm: &mut Swarm<PairBehaviour>,
+    inviter: LibPeerId,
+    code: &str,
+) -> Result<PairingCredentials, PairingError> {
+    let (mut joiner, start) = Joiner::start(code);
+    swarm.behaviour_mut().pairing.send_request(&inviter, start);
+
+    loop {
+        let event = match swarm.select_next_some().await {
+            SwarmEvent::Behaviour(PairBehaviourEvent::Pairing(event)) => event,
+            _ => continue,
+        };
+        match event {
+            request_response::Event::Message {
+                peer,
+                message: request_response::Message::Response { response, .. },
+                ..
+            } if peer == inviter => match response {
+                PairingResponse::Started { spake, confirm } => {
+                    let finish = joiner.confirm(&spake, &confirm)?;
+                    swarm.behaviour_mut().pairing.send_request(&inviter, finish);
+                }
+                PairingResponse::Credentials { nonce, sealed } => {
+                    return joiner.open(&nonce, &sealed);
+                }
+                PairingResponse::Refused { reason } => return Err(PairingError::Refused(reason)),
+            },
+            request_response::Event::OutboundFailure { error, .. } => {
+                return Err(match error {
+                    request_response::OutboundFailure::DialFailure => {
+                        PairingError::Unreachable("no address of the invite answered".to_string())
+                    }
+                    request_response::OutboundFailure::Timeout => PairingError::Timeout,
+                    other => PairingError::Protocol(other.to_string()),
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
 async fn handle_snapshot_event(
-    event: request_response::Event<SyncRequest, SyncResponse>,
+    mut event: request_response::Event<SyncRequest, SyncResponse>,
     state: &EngineState,
     swarm: &mut Swarm<WebBehaviour>,
 ) {
     use request_response::{Event, Message};
+    // Open sealed changes before any arm checks an HMAC — the sender
+    // computed it over the plaintext.
+    let opened = match &mut event {
+        Event::Message {
+            message: Message::Request { request, .. },
+            ..
+        } => request.open_changes(payload_key(state).as_ref()),
+        Event::Message {
+            message: Message::Response { response, .. },
+            ..
+        } => response.open_changes(payload_key(state).as_ref()),
+        _ => Ok(()),
+    };
+    if let Err(e) = opened {
+        log::debug!("WebSyncClient: dropping sync message with unopenable changes — {e}");
+        return;
+    }
     match event {
         Event::Message {
             peer,
@@ -2282,6 +2870,10 @@ async fn handle_snapshot_event(
             SyncRequest::Push {
                 changeset,
                 topic,
+                hops,
+                prev_db_version,
+                seq,
+                sealed: _,
                 hmac,
             } => {
                 if topic != state.topic {
@@ -2292,6 +2884,10 @@ async fn handle_snapshot_event(
                     let verify = SyncRequest::Push {
                         changeset: changeset.clone(),
                         topic: topic.clone(),
+                        hops,
+                        prev_db_version,
+                        seq,
+                        sealed: None,
                         hmac: None,
                     };
                     let bytes = match serde_json::to_vec(&verify) {
@@ -2305,7 +2901,7 @@ async fn handle_snapshot_event(
                             return;
                         }
                     };
-                    if !gk.verify(&bytes, &tag) {
+                    if !accepts_tag(gk, swarm, &bytes, &tag) {
                         log::debug!("WebSyncClient: dropping Push from {peer} — bad HMAC");
                         return;
                     }
@@ -2325,11 +2921,20 @@ async fn handle_snapshot_event(
             // loopback handler's logic — same store, same query — just
             // routed through libp2p's request_response instead of
             // re-using the Push channel.
+            // Paging (`page_size` / `cursor`) is not implemented on the
+            // browser side: the whole history goes back in one response
+            // with no `next_cursor`, which a paging requester treats as
+            // the last page. Nor are origin watermarks: the browser never
+            // announces them, so `origin_versions` is never set here.
             SyncRequest::VersionVector {
                 my_db_version: peer_db_version,
                 your_last_db_version: since,
                 site_id: peer_site,
                 topic: req_topic,
+                page_size,
+                cursor,
+                origin_versions,
+                seq,
                 hmac,
             } => {
                 if req_topic != state.topic {
@@ -2344,6 +2949,10 @@ async fn handle_snapshot_event(
                         your_last_db_version: since,
                         site_id: peer_site,
                         topic: req_topic.clone(),
+                        page_size,
+                        cursor,
+                        origin_versions,
+                        seq,
                         hmac: None,
                     };
                     let bytes = match serde_json::to_vec(&verify) {
@@ -2359,7 +2968,7 @@ async fn handle_snapshot_event(
                             return;
                         }
                     };
-                    if !gk.verify(&bytes, &tag) {
+                    if !accepts_tag(gk, swarm, &bytes, &tag) {
                         log::debug!("WebSyncClient: dropping VersionVector from {peer} — bad HMAC");
                         return;
                     }
@@ -2382,6 +2991,9 @@ async fn handle_snapshot_event(
                     your_last_db_version: peer_db_version,
                     site_id: state.site_id,
                     topic: state.topic.clone(),
+                    next_cursor: None,
+                    origin_versions: None,
+                    sealed: None,
                     hmac: None,
                 };
                 if let Some(gk) = &state.group_key {
@@ -2399,25 +3011,94 @@ async fn handle_snapshot_event(
                             your_last_db_version: *your_last_db_version,
                             site_id: *site_id,
                             topic: topic.clone(),
+                            next_cursor: None,
+                            origin_versions: None,
+                            sealed: None,
                             hmac: None,
                         },
                         _ => resp.clone(),
                     };
-                    if let Ok(bytes) = serde_json::to_vec(&unsigned) {
-                        let tag = gk.mac(&bytes);
+                    if let Ok(bytes) = serde_json::to_vec(&unsigned)
+                        && let Some(key) = key_for_peer(state, &peer).await
+                    {
+                        let tag = key.mac(&bytes);
                         if let SyncResponse::ChangesetResponse { ref mut hmac, .. } = resp {
                             *hmac = Some(tag);
                         }
                     }
+                    if state.sealing_peers.lock().await.contains(&peer) {
+                        resp.seal_changes(&gk.payload_key());
+                    }
                 }
                 let _ = swarm.behaviour_mut().snapshot.send_response(channel, resp);
             }
+            SyncRequest::Hello { hello, topic, .. } => {
+                if topic != state.topic {
+                    log::debug!("WebSyncClient: dropping Hello from {peer} — topic mismatch");
+                    return;
+                }
+                // Answer so native peers learn what we don't serve (paged
+                // catch-up, snapshots) instead of treating us as a legacy
+                // build. Their hello is informational to us, except for
+                // whether they want sealed changes.
+                let sealing = state.group_key.is_some();
+                if sealing && hello.supports(FEATURE_SEALED_PAYLOADS) {
+                    state.sealing_peers.lock().await.insert(peer);
+                }
+                if hello.supports(FEATURE_RECEIVER_MACS) {
+                    state.receiver_mac_peers.lock().await.insert(peer);
+                }
+                let mut resp = SyncResponse::Hello {
+                    hello: browser_hello(sealing),
+                    hmac: None,
+                };
+                if let Some(gk) = &state.group_key
+                    && let Ok(bytes) = serde_json::to_vec(&resp)
+                {
+                    let tag = gk.mac(&bytes);
+                    if let SyncResponse::Hello { ref mut hmac, .. } = resp {
+                        *hmac = Some(tag);
+                    }
+                }
+                let _ = swarm.behaviour_mut().snapshot.send_response(channel, resp);
+            }
+            SyncRequest::AntiEntropy { .. } => {
+                // Our hello doesn't announce anti-entropy, so a compliant
+                // peer never asks; decline like an unknown request.
+                drop(channel);
+            }
+            SyncRequest::StateSnapshot { .. } => {
+                // The browser client doesn't serve state snapshots. Dropping
+                // the channel fails the request, and the native requester
+                // falls back to version vector catch-up with us.
+                log::debug!("WebSyncClient: declining StateSnapshot request from {peer}");
+                drop(channel);
+            }
             SyncRequest::IdentityAnnounce { .. } => {
                 let _ = swarm
                     .behaviour_mut()
                     .snapshot
                     .send_response(channel, SyncResponse::IdentityAck);
             }
+            SyncRequest::KeyRotation { .. } => {
+                // The browser client never announces key rotation, so a
+                // well-behaved peer doesn't send one. Its passphrase is
+                // changed by reconfiguring it.
+                log::debug!("WebSyncClient: declining KeyRotation request from {peer}");
+                drop(channel);
+            }
+            SyncRequest::Revocations { .. } => {
+                // Not announced by the browser client either; it has no
+                // identity key of its own to revoke with.
+                log::debug!("WebSyncClient: declining Revocations request from {peer}");
+                drop(channel);
+            }
+            SyncRequest::Capabilities { .. } => {
+                // Not announced by the browser client: it doesn't enforce
+                // grants, so it has none to exchange.
+                log::debug!("WebSyncClient: declining Capabilities request from {peer}");
+                drop(channel);
+            }
         },
         // Real-network counterpart of the loopback ChangesetResponse
         // path. Without this, the catch-up data ships from the peer
@@ -2443,6 +3124,9 @@ async fn handle_snapshot_event(
                 your_last_db_version,
                 site_id: peer_site_id,
                 topic: peer_topic,
+                next_cursor,
+                origin_versions,
+                sealed: _,
                 hmac,
             } => {
                 if peer_topic != state.topic {
@@ -2458,6 +3142,9 @@ async fn handle_snapshot_event(
                         your_last_db_version,
                         site_id: peer_site_id,
                         topic: peer_topic.clone(),
+                        next_cursor,
+                        origin_versions,
+                        sealed: None,
                         hmac: None,
                     };
                     let bytes = match serde_json::to_vec(&verify) {
@@ -2473,7 +3160,7 @@ async fn handle_snapshot_event(
                             return;
                         }
                     };
-                    if !gk.verify(&bytes, &tag) {
+                    if !accepts_tag(gk, swarm, &bytes, &tag) {
                         log::debug!(
                             "WebSyncClient: dropping ChangesetResponse from {peer} — bad HMAC"
                         );
@@ -2496,9 +3183,16 @@ async fn handle_snapshot_event(
                 let _ = state.inbound_tx.send(changeset.clone());
                 apply_remote_changeset(state, &peer, &changeset).await;
             }
-            SyncResponse::PushAck | SyncResponse::IdentityAck => {
+            SyncResponse::PushAck | SyncResponse::IdentityAck | SyncResponse::KeyRotationAck => {
                 // Acknowledgements only — nothing to apply.
             }
+            SyncResponse::Hello { .. }
+            | SyncResponse::StateSnapshot { .. }
+            | SyncResponse::AntiEntropy { .. }
+            | SyncResponse::Revocations { .. }
+            | SyncResponse::Capabilities { .. } => {
+                // Never requested by the browser client.
+            }
         },
         Event::OutboundFailure { peer, error, .. } => {
             log::warn!("WebSyncClient: outbound to {peer} failed: {error}");
@@ -2607,6 +3301,10 @@ fn build_push_request(
     let mut req = SyncRequest::Push {
         changeset,
         topic: topic.to_string(),
+        hops: None,
+        prev_db_version: None,
+        seq: None,
+        sealed: None,
         hmac: None,
     };
     if let Some(gk) = group_key {
@@ -2622,6 +3320,53 @@ fn build_push_request(
     req
 }
 
+/// Key to MAC a sync message to `peer` with. The browser client never sets
+/// up a session, so the tag is bound to the receiver's PeerId instead (see
+/// [`GroupKey::receiver_key`]) if its hello says it accepts that. Hellos
+/// keep the plain group key.
+async fn key_for_peer(state: &EngineState, peer: &LibPeerId) -> Option<GroupKey> {
+    let gk = state.group_key.as_ref()?;
+    if state.receiver_mac_peers.lock().await.contains(peer) {
+        Some(gk.receiver_key(&peer.to_bytes()))
+    } else {
+        Some(gk.clone())
+    }
+}
+
+/// Whether `tag` over `bytes` is one a member may MAC a sync message to us
+/// with: bound to our PeerId, or the plain group key of native builds that
+/// predate receiver-bound MACs.
+fn accepts_tag(gk: &GroupKey, swarm: &Swarm<WebBehaviour>, bytes: &[u8], tag: &[u8; 32]) -> bool {
+    gk.receiver_key(&swarm.local_peer_id().to_bytes())
+        .verify(bytes, tag)
+        || gk.verify(bytes, tag)
+}
+
+/// Seal the changes in a signed request for `peer` if its hello asked for
+/// it. Mirrors the native engine's `seal_request`.
+async fn seal_for_peer(state: &EngineState, peer: &LibPeerId, mut req: SyncRequest) -> SyncRequest {
+    if let Some(gk) = &state.group_key
+        && state.sealing_peers.lock().await.contains(peer)
+    {
+        req.seal_changes(&gk.payload_key());
+    }
+    req
+}
+
+/// Seal the changes in a signed loopback request. Both ends are this
+/// build, so there's no hello to consult: a passphrase is enough.
+fn seal_for_loopback(state: &EngineState, mut req: SyncRequest) -> SyncRequest {
+    if let Some(gk) = &state.group_key {
+        req.seal_changes(&gk.payload_key());
+    }
+    req
+}
+
+/// Key to open sealed changes with, when a passphrase is configured.
+fn payload_key(state: &EngineState) -> Option<PayloadKey> {
+    state.group_key.as_ref().map(GroupKey::payload_key)
+}
+
 /// Generate 16 cryptographically random bytes for a fresh `site_id`.
 ///
 /// The browser provides this via `crypto.getRandomValues`; getrandom 0.2
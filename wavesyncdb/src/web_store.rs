@@ -49,6 +49,7 @@ const STORE_PEER_ADDRS: &str = "peer_addrs";
 const META_SITE_ID: &str = "site_id";
 const META_DB_VERSION: &str = "db_version";
 const META_KEYPAIR: &str = "keypair";
+const META_PAIRING_CREDENTIALS: &str = "pairing_credentials";
 
 /// IndexedDB schema version. Bump when adding object stores; the
 /// `on_upgrade_needed` callback in [`BrowserStore::open`] creates any
@@ -185,6 +186,29 @@ impl BrowserStore {
         self.meta_put_bytes(META_DB_VERSION, &v.to_le_bytes()).await
     }
 
+    /// Read the group credentials a pairing received, if any (see
+    /// [`crate::pairing`]).
+    pub async fn get_pairing_credentials(
+        &self,
+    ) -> Result<Option<crate::pairing::PairingCredentials>, StoreError> {
+        let Some(bytes) = self.meta_get_bytes(META_PAIRING_CREDENTIALS).await? else {
+            return Ok(None);
+        };
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| StoreError::Serde(e.to_string()))
+    }
+
+    /// Persist the group credentials a pairing received.
+    pub async fn put_pairing_credentials(
+        &self,
+        credentials: &crate::pairing::PairingCredentials,
+    ) -> Result<(), StoreError> {
+        let bytes =
+            serde_json::to_vec(credentials).map_err(|e| StoreError::Serde(e.to_string()))?;
+        self.meta_put_bytes(META_PAIRING_CREDENTIALS, &bytes).await
+    }
+
     async fn meta_get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, StoreError> {
         let tx = self
             .db
@@ -411,6 +435,7 @@ impl BrowserStore {
                 cl: row.cl,
                 seq: row.seq,
                 db_version: row.db_version,
+                sig: None,
             });
         }
 
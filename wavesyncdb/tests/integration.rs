@@ -4,7 +4,9 @@ use std::time::Duration;
 
 use sea_orm::{ActiveModelTrait, ConnectionTrait, EntityTrait, Set};
 use uuid::Uuid;
-use wavesyncdb::WaveSyncDbBuilder;
+use wavesyncdb::capability::{Capabilities, Operation};
+use wavesyncdb::pairing::{DEFAULT_PAIRING_TTL, PairingError};
+use wavesyncdb::{NetworkEvent, SyncConfig, WaveSyncDbBuilder};
 
 use common::{assert_eventually, make_node_id, make_peer, mem_db, note, task};
 
@@ -465,6 +467,52 @@ async fn test_sync_with_matching_passphrase() {
     assert_eq!(tasks[0].title, "auth-task");
 }
 
+#[tokio::test]
+async fn test_sync_with_required_sessions() {
+    let _ = env_logger::try_init();
+    let topic = format!("test-sessions-{}", Uuid::new_v4());
+    let timeout = Duration::from_secs(15);
+
+    let mut peers = Vec::new();
+    for (seed, name) in [(178, "sessions_a"), (179, "sessions_b")] {
+        let peer = WaveSyncDbBuilder::new(&mem_db(name), &topic)
+            .with_node_id(make_node_id(seed))
+            .with_passphrase("shared-secret")
+            .with_required_sessions(true)
+            .with_mdns_query_interval(Duration::from_millis(100))
+            .with_mdns_ttl(Duration::from_secs(5))
+            .with_sync_interval(Duration::from_secs(2))
+            .build()
+            .await
+            .expect("Failed to create peer");
+        peer.schema().register(task::Entity).sync().await.unwrap();
+        peers.push(peer);
+    }
+    let (peer_a, peer_b) = (&peers[0], &peers[1]);
+
+    task::ActiveModel {
+        id: Set(Uuid::new_v4().to_string()),
+        title: Set("session-task".into()),
+        completed: Set(false),
+    }
+    .insert(peer_a)
+    .await
+    .unwrap();
+
+    // Every message after the hellos goes over a session, so it gets
+    // through even with messages outside one refused.
+    assert_eventually("Peer B has session-task", timeout, || async {
+        task::Entity::find()
+            .all(peer_b)
+            .await
+            .map(|v| v.len())
+            .unwrap_or(0)
+            == 1
+    })
+    .await;
+    assert_eq!(peer_b.diagnostics().replays_rejected, 0);
+}
+
 #[tokio::test]
 async fn test_sync_with_mismatched_passphrase() {
     let _ = env_logger::try_init();
@@ -596,6 +644,648 @@ async fn test_sync_one_passphrase_one_not() {
     );
 }
 
+#[tokio::test]
+async fn test_rotate_passphrase_keeps_group_together() {
+    let _ = env_logger::try_init();
+    let topic = format!("test-rotate-{}", Uuid::new_v4());
+    let timeout = Duration::from_secs(20);
+
+    let mut peers = Vec::new();
+    for (seed, name) in [(40, "rotate_a"), (41, "rotate_b")] {
+        let peer = WaveSyncDbBuilder::new(&mem_db(name), &topic)
+            .with_node_id(make_node_id(seed))
+            .with_passphrase("old-secret")
+            .with_mdns_query_interval(Duration::from_millis(100))
+            .with_mdns_ttl(Duration::from_secs(5))
+            .with_sync_interval(Duration::from_secs(2))
+            .build()
+            .await
+            .expect("Failed to create peer");
+        peer.schema().register(task::Entity).sync().await.unwrap();
+        peers.push(peer);
+    }
+    let (peer_a, peer_b) = (&peers[0], &peers[1]);
+
+    let insert = |title: &'static str| task::ActiveModel {
+        id: Set(Uuid::new_v4().to_string()),
+        title: Set(title.into()),
+        completed: Set(false),
+    };
+    insert("before").insert(peer_a).await.unwrap();
+    assert_eventually(
+        "B has the task written before the rotation",
+        timeout,
+        || async {
+            task::Entity::find()
+                .all(peer_b)
+                .await
+                .map(|v| v.len())
+                .unwrap_or(0)
+                == 1
+        },
+    )
+    .await;
+
+    assert_eq!(peer_a.rotate_passphrase("new-secret").await.unwrap(), 1);
+    assert!(peer_a.rotate_passphrase("new-secret").await.is_err());
+
+    // B adopts the rotation and keeps syncing with A under the new key.
+    assert_eventually("B adopted the rotated key", timeout, || async {
+        peer_b.diagnostics().key_rotations == 1
+    })
+    .await;
+    insert("after").insert(peer_b).await.unwrap();
+    assert_eventually(
+        "A has the task written after the rotation",
+        timeout,
+        || async {
+            task::Entity::find()
+                .all(peer_a)
+                .await
+                .map(|v| v.len())
+                .unwrap_or(0)
+                == 2
+        },
+    )
+    .await;
+
+    // A device configured with the new passphrase joins the same group.
+    let peer_c = WaveSyncDbBuilder::new(&mem_db("rotate_c"), &topic)
+        .with_node_id(make_node_id(42))
+        .with_passphrase("new-secret")
+        .with_mdns_query_interval(Duration::from_millis(100))
+        .with_mdns_ttl(Duration::from_secs(5))
+        .with_sync_interval(Duration::from_secs(2))
+        .build()
+        .await
+        .expect("Failed to create Peer C");
+    peer_c.schema().register(task::Entity).sync().await.unwrap();
+    assert_eventually("C has both tasks", timeout, || async {
+        task::Entity::find()
+            .all(&peer_c)
+            .await
+            .map(|v| v.len())
+            .unwrap_or(0)
+            == 2
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_upgrade_key_derivation_keeps_group_together() {
+    let _ = env_logger::try_init();
+    let topic = format!("test-kdf-upgrade-{}", Uuid::new_v4());
+    let timeout = Duration::from_secs(20);
+
+    let mut peers = Vec::new();
+    for (seed, name) in [(52, "kdf_a"), (53, "kdf_b")] {
+        let peer = WaveSyncDbBuilder::new(&mem_db(name), &topic)
+            .with_node_id(make_node_id(seed))
+            .with_passphrase("shared-secret")
+            .with_mdns_query_interval(Duration::from_millis(100))
+            .with_mdns_ttl(Duration::from_secs(5))
+            .with_sync_interval(Duration::from_secs(2))
+            .build()
+            .await
+            .expect("Failed to create peer");
+        peer.schema().register(task::Entity).sync().await.unwrap();
+        peers.push(peer);
+    }
+    let (peer_a, peer_b) = (&peers[0], &peers[1]);
+    let insert = |title: &'static str| task::ActiveModel {
+        id: Set(Uuid::new_v4().to_string()),
+        title: Set(title.into()),
+        completed: Set(false),
+    };
+    insert("before").insert(peer_a).await.unwrap();
+    assert_eventually(
+        "B has the task written before the upgrade",
+        timeout,
+        || async {
+            task::Entity::find()
+                .all(peer_b)
+                .await
+                .map(|v| v.len())
+                .unwrap_or(0)
+                == 1
+        },
+    )
+    .await;
+
+    assert_eq!(peer_a.upgrade_key_derivation().await.unwrap(), 1);
+    assert!(peer_a.upgrade_key_derivation().await.is_err());
+
+    assert_eventually("B moved to the Argon2id key", timeout, || async {
+        peer_b.diagnostics().key_rotations == 1
+    })
+    .await;
+    insert("after").insert(peer_b).await.unwrap();
+    assert_eventually(
+        "A has the task written after the upgrade",
+        timeout,
+        || async {
+            task::Entity::find()
+                .all(peer_a)
+                .await
+                .map(|v| v.len())
+                .unwrap_or(0)
+                == 2
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_revoke_device_shuts_it_out() {
+    let _ = env_logger::try_init();
+    let topic = format!("test-revoke-{}", Uuid::new_v4());
+    let timeout = Duration::from_secs(20);
+
+    let mut peers = Vec::new();
+    for (seed, name) in [(43, "revoke_a"), (44, "revoke_b"), (45, "revoke_c")] {
+        let peer = WaveSyncDbBuilder::new(&mem_db(name), &topic)
+            .with_node_id(make_node_id(seed))
+            .with_passphrase("shared-secret")
+            .with_mdns_query_interval(Duration::from_millis(100))
+            .with_mdns_ttl(Duration::from_secs(5))
+            .with_sync_interval(Duration::from_secs(2))
+            .build()
+            .await
+            .expect("Failed to create peer");
+        peer.schema().register(task::Entity).sync().await.unwrap();
+        peers.push(peer);
+    }
+    let (peer_a, peer_b, peer_c) = (&peers[0], &peers[1], &peers[2]);
+    async fn count(peer: &wavesyncdb::WaveSyncDb) -> usize {
+        task::Entity::find()
+            .all(peer)
+            .await
+            .map(|v| v.len())
+            .unwrap_or(0)
+    }
+    let insert = |title: &'static str| task::ActiveModel {
+        id: Set(Uuid::new_v4().to_string()),
+        title: Set(title.into()),
+        completed: Set(false),
+    };
+
+    insert("before").insert(peer_c).await.unwrap();
+    assert_eventually("A and B have C's task", timeout, || async {
+        count(peer_a).await == 1 && count(peer_b).await == 1
+    })
+    .await;
+
+    let a_id = peer_a.network_status().local_peer_id.0;
+    let c_id = peer_c.network_status().local_peer_id.0;
+    assert!(peer_a.revoke_device(&a_id).await.is_err());
+    let passphrase = peer_a.revoke_device(&c_id).await.unwrap();
+    assert!(passphrase.is_some_and(|p| p != "shared-secret"));
+    assert!(peer_a.revoke_device(&c_id).await.is_err());
+
+    // B hears of the revocation, follows the rotation and drops C.
+    assert_eventually("B rotated away from C", timeout, || async {
+        peer_b.diagnostics().key_rotations == 1
+            && !peer_b
+                .network_status()
+                .connected_peers
+                .iter()
+                .any(|p| p.peer_id.0 == c_id)
+    })
+    .await;
+
+    // A and B keep syncing; C's writes no longer reach them.
+    insert("from c").insert(peer_c).await.unwrap();
+    insert("from b").insert(peer_b).await.unwrap();
+    assert_eventually("A has B's task", timeout, || async {
+        count(peer_a).await == 2
+    })
+    .await;
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert_eq!(count(peer_a).await, 2);
+    assert_eq!(count(peer_b).await, 2);
+}
+
+#[tokio::test]
+async fn test_read_only_member_writes_are_refused() {
+    let _ = env_logger::try_init();
+    let topic = format!("test-caps-{}", Uuid::new_v4());
+    let timeout = Duration::from_secs(20);
+
+    // A grants; both have to know it as the issuer before they start.
+    let url_a = mem_db("caps_a");
+    let issuer = {
+        let db = sea_orm::Database::connect(&url_a).await.unwrap();
+        wavesyncdb::shadow::create_meta_table(&db).await.unwrap();
+        let keypair = wavesyncdb::shadow::get_or_create_libp2p_keypair(&db)
+            .await
+            .unwrap();
+        keypair.public().try_into_ed25519().unwrap().to_bytes()
+    };
+
+    let mut peers = Vec::new();
+    for (seed, url) in [(46, url_a), (47, mem_db("caps_b"))] {
+        let peer = WaveSyncDbBuilder::new(&url, &topic)
+            .with_node_id(make_node_id(seed))
+            .with_passphrase("shared-secret")
+            .with_capability_issuer(issuer)
+            // Writes from either device count until B is granted less.
+            .with_default_capabilities(Capabilities::full("member"))
+            .with_mdns_query_interval(Duration::from_millis(100))
+            .with_mdns_ttl(Duration::from_secs(5))
+            .with_sync_interval(Duration::from_secs(2))
+            .build()
+            .await
+            .expect("Failed to create peer");
+        peer.schema().register(task::Entity).sync().await.unwrap();
+        peers.push(peer);
+    }
+    let (peer_a, peer_b) = (&peers[0], &peers[1]);
+    async fn count(peer: &wavesyncdb::WaveSyncDb) -> usize {
+        task::Entity::find()
+            .all(peer)
+            .await
+            .map(|v| v.len())
+            .unwrap_or(0)
+    }
+    let insert = |title: &'static str| task::ActiveModel {
+        id: Set(Uuid::new_v4().to_string()),
+        title: Set(title.into()),
+        completed: Set(false),
+    };
+
+    assert_eventually("A and B connected", timeout, || async {
+        peer_a.network_status().group_peer_count() == 1
+    })
+    .await;
+    let b_id = peer_b.network_status().local_peer_id.0;
+    peer_a
+        .grant_capabilities(&b_id, Capabilities::read_only("kiosk"))
+        .await
+        .unwrap();
+    assert_eventually("A shows B's role", timeout, || async {
+        peer_a.network_status().connected_peers.iter().any(|p| {
+            p.peer_id.0 == b_id && p.capabilities.as_ref().is_some_and(|c| c.role == "kiosk")
+        })
+    })
+    .await;
+
+    // B still reads A's writes, but A refuses B's.
+    insert("from a").insert(peer_a).await.unwrap();
+    assert_eventually("B has A's task", timeout, || async {
+        count(peer_b).await == 1
+    })
+    .await;
+    insert("from b").insert(peer_b).await.unwrap();
+    assert_eventually("A refused B's task", timeout, || async {
+        peer_a.diagnostics().unauthorized_changes_rejected > 0
+    })
+    .await;
+    assert_eq!(count(peer_a).await, 1);
+    let denials = peer_a.capability_denials(10).await.unwrap();
+    assert!(!denials.is_empty());
+    assert!(
+        denials
+            .iter()
+            .all(|d| d.role == "kiosk" && d.operation == Operation::Insert)
+    );
+}
+
+#[tokio::test]
+async fn test_unsigned_change_under_connected_peers_site_is_refused() {
+    let _ = env_logger::try_init();
+    let topic = format!("test-forge-{}", Uuid::new_v4());
+    let timeout = Duration::from_secs(20);
+    let url_m = mem_db("forge_m");
+
+    async fn build(url: &str, topic: &str, seed: Option<u8>, mdns: bool) -> wavesyncdb::WaveSyncDb {
+        let mut builder = WaveSyncDbBuilder::new(url, topic)
+            .with_passphrase("forge-secret")
+            .with_mdns_enabled(mdns)
+            .with_mdns_query_interval(Duration::from_millis(100))
+            .with_mdns_ttl(Duration::from_secs(5))
+            .with_sync_interval(Duration::from_secs(2));
+        if let Some(seed) = seed {
+            builder = builder.with_node_id(make_node_id(seed));
+        }
+        let peer = builder.build().await.expect("Failed to create peer");
+        peer.schema().register(task::Entity).sync().await.unwrap();
+        peer
+    }
+
+    let peer_r = build(&mem_db("forge_r"), &topic, Some(56), true).await;
+    // No explicit node id: V's site is the one its key derives.
+    let peer_v = build(&mem_db("forge_v"), &topic, None, true).await;
+    assert_eventually("R and V connected", timeout, || async {
+        peer_r.network_status().group_peer_count() == 1
+    })
+    .await;
+
+    // M writes a row offline and rewrites its clocks to claim V's site,
+    // without a signature — as a build that doesn't sign would send it.
+    let victim: String = peer_v
+        .site_id()
+        .0
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    {
+        let peer_m = build(&url_m, &topic, Some(57), false).await;
+        task::ActiveModel {
+            id: Set("forged-task".to_string()),
+            title: Set("forged".into()),
+            completed: Set(false),
+        }
+        .insert(&peer_m)
+        .await
+        .unwrap();
+        peer_m
+            .inner()
+            .execute_unprepared(&format!(
+                "UPDATE _wavesync_tasks_clock SET site_id = X'{victim}'
+                 WHERE pk = 'forged-task';
+                 DELETE FROM _wavesync_change_sigs"
+            ))
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let peer_m = build(&url_m, &topic, Some(57), true).await;
+    task::ActiveModel {
+        id: Set("honest-task".to_string()),
+        title: Set("honest".into()),
+        completed: Set(false),
+    }
+    .insert(&peer_m)
+    .await
+    .unwrap();
+
+    // R takes M's own writes but not the one claiming V's site, which R
+    // pinned to V's key when V connected.
+    assert_eventually("R has M's honest task", timeout, || async {
+        task::Entity::find_by_id("honest-task")
+            .one(&peer_r)
+            .await
+            .ok()
+            .flatten()
+            .is_some()
+    })
+    .await;
+    assert_eventually("R refused the forged change", timeout, || async {
+        peer_r.diagnostics().forged_changes_rejected > 0
+    })
+    .await;
+    let forged = task::Entity::find_by_id("forged-task")
+        .one(&peer_r)
+        .await
+        .unwrap();
+    assert!(forged.is_none());
+}
+
+#[tokio::test]
+async fn test_inflated_clock_quarantines_its_site() {
+    let _ = env_logger::try_init();
+    let topic = format!("test-clock-{}", Uuid::new_v4());
+    let timeout = Duration::from_secs(20);
+
+    let peer_a = make_peer(&mem_db("clock_a"), &topic, 54).await;
+    let peer_b = make_peer(&mem_db("clock_b"), &topic, 55).await;
+    let title_on_b = || async {
+        task::Entity::find_by_id("clock-task")
+            .one(&peer_b)
+            .await
+            .ok()
+            .flatten()
+            .map(|t| t.title)
+    };
+
+    let inserted = task::ActiveModel {
+        id: Set("clock-task".to_string()),
+        title: Set("original".into()),
+        completed: Set(false),
+    }
+    .insert(&peer_a)
+    .await
+    .unwrap();
+    assert_eventually("B has A's task", timeout, || async {
+        title_on_b().await.as_deref() == Some("original")
+    })
+    .await;
+
+    // Push A's clock for the title far past anything B holds.
+    peer_a
+        .inner()
+        .execute_unprepared(
+            "UPDATE _wavesync_tasks_clock SET col_version = 5000000000
+             WHERE pk = 'clock-task' AND cid = 'title'",
+        )
+        .await
+        .unwrap();
+    let mut active: task::ActiveModel = inserted.into();
+    active.title = Set("inflated".into());
+    let inserted = active.update(&peer_a).await.unwrap();
+
+    assert_eventually("B quarantined A's site", timeout, || async {
+        peer_b.diagnostics().inflated_clocks_rejected > 0
+    })
+    .await;
+    assert_eq!(title_on_b().await.as_deref(), Some("original"));
+    let held = peer_b.quarantined_sites().await.unwrap();
+    assert_eq!(held.len(), 1);
+    assert_eq!(held[0].site_id, *peer_a.site_id());
+    // Anti-entropy can carry the raised clock over before the update does.
+    assert!(held[0].clock >= 5_000_000_000);
+
+    // Repair A's clocks, let A's site back in, and its writes go through.
+    assert_eq!(peer_a.repair_inflated_clocks(1_000).await.unwrap(), 1);
+    assert!(peer_b.release_quarantine(peer_a.site_id()).await.unwrap());
+    let mut active: task::ActiveModel = inserted.into();
+    active.title = Set("repaired".into());
+    active.update(&peer_a).await.unwrap();
+    assert_eventually("B has A's repaired write", timeout, || async {
+        title_on_b().await.as_deref() == Some("repaired")
+    })
+    .await;
+    assert!(peer_b.quarantined_sites().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_paired_device_joins_the_group() {
+    let _ = env_logger::try_init();
+    let topic = format!("test-pair-{}", Uuid::new_v4());
+    let timeout = Duration::from_secs(20);
+
+    let peer_a = WaveSyncDbBuilder::new(&mem_db("pair_a"), &topic)
+        .with_node_id(make_node_id(48))
+        .with_passphrase("shared-secret")
+        .with_mdns_enabled(false)
+        .with_sync_interval(Duration::from_secs(2))
+        .build()
+        .await
+        .expect("Failed to create peer");
+    peer_a.schema().register(task::Entity).sync().await.unwrap();
+    let mut events = peer_a.network_event_rx();
+
+    // The invite lists A's listen addresses, which take a moment to bind.
+    let deadline = tokio::time::Instant::now() + timeout;
+    let invite = loop {
+        match peer_a.start_pairing(DEFAULT_PAIRING_TTL).await {
+            Ok(invite) => break invite,
+            Err(_) if tokio::time::Instant::now() < deadline => {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Err(e) => panic!("A never opened an invitation: {e}"),
+        }
+    };
+
+    let b_url = mem_db("pair_b");
+    let config = SyncConfig::pair(&b_url, &invite).await.unwrap();
+    // B paired with the keypair it stored, and syncs as that identity.
+    let b_id = {
+        let db = sea_orm::Database::connect(&b_url).await.unwrap();
+        let keypair = wavesyncdb::shadow::get_or_create_libp2p_keypair(&db)
+            .await
+            .unwrap();
+        wavesyncdb::PeerId(keypair.public().to_peer_id().to_string())
+    };
+    assert_eq!(config.topic, topic);
+    assert_eq!(config.passphrase.as_deref(), Some("shared-secret"));
+    assert!(!config.bootstrap_peers.is_empty());
+
+    // The code is spent.
+    assert!(matches!(
+        SyncConfig::pair(&mem_db("pair_c"), &invite).await,
+        Err(PairingError::Refused(_))
+    ));
+
+    let peer_b = WaveSyncDbBuilder::from_config(&config)
+        .with_node_id(make_node_id(49))
+        .with_mdns_enabled(false)
+        .with_sync_interval(Duration::from_secs(2))
+        .build()
+        .await
+        .expect("Failed to create peer");
+    peer_b.schema().register(task::Entity).sync().await.unwrap();
+
+    let paired = tokio::time::timeout(timeout, async {
+        loop {
+            if let Ok(NetworkEvent::DevicePaired(peer)) = events.recv().await {
+                break peer;
+            }
+        }
+    })
+    .await
+    .expect("A never reported the pairing");
+    assert_eq!(paired, b_id);
+
+    task::ActiveModel {
+        id: Set(Uuid::new_v4().to_string()),
+        title: Set("from a".into()),
+        completed: Set(false),
+    }
+    .insert(&peer_a)
+    .await
+    .unwrap();
+    assert_eventually("B has A's task", timeout, || async {
+        task::Entity::find()
+            .all(&peer_b)
+            .await
+            .map(|v| v.len())
+            .unwrap_or(0)
+            == 1
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_bundle_carries_changes_between_offline_devices() {
+    let _ = env_logger::try_init();
+    // Distinct topics would refuse the bundle; mDNS off keeps them apart.
+    let topic = format!("test-bundle-{}", Uuid::new_v4());
+    let offline_peer = |url: String, seed: u8| {
+        let topic = topic.clone();
+        async move {
+            let peer = WaveSyncDbBuilder::new(&url, &topic)
+                .with_node_id(make_node_id(seed))
+                .with_passphrase("field-secret")
+                .with_mdns_enabled(false)
+                .build()
+                .await
+                .expect("Failed to create peer");
+            peer.schema().register(task::Entity).sync().await.unwrap();
+            peer
+        }
+    };
+    let peer_a = offline_peer(mem_db("bundle_a"), 56).await;
+    let peer_b = offline_peer(mem_db("bundle_b"), 57).await;
+    let path =
+        std::env::temp_dir().join(format!("wavesync_bundle_{}.json", Uuid::new_v4().simple()));
+
+    for title in ["first", "second"] {
+        task::ActiveModel {
+            id: Set(format!("bundle-{title}")),
+            title: Set(title.into()),
+            completed: Set(false),
+        }
+        .insert(&peer_a)
+        .await
+        .unwrap();
+    }
+    let since = peer_b.origin_versions().await.unwrap();
+    let exported = peer_a.export_bundle(&since, &path, true).await.unwrap();
+    assert!(exported > 0);
+
+    let imported = peer_b.import_bundle(&path).await.unwrap();
+    assert_eq!(imported.merged, exported);
+    assert_eq!(imported.refused, 0);
+    assert_eq!(imported.header.site_id, *peer_a.site_id());
+    assert_eq!(task::Entity::find().all(&peer_b).await.unwrap().len(), 2);
+    // B adopted A's watermark, so the next bundle holds only what's new.
+    let a_site = *peer_a.site_id();
+    let a_watermark = imported.watermarks.get(&a_site);
+    assert_eventually(
+        "B adopted A's watermark",
+        Duration::from_secs(5),
+        || async { peer_b.origin_versions().await.unwrap().get(&a_site) == a_watermark },
+    )
+    .await;
+    let since = peer_b.origin_versions().await.unwrap();
+
+    let mut active: task::ActiveModel = task::Entity::find_by_id("bundle-first")
+        .one(&peer_a)
+        .await
+        .unwrap()
+        .unwrap()
+        .into();
+    active.completed = Set(true);
+    active.update(&peer_a).await.unwrap();
+    assert_eq!(peer_a.export_bundle(&since, &path, false).await.unwrap(), 1);
+    assert_eq!(peer_b.import_bundle(&path).await.unwrap().merged, 1);
+    let first = task::Entity::find_by_id("bundle-first")
+        .one(&peer_b)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(first.completed);
+
+    // A device outside the group can't read it.
+    let stranger = WaveSyncDbBuilder::new(&mem_db("bundle_c"), &topic)
+        .with_node_id(make_node_id(58))
+        .with_passphrase("other-secret")
+        .with_mdns_enabled(false)
+        .build()
+        .await
+        .unwrap();
+    stranger
+        .schema()
+        .register(task::Entity)
+        .sync()
+        .await
+        .unwrap();
+    assert!(stranger.import_bundle(&path).await.is_err());
+    let _ = std::fs::remove_file(&path);
+}
+
 #[tokio::test]
 async fn test_same_db_reconnection_sync() {
     let _ = env_logger::try_init();
@@ -783,6 +783,68 @@ async fn test_r5_identity_not_sent_without_passphrase() {
     );
 }
 
+#[tokio::test]
+async fn test_r5_identity_signed_and_endorsed() {
+    use libp2p::identity::ed25519;
+    use wavesyncdb::identity::{IdentityEndorsement, IdentityVerification};
+
+    let _ = env_logger::try_init();
+    let topic = format!("test-r5d-{}", Uuid::new_v4());
+    let passphrase = "test-secret-r5d";
+    let timeout = Duration::from_secs(15);
+    let issuer = ed25519::Keypair::generate();
+    let issuer_key = issuer.public().to_bytes();
+
+    let build = |url: String, node: u8| {
+        WaveSyncDbBuilder::new(&url, &topic)
+            .with_node_id(common::make_node_id(node))
+            .with_passphrase(passphrase)
+            .with_identity_issuer(issuer_key)
+            .with_mdns_query_interval(Duration::from_millis(100))
+            .with_mdns_ttl(Duration::from_secs(5))
+            .with_sync_interval(Duration::from_secs(2))
+            .build()
+    };
+    let peer_a = build(mem_db("r5d_a"), 216).await.unwrap();
+    peer_a.schema().register(task::Entity).sync().await.unwrap();
+    let peer_b = build(mem_db("r5d_b"), 217).await.unwrap();
+    peer_b.schema().register(task::Entity).sync().await.unwrap();
+
+    let a_peer_id = peer_a.network_status().local_peer_id.0;
+    let a_device =
+        wavesyncdb::revocation::key_for_peer(&a_peer_id.parse().unwrap()).expect("ed25519 peer id");
+    let verification_of_a = || {
+        peer_b
+            .network_status()
+            .connected_peers
+            .iter()
+            .find(|p| p.peer_id.0 == a_peer_id)
+            .filter(|p| p.app_id.as_deref() == Some("user-321"))
+            .map(|p| p.identity_verification)
+    };
+
+    // A self-asserted identity is signed, but not endorsed.
+    peer_a.set_peer_identity("user-321");
+    assert_eventually("B sees A's signed identity", timeout, || async {
+        verification_of_a() == Some(IdentityVerification::DeviceSigned)
+    })
+    .await;
+
+    // An endorsement for another device is refused.
+    let foreign = IdentityEndorsement::issue(&issuer, "user-321", [7u8; 32], None);
+    assert!(peer_a.set_endorsed_peer_identity(foreign).await.is_err());
+
+    let endorsement = IdentityEndorsement::issue(&issuer, "user-321", a_device, None);
+    peer_a
+        .set_endorsed_peer_identity(endorsement)
+        .await
+        .unwrap();
+    assert_eventually("B sees A's endorsed identity", timeout, || async {
+        verification_of_a() == Some(IdentityVerification::Endorsed { issuer: issuer_key })
+    })
+    .await;
+}
+
 // ---------------------------------------------------------------------------
 // Regression: out-of-order changeset delivery (UPDATE before INSERT)
 // INSERT then rapid UPDATE should converge on peer B without workarounds.
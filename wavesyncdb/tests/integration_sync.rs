@@ -862,18 +862,7 @@ async fn test_add_wins_policy_integration() {
         )
         .await
         .unwrap();
-    peer_a
-        .execute_unprepared(
-            "CREATE TABLE IF NOT EXISTS \"_wavesync_tasks_clock\" (
-                pk TEXT NOT NULL,
-                cid TEXT NOT NULL,
-                col_version INTEGER NOT NULL,
-                db_version INTEGER NOT NULL,
-                site_id BLOB NOT NULL,
-                seq INTEGER NOT NULL DEFAULT 0,
-                PRIMARY KEY (pk, cid)
-            )",
-        )
+    wavesyncdb::shadow::create_shadow_table(peer_a.inner(), "tasks")
         .await
         .unwrap();
     peer_a.register_table(TableMeta {
@@ -885,6 +874,7 @@ async fn test_add_wins_policy_integration() {
             "completed".to_string(),
         ],
         delete_policy: DeletePolicy::AddWins,
+        column_types: Default::default(),
     });
 
     let peer_b = WaveSyncDbBuilder::new(&url_b, &topic)
@@ -906,18 +896,7 @@ async fn test_add_wins_policy_integration() {
         )
         .await
         .unwrap();
-    peer_b
-        .execute_unprepared(
-            "CREATE TABLE IF NOT EXISTS \"_wavesync_tasks_clock\" (
-                pk TEXT NOT NULL,
-                cid TEXT NOT NULL,
-                col_version INTEGER NOT NULL,
-                db_version INTEGER NOT NULL,
-                site_id BLOB NOT NULL,
-                seq INTEGER NOT NULL DEFAULT 0,
-                PRIMARY KEY (pk, cid)
-            )",
-        )
+    wavesyncdb::shadow::create_shadow_table(peer_b.inner(), "tasks")
         .await
         .unwrap();
     peer_b.register_table(TableMeta {
@@ -929,6 +908,7 @@ async fn test_add_wins_policy_integration() {
             "completed".to_string(),
         ],
         delete_policy: DeletePolicy::AddWins,
+        column_types: Default::default(),
     });
 
     // Signal registry ready on both peers
@@ -1494,3 +1474,59 @@ async fn test_insert_delete_before_sync() {
         .unwrap_or(0);
     assert_eq!(count, 0, "B should have 0 rows after insert+delete");
 }
+
+// ---------------------------------------------------------------------------
+// Background sync in a process that doesn't link the app's entities (the
+// `wavesync` CLI) registers the tables it finds in the database before the
+// engine starts syncing, so remote rows for them are applied.
+// ---------------------------------------------------------------------------
+#[tokio::test]
+async fn test_background_sync_with_schema_only_registry() {
+    use wavesyncdb::background_sync::{BackgroundSyncResult, background_sync};
+
+    let _ = env_logger::try_init();
+    let topic = format!("test-bg-schema-{}", Uuid::new_v4());
+    let db_b_url = mem_db("bg_schema_b");
+
+    // B's app saved a crate name this test binary has no entities under.
+    let peer_b = WaveSyncDbBuilder::new(&db_b_url, &topic)
+        .with_node_id(make_node_id(180))
+        .with_mdns_enabled(false)
+        .build()
+        .await
+        .expect("Failed to create Peer B");
+    peer_b
+        .get_schema_registry("no_such_app")
+        .register(task::Entity)
+        .sync()
+        .await
+        .unwrap();
+    peer_b.shutdown().await;
+    drop(peer_b);
+
+    let peer_a = make_peer(&mem_db("bg_schema_a"), &topic, 181).await;
+    task::ActiveModel {
+        id: Set("bg-task".to_string()),
+        title: Set("From A".into()),
+        completed: Set(false),
+    }
+    .insert(&peer_a)
+    .await
+    .unwrap();
+
+    let result = background_sync(&db_b_url, Duration::from_secs(20))
+        .await
+        .expect("Background sync failed");
+    assert!(
+        matches!(result, BackgroundSyncResult::Synced { .. }),
+        "{result:?}"
+    );
+
+    let db_b = sea_orm::Database::connect(&db_b_url).await.unwrap();
+    let row = task::Entity::find_by_id("bg-task")
+        .one(&db_b)
+        .await
+        .unwrap()
+        .expect("B should have A's row");
+    assert_eq!(row.title, "From A");
+}
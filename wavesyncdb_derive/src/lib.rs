@@ -70,7 +70,7 @@ pub fn derive_sync_entity(input: TokenStream) -> TokenStream {
             wavesyncdb::SyncEntityInfo {
                 module_path: module_path!(),
                 schema_fn: |backend| {
-                    use sea_orm::{EntityTrait, Iterable, IdenStatic, PrimaryKeyToColumn, Schema};
+                    use sea_orm::{ColumnTrait, EntityTrait, Iterable, IdenStatic, PrimaryKeyToColumn, Schema};
                     use sea_orm::sea_query::SqliteQueryBuilder;
 
                     let schema = Schema::new(backend);
@@ -88,12 +88,21 @@ pub fn derive_sync_entity(input: TokenStream) -> TokenStream {
                         .next()
                         .map(|pk| IdenStatic::as_str(&pk.into_column()).to_string())
                         .unwrap_or_default();
+                    let column_types = Column::iter()
+                        .map(|c| {
+                            (
+                                IdenStatic::as_str(&c).to_string(),
+                                wavesyncdb::ColumnType::from_column_def(&c.def()),
+                            )
+                        })
+                        .collect();
 
                     (create_sql, wavesyncdb::TableMeta {
                         table_name,
                         primary_key_column,
                         columns,
                         delete_policy: wavesyncdb::DeletePolicy::DeleteWins,
+                        column_types,
                     })
                 },
             }